@@ -0,0 +1,384 @@
+use anyhow::Result;
+use std::sync::{Arc, Mutex};
+use crate::app::log::MessageLog;
+use crate::app::{theme, symbols};
+use crate::app::text_input::{TextInput, valid_number};
+use crate::app::sequencer::{PlaybackState, Section};
+use crate::file::ExportOptions;
+use crossterm::event::{KeyEvent, KeyCode};
+use tui::{
+    text::{Span, Spans},
+    style::{Style, Modifier},
+    widgets::{Block, Paragraph, Borders, Wrap},
+    layout::{Rect, Alignment, Constraint, Direction, Layout},
+};
+
+/// Scenes are named by letter,
+/// falling back to numbers.
+fn scene_name(idx: usize) -> String {
+    if idx < 26 {
+        ((b'A' + idx as u8) as char).to_string()
+    } else {
+        (idx + 1).to_string()
+    }
+}
+
+enum TextTarget {
+    Tempo,
+    Export,
+}
+
+pub struct Arranger<'a> {
+    state: Arc<Mutex<PlaybackState>>,
+
+    save_dir: String,
+    export_options: ExportOptions,
+    text_input: Option<(TextInput<'a>, TextTarget)>,
+
+    // Selected scene and
+    // section of the arrangement
+    scene_idx: usize,
+    section_idx: usize,
+
+    // Status messages
+    log: MessageLog,
+}
+
+impl<'a> Arranger<'a> {
+    pub fn new(state: Arc<Mutex<PlaybackState>>, log: MessageLog, save_dir: String) -> Arranger<'a> {
+        Arranger {
+            state,
+            save_dir,
+            export_options: ExportOptions::default(),
+            text_input: None,
+            scene_idx: 0,
+            section_idx: 0,
+            log,
+        }
+    }
+
+    pub fn set_save_dir(&mut self, save_dir: String) {
+        self.save_dir = save_dir;
+    }
+
+    /// Set how exported files are written,
+    /// e.g. the MIDI channel they're on.
+    pub fn set_export_options(&mut self, options: ExportOptions) {
+        self.export_options = options;
+    }
+
+    pub fn capture_input(&self) -> bool {
+        self.text_input.is_some()
+    }
+
+    pub fn render(&mut self, rect: Rect) -> Vec<(Paragraph, Rect)> {
+        let mut rects = vec![];
+
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([
+                // Main
+                Constraint::Min(6),
+
+                // Messages chunk
+                Constraint::Length(1),
+            ].as_ref())
+            .split(rect);
+
+        let message = match &self.text_input {
+            Some((ti, _)) => ti.render(),
+            None => Paragraph::new(self.log.current())
+                .alignment(Alignment::Right)
+        };
+        rects.push((message, chunks[1]));
+
+        let display_chunks = Layout::default()
+            .direction(Direction::Horizontal)
+            .margin(2)
+            .constraints([
+                    // Scenes chunk
+                    Constraint::Ratio(1, 3),
+
+                    // Timeline chunk
+                    Constraint::Ratio(2, 3),
+                ].as_ref())
+            .split(chunks[0]);
+
+        let state = self.state.lock().unwrap();
+        let playing = state.section.and_then(|(idx, played)| {
+            state.arrangement.get(idx).map(|section| (idx, played, section.scene))
+        });
+
+        // Scenes, with their chords
+        let scenes: Vec<Spans> = state.scenes.iter().enumerate().map(|(i, scene)| {
+            let mut style = Style::default();
+            if i == self.scene_idx {
+                style = style.patch(theme::selected());
+            } else if matches!(playing, Some((_, _, scene)) if scene == i) {
+                style = style.patch(theme::playing());
+            }
+            let chords: Vec<String> = scene.chords().iter().map(|cs| symbols::chord(cs)).collect();
+            Spans::from(vec![
+                Span::styled(format!("{} ", scene_name(i)), style.add_modifier(Modifier::BOLD)),
+                Span::styled(format!("{}b ", scene.bars()), style),
+                Span::styled(chords.join(" "), style),
+            ])
+        }).collect();
+        let scenes = Paragraph::new(scenes)
+            .block(
+                Block::default()
+                    .title("Scenes")
+                    .borders(Borders::TOP)
+                    .style(Style::default())
+            );
+        rects.push((scenes, display_chunks[0]));
+
+        // Sections of the arrangement, in order
+        let sections: Vec<Span> = state.arrangement.iter().enumerate().map(|(i, section)| {
+            let mut name = scene_name(section.scene);
+            if let Some(tempo) = section.tempo {
+                let arrow = if section.ramp { "~>" } else { "@" };
+                name = format!("{} {}{}", name, arrow, tempo);
+            }
+            let (label, mut style) = match playing {
+                Some((idx, played, _)) if idx == i => {
+                    (format!("[{} {}/{}]", name, played + 1, section.repeats),
+                     theme::playing())
+                }
+                _ => (format!("[{} x{}]", name, section.repeats), Style::default())
+            };
+            if i == self.section_idx {
+                style = style.patch(theme::selected()).add_modifier(Modifier::BOLD);
+            }
+            Span::styled(label, style)
+        }).collect();
+        let timeline = Paragraph::new(Spans::from(sections))
+            .wrap(Wrap { trim: false })
+            .block(
+                Block::default()
+                    .title("Arrangement")
+                    .borders(Borders::TOP)
+                    .style(Style::default())
+            );
+        rects.push((timeline, display_chunks[1]));
+
+        rects
+    }
+
+    pub fn process_input(&mut self, key: KeyEvent) -> Result<()> {
+        if let Some((text_input, target)) = &mut self.text_input {
+            let (input, close) = text_input.process_input(key)?;
+            if close {
+                if let Some(input) = input {
+                    let mut state = self.state.lock().unwrap();
+                    match target {
+                        TextTarget::Tempo => {
+                            if let Some(section) = state.arrangement.get_mut(self.section_idx) {
+                                match input.parse::<usize>() {
+                                    Ok(0) => section.tempo = None,
+                                    Ok(tempo) => section.tempo = Some(tempo),
+                                    Err(_) => self.log.error("Invalid tempo"),
+                                }
+                            }
+                        }
+                        TextTarget::Export => {
+                            match state.export_arrangement(self.export_options, input) {
+                                Ok(_) => {
+                                    self.log.push("Saved file");
+                                },
+                                Err(err) => {
+                                    self.log.error(format!("Failed to save: {}", err));
+                                }
+                            }
+                        }
+                    }
+                }
+                self.text_input = None;
+            }
+            return Ok(());
+        }
+
+        let mut state = self.state.lock().unwrap();
+        let n_scenes = state.scenes.len();
+        let n_sections = state.arrangement.len();
+
+        match key.code {
+            // Capture the sequencer's progression as a new scene
+            KeyCode::Char('c') => {
+                let progression = state.progression().clone();
+                state.scenes.push(progression);
+                self.scene_idx = state.scenes.len() - 1;
+                self.log.push(format!("Captured scene {}", scene_name(self.scene_idx)));
+            }
+
+            // Select a scene
+            KeyCode::Char('j') => {
+                if n_scenes > 0 {
+                    self.scene_idx = (self.scene_idx + 1) % n_scenes;
+                }
+            }
+            KeyCode::Char('k') => {
+                if n_scenes > 0 {
+                    self.scene_idx = (self.scene_idx + n_scenes - 1) % n_scenes;
+                }
+            }
+
+            // Trigger the selected scene immediately,
+            // leaving the arrangement
+            KeyCode::Char('t') => {
+                if self.scene_idx < n_scenes {
+                    state.section = None;
+                    state.load_scene(self.scene_idx);
+                }
+            }
+
+            // Add the selected scene after the selected section
+            KeyCode::Char('a') => {
+                if self.scene_idx < n_scenes {
+                    let idx = if n_sections == 0 { 0 } else { self.section_idx + 1 };
+                    state.arrangement.insert(idx, Section::new(self.scene_idx));
+                    if let Some((playing, played)) = state.section {
+                        if playing >= idx {
+                            state.section = Some((playing + 1, played));
+                        }
+                    }
+                    self.section_idx = idx;
+                } else {
+                    self.log.error("No scene to add, capture one with [c]");
+                }
+            }
+
+            // Delete the selected section
+            KeyCode::Char('d') => {
+                if self.section_idx < n_sections {
+                    state.arrangement.remove(self.section_idx);
+                    state.section = match state.section {
+                        Some((playing, _)) if playing == self.section_idx => None,
+                        Some((playing, played)) if playing > self.section_idx => {
+                            Some((playing - 1, played))
+                        }
+                        section => section,
+                    };
+                    self.section_idx = self.section_idx.min(n_sections.saturating_sub(2));
+                }
+            }
+
+            // Select a section
+            KeyCode::Char('l') => {
+                if n_sections > 0 {
+                    self.section_idx = (self.section_idx + 1) % n_sections;
+                }
+            }
+            KeyCode::Char('h') => {
+                if n_sections > 0 {
+                    self.section_idx = (self.section_idx + n_sections - 1) % n_sections;
+                }
+            }
+
+            // Move the selected section earlier or later
+            KeyCode::Char('<') | KeyCode::Char('>') => {
+                let other = if key.code == KeyCode::Char('<') {
+                    self.section_idx.checked_sub(1)
+                } else {
+                    Some(self.section_idx + 1).filter(|i| *i < n_sections)
+                };
+                if let Some(other) = other {
+                    state.arrangement.swap(self.section_idx, other);
+                    if let Some((playing, played)) = state.section {
+                        if playing == self.section_idx {
+                            state.section = Some((other, played));
+                        } else if playing == other {
+                            state.section = Some((self.section_idx, played));
+                        }
+                    }
+                    self.section_idx = other;
+                }
+            }
+
+            // Change how many times the selected section repeats
+            KeyCode::Char('+') | KeyCode::Char('=') => {
+                if let Some(section) = state.arrangement.get_mut(self.section_idx) {
+                    section.repeats += 1;
+                }
+            }
+            KeyCode::Char('-') => {
+                if let Some(section) = state.arrangement.get_mut(self.section_idx) {
+                    section.repeats = (section.repeats - 1).max(1);
+                }
+            }
+
+            // Change tempo when the selected section starts,
+            // or ramp to it across the section
+            KeyCode::Char('b') => {
+                if self.section_idx < n_sections {
+                    self.log.clear();
+                    let text_input = TextInput::new("Tempo (0 to keep the previous one): ", |c: char| c.is_ascii_digit())
+                        .validate(valid_number);
+                    self.text_input = Some((text_input, TextTarget::Tempo));
+                }
+            }
+            KeyCode::Char('r') => {
+                if let Some(section) = state.arrangement.get_mut(self.section_idx) {
+                    if section.tempo.is_some() {
+                        section.ramp = !section.ramp;
+                    } else {
+                        self.log.error("Set a tempo for the section with [b] first");
+                    }
+                }
+            }
+
+            // Export the whole arrangement to MIDI
+            KeyCode::Char('E') => {
+                if n_sections > 0 {
+                    self.log.clear();
+                    let mut text_input = TextInput::new("Path: ", |_c: char| true);
+                    text_input.set_input(self.save_dir.to_string());
+                    self.text_input = Some((text_input, TextTarget::Export));
+                } else {
+                    self.log.error("The arrangement is empty");
+                }
+            }
+
+            // Play the arrangement from the selected section,
+            // or stop following it
+            KeyCode::Char('p') => {
+                if state.section.is_some() {
+                    state.section = None;
+                } else if self.section_idx < n_sections {
+                    state.play_section(self.section_idx);
+                } else {
+                    self.log.error("The arrangement is empty");
+                }
+            }
+
+            _ => {}
+        }
+        Ok(())
+    }
+
+    pub fn params<'b>(&self) -> Vec<Span<'b>> {
+        let param_style = theme::param();
+        let state = self.state.lock().unwrap();
+        let playing = match state.section {
+            Some((idx, _)) => format!("section {}", idx + 1),
+            None => "loop".to_string(),
+        };
+        vec![
+            Span::raw("scenes:"),
+            Span::styled(state.scenes.len().to_string(), param_style),
+            Span::raw(" sections:"),
+            Span::styled(state.arrangement.len().to_string(), param_style),
+            Span::raw(" playing:"),
+            Span::styled(playing, param_style),
+        ]
+    }
+
+    pub fn controls<'b>(&self) -> Vec<Span<'b>> {
+        vec![
+            Span::raw(" [c]apture [t]rigger"),
+            Span::raw(" [a]dd [d]elete [<>]move [+-]repeats"),
+            Span::raw(" [b]pm [r]amp"),
+            Span::raw(" [p]lay [E]xport"),
+        ]
+    }
+}
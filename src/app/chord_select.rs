@@ -1,8 +1,16 @@
 use anyhow::Result;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
 use super::select::Select;
-use super::text_input::TextInput;
-use tui::widgets::Paragraph;
-use crate::core::{ChordSpec, NUMERALS};
+use super::text_input::{TextInput, valid_chord};
+use super::symbols;
+use tui::{
+    text::{Span, Spans},
+    widgets::{Block, Borders, Paragraph},
+    layout::{Rect, Constraint, Direction, Layout},
+};
+use crate::core::{Key, ChordSpec, NUMERALS};
+use crate::progression::ChordTypes;
 use crossterm::event::{KeyEvent, KeyCode};
 
 const MAJ_CHORD_TYPES: [&str; 16] = [
@@ -18,44 +26,122 @@ const MIN_CHORD_TYPES: [&str; 9] = [
     "-", "-:b7", "-:7"
 ];
 
-fn chord_options(root: usize) -> Vec<String> {
+fn chord_options(root: usize, extra: &ChordTypes) -> Vec<String> {
     let numeral = NUMERALS[root % 7].to_string();
-    let maj_chords = MAJ_CHORD_TYPES.iter()
+    let maj_chords = MAJ_CHORD_TYPES.iter().copied()
+        .chain(extra.major.iter().map(|c| c.as_str()))
         .map(|c| format!("{}{}", numeral, c));
 
     let min_numeral = numeral.to_lowercase();
-    let min_chords = MIN_CHORD_TYPES.iter()
+    let min_chords = MIN_CHORD_TYPES.iter().copied()
+        .chain(extra.minor.iter().map(|c| c.as_str()))
         .map(|c| format!("{}{}", min_numeral, c));
 
     maj_chords.chain(min_chords).collect()
 }
 
+// How many recent chords to keep
+const MAX_RECENT: usize = 8;
+
+/// Chords that were recently placed or played,
+/// most recent first.
+#[derive(Default)]
+pub struct RecentChords {
+    chords: Vec<ChordSpec>,
+}
+
+impl RecentChords {
+    pub fn push(&mut self, cs: &ChordSpec) {
+        self.chords.retain(|c| c != cs);
+        self.chords.insert(0, cs.clone());
+        self.chords.truncate(MAX_RECENT);
+    }
+
+    pub fn chords(&self) -> Vec<ChordSpec> {
+        self.chords.clone()
+    }
+}
+
+/// Fuzzy-match a query against a candidate string.
+/// All the query's characters must appear in the candidate,
+/// in order. Returns a score (higher is better)
+/// and the positions of the matched characters.
+fn fuzzy_match(query: &str, cand: &str) -> Option<(isize, Vec<usize>)> {
+    let mut score = 0;
+    let mut positions: Vec<usize> = vec![];
+    let mut chars = cand.chars().enumerate();
+    for q in query.chars() {
+        let (i, _) = chars.by_ref().find(|(_, c)| *c == q)?;
+
+        // Reward consecutive matches and
+        // matches at the start of the candidate
+        score += match positions.last() {
+            Some(last) if last + 1 == i => 3,
+            None if i == 0 => 3,
+            _ => 1,
+        };
+        positions.push(i);
+    }
+
+    // Prefer shorter candidates
+    score -= (cand.chars().count() - positions.len()) as isize;
+    Some((score, positions))
+}
+
 pub struct ChordSelect<'a> {
     numeral: usize,
     select: Select,
+
+    // Recently used chords,
+    // pinned to the top of the options
+    recent: Vec<String>,
+
+    // User-defined chord types
+    chord_types: ChordTypes,
+
     pub text_input: TextInput<'a>,
+
+    // Whether browsed chords should be played
+    pub audition: bool,
 }
 
 impl<'a> Default for ChordSelect<'a> {
     fn default() -> Self {
         let mut text_input = TextInput::new(
             "Chord: ",
-            |_c: char| { true });
+            |_c: char| { true })
+            .validate(valid_chord);
 
-        let choices = chord_options(0);
+        let chord_types = ChordTypes::default();
+        let choices = chord_options(0, &chord_types);
         text_input.set_input(choices[0].to_string());
         ChordSelect {
             numeral: 0,
             text_input,
-            select: Select {
-                idx: 0,
-                choices,
-            }
+            select: Select::new(choices),
+            recent: vec![],
+            chord_types,
+            audition: true,
         }
     }
 }
 
 impl<'a> ChordSelect<'a> {
+    /// Open a selector, pre-selecting the given chord if any,
+    /// offering the user's chord types along with the
+    /// built-in ones, and the recent chords pinned on top.
+    pub fn new(cs: Option<&ChordSpec>, chord_types: ChordTypes, recent: &Arc<Mutex<RecentChords>>, audition: bool) -> ChordSelect<'a> {
+        let select = match cs {
+            Some(cs) => ChordSelect::with_chord(cs),
+            None => ChordSelect::default(),
+        };
+        let mut select = select
+            .chord_types(chord_types)
+            .recent(recent.lock().unwrap().chords());
+        select.audition = audition;
+        select
+    }
+
     // Pre-select a given chord, if possible.
     pub fn with_chord(cs: &ChordSpec) -> ChordSelect<'a> {
         let cs_str = cs.to_string();
@@ -70,26 +156,136 @@ impl<'a> ChordSelect<'a> {
         sel
     }
 
+    /// Pin recently used chords to the top of the options.
+    pub fn recent(mut self, recent: Vec<ChordSpec>) -> ChordSelect<'a> {
+        self.recent = recent.iter().map(|cs| cs.to_string()).collect();
+        self.refresh();
+        self
+    }
+
+    /// Offer additional, user-defined chord types.
+    pub fn chord_types(mut self, chord_types: ChordTypes) -> ChordSelect<'a> {
+        self.chord_types = chord_types;
+        self.refresh();
+        self
+    }
+
+    /// Rebuild the options, keeping the entered
+    /// or current highlighted chord.
+    fn refresh(&mut self) {
+        let current = self.select.choices.get(self.select.idx).cloned();
+        self.set_numeral(self.numeral);
+
+        let choices = &self.select.choices;
+        self.select.idx = choices.iter().position(|cs| cs == &self.text_input.input)
+            .or_else(|| current.and_then(|cur| choices.iter().position(|cs| cs == &cur)))
+            .unwrap_or(0);
+    }
+
     pub fn set_numeral(&mut self, numeral_idx: usize) {
         self.numeral = numeral_idx;
-        self.select.choices = chord_options(self.numeral);
+        self.select.choices = self.recent.iter().cloned()
+            .chain(chord_options(self.numeral, &self.chord_types))
+            .collect();
+        self.select.pinned = self.recent.len();
+        self.select.highlights.clear();
     }
 
-    pub fn render<'b>(&self, height: usize) -> Paragraph<'b> {
-        self.select.render(height)
+    /// Filter the options across all numerals
+    /// by fuzzy-matching the text input.
+    /// An empty input restores the options
+    /// for the current numeral.
+    fn update_filter(&mut self) {
+        self.select.idx = 0;
+        let query = &self.text_input.input;
+        if query.is_empty() {
+            self.set_numeral(self.numeral);
+            return;
+        }
+
+        let mut options = self.recent.clone();
+        let all = (0..NUMERALS.len())
+            .flat_map(|root| chord_options(root, &self.chord_types));
+        for opt in all {
+            if !options.contains(&opt) {
+                options.push(opt);
+            }
+        }
+        let mut matches: Vec<(isize, String, Vec<usize>)> = options.into_iter()
+            .filter_map(|opt| {
+                fuzzy_match(query, &opt).map(|(score, positions)| (score, opt, positions))
+            }).collect();
+
+        // Stable sort, so ties stay in numeral order
+        matches.sort_by_key(|(score, _, _)| -score);
+        let (choices, highlights) = matches.into_iter()
+            .map(|(_, opt, positions)| (opt, positions))
+            .unzip();
+        self.select.choices = choices;
+        self.select.highlights = highlights;
+        self.select.pinned = 0;
+    }
+
+    /// The currently highlighted option, if any.
+    fn highlighted(&self) -> Option<ChordSpec> {
+        self.select.choices.get(self.select.idx)
+            .and_then(|cs| ChordSpec::from_str(cs).ok())
+    }
+
+    pub fn render<'b>(&self, rect: Rect, key: &Key) -> Vec<(Paragraph<'b>, Rect)> {
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([
+                // Options chunk
+                Constraint::Min(3),
+
+                // Highlighted chord chunk
+                Constraint::Length(3),
+            ].as_ref())
+            .split(rect);
+
+        let title = if self.audition {
+            "[Tab] audition:on"
+        } else {
+            "[Tab] audition:off"
+        };
+        let height = chunks[0].height.saturating_sub(1) as usize;
+        let options = self.select.render(height)
+            .block(Block::default().title(title).borders(Borders::LEFT));
+
+        // Show the notes and intervals
+        // of the highlighted chord
+        let (name, lines) = match self.highlighted() {
+            Some(cs) => {
+                let chord = cs.chord_for_key(key);
+                let notes: Vec<Span> = chord.describe_notes(symbols::accidentals()).into_iter()
+                    .map(|n| Span::raw(format!("{:<5}", symbols::note(&n)))).collect();
+                let intervals: Vec<Span> = chord.describe_intervals().into_iter()
+                    .map(|i| Span::raw(format!("{:<5}", i))).collect();
+                (symbols::chord(&cs), vec![Spans::from(notes), Spans::from(intervals)])
+            }
+            None => ("".to_string(), vec![])
+        };
+        let chord = Paragraph::new(lines)
+            .block(Block::default().title(name).borders(Borders::LEFT));
+
+        vec![(options, chunks[0]), (chord, chunks[1])]
     }
 
     /// Process input and returns a selected ChordSpec, if any,
     /// and if the widget should be closed.
     pub fn process_input(&mut self, key: KeyEvent) -> Result<(Option<ChordSpec>, bool)> {
-        self.select.process_input(key)?;
-        let idx = self.select.idx;
-
-        let cs: ChordSpec = self.select.choices[idx].clone().try_into()?;
         match key.code {
-            KeyCode::Char('j') | KeyCode::Char('k') | KeyCode::Char(' ') => {
-                self.text_input.set_input(cs.to_string());
-                Ok((Some(cs), false))
+            KeyCode::Char('j') | KeyCode::Char('k') | KeyCode::Char(' ') |
+                KeyCode::PageUp | KeyCode::PageDown => {
+                self.select.process_input(key)?;
+                match self.highlighted() {
+                    Some(cs) => {
+                        self.text_input.set_input(cs.to_string());
+                        Ok((Some(cs), false))
+                    }
+                    None => Ok((None, false))
+                }
             }
             KeyCode::Char('h') => {
                 let numeral = if self.numeral > 0 {
@@ -99,7 +295,9 @@ impl<'a> ChordSelect<'a> {
                 };
                 self.set_numeral(numeral);
 
-                let cs = &self.select.choices[0];
+                // Skip past the pinned chords
+                self.select.idx = self.select.pinned;
+                let cs = &self.select.choices[self.select.idx];
                 self.text_input.set_input(cs.to_string());
                 Ok((None, false))
             }
@@ -110,22 +308,58 @@ impl<'a> ChordSelect<'a> {
                     0
                 };
                 self.set_numeral(numeral);
-                let cs = &self.select.choices[0];
+
+                // Skip past the pinned chords
+                self.select.idx = self.select.pinned;
+                let cs = &self.select.choices[self.select.idx];
                 self.text_input.set_input(cs.to_string());
                 Ok((None, false))
             }
             KeyCode::Enter => {
-                // TODO handle this properly
-                let cs: ChordSpec = self.text_input.input.clone().try_into()?;
+                // Prefer the typed chord; if it isn't valid,
+                // fall back to the highlighted search match.
+                let cs = match ChordSpec::from_str(&self.text_input.input) {
+                    Ok(cs) => cs,
+                    Err(err) => self.highlighted().ok_or(err)?,
+                };
                 Ok((Some(cs), true))
             }
             KeyCode::Esc => {
                 Ok((None, true))
             }
+            KeyCode::Tab => {
+                self.audition = !self.audition;
+                Ok((None, false))
+            }
             _ => {
+                // Only re-filter if the input was edited,
+                // not if e.g. the cursor was moved.
+                let prev = self.text_input.input.clone();
                 self.text_input.process_input(key)?;
+                if self.text_input.input != prev {
+                    self.update_filter();
+                }
                 Ok((None, false))
             }
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_fuzzy_match() {
+        let (_, positions) = fuzzy_match("b79", "V:b7,9").unwrap();
+        assert_eq!(positions, vec![2, 3, 5]);
+
+        assert!(fuzzy_match("b79", "V:7,9").is_none());
+        assert!(fuzzy_match("I", "i:7").is_none());
+
+        // Consecutive matches score higher
+        let (a, _) = fuzzy_match("b7", "I:b7").unwrap();
+        let (b, _) = fuzzy_match("b7", "I:b9,7").unwrap();
+        assert!(a > b);
+    }
+}
@@ -0,0 +1,187 @@
+use crossterm::event::{KeyEvent, KeyCode};
+use tui::{
+    style::{Style, Modifier},
+    text::{Span, Spans},
+    widgets::{Block, Paragraph, Borders},
+};
+use super::{theme, symbols};
+use crate::core::{Key, Mode, Note, ChordSpec};
+
+// C's pitch class, as notes count from A
+const C: isize = 3;
+
+/// The major key's root `fifths` steps
+/// clockwise around the circle from C.
+fn major_root(fifths: usize) -> isize {
+    (C + 7 * fifths as isize).rem_euclid(12)
+}
+
+/// Pitch classes of a chord in a key.
+fn pitch_classes(cs: &ChordSpec, key: &Key) -> Vec<isize> {
+    cs.chord_for_key(key).notes().iter()
+        .map(|n| n.semitones.rem_euclid(12)).collect()
+}
+
+/// Picks a key around the circle of fifths, with each
+/// major key's relative minor under it, showing how many
+/// of the chords stay diatonic in the picked key,
+/// i.e. played as they sound in the current one.
+pub struct KeyPicker {
+    key: Key,
+    chords: Vec<ChordSpec>,
+
+    // Steps clockwise from C,
+    // and whether the relative minor is picked
+    fifths: usize,
+    minor: bool,
+}
+
+impl KeyPicker {
+    pub fn new(key: Key, chords: Vec<ChordSpec>) -> KeyPicker {
+        // Each chord only counts once
+        let mut unique: Vec<ChordSpec> = vec![];
+        for cs in chords {
+            if !unique.contains(&cs) {
+                unique.push(cs);
+            }
+        }
+        let chords = unique;
+
+        // Start on the current key, or for
+        // other modes on their relative major
+        let minor = key.mode == Mode::Minor;
+        let major = key.relative_major().root.semitones;
+        let fifths = (0..12).find(|i| major_root(*i) == major.rem_euclid(12)).unwrap_or(0);
+        KeyPicker { key, chords, fifths, minor }
+    }
+
+    /// The key at a position, with its root
+    /// as near to the current root as possible.
+    fn key_at(&self, fifths: usize, minor: bool) -> Key {
+        let (pitch, mode) = if minor {
+            (major_root(fifths) - 3, Mode::Minor)
+        } else {
+            (major_root(fifths), Mode::Major)
+        };
+        let current = self.key.root.semitones;
+        let offset = (pitch - current + 5).rem_euclid(12) - 5;
+        Key {
+            root: Note { semitones: current + offset },
+            mode,
+        }
+    }
+
+    pub fn selected(&self) -> Key {
+        self.key_at(self.fifths, self.minor)
+    }
+
+    /// How many of the chords, as they sound in
+    /// the current key, only use notes of the given key.
+    pub fn diatonic(&self, key: &Key) -> usize {
+        let scale: Vec<isize> = key.scale().iter()
+            .map(|n| n.semitones.rem_euclid(12)).collect();
+        self.chords.iter()
+            .filter(|cs| pitch_classes(cs, &self.key).iter().all(|p| scale.contains(p)))
+            .count()
+    }
+
+    /// Returns the picked key, if one was,
+    /// and whether the picker should close.
+    pub fn process_input(&mut self, key: KeyEvent) -> (Option<Key>, bool) {
+        match key.code {
+            KeyCode::Char('h') | KeyCode::Left => {
+                self.fifths = (self.fifths + 11) % 12;
+            }
+            KeyCode::Char('l') | KeyCode::Right => {
+                self.fifths = (self.fifths + 1) % 12;
+            }
+            KeyCode::Char('j') | KeyCode::Down => {
+                self.minor = true;
+            }
+            KeyCode::Char('k') | KeyCode::Up => {
+                self.minor = false;
+            }
+            KeyCode::Enter => return (Some(self.selected()), true),
+            KeyCode::Esc => return (None, true),
+            _ => {}
+        }
+        (None, false)
+    }
+
+    pub fn render<'a>(&self) -> Paragraph<'a> {
+        let row = |minor: bool| -> Spans<'a> {
+            let spans: Vec<Span> = (0..12).map(|fifths| {
+                let key = self.key_at(fifths, minor);
+                let mut name = symbols::note(&key.root.spell_pitch(&key, symbols::accidentals()));
+                if minor {
+                    name = name.to_lowercase();
+                }
+                let style = if fifths == self.fifths && minor == self.minor {
+                    theme::selected()
+                } else if key == self.key {
+                    Style::default().add_modifier(Modifier::BOLD)
+                } else if self.diatonic(&key) == self.chords.len() {
+                    Style::default()
+                } else {
+                    theme::muted()
+                };
+                Span::styled(format!("{:<3}", name), style)
+            }).collect();
+            Spans::from(spans)
+        };
+
+        let key = self.selected();
+        let diatonic = format!("{} of {} chords stay diatonic",
+            self.diatonic(&key), self.chords.len());
+        let lines = vec![
+            row(false),
+            row(true),
+            Spans::from(""),
+            Spans::from(vec![
+                Span::styled(format!("{} {}", symbols::key_root(&key), key.mode), theme::param()),
+                Span::raw(": "),
+                Span::raw(diatonic),
+            ]),
+            Spans::from(Span::styled(
+                "[h/l] around the circle [j/k] relative minor/major",
+                theme::muted())),
+        ];
+        Paragraph::new(lines)
+            .block(
+                Block::default()
+                    .title("Key")
+                    .borders(Borders::TOP)
+            )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_key_picker() {
+        let key: Key = "C4".parse().unwrap();
+        let chords: Vec<ChordSpec> = ["I", "IV", "V"].iter()
+            .map(|cs| cs.parse().unwrap()).collect();
+        let mut picker = KeyPicker::new(key, chords);
+        assert_eq!(picker.selected(), key);
+        assert_eq!(picker.diatonic(&key), 3);
+
+        // G major loses IV (F), F major loses V (B)
+        picker.process_input(KeyEvent::from(KeyCode::Char('l')));
+        let g = picker.selected();
+        assert_eq!(g, "G3".parse().unwrap());
+        assert_eq!(picker.diatonic(&g), 2);
+        picker.process_input(KeyEvent::from(KeyCode::Char('h')));
+        picker.process_input(KeyEvent::from(KeyCode::Char('h')));
+        let f = picker.selected();
+        assert_eq!(f, "F4".parse().unwrap());
+        assert_eq!(picker.diatonic(&f), 2);
+
+        // C major's relative minor
+        picker.process_input(KeyEvent::from(KeyCode::Char('l')));
+        picker.process_input(KeyEvent::from(KeyCode::Char('j')));
+        assert_eq!(picker.selected(), "A3 minor".parse().unwrap());
+    }
+}
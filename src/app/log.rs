@@ -0,0 +1,132 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use super::theme;
+use tui::{
+    style::{Style, Color},
+    text::{Span, Spans},
+    widgets::{Block, Paragraph, Borders},
+};
+
+// How many messages to keep
+const MAX_MESSAGES: usize = 100;
+
+// How long info messages are shown for
+const MESSAGE_TIMEOUT: Duration = Duration::from_secs(3);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Level {
+    Info,
+    Error,
+}
+
+impl Level {
+    fn style(&self) -> Style {
+        match self {
+            Level::Info => Style::default(),
+            Level::Error => theme::invalid(),
+        }
+    }
+}
+
+struct Message {
+    text: String,
+    level: Level,
+    time: Instant,
+}
+
+#[derive(Default)]
+struct Messages {
+    messages: VecDeque<Message>,
+
+    // Whether the latest message
+    // should still be shown
+    show_latest: bool,
+}
+
+/// A log of status messages, shared between modes.
+#[derive(Clone, Default)]
+pub struct MessageLog {
+    inner: Arc<Mutex<Messages>>,
+}
+
+impl MessageLog {
+    fn log(&self, text: String, level: Level) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.messages.push_back(Message {
+            text,
+            level,
+            time: Instant::now(),
+        });
+        if inner.messages.len() > MAX_MESSAGES {
+            inner.messages.pop_front();
+        }
+        inner.show_latest = true;
+    }
+
+    /// Show an info message, which
+    /// is hidden after a few seconds.
+    pub fn push<S: Into<String>>(&self, msg: S) {
+        self.log(msg.into(), Level::Info);
+    }
+
+    /// Show an error message, which is
+    /// shown until it's acknowledged.
+    pub fn error<S: Into<String>>(&self, msg: S) {
+        self.log(msg.into(), Level::Error);
+    }
+
+    /// Hide the latest message from the status line.
+    /// It's still kept in the log.
+    pub fn clear(&self) {
+        self.inner.lock().unwrap().show_latest = false;
+    }
+
+    /// Hide the latest message if it's an error,
+    /// i.e. the user has seen it.
+    pub fn acknowledge(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        if let Some(Message { level: Level::Error, .. }) = inner.messages.back() {
+            inner.show_latest = false;
+        }
+    }
+
+    /// Hide the latest message if it's
+    /// an info message that has timed out.
+    pub fn tick(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        if let Some(Message { level: Level::Info, time, .. }) = inner.messages.back() {
+            if time.elapsed() >= MESSAGE_TIMEOUT {
+                inner.show_latest = false;
+            }
+        }
+    }
+
+    /// The message to show in the status line, if any.
+    pub fn current<'a>(&self) -> Spans<'a> {
+        let inner = self.inner.lock().unwrap();
+        match inner.messages.back() {
+            Some(msg) if inner.show_latest => {
+                Spans::from(Span::styled(msg.text.to_string(), msg.level.style()))
+            }
+            _ => Spans::default(),
+        }
+    }
+
+    /// Render the most recent messages,
+    /// newest at the bottom.
+    pub fn render<'a>(&self, height: usize) -> Paragraph<'a> {
+        let inner = self.inner.lock().unwrap();
+        let skip = inner.messages.len().saturating_sub(height);
+        let lines: Vec<Spans> = inner.messages.iter().skip(skip)
+            .map(|msg| Spans::from(Span::styled(msg.text.to_string(), msg.level.style())))
+            .collect();
+        Paragraph::new(lines)
+            .block(
+                Block::default()
+                    .title("Log")
+                    .borders(Borders::TOP)
+                    .style(Style::default().fg(Color::Gray))
+            )
+    }
+}
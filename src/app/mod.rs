@@ -1,16 +1,37 @@
+mod log;
 mod select;
 mod sequencer;
 mod text_input;
 mod chord_select;
 mod performance;
+mod arranger;
+mod trainer;
+mod settings;
+mod wizard;
+mod theme;
+mod symbols;
+mod scale;
+mod played;
+mod key_picker;
+#[cfg(feature = "osc")]
+mod remote;
 
 use anyhow::Result;
 use std::{
+    panic,
+    path::{Path, PathBuf},
     time::Duration,
     sync::{Arc, Mutex},
 };
-use crate::midi::MIDIOutput;
-use crate::progression::ProgressionTemplate;
+use crate::midi::{MIDIOutput, MIDIInput, MIDIKeyboard, ClockSource, Output, port_index, port_indices};
+use crate::config::Config;
+use crate::session::{Session, SavedTrack, Pads};
+use crate::progression::{Progression, ProgressionTemplate};
+use crate::core::{Key, ChordSpec};
+#[cfg(feature = "osc")]
+use std::sync::mpsc::Receiver;
+#[cfg(feature = "osc")]
+use remote::Remote;
 use tui::{
     Terminal,
     backend::Backend,
@@ -18,16 +39,43 @@ use tui::{
     layout::{Rect, Alignment, Constraint, Direction, Layout},
     text::{Span, Spans},
 };
+use log::MessageLog;
 use select::Select;
-use sequencer::Sequencer;
+use text_input::TextInput;
+use chord_select::RecentChords;
+use sequencer::{Sequencer, Track, DEFAULT_TRACK};
 use performance::Performance;
-use crossterm::event::{self, Event, KeyCode};
+use arranger::Arranger;
+use trainer::Trainer;
+use settings::{Settings, Setting};
+pub use wizard::{choose_ports, offer_session};
+pub use theme::Theme;
+pub use symbols::Symbols;
+pub use sequencer::Metronome;
+use crossterm::event::{self, Event, KeyEvent, KeyCode, KeyModifiers};
 
 const TICK_RATE: Duration = Duration::from_millis(100);
 
+// Height of the message log pane
+const LOG_HEIGHT: u16 = 8;
+
+// Smallest terminal the panes fit in
+const MIN_WIDTH: u16 = 60;
+const MIN_HEIGHT: u16 = 12;
+
 pub enum Mode {
     Sequencer,
     Performance,
+    Arranger,
+    Trainer,
+}
+
+/// What to do with the session
+/// file whose path is being entered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SessionAction {
+    Save,
+    Load,
 }
 
 pub struct App<'a> {
@@ -35,33 +83,584 @@ pub struct App<'a> {
     midi: Arc<Mutex<MIDIOutput>>,
     sequencer: Sequencer<'a>,
     performance: Performance<'a>,
+    arranger: Arranger<'a>,
+    trainer: Trainer<'a>,
     select: Option<Select>,
+    settings: Option<Settings<'a>>,
+    session_input: Option<(TextInput<'a>, SessionAction)>,
+    config: Config,
+    log: MessageLog,
+    show_log: bool,
+    show_scale: bool,
+
+    // Chords played on a keyboard
+    keyboard: MIDIKeyboard,
+
+    // Commands from an OSC controller
+    #[cfg(feature = "osc")]
+    remote: Option<Receiver<Remote>>,
 }
 
 impl<'a> App<'a> {
-    pub fn new(template: ProgressionTemplate, midi_in_port: usize, midi_out_port: usize, save_dir: String) -> App<'a> {
-        let midi = MIDIOutput::from_port(midi_out_port).unwrap();
-        let midi = Arc::new(Mutex::new(midi));
-        let mut seq = Sequencer::new(midi.clone(), template.clone(), save_dir.clone());
-        seq.connect_port(midi_in_port).unwrap();
-        App {
+    pub fn new(template: ProgressionTemplate, config: Config) -> App<'a> {
+        let midi = Arc::new(Mutex::new(MIDIOutput::new()));
+        let recent = Arc::new(Mutex::new(RecentChords::default()));
+        let log = MessageLog::default();
+        let save_dir = config.save_dir.clone();
+        let seq = Sequencer::new(midi.clone(), recent.clone(), log.clone(), template.clone(), save_dir.clone());
+        let arranger = Arranger::new(seq.state(), log.clone(), save_dir.clone());
+        let mut app = App {
             midi: midi.clone(),
             select: None,
+            settings: None,
+            session_input: None,
             mode: Mode::Performance,
             sequencer: seq,
-            performance: Performance::new(midi.clone(), template, save_dir),
+            arranger,
+            trainer: Trainer::new(midi.clone(), log.clone(), template.clone()),
+            performance: Performance::new(midi.clone(), recent, log.clone(), template, save_dir),
+            config,
+            log,
+            show_log: false,
+            show_scale: false,
+            keyboard: MIDIKeyboard::new(),
+            #[cfg(feature = "osc")]
+            remote: None,
+        };
+
+        // Connecting the clock also sets the input port,
+        // and the preview's patch and soundfont are set before it's connected
+        for setting in [Setting::Volume, Setting::Metronome, Setting::SoundFont, Setting::MidiOut, Setting::Channel, Setting::BassChannel, Setting::ExportChannel, Setting::ChordMarkers, Setting::NoteOff, Setting::Tempo, Setting::Smoothing, Setting::ClockOut, Setting::Clock, Setting::NoteIn, Setting::Accidentals, Setting::Theme, Setting::Symbols, Setting::KeyPicker, Setting::Accent] {
+            app.apply_setting(setting);
+        }
+
+        let key = Key {
+            root: app.config.key,
+            mode: app.config.mode,
+        };
+        app.performance.set_key(key);
+        app.trainer.set_key(key);
+        if let Err(err) = app.sequencer.set_params(key, app.config.bars, app.config.resolution) {
+            app.log.error(err.to_string());
+        }
+        app.sequencer.set_swing(app.config.swing);
+        app.sequencer.set_accent(app.config.accent.clone());
+        app
+    }
+
+    /// Apply a setting from the config,
+    /// reporting any errors in the log.
+    fn apply_setting(&mut self, setting: Setting) {
+        let result = match setting {
+            Setting::MidiOut | Setting::Output | Setting::Broadcast => {
+                match &self.config.midi_out_port {
+                    _ if self.config.output == Output::Audio => {
+                        self.midi.lock().unwrap().connect_audio()
+                            .map_err(|err| err.to_string())
+                    }
+                    Some(name) => {
+                        let mut names = vec![name.clone()];
+                        names.extend(self.config.broadcast_ports.iter().cloned());
+                        let mut midi = self.midi.lock().unwrap();
+                        midi.available_ports()
+                            .and_then(|ports| port_indices(&ports, &names))
+                            .and_then(|idxs| midi.connect_ports(&idxs))
+                            .map_err(|err| err.to_string())
+                    }
+                    None => Err("No MIDI output port, choose one in [,]settings".to_string()),
+                }
+            }
+            Setting::MidiIn | Setting::Clock => {
+                match self.config.clock {
+                    ClockSource::Internal => {
+                        self.sequencer.use_internal_clock();
+                        Ok(())
+                    }
+                    #[cfg(feature = "jack")]
+                    ClockSource::Jack => {
+                        self.sequencer.use_jack_clock()
+                            .map_err(|err| err.to_string())
+                    }
+                    ClockSource::External => {
+                        match &self.config.midi_in_port {
+                            Some(name) => {
+                                MIDIInput::new().available_ports()
+                                    .and_then(|ports| port_index(&ports, name))
+                                    .and_then(|idx| self.sequencer.connect_port(idx))
+                                    .map_err(|err| err.to_string())
+                            }
+                            None => Err("No MIDI clock input port, choose one in [,]settings".to_string()),
+                        }
+                    }
+                }
+            }
+            Setting::NoteIn => {
+                match &self.config.note_in_port {
+                    Some(name) => {
+                        MIDIInput::new().available_ports()
+                            .and_then(|ports| port_index(&ports, name))
+                            .and_then(|idx| self.keyboard.connect_port(idx))
+                            .map_err(|err| err.to_string())
+                    }
+                    None => Ok(()),
+                }
+            }
+            Setting::ClockOut => {
+                self.midi.lock().unwrap().send_clock = self.config.clock_out;
+                Ok(())
+            }
+            Setting::Channel => {
+                self.midi.lock().unwrap().channel = self.config.channel.saturating_sub(1);
+                Ok(())
+            }
+            Setting::Smoothing => {
+                self.sequencer.set_clock_smoothing(self.config.clock_smoothing);
+                Ok(())
+            }
+            Setting::BassChannel => {
+                self.sequencer.set_bass_channel(self.config.bass_channel.saturating_sub(1));
+                Ok(())
+            }
+
+            Setting::NoteOff | Setting::ReleaseVelocity | Setting::Overlap => {
+                let mut midi = self.midi.lock().unwrap();
+                midi.note_off = self.config.note_off;
+                midi.release_velocity = self.config.release_velocity;
+                midi.overlap = self.config.overlap;
+                Ok(())
+            }
+            Setting::Tempo => {
+                self.sequencer.set_tempo(self.config.tempo);
+                self.performance.set_tempo(self.config.tempo);
+                Ok(())
+            }
+            Setting::SaveDir => {
+                self.sequencer.set_save_dir(self.config.save_dir.clone());
+                self.performance.set_save_dir(self.config.save_dir.clone());
+                self.arranger.set_save_dir(self.config.save_dir.clone());
+                Ok(())
+            }
+            Setting::ExportChannel | Setting::ChordMarkers | Setting::Accidentals => {
+                symbols::set_accidentals(self.config.accidentals);
+                let options = self.config.export_options();
+                self.sequencer.set_export_options(options);
+                self.performance.set_export_options(options);
+                self.arranger.set_export_options(options);
+                Ok(())
+            }
+            Setting::Theme => {
+                self.config.theme.set();
+                Ok(())
+            }
+            Setting::Symbols => {
+                self.config.symbols.set();
+                Ok(())
+            }
+            Setting::KeyPicker => {
+                self.sequencer.set_circle_of_fifths(self.config.circle_of_fifths);
+                self.performance.set_circle_of_fifths(self.config.circle_of_fifths);
+                Ok(())
+            }
+            Setting::Waveform | Setting::Volume => {
+                self.midi.lock().unwrap().set_patch(self.config.preview);
+                Ok(())
+            }
+            Setting::Metronome => {
+                self.midi.lock().unwrap().set_mixer(self.config.mixer.clone());
+                Ok(())
+            }
+            Setting::Accent | Setting::Subdivide | Setting::CountOff => {
+                self.sequencer.set_metronome(self.config.metronome.clone());
+                Ok(())
+            }
+            Setting::SoundFont | Setting::Samples => {
+                // Fall back to the synth if they can't be loaded
+                let mut midi = self.midi.lock().unwrap();
+                match self.config.instruments() {
+                    Ok(instruments) => {
+                        midi.set_instruments(instruments);
+                        Ok(())
+                    }
+                    Err(err) => {
+                        midi.set_instruments(None);
+                        Err(err.to_string())
+                    }
+                }
+            }
+        };
+        if let Err(err) = result {
+            self.log.error(err);
+        }
+    }
+
+    /// Accept commands from OSC controllers on a UDP port.
+    #[cfg(feature = "osc")]
+    pub fn listen_osc(mut self, port: u16) -> Result<Self> {
+        self.remote = Some(remote::listen(port)?);
+        self.log.push(format!("Listening for OSC on port {}", port));
+        Ok(self)
+    }
+
+    /// Apply any commands received from OSC controllers.
+    #[cfg(feature = "osc")]
+    fn process_remote(&mut self) {
+        let commands: Vec<Remote> = match &self.remote {
+            Some(rx) => rx.try_iter().collect(),
+            None => return,
+        };
+        for command in commands {
+            match command {
+                Remote::Trigger(idx) => self.performance.trigger(idx),
+                Remote::Root(root) => {
+                    let key = self.performance.key();
+                    self.performance.set_key(Key { root, ..key });
+                    let key = self.sequencer.key();
+                    if let Err(err) = self.sequencer.set_key(Key { root, ..key }) {
+                        self.log.error(err.to_string());
+                    }
+                }
+                Remote::Mode(mode) => {
+                    let key = self.performance.key();
+                    self.performance.set_key(Key { mode, ..key });
+                    let key = self.sequencer.key();
+                    if let Err(err) = self.sequencer.set_key(Key { mode, ..key }) {
+                        self.log.error(err.to_string());
+                    }
+                }
+                Remote::Tempo(bpm) => {
+                    self.config.tempo = bpm;
+                    self.apply_setting(Setting::Tempo);
+                }
+                Remote::Start => self.apply_setting(Setting::Clock),
+                Remote::Stop => self.sequencer.stop(),
+            }
+        }
+    }
+
+    /// Play the performance mode's slots bound
+    /// to notes played on the MIDI note input.
+    /// In other modes the notes are dropped.
+    fn process_notes(&mut self) {
+        let notes = self.keyboard.notes_played();
+        if let Mode::Performance = self.mode {
+            for note in notes {
+                self.performance.note_played(note);
+            }
+        }
+    }
+
+    /// Open the settings screen.
+    fn open_settings(&mut self) {
+        let in_ports = MIDIInput::new().available_ports().unwrap_or_default();
+        let out_ports = self.midi.lock().unwrap().available_ports().unwrap_or_default();
+        self.settings = Some(Settings::new(self.config.clone(), in_ports, out_ports));
+    }
+
+    /// Save a changed setting to the config file,
+    /// reporting any errors in the log.
+    /// Only that setting is saved so that
+    /// command line overrides aren't persisted.
+    fn save_setting(&self, setting: Setting) {
+        let result = Config::load().and_then(|mut config| {
+            setting.copy(&self.config, &mut config);
+            config.save()
+        });
+        if let Err(err) = result {
+            self.log.error(format!("Failed to save settings: {}", err));
+        }
+    }
+
+    /// Nudge the preview's volume or the metronome level
+    /// by a step, saving it like it was set in the settings.
+    fn nudge_level(&mut self, setting: Setting, step: f32) {
+        let level = match setting {
+            Setting::Metronome => &mut self.config.mixer.metronome,
+            _ => &mut self.config.preview.volume,
+        };
+        *level = ((*level + step) * 10.).round().clamp(0., 10.) / 10.;
+        let percent = (*level * 100.).round();
+        self.apply_setting(setting);
+        self.save_setting(setting);
+        match setting {
+            Setting::Metronome => self.log.push(format!("Metronome level {}%", percent)),
+            _ => self.log.push(format!("Volume {}%", percent)),
+        }
+    }
+
+    /// The current session, to pick up from next time.
+    pub fn session(&self) -> Session {
+        let state = self.sequencer.state();
+        let s = state.lock().unwrap();
+        let mut session = Session::new(
+            s.key, s.tempo, s.swing, s.track_progression(0),
+            self.performance.key(), self.performance.mappings());
+        session.accent = s.accent.clone();
+        session.clip = s.track_loop(0);
+        let first = &s.tracks[0];
+        session.track_name = Some(first.name.clone());
+        session.channel = first.channel;
+        session.port = first.port.clone();
+        session.bass = first.bass;
+        session.tracks = s.tracks.iter().enumerate().skip(1)
+            .map(|(idx, track)| SavedTrack {
+                channel: track.channel,
+                port: track.port.clone(),
+                bass: track.bass,
+                clip: s.track_loop(idx),
+                ..SavedTrack::new(&track.name, s.track_progression(idx))
+            })
+            .collect();
+        session.channels = self.performance.channels().to_vec();
+        session.arps = self.performance.arps().iter()
+            .map(|arp| arp.map(|arp| arp.to_string()))
+            .collect();
+        session.notes = self.performance.notes().to_vec();
+        session.midi_in_port = self.config.midi_in_port.clone();
+        session.midi_out_port = self.config.midi_out_port.clone();
+        session
+    }
+
+    /// Stop any notes playing if the app panics,
+    /// so they aren't left hanging on the synth.
+    /// Hooks stack, so this should only be called once.
+    pub fn silence_on_panic(&self) {
+        let midi = self.midi.clone();
+        let hook = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            // The panicking thread may be holding the lock
+            if let Ok(mut midi) = midi.try_lock() {
+                let _ = midi.close();
+            }
+            hook(info);
+        }));
+    }
+
+    /// Pick up from a previous session,
+    /// reporting any errors in the log.
+    pub fn restore(&mut self, session: &Session) {
+        let result = session.progression().and_then(|progression| {
+            let mappings = session.mappings()?;
+            let arps = session.arps()?;
+            let name = session.track_name.as_deref().unwrap_or(DEFAULT_TRACK);
+            let mut tracks = vec![Track {
+                channel: session.channel,
+                port: session.port.clone(),
+                bass: session.bass,
+                ..Track::new(name, progression.clone())
+            }];
+            for saved in &session.tracks {
+                let mut track = Track {
+                    channel: saved.channel,
+                    port: saved.port.clone(),
+                    bass: saved.bass,
+                    ..Track::new(&saved.name, saved.progression()?)
+                };
+                if let Some(clip) = saved.clip {
+                    track.clip = clip;
+                }
+                tracks.push(track);
+            }
+            self.sequencer.set_progression(session.key, progression);
+            self.sequencer.set_tracks(tracks);
+            if let Some(clip) = session.clip {
+                self.sequencer.set_clip(clip);
+            }
+            self.sequencer.set_swing(session.swing);
+            self.sequencer.set_accent(session.accent.clone());
+            self.sequencer.set_tempo(session.tempo);
+            self.performance.set_tempo(session.tempo);
+            self.performance.set_key(session.performance_key);
+            self.performance.set_mappings(mappings);
+            self.performance.set_channels(session.channels.clone());
+            self.performance.set_arps(arps);
+            self.performance.set_notes(session.notes.clone());
+            Ok(())
+        });
+        match result {
+            Ok(_) => self.log.push("Restored the session"),
+            Err(err) => self.log.error(format!("Failed to restore the session: {}", err)),
+        }
+    }
+
+    /// Bind the performance mode's chords saved
+    /// to a file, reporting any errors in the log.
+    pub fn set_pads(&mut self, pads: &Pads) {
+        match self.performance.set_pads(pads) {
+            Ok(_) => self.log.push("Loaded the pads"),
+            Err(err) => self.log.error(format!("Failed to load the pads: {}", err)),
+        }
+    }
+
+    /// Ask for the path of a session
+    /// file to save to or load from.
+    fn open_session_input(&mut self, action: SessionAction) {
+        let label = match action {
+            SessionAction::Save => "Save session to: ",
+            SessionAction::Load => "Load session from: ",
+        };
+        let mut text_input = TextInput::new(label, |_c: char| true);
+        let path = PathBuf::from(&self.config.save_dir).join("session.yaml");
+        text_input.set_input(path.to_string_lossy().to_string());
+        self.session_input = Some((text_input, action));
+    }
+
+    /// Save the session to a file.
+    fn save_session(&self, path: &Path) {
+        match self.session().save_to(path) {
+            Ok(_) => self.log.push("Saved the session"),
+            Err(err) => self.log.error(format!("Failed to save the session: {}", err)),
+        }
+    }
+
+    /// Pick up a session saved to a file,
+    /// along with the ports it used.
+    fn load_session(&mut self, path: &Path) {
+        match Session::load_from(path) {
+            Ok(session) => {
+                if session.midi_out_port.is_some() && session.midi_out_port != self.config.midi_out_port {
+                    self.config.midi_out_port = session.midi_out_port.clone();
+                    self.apply_setting(Setting::MidiOut);
+                }
+                if session.midi_in_port.is_some() && session.midi_in_port != self.config.midi_in_port {
+                    self.config.midi_in_port = session.midi_in_port.clone();
+                    self.apply_setting(Setting::MidiIn);
+                }
+                self.restore(&session);
+            }
+            Err(err) => self.log.error(format!("Failed to load the session: {}", err)),
+        }
+    }
+
+    /// Load the chords imported from a MIDI file into
+    /// the sequencer, in their key, and switch to it.
+    pub fn open(&mut self, key: Key, progression: Progression) {
+        self.sequencer.set_progression(key, progression);
+        self.performance.leave();
+        self.mode = Mode::Sequencer;
+        self.log.push(format!("Imported in {}", key));
+    }
+
+    /// The key of the current mode.
+    /// The arranger plays in the sequencer's.
+    fn key(&self) -> Key {
+        match self.mode {
+            Mode::Performance => self.performance.key(),
+            Mode::Sequencer | Mode::Arranger => self.sequencer.key(),
+            Mode::Trainer => self.trainer.key(),
+        }
+    }
+
+    /// Put the chord last played on the MIDI note input
+    /// on the sequencer's selected step, or the
+    /// performance mode's selected slot.
+    fn capture_chord(&mut self) {
+        if self.keyboard.name().is_none() {
+            self.log.error("No MIDI note input, choose one in [,]settings");
+            return;
+        }
+        match (self.keyboard.chord(), &self.mode) {
+            (None, _) => self.log.push("Play a chord on the MIDI note input first"),
+            (Some(chord), Mode::Performance) => self.performance.put_chord(&chord),
+            (Some(chord), Mode::Sequencer) => self.sequencer.put_chord(&chord),
+            _ => self.log.push("Played chords go into the sequencer or performance mode"),
+        }
+    }
+
+    /// Load the sequencer's chords, each only once and
+    /// in the order they're first played, into the
+    /// performance mode's slots, along with its key.
+    fn send_to_performance(&mut self) {
+        let (key, chords) = {
+            let state = self.sequencer.state();
+            let s = state.lock().unwrap();
+            let mut chords: Vec<ChordSpec> = vec![];
+            for cs in s.progression().chords() {
+                if !chords.contains(cs) {
+                    chords.push(cs.clone());
+                }
+            }
+            (s.key, chords)
+        };
+
+        let slots = self.performance.mappings().len();
+        if chords.len() > slots {
+            self.log.push(format!("Sent the first {} of {} chords to performance mode", slots, chords.len()));
+        } else {
+            self.log.push(format!("Sent {} chords to performance mode", chords.len()));
+        }
+        self.performance.set_key(key);
+        self.performance.set_mappings(chords.into_iter().map(Some).collect());
+    }
+
+    /// Start a new sequencer progression from the
+    /// chords bound in performance mode, in its key.
+    fn build_from_performance(&mut self) {
+        let chords: Vec<ChordSpec> = self.performance.mappings().iter().flatten().cloned().collect();
+        if chords.is_empty() {
+            self.log.push("No chords are bound in performance mode");
+        } else {
+            self.sequencer.set_chords(self.performance.key(), chords);
+            self.log.push("Built a progression from performance mode's chords, [z] to undo");
         }
     }
 
     pub fn shutdown(&mut self) -> Result<()> {
         self.midi.lock().unwrap().close()
     }
+
+    /// Pass input to the current mode,
+    /// reporting any errors in the log.
+    fn process_input(&mut self, key: KeyEvent) {
+        let result = match self.mode {
+            Mode::Performance => {
+                self.performance.process_input(key)
+            }
+            Mode::Sequencer => {
+                self.sequencer.process_input(key)
+            }
+            Mode::Arranger => {
+                self.arranger.process_input(key)
+            }
+            Mode::Trainer => {
+                self.trainer.process_input(key)
+            }
+        };
+        if let Err(err) = result {
+            self.log.error(err.to_string());
+        }
+    }
 }
 
 pub fn run_app<B: Backend>(terminal: &mut Terminal<B>, mut app: App) -> Result<()> {
+    let res = event_loop(terminal, &mut app);
+
+    // Quitting shuts down on its own,
+    // but errors would leave notes hanging
+    if res.is_err() {
+        let _ = app.shutdown();
+    }
+    res
+}
+
+fn event_loop<B: Backend>(terminal: &mut Terminal<B>, app: &mut App) -> Result<()> {
     loop {
         terminal.draw(|frame| {
             let size = frame.size();
+            if size.width < MIN_WIDTH || size.height < MIN_HEIGHT {
+                let notice = Paragraph::new(vec![
+                    Spans::from("Terminal too small"),
+                    Spans::from(format!("Needs at least {}x{}, is {}x{}",
+                            MIN_WIDTH, MIN_HEIGHT, size.width, size.height)),
+                ]).alignment(Alignment::Center);
+                let rect = Rect {
+                    y: size.height.saturating_sub(2) / 2,
+                    height: size.height.min(2),
+                    ..size
+                };
+                frame.render_widget(notice, rect);
+                return;
+            }
+
             let rects = Layout::default()
                 .direction(Direction::Vertical)
                 .constraints([
@@ -76,6 +675,39 @@ pub fn run_app<B: Backend>(terminal: &mut Terminal<B>, mut app: App) -> Result<(
                     ].as_ref())
                 .split(size);
 
+            // Show the message log below the main rect
+            let (main_rect, log_rect) = if app.show_log {
+                let chunks = Layout::default()
+                    .direction(Direction::Vertical)
+                    .constraints([
+                        Constraint::Min(6),
+                        Constraint::Length(LOG_HEIGHT),
+                    ].as_ref())
+                    .split(rects[1]);
+                (chunks[0], Some(chunks[1]))
+            } else {
+                (rects[1], None)
+            };
+            if let Some(rect) = log_rect {
+                let height = rect.height.saturating_sub(1) as usize;
+                frame.render_widget(app.log.render(height), rect);
+            }
+
+            // Show the current key's scale beside it
+            let main_rect = if app.show_scale {
+                let chunks = Layout::default()
+                    .direction(Direction::Horizontal)
+                    .constraints([
+                        Constraint::Min(0),
+                        Constraint::Length(scale::SCALE_WIDTH),
+                    ].as_ref())
+                    .split(main_rect);
+                frame.render_widget(scale::render(&app.key()), chunks[1]);
+                chunks[0]
+            } else {
+                main_rect
+            };
+
             // Params help bar
             let mut params = vec![];
             match app.mode {
@@ -85,11 +717,34 @@ pub fn run_app<B: Backend>(terminal: &mut Terminal<B>, mut app: App) -> Result<(
                 Mode::Sequencer => {
                     params.extend(app.sequencer.params());
                 }
+                Mode::Arranger => {
+                    params.extend(app.arranger.params());
+                }
+                Mode::Trainer => {
+                    params.extend(app.trainer.params());
+                }
             }
 
+            // Show the chord played on the
+            // MIDI note input beside them
+            let params_rect = if app.keyboard.name().is_some() {
+                let chunks = Layout::default()
+                    .direction(Direction::Horizontal)
+                    .constraints([
+                        Constraint::Min(0),
+                        Constraint::Length(played::PLAYED_WIDTH),
+                    ].as_ref())
+                    .split(rects[0]);
+                let chord = app.keyboard.chord();
+                frame.render_widget(played::render(chord.as_ref(), &app.key()), chunks[1]);
+                chunks[0]
+            } else {
+                rects[0]
+            };
+
             let params_help = Paragraph::new(Spans::from(params))
                 .alignment(Alignment::Center);
-            frame.render_widget(params_help, rects[0]);
+            frame.render_widget(params_help, params_rect);
 
             // Controls help bar
             let mut controls = vec![];
@@ -100,21 +755,46 @@ pub fn run_app<B: Backend>(terminal: &mut Terminal<B>, mut app: App) -> Result<(
                 Mode::Sequencer => {
                     controls.extend(app.sequencer.controls());
                 }
+                Mode::Arranger => {
+                    controls.extend(app.arranger.controls());
+                }
+                Mode::Trainer => {
+                    controls.extend(app.trainer.controls());
+                }
+            }
+            if app.keyboard.name().is_some() {
+                controls.push(Span::raw(" [^k]eep played chord"));
             }
             controls.push(
-                Span::raw(" [M]ode [P]ort [Q]uit"));
-            let controls_help = Paragraph::new(Spans::from(controls))
-                .alignment(Alignment::Left);
-            frame.render_widget(controls_help, rects[2]);
+                Span::raw(" [M]ode [P]ort me[T]ronome [L]og [#]scale [,]Settings session:[^s]ave [^o]pen [Q]uit"));
+            match &app.session_input {
+                Some((text_input, _)) => {
+                    frame.render_widget(text_input.render(), rects[2]);
+                }
+                None => {
+                    let controls_help = Paragraph::new(Spans::from(controls))
+                        .alignment(Alignment::Left);
+                    frame.render_widget(controls_help, rects[2]);
+                }
+            }
 
             match &mut app.select {
                 None => {
-                    let chunks: Vec<(Paragraph, Rect)> = match app.mode {
-                        Mode::Performance => {
-                            app.performance.render(rects[1])
+                    let chunks: Vec<(Paragraph, Rect)> = match (&app.settings, &app.mode) {
+                        (Some(settings), _) => {
+                            settings.render(main_rect)
+                        }
+                        (None, Mode::Performance) => {
+                            app.performance.render(main_rect)
+                        }
+                        (None, Mode::Sequencer) => {
+                            app.sequencer.render(main_rect)
                         }
-                        Mode::Sequencer => {
-                            app.sequencer.render(rects[1])
+                        (None, Mode::Arranger) => {
+                            app.arranger.render(main_rect)
+                        }
+                        (None, Mode::Trainer) => {
+                            app.trainer.render(main_rect)
                         }
                     };
                     for (p, rect) in chunks {
@@ -122,14 +802,32 @@ pub fn run_app<B: Backend>(terminal: &mut Terminal<B>, mut app: App) -> Result<(
                     }
                 }
                 Some(select) => {
-                    let height = rects[1].height as usize;
-                    frame.render_widget(select.render(height), rects[1]);
+                    let height = main_rect.height as usize;
+                    frame.render_widget(select.render(height), main_rect);
                 }
             }
         })?;
 
+        // Let info messages time out
+        app.log.tick();
+
+        app.process_notes();
+
+        #[cfg(feature = "osc")]
+        app.process_remote();
+
         if event::poll(TICK_RATE)? {
-            if let Event::Key(key) = event::read()? {
+            let event = event::read()?;
+
+            // Re-flow the panes for the new size
+            // rather than waiting for the next draw
+            if let Event::Resize(_, _) = event {
+                terminal.autoresize()?;
+            }
+            if let Event::Key(key) = event {
+                // Any key press acknowledges an error
+                app.log.acknowledge();
+
                 // Check if one of the modes is capturing all input
                 let input_mode = match app.mode {
                     Mode::Performance => {
@@ -138,33 +836,80 @@ pub fn run_app<B: Backend>(terminal: &mut Terminal<B>, mut app: App) -> Result<(
                     Mode::Sequencer => {
                         app.sequencer.capture_input()
                     }
+                    Mode::Arranger => {
+                        app.arranger.capture_input()
+                    }
+                    Mode::Trainer => {
+                        app.trainer.capture_input()
+                    }
                 };
 
-                if input_mode {
-                    match app.mode {
-                        Mode::Performance => {
-                            app.performance.process_input(key)?;
+                if let Some((text_input, action)) = app.session_input.as_mut() {
+                    let action = *action;
+                    let (input, close) = text_input.process_input(key)?;
+                    if close {
+                        app.session_input = None;
+                        if let Some(path) = input {
+                            match action {
+                                SessionAction::Save => app.save_session(Path::new(&path)),
+                                SessionAction::Load => app.load_session(Path::new(&path)),
+                            }
+                        }
+                    }
+                } else if let Some(settings) = app.settings.as_mut() {
+                    let result = settings.process_input(key);
+                    let config = settings.config.clone();
+                    match result {
+                        Ok((changed, close)) => {
+                            if let Some(setting) = changed {
+                                app.config = config;
+                                app.apply_setting(setting);
+                                app.save_setting(setting);
+                            }
+                            if close {
+                                app.settings = None;
+                            }
                         }
-                        Mode::Sequencer => {
-                            app.sequencer.process_input(key)?;
+                        Err(err) => {
+                            app.log.error(err.to_string());
                         }
                     }
+                } else if input_mode {
+                    app.process_input(key);
                 } else {
                     match &mut app.select {
                         // Midi port selection
                         Some(ref mut select) => {
                             let (selected, close) = select.process_input(key)?;
-                            if let Some(idx) = selected {
-                                app.midi.lock().unwrap().connect_port(idx).unwrap();
+                            let port = selected.and_then(|idx| select.choices.get(idx).cloned());
+                            if port.is_some() {
+                                // Choosing a port switches
+                                // away from the audio preview
+                                app.config.output = Output::Midi;
+                                app.config.midi_out_port = port;
+                                app.apply_setting(Setting::MidiOut);
+                                app.save_setting(Setting::Output);
+                                app.save_setting(Setting::MidiOut);
                             }
                             if close {
                                 app.select = None;
                             }
                         },
                         None => {
+                            // Keys remapped in the config
+                            let key = match key.code {
+                                KeyCode::Char(c) => KeyEvent {
+                                    code: KeyCode::Char(app.config.remap(c)),
+                                    ..key
+                                },
+                                _ => key,
+                            };
                             match key.code {
                                 // Quit
                                 KeyCode::Char('Q') => {
+                                    // Not being able to save the
+                                    // session shouldn't stop quitting
+                                    let _ = app.session().save();
                                     app.shutdown().unwrap();
                                     return Ok(());
                                 },
@@ -172,30 +917,89 @@ pub fn run_app<B: Backend>(terminal: &mut Terminal<B>, mut app: App) -> Result<(
                                 // Switch mode
                                 KeyCode::Char('M') => {
                                     app.mode = match app.mode {
-                                        Mode::Sequencer => {
-                                            Mode::Performance
-                                        },
                                         Mode::Performance => {
+                                            app.performance.leave();
                                             Mode::Sequencer
                                         },
+                                        Mode::Sequencer => {
+                                            Mode::Arranger
+                                        },
+                                        Mode::Arranger => {
+                                            Mode::Trainer
+                                        },
+                                        Mode::Trainer => {
+                                            Mode::Performance
+                                        },
                                     }
                                 },
 
+                                // Toggle the metronome
+                                KeyCode::Char('T') => {
+                                    let on = app.sequencer.toggle_metronome();
+                                    app.log.push(if on { "Metronome on" } else { "Metronome off" });
+                                }
+
+                                // Preview volume and metronome level
+                                KeyCode::Char('[') => app.nudge_level(Setting::Volume, -0.1),
+                                KeyCode::Char(']') => app.nudge_level(Setting::Volume, 0.1),
+                                KeyCode::Char('{') => app.nudge_level(Setting::Metronome, -0.1),
+                                KeyCode::Char('}') => app.nudge_level(Setting::Metronome, 0.1),
+
+                                // Toggle the message log
+                                KeyCode::Char('L') => {
+                                    app.show_log = !app.show_log;
+                                }
+
+                                // Toggle the scale pane
+                                KeyCode::Char('#') => {
+                                    app.show_scale = !app.show_scale;
+                                }
+
+                                // Open settings
+                                KeyCode::Char(',') => {
+                                    app.open_settings();
+                                }
+
+                                // Save or load the session
+                                KeyCode::Char('s') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                                    app.open_session_input(SessionAction::Save);
+                                }
+                                KeyCode::Char('o') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                                    app.open_session_input(SessionAction::Load);
+                                }
+
+                                // Put the chord played on the MIDI
+                                // note input on the selected step or slot
+                                KeyCode::Char('k') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                                    app.capture_chord();
+                                }
+
+                                // Send the sequencer's chords
+                                // to the performance mode's slots
+                                KeyCode::Char('>') if matches!(app.mode, Mode::Sequencer) => {
+                                    app.send_to_performance();
+                                }
+
+                                // Or build a progression from them
+                                KeyCode::Char('<') if matches!(app.mode, Mode::Sequencer) => {
+                                    app.build_from_performance();
+                                }
+
                                 // Change the MIDI output port
                                 KeyCode::Char('P') => {
-                                    let ports = app.midi.lock().unwrap().available_ports().unwrap();
-                                    app.select = Some(Select::new(ports));
-                                }
-                                _ => {
-                                    match app.mode {
-                                        Mode::Performance => {
-                                            app.performance.process_input(key)?;
+                                    let ports = app.midi.lock().unwrap().available_ports();
+                                    match ports {
+                                        Ok(ports) => {
+                                            app.select = Some(Select::new(ports));
                                         }
-                                        Mode::Sequencer => {
-                                            app.sequencer.process_input(key)?;
+                                        Err(err) => {
+                                            app.log.error(err.to_string());
                                         }
                                     }
                                 }
+                                _ => {
+                                    app.process_input(key);
+                                }
                             }
                         }
                     }
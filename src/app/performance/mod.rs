@@ -1,63 +1,354 @@
-use anyhow::Result;
-use crate::midi::MIDIOutput;
+use anyhow::{Result, anyhow};
+use crate::midi::{MIDIOutput, HeldChord, Arp};
 use std::sync::{Arc, Mutex};
-use crate::file::save_to_midi_file;
-use crate::app::text_input::TextInput;
-use crate::app::chord_select::ChordSelect;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration as Time;
+use crate::file::{save_to_midi_file, ExportOptions};
+use crate::app::text_input::{TextInput, Prompt, Validator, add_to_history, valid_note, valid_positive, valid_chords, valid_channels, parse_channel, valid_arps, parse_arp};
+use crate::app::chord_select::{ChordSelect, RecentChords};
+use crate::app::log::MessageLog;
+use crate::app::key_picker::KeyPicker;
+use crate::app::{theme, symbols};
+use crate::session::Pads;
 use crate::progression::ProgressionTemplate;
-use crate::core::{Key, Mode, Duration, ChordSpec, ChordParseError, voice_lead};
+use crate::core::{Key, Chord, Duration, ChordSpec, ChordParseError, voice_lead};
 use crossterm::event::{KeyEvent, KeyCode, KeyModifiers};
 use tui::{
     text::{Span, Spans},
-    style::{Style, Modifier, Color},
+    style::Style,
     widgets::{Block, Paragraph, Borders},
     layout::{Rect, Alignment, Constraint, Direction, Layout},
 };
 
+// Slots in a bank, one per number key
+const BANK_SIZE: usize = 9;
+
+// Banks of slots to switch between
+const BANKS: usize = 4;
+
+/// Chords to bind to every slot, bank after
+/// bank, leaving any slots past them empty.
+fn banked(mut mappings: Vec<Option<ChordSpec>>) -> Vec<Option<ChordSpec>> {
+    mappings.resize(BANK_SIZE * BANKS, None);
+    mappings
+}
+
+/// The mapping slot for a number key, if any.
+/// Slots are numbered from 1.
+fn mapping_idx(c: char) -> Option<usize> {
+    c.to_digit(10).and_then(|d| (d as usize).checked_sub(1))
+}
+
 enum InputMode<'a> {
     Normal,
     Text(TextInput<'a>, TextTarget),
     Chord(ChordSelect<'a>, usize),
+    Key(KeyPicker),
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 enum TextTarget {
     Root,
     Duration,
     Progression,
+    Channels,
+    Arps,
     Export,
+    WritePads,
+    LoadPads,
+}
+
+impl Prompt for TextTarget {
+    fn validator(self) -> Option<Validator> {
+        match self {
+            TextTarget::Root => Some(valid_note),
+            TextTarget::Duration => Some(valid_positive),
+            TextTarget::Progression => Some(valid_chords),
+            TextTarget::Channels => Some(valid_channels),
+            TextTarget::Arps => Some(valid_arps),
+            TextTarget::Export | TextTarget::WritePads | TextTarget::LoadPads => None,
+        }
+    }
 }
 
 pub struct Performance<'a> {
     midi: Arc<Mutex<MIDIOutput>>,
+    recent: Arc<Mutex<RecentChords>>,
 
     key: Key,
     note_duration: u64,
-    mappings: [Option<ChordSpec>; 9],
+
+    // Chords bound to each slot of each bank,
+    // and the bank the number keys play
+    mappings: Vec<Option<ChordSpec>>,
+    bank: usize,
+
+    // MIDI channel each slot plays on, 0-15,
+    // if not the output's
+    channels: [Option<u8>; 9],
+
+    // Arpeggio each slot plays its chord as, if any
+    arps: [Option<Arp>; 9],
+
+    // MIDI note that plays each slot, if any,
+    // e.g. from a controller's pads
+    notes: [Option<u8>; 9],
+
+    // Slot last played or bound
+    selected: Option<usize>,
+
+    // Bind the next MIDI note
+    // received to the selected slot
+    learning: bool,
+
+    // Play chords while browsing them
+    audition: bool,
+
+    // Pick the root around the circle
+    // of fifths rather than typing it
+    circle_of_fifths: bool,
+
+    // Hold each chord until the next is played,
+    // or it's stopped, rather than for the
+    // duration, and the chord being held
+    latch: bool,
+    held: Option<HeldChord>,
+
+    // Voice lead each chord from the one played
+    // before it, as it's played, and that chord
+    // as it was voiced
+    voice_lead: bool,
+    last_played: Option<ChordSpec>,
+
+    // Previous inputs for each prompt
+    history: HashMap<TextTarget, Vec<String>>,
 
     save_dir: String,
+    export_options: ExportOptions,
+    tempo: usize,
     input_mode: InputMode<'a>,
 
-    // Last status message
-    message: &'a str,
+    // Status messages
+    log: MessageLog,
 
     template: ProgressionTemplate,
 }
 
 impl<'a> Performance<'a> {
-    pub fn new(midi: Arc<Mutex<MIDIOutput>>, template: ProgressionTemplate, save_dir: String) -> Performance<'a> {
+    pub fn new(midi: Arc<Mutex<MIDIOutput>>, recent: Arc<Mutex<RecentChords>>, log: MessageLog, template: ProgressionTemplate, save_dir: String) -> Performance<'a> {
         let key = Key::default();
         Performance {
             key,
             midi,
+            recent,
             save_dir,
+            export_options: ExportOptions::default(),
+            tempo: 120,
             note_duration: 5,
-            mappings: Default::default(),
-            message: "",
+            mappings: vec![None; BANK_SIZE * BANKS],
+            bank: 0,
+            channels: Default::default(),
+            arps: Default::default(),
+            notes: Default::default(),
+            selected: None,
+            learning: false,
+            audition: true,
+            circle_of_fifths: true,
+            latch: false,
+            held: None,
+            voice_lead: false,
+            last_played: None,
+            history: HashMap::default(),
+            log,
             input_mode: InputMode::Normal,
             template,
         }
     }
 
+    pub fn set_tempo(&mut self, bpm: usize) {
+        self.tempo = bpm;
+    }
+
+    pub fn set_save_dir(&mut self, save_dir: String) {
+        self.save_dir = save_dir;
+    }
+
+    /// Set how exported files are written,
+    /// e.g. the MIDI channel they're on.
+    pub fn set_export_options(&mut self, options: ExportOptions) {
+        self.export_options = options;
+    }
+
+    pub fn set_circle_of_fifths(&mut self, circle_of_fifths: bool) {
+        self.circle_of_fifths = circle_of_fifths;
+    }
+
+    pub fn key(&self) -> Key {
+        self.key
+    }
+
+    /// Chords bound to each slot,
+    /// bank after bank.
+    pub fn mappings(&self) -> &[Option<ChordSpec>] {
+        &self.mappings
+    }
+
+    /// Bind chords to each slot, bank after bank,
+    /// starting over from the first bank.
+    pub fn set_mappings(&mut self, mappings: Vec<Option<ChordSpec>>) {
+        self.mappings = banked(mappings);
+        self.bank = 0;
+    }
+
+    /// Bind chords saved to a file,
+    /// with their key and duration.
+    pub fn set_pads(&mut self, pads: &Pads) -> Result<()> {
+        let mappings = pads.mappings()?;
+        if mappings.len() > BANK_SIZE * BANKS {
+            return Err(anyhow!("There are only {} slots for its {} chords", BANK_SIZE * BANKS, mappings.len()));
+        }
+        if pads.duration == 0 {
+            return Err(anyhow!("Chords can't be held for 0"));
+        }
+        self.set_mappings(mappings);
+        self.key = pads.key;
+        self.note_duration = pads.duration;
+        Ok(())
+    }
+
+    /// Chords bound to the current bank's slots.
+    fn bank(&self) -> &[Option<ChordSpec>] {
+        let offset = self.bank * BANK_SIZE;
+        &self.mappings[offset..offset + BANK_SIZE]
+    }
+
+    /// MIDI channels each slot plays on.
+    pub fn channels(&self) -> &[Option<u8>] {
+        &self.channels
+    }
+
+    pub fn set_channels(&mut self, channels: Vec<Option<u8>>) {
+        self.channels = Default::default();
+        for (slot, channel) in self.channels.iter_mut().zip(channels) {
+            *slot = channel;
+        }
+    }
+
+    /// Arpeggios each slot plays its chord as.
+    pub fn arps(&self) -> &[Option<Arp>] {
+        &self.arps
+    }
+
+    pub fn set_arps(&mut self, arps: Vec<Option<Arp>>) {
+        self.arps = Default::default();
+        for (slot, arp) in self.arps.iter_mut().zip(arps) {
+            *slot = arp;
+        }
+    }
+
+    /// MIDI notes that play each slot.
+    pub fn notes(&self) -> &[Option<u8>] {
+        &self.notes
+    }
+
+    pub fn set_notes(&mut self, notes: Vec<Option<u8>>) {
+        self.notes = Default::default();
+        for (slot, note) in self.notes.iter_mut().zip(notes) {
+            *slot = note;
+        }
+    }
+
+    /// Stop learning, and stop the chord being
+    /// held, on switching to another mode.
+    pub fn leave(&mut self) {
+        self.learning = false;
+        if let Some(held) = self.held.take() {
+            self.midi.lock().unwrap().release(held);
+        }
+    }
+
+    /// Play the slot a MIDI note is bound to, if any,
+    /// or if learning, bind the note to the selected slot.
+    pub fn note_played(&mut self, note: u8) {
+        match (self.learning, self.selected) {
+            (true, Some(idx)) => {
+                // Each note plays only one slot
+                for slot in self.notes.iter_mut() {
+                    if *slot == Some(note) {
+                        *slot = None;
+                    }
+                }
+                self.notes[idx] = Some(note);
+                self.learning = false;
+                self.log.push(format!("Slot {} plays on MIDI note {}", idx + 1, note));
+            }
+            _ => {
+                if let Some(idx) = self.notes.iter().position(|n| *n == Some(note)) {
+                    self.trigger(idx);
+                }
+            }
+        }
+    }
+
+    pub fn set_key(&mut self, key: Key) {
+        self.key = key;
+    }
+
+    /// Bind a chord played on a keyboard to the
+    /// selected slot, or if there isn't one,
+    /// the first free one, named in the key.
+    pub fn put_chord(&mut self, chord: &Chord) {
+        let idx = self.selected
+            .or_else(|| self.bank().iter().position(|cs| cs.is_none()))
+            .unwrap_or(0);
+        match ChordSpec::from_chord(chord, &self.key) {
+            Some(cs) => {
+                self.log.push(format!("Bound {} to slot {}", symbols::chord_in_key(&cs, &self.key), idx + 1));
+                self.recent.lock().unwrap().push(&cs);
+                self.mappings[self.bank * BANK_SIZE + idx] = Some(cs);
+                self.selected = Some(idx);
+            }
+            None => self.log.error("Couldn't recognize the chord"),
+        }
+    }
+
+    /// Play the chord bound to a slot of the current bank, if any,
+    /// voice led from the last chord if that's on, and ending
+    /// the chord being held if latching. Arpeggios play for
+    /// the duration even when latching.
+    pub fn trigger(&mut self, idx: usize) {
+        if let Some(cs) = self.bank().get(idx).cloned().flatten() {
+            let voiced = match &self.last_played {
+                Some(last) if self.voice_lead => voice_lead(&vec![last.clone(), cs.clone()]).remove(1),
+                _ => cs.clone(),
+            };
+            let chord = voiced.chord_for_key(&self.key);
+            let mut midi = self.midi.lock().unwrap();
+            if let Some(held) = self.held.take() {
+                midi.release(held);
+            }
+            match self.arps[idx] {
+                Some(arp) => {
+                    // Held as long as a chord would be
+                    let beat = Time::from_secs_f64(60. / self.tempo.max(1) as f64);
+                    let length = Time::from_millis(self.note_duration * 150);
+                    for (note, start, length) in arp.notes(&chord, beat, length) {
+                        midi.play_chord_later(&note, start, length, 1., self.channels[idx], None);
+                    }
+                }
+                None if self.latch => {
+                    self.held = Some(midi.hold_chord(&chord, self.channels[idx], None));
+                }
+                None => midi.play_chord(&chord, self.note_duration, self.channels[idx], None),
+            }
+            drop(midi);
+            self.recent.lock().unwrap().push(&cs);
+            self.last_played = Some(voiced);
+            self.selected = Some(idx);
+        }
+    }
+
     pub fn capture_input(&self) -> bool {
         match self.input_mode {
             InputMode::Normal => false,
@@ -82,44 +373,49 @@ impl<'a> Performance<'a> {
         let message = match &self.input_mode {
             InputMode::Text(ti, _) => ti.render(),
             InputMode::Chord(select, _) => select.text_input.render(),
-            _ => Paragraph::new(self.message)
+            _ => Paragraph::new(self.log.current())
                 .alignment(Alignment::Right)
         };
         rects.push((message, chunks[1]));
 
+        let display_chunks = Layout::default()
+            .direction(Direction::Horizontal)
+            .margin(2)
+            .constraints([
+                    // Progression chunk
+                    Constraint::Ratio(1, 2),
+
+                    // Chord select or key picker chunk
+                    Constraint::Ratio(1, 2),
+                ].as_ref())
+            .split(chunks[0]);
         match &self.input_mode {
             InputMode::Chord(select, idx) => {
-                let display_chunks = Layout::default()
-                    .direction(Direction::Horizontal)
-                    .margin(2)
-                    .constraints([
-                            // Progression chunk
-                            Constraint::Ratio(1, 2),
-
-                            // Chord select chunk
-                            Constraint::Ratio(1, 2),
-                        ].as_ref())
-                    .split(chunks[0]);
-
-                let height = display_chunks[1].height as usize;
-                rects.push((select.render(height), display_chunks[1]));
-
-                rects.push((render_mappings(&self.key, &self.mappings, Some(*idx)), display_chunks[0]));
+                rects.extend(select.render(display_chunks[1], &self.key));
+
+                rects.push((render_mappings(&self.key, self.bank(), &self.channels, self.bank, Some(*idx)), display_chunks[0]));
+            }
+            InputMode::Key(picker) => {
+                rects.push((picker.render(), display_chunks[1]));
+                rects.push((render_mappings(&self.key, self.bank(), &self.channels, self.bank, None), display_chunks[0]));
             }
             _ => {
-                rects.push((render_mappings(&self.key, &self.mappings, None), chunks[0]));
+                rects.push((render_mappings(&self.key, self.bank(), &self.channels, self.bank, self.selected), chunks[0]));
             }
         }
         rects
     }
 
     pub fn process_input(&mut self, key: KeyEvent) -> Result<()> {
-        let mut midi = self.midi.lock().unwrap();
+        let offset = self.bank * BANK_SIZE;
+        let midi = self.midi.clone();
+        let mut midi = midi.lock().unwrap();
         match &mut self.input_mode {
             InputMode::Text(ref mut text_input, target) => {
                 let (input, close) = text_input.process_input(key)?;
                 if close {
                     if let Some(input) = input {
+                        add_to_history(self.history.entry(*target).or_default(), &input);
                         match target {
                             TextTarget::Root => {
                                 self.key.root = match input.try_into() {
@@ -127,78 +423,140 @@ impl<'a> Performance<'a> {
                                         note
                                     }
                                     Err(_) => {
-                                        self.message = "Invalid root note";
+                                        self.log.error("Invalid root note");
                                         self.key.root
                                     }
                                 };
                             }
                             TextTarget::Duration => {
-                                self.note_duration = input.parse::<u64>()?;
+                                // Pads can't be held for nothing,
+                                // or they couldn't be loaded again
+                                match input.parse::<u64>() {
+                                    Ok(duration) if duration > 0 => {
+                                        self.note_duration = duration;
+                                    }
+                                    _ => {
+                                        self.log.error("Invalid duration");
+                                    }
+                                }
                             }
                             TextTarget::Progression => {
+                                // Chords past the bank's
+                                // go on into the next banks
                                 let mappings: Result<Vec<ChordSpec>, ChordParseError> = input.split_whitespace()
-                                    .take(9).map(|cs_str| cs_str.try_into()).collect();
+                                    .take(self.mappings.len() - offset).map(|cs_str| ChordSpec::parse_in_key(cs_str, &self.key)).collect();
                                 if let Ok(chord_specs) = mappings {
                                     for (i, cs) in chord_specs.into_iter().enumerate() {
-                                        self.mappings[i] = Some(cs);
+                                        self.mappings[offset + i] = Some(cs);
                                     }
                                 } else {
-                                    self.message = "Invalid chord";
+                                    self.log.error("Invalid chord");
                                 }
                             }
-                            TextTarget::Export => {
-                                let chords = self.mappings.iter().map(|m| {
-                                    match m {
-                                        Some(cs) => Some(cs.chord_for_key(&self.key)),
-                                        None => None
+                            TextTarget::Channels => {
+                                let channels: Result<Vec<Option<u8>>, String> = input.split_whitespace()
+                                    .take(9).map(parse_channel).collect();
+                                match channels {
+                                    Ok(channels) => {
+                                        for (i, channel) in channels.into_iter().enumerate() {
+                                            self.channels[i] = channel;
+                                        }
+                                    }
+                                    Err(err) => {
+                                        self.log.error(err);
                                     }
-                                }).collect();
+                                }
+                            }
+                            TextTarget::Arps => {
+                                let arps: Result<Vec<Option<Arp>>, String> = input.split_whitespace()
+                                    .take(9).map(parse_arp).collect();
+                                match arps {
+                                    Ok(arps) => {
+                                        for (i, arp) in arps.into_iter().enumerate() {
+                                            self.arps[i] = arp;
+                                        }
+                                    }
+                                    Err(err) => {
+                                        self.log.error(err);
+                                    }
+                                }
+                            }
+                            TextTarget::Export => {
+                                let chords: Vec<_> = self.mappings[offset..offset + BANK_SIZE].iter().cloned().zip(self.channels).collect();
                                 let result = save_to_midi_file(
-                                    120, // default tempo
+                                    self.tempo,
                                     2,   // default ticks per beat
                                     &chords,
+                                    &self.key,
+                                    self.export_options,
                                     input);
                                 match result {
                                     Ok(_) => {
-                                        self.message = "Saved file";
+                                        self.log.push("Saved file");
                                     },
-                                    Err(_) => {
-                                        self.message = "Failed to save";
+                                    Err(err) => {
+                                        self.log.error(format!("Failed to save: {}", err));
                                     }
                                 }
                             }
+                            TextTarget::WritePads => {
+                                let pads = Pads::new(self.key, self.note_duration, &self.mappings);
+                                match pads.save_to(Path::new(&input)) {
+                                    Ok(_) => self.log.push("Saved the pads"),
+                                    Err(err) => self.log.error(format!("Failed to save the pads: {}", err)),
+                                }
+                            }
+                            TextTarget::LoadPads => {
+                                let loaded = Pads::load_from(Path::new(&input))
+                                    .and_then(|pads| self.set_pads(&pads));
+                                match loaded {
+                                    Ok(_) => self.log.push("Loaded the pads"),
+                                    Err(err) => self.log.error(format!("Failed to load the pads: {}", err)),
+                                }
+                            }
                         }
                     }
                     self.input_mode = InputMode::Normal;
                 }
             }
+            InputMode::Key(ref mut picker) => {
+                let (picked, close) = picker.process_input(key);
+                if let Some(key) = picked {
+                    self.key = key;
+                }
+                if close {
+                    self.input_mode = InputMode::Normal;
+                }
+            }
             InputMode::Chord(ref mut chord_select, idx) => {
                 match chord_select.process_input(key) {
                     Ok((sel, close)) => {
+                        self.audition = chord_select.audition;
                         if let Some(cs) = sel {
-                            let chord = cs.chord_for_key(&self.key);
-                            midi.play_chord(&chord, self.note_duration);
-                            self.mappings[*idx] = Some(cs);
+                            if self.audition {
+                                let chord = cs.chord_for_key(&self.key);
+                                midi.play_chord(&chord, self.note_duration, self.channels[*idx], None);
+                            }
+                            self.mappings[offset + *idx] = Some(cs);
                         }
                         if close {
+                            if let Some(cs) = &self.mappings[offset + *idx] {
+                                self.recent.lock().unwrap().push(cs);
+                            }
                             self.input_mode = InputMode::Normal;
                         }
 
-                        match key.code {
-                            KeyCode::Char(c) => {
-                                if c.is_numeric() {
-                                    let idx = c.to_string().parse::<usize>()? - 1;
-                                    if let Some(cs) = &self.mappings[idx] {
-                                        let chord = cs.chord_for_key(&self.key);
-                                        midi.play_chord(&chord, self.note_duration);
-                                    }
+                        if let KeyCode::Char(c) = key.code {
+                            if let Some(idx) = mapping_idx(c) {
+                                if let Some(cs) = &self.mappings[offset + idx] {
+                                    let chord = cs.chord_for_key(&self.key);
+                                    midi.play_chord(&chord, self.note_duration, self.channels[idx], None);
                                 }
                             }
-                            _ => {}
                         }
                     }
-                    Err(_) => {
-                        self.message = "Invalid chord";
+                    Err(err) => {
+                        self.log.error(err.to_string());
                         self.input_mode = InputMode::Normal;
                     }
                 }
@@ -210,17 +568,11 @@ impl<'a> Performance<'a> {
                         modifiers: KeyModifiers::ALT,
                         code: KeyCode::Char(c),
                     } => {
-                        if c.is_numeric() {
-                            let idx = c.to_string().parse::<usize>()?;
-                            if idx > 0 {
-                                let select = if let Some(cs) = &self.mappings[idx-1] {
-                                    ChordSelect::with_chord(cs)
-                                } else {
-                                    ChordSelect::default()
-                                };
-                                self.input_mode = InputMode::Chord(
-                                    select, idx-1);
-                            }
+                        if let Some(idx) = mapping_idx(c) {
+                            self.selected = Some(idx);
+                            let select = ChordSelect::new(self.mappings[offset + idx].as_ref(), self.template.chord_types.clone(), &self.recent, self.audition);
+                            self.input_mode = InputMode::Chord(
+                                select, idx);
                         }
 
                     }
@@ -229,39 +581,63 @@ impl<'a> Performance<'a> {
                 match key.code {
                     // Change root
                     KeyCode::Char('r') => {
-                        self.input_mode = InputMode::Text(
-                            TextInput::new("Root: ", |c: char| c.is_alphanumeric()),
-                            TextTarget::Root);
+                        self.input_mode = if self.circle_of_fifths {
+                            let chords = self.bank().iter().flatten().cloned().collect();
+                            InputMode::Key(KeyPicker::new(self.key, chords))
+                        } else {
+                            InputMode::Text(
+                                TextInput::with_history("Root: ", |c: char| c.is_alphanumeric(), &self.history, TextTarget::Root),
+                                TextTarget::Root)
+                        };
                     }
 
                     // Change duration
                     KeyCode::Char('u') => {
                         self.input_mode = InputMode::Text(
-                            TextInput::new("Duration: ", |c: char| c.is_numeric()),
+                            TextInput::with_history("Duration: ", |c: char| c.is_numeric(), &self.history, TextTarget::Duration),
                             TextTarget::Duration);
                     }
 
                     // Change mode
                     KeyCode::Char('m') => {
-                        self.key.mode = match self.key.mode {
-                            Mode::Major => Mode::Minor,
-                            Mode::Minor => Mode::Major,
-                        };
+                        self.key.mode = self.key.mode.next();
                     }
 
                     // Enter a progression, space-delimited
                     KeyCode::Char('p') => {
                         self.input_mode = InputMode::Text(
-                            TextInput::new("Progression: ", |_c: char| true),
+                            TextInput::with_history("Progression: ", |_c: char| true, &self.history, TextTarget::Progression),
                             TextTarget::Progression);
                     }
 
+                    // Set the MIDI channel of each slot,
+                    // space-delimited
+                    KeyCode::Char('@') => {
+                        let mut text_input = TextInput::with_history("Channels (1-16 or - per slot): ", |c: char| c.is_ascii_digit() || c == '-' || c == ' ', &self.history, TextTarget::Channels);
+                        let channels: Vec<String> = self.channels.iter()
+                            .map(|c| c.map_or("-".to_string(), |c| (c + 1).to_string()))
+                            .collect();
+                        text_input.set_input(channels.join(" "));
+                        self.input_mode = InputMode::Text(text_input, TextTarget::Channels);
+                    }
+
+                    // Set the arpeggio of each slot,
+                    // space-delimited
+                    KeyCode::Char('a') => {
+                        let mut text_input = TextInput::with_history("Arpeggios (e.g. up/16 or - per slot): ", |c: char| c.is_ascii_alphanumeric() || c == '/' || c == '-' || c == ' ', &self.history, TextTarget::Arps);
+                        let arps: Vec<String> = self.arps.iter()
+                            .map(|a| a.map_or("-".to_string(), |a| a.to_string()))
+                            .collect();
+                        text_input.set_input(arps.join(" "));
+                        self.input_mode = InputMode::Text(text_input, TextTarget::Arps);
+                    }
+
                     // Apply voice leading algorithm to progression
                     KeyCode::Char('v') => {
                         // Kind of messy
-                        let cses = self.mappings.iter().flatten().cloned().collect();
+                        let cses = self.bank().iter().flatten().cloned().collect();
                         let mut vl_prog = voice_lead(&cses);
-                        for maybe_cs in self.mappings.iter_mut() {
+                        for maybe_cs in self.mappings[offset..offset + BANK_SIZE].iter_mut() {
                             *maybe_cs = match maybe_cs {
                                 Some(_) => Some(vl_prog.remove(0)),
                                 None => None
@@ -269,30 +645,113 @@ impl<'a> Performance<'a> {
                         }
                     }
 
+                    // Hold chords until the next is played,
+                    // or stop holding them
+                    KeyCode::Char('h') => {
+                        self.latch = !self.latch;
+                        if let Some(held) = self.held.take() {
+                            midi.release(held);
+                        }
+                        self.log.push(if self.latch {
+                            "Holding chords until the next, [Space] to stop"
+                        } else {
+                            "Playing chords for the duration"
+                        });
+                    }
+
+                    // Stop the chord being held
+                    KeyCode::Char(' ') => {
+                        if let Some(held) = self.held.take() {
+                            midi.release(held);
+                        }
+                    }
+
+                    // Voice lead chords as they're played,
+                    // starting from the next one
+                    KeyCode::Char('O') => {
+                        self.voice_lead = !self.voice_lead;
+                        self.last_played = None;
+                        self.log.push(if self.voice_lead {
+                            "Voice leading as played"
+                        } else {
+                            "Playing chords as bound"
+                        });
+                    }
+
+                    // Bind the next MIDI note received
+                    // to the selected slot, or stop
+                    KeyCode::Char('l') => {
+                        match self.selected {
+                            _ if self.learning => {
+                                self.learning = false;
+                                self.log.push("Stopped learning");
+                            }
+                            Some(idx) => {
+                                self.learning = true;
+                                self.log.push(format!("Play a note to bind to slot {}, [Backspace] to unbind it", idx + 1));
+                            }
+                            None => self.log.error("Play or bind a slot to learn its note"),
+                        }
+                    }
+
+                    // Unbind the selected slot's note
+                    KeyCode::Backspace if self.learning => {
+                        if let Some(idx) = self.selected {
+                            self.notes[idx] = None;
+                            self.log.push(format!("Slot {} has no MIDI note", idx + 1));
+                        }
+                        self.learning = false;
+                    }
+
+                    // Switch banks, wrapping around
+                    KeyCode::Char('<') => {
+                        self.bank = (self.bank + BANKS - 1) % BANKS;
+                    }
+                    KeyCode::Char('>') => {
+                        self.bank = (self.bank + 1) % BANKS;
+                    }
+
+                    // Switch between numerals
+                    // and letter names for chords
+                    KeyCode::Char('Y') => {
+                        symbols::toggle_letter_names();
+                    }
+
                     // Generate a new random progression
                     KeyCode::Char('R') => {
-                        let progression = self.template.gen_progression(&self.key.mode, 8, &Duration::Quarter);
-                        for (i, cs) in progression.sequence.into_iter().flatten().take(9).enumerate() {
-                            self.mappings[i] = Some(cs);
+                        let progression = self.template.gen_progression(&self.key.mode, 8, &Duration::Quarter, None);
+                        for (i, cs) in progression.sequence.into_iter().flatten().take(BANK_SIZE).enumerate() {
+                            self.mappings[offset + i] = Some(cs);
                         }
                     }
 
                     // Start export to MIDI flow
                     KeyCode::Char('E') => {
-                        let mut text_input = TextInput::new("Path: ", |_c: char| true);
+                        let mut text_input = TextInput::with_history("Path: ", |_c: char| true, &self.history, TextTarget::Export);
                         text_input.set_input(self.save_dir.to_string());
                         self.input_mode = InputMode::Text(
                             text_input, TextTarget::Export);
                     }
 
+                    // Save the chords in every bank to a file,
+                    // or load them, with the key and duration
+                    KeyCode::Char('W') | KeyCode::Char('o') => {
+                        let (label, target) = if key.code == KeyCode::Char('W') {
+                            ("Write pads to: ", TextTarget::WritePads)
+                        } else {
+                            ("Load pads from: ", TextTarget::LoadPads)
+                        };
+                        let mut text_input = TextInput::with_history(label, |_c: char| true, &self.history, target);
+                        let path = PathBuf::from(&self.save_dir).join("pads.yaml");
+                        text_input.set_input(path.to_string_lossy().to_string());
+                        self.input_mode = InputMode::Text(text_input, target);
+                    }
+
                     // Play the chord bound to that number
                     KeyCode::Char(c) => {
-                        if c.is_numeric() {
-                            let idx = c.to_string().parse::<usize>()? - 1;
-                            if let Some(cs) = &self.mappings[idx] {
-                                let chord = cs.chord_for_key(&self.key);
-                                midi.play_chord(&chord, self.note_duration);
-                            }
+                        if let Some(idx) = mapping_idx(c) {
+                            drop(midi);
+                            self.trigger(idx);
                         }
                     }
 
@@ -304,11 +763,10 @@ impl<'a> Performance<'a> {
     }
 
     pub fn params<'b>(&self) -> Vec<Span<'b>> {
-        let param_style = Style::default().fg(Color::LightBlue)
-            .add_modifier(Modifier::BOLD);
+        let param_style = theme::param();
         let params = vec![
             Span::raw("[r]oot:"),
-            Span::styled(self.key.root.to_string(), param_style),
+            Span::styled(symbols::key_root(&self.key), param_style),
             Span::raw(" d[u]ration:"),
             Span::styled(self.note_duration.to_string(), param_style),
             Span::raw(" [m]ode:"),
@@ -320,15 +778,23 @@ impl<'a> Performance<'a> {
     pub fn controls<'b>(&self) -> Vec<Span<'b>> {
         let controls = vec![
             Span::raw(" [p]rogression"),
+            Span::raw(" [<>]bank"),
+            Span::raw(" ch[@]annels"),
+            Span::raw(" [a]rpeggios"),
+            Span::raw(if self.learning { " [l]earning" } else { " [l]earn" }),
             Span::raw(" [v]oice-lead"),
+            Span::raw(if self.voice_lead { " v[O]ice-lead as played:on" } else { " v[O]ice-lead as played:off" }),
+            Span::raw(if self.latch { " [h]old:on" } else { " [h]old:off" }),
             Span::raw(" [E]xport"),
+            Span::raw(" pads:[W]rite/l[o]ad"),
             Span::raw(" [R]andom"),
+            Span::raw(if symbols::letter_names() { " [Y]names:letters" } else { " [Y]names:numerals" }),
         ];
         controls
     }
 }
 
-pub fn render_mappings<'a>(key: &Key, mappings: &[Option<ChordSpec>], selected: Option<usize>) -> Paragraph<'a> {
+pub fn render_mappings<'a>(key: &Key, mappings: &[Option<ChordSpec>], channels: &[Option<u8>], bank: usize, selected: Option<usize>) -> Paragraph<'a> {
     // The lines that will be rendered.
     let mut lines = vec![];
 
@@ -343,11 +809,16 @@ pub fn render_mappings<'a>(key: &Key, mappings: &[Option<ChordSpec>], selected:
     let mut required_lines = 0;
 
     let chord_id_spans: Vec<Span> = (0..mappings.len()).map(|i| {
-        // Each chord has 5 spaces to work with
-        let name = format!("{:^5}", (i+1).to_string());
+        // Each chord has 5 spaces to work with,
+        // showing its channel if it has its own
+        let id = match channels.get(i).copied().flatten() {
+            Some(channel) => format!("{}:{}", i + 1, channel + 1),
+            None => (i + 1).to_string(),
+        };
+        let name = format!("{:^5}", id);
 
         let style = if selected.is_some() && i == selected.unwrap() {
-            Style::default().fg(Color::LightBlue)
+            theme::selected()
         } else {
             Style::default()
         };
@@ -360,12 +831,13 @@ pub fn render_mappings<'a>(key: &Key, mappings: &[Option<ChordSpec>], selected:
         let (name, notes) = match mcs {
             Some(cs) => {
                 // For rendering chord notes
-                let notes = cs.chord_for_key(key).describe_notes();
+                let notes: Vec<String> = cs.chord_for_key(key).describe_notes(symbols::accidentals())
+                    .iter().map(|note| symbols::note(note)).collect();
                 if notes.len() > required_lines {
                     required_lines = notes.len();
                 }
 
-                (cs.to_string(), notes)
+                (symbols::chord_in_key(cs, key), notes)
             }
             None => ("".to_string(), vec![])
         };
@@ -395,8 +867,42 @@ pub fn render_mappings<'a>(key: &Key, mappings: &[Option<ChordSpec>], selected:
         .alignment(Alignment::Center)
         .block(
             Block::default()
-                .title("Mappings")
+                .title(format!("Mappings: bank {}/{}", bank + 1, BANKS))
                 .borders(Borders::TOP)
                 .style(Style::default())
         )
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_set_pads() {
+        let midi = Arc::new(Mutex::new(MIDIOutput::new()));
+        let recent = Arc::new(Mutex::new(RecentChords::default()));
+        let mut performance = Performance::new(midi, recent, MessageLog::default(), ProgressionTemplate::default(), String::new());
+
+        let key = Key::from_str("D4 minor").unwrap();
+        let chords = vec![Some(ChordSpec::from_str("ii:7").unwrap()); BANK_SIZE * BANKS];
+        performance.set_pads(&Pads::new(key, 8, &chords)).unwrap();
+        assert_eq!(performance.key, key);
+        assert_eq!(performance.note_duration, 8);
+
+        // More chords than there are slots, or
+        // holding them for nothing, is an error
+        let mut too_many = chords.clone();
+        too_many.push(chords[0].clone());
+        assert!(performance.set_pads(&Pads::new(key, 8, &too_many)).is_err());
+        assert!(performance.set_pads(&Pads::new(key, 0, &chords)).is_err());
+        assert_eq!(performance.note_duration, 8);
+
+        // Nor can the duration be set to 0,
+        // so written pads can always be loaded
+        for code in [KeyCode::Char('u'), KeyCode::Char('0'), KeyCode::Enter] {
+            performance.process_input(KeyEvent { code, modifiers: KeyModifiers::NONE }).unwrap();
+        }
+        assert_eq!(performance.note_duration, 8);
+    }
+}
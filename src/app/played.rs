@@ -0,0 +1,26 @@
+use tui::{
+    text::{Span, Spans},
+    widgets::Paragraph,
+    layout::Alignment,
+};
+use super::{theme, symbols};
+use crate::core::{Key, Chord, ChordSpec};
+
+// Width of the played chord pane
+pub const PLAYED_WIDTH: u16 = 24;
+
+/// The chord last played on the MIDI note
+/// input, as a numeral and its letter name.
+pub fn render<'a>(chord: Option<&Chord>, key: &Key) -> Paragraph<'a> {
+    let mut spans = vec![Span::raw("played:")];
+    match chord.and_then(|chord| ChordSpec::from_chord(chord, key)) {
+        Some(cs) => {
+            spans.push(Span::styled(symbols::chord(&cs), theme::param()));
+            spans.push(Span::raw(" "));
+            spans.push(Span::raw(symbols::note(&cs.name_in_key(key, symbols::accidentals()))));
+        }
+        None => spans.push(Span::styled("-", theme::muted())),
+    }
+    Paragraph::new(Spans::from(spans))
+        .alignment(Alignment::Right)
+}
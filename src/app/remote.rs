@@ -0,0 +1,100 @@
+use anyhow::Result;
+use std::net::UdpSocket;
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use rosc::{decoder, OscPacket, OscMessage, OscType};
+use crate::core::{Note, Mode};
+
+/// Commands sent from an OSC controller.
+#[derive(Debug)]
+pub enum Remote {
+    // Play the chord bound to a performance slot
+    Trigger(usize),
+
+    Root(Note),
+    Mode(Mode),
+    Tempo(usize),
+
+    // Start or stop the sequencer
+    Start,
+    Stop,
+}
+
+/// Listen for OSC messages on a UDP port
+/// and pass on the commands they map to.
+pub fn listen(port: u16) -> Result<Receiver<Remote>> {
+    let socket = UdpSocket::bind(("0.0.0.0", port))?;
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        let mut buf = [0u8; decoder::MTU];
+        while let Ok((size, _)) = socket.recv_from(&mut buf) {
+            // Skip anything that isn't valid OSC
+            if let Ok((_, packet)) = decoder::decode_udp(&buf[..size]) {
+                for remote in commands(packet) {
+                    // The app has shut down
+                    if tx.send(remote).is_err() {
+                        return;
+                    }
+                }
+            }
+        }
+    });
+    Ok(rx)
+}
+
+fn commands(packet: OscPacket) -> Vec<Remote> {
+    match packet {
+        OscPacket::Message(msg) => parse(&msg).into_iter().collect(),
+        OscPacket::Bundle(bundle) => bundle.content.into_iter().flat_map(commands).collect(),
+    }
+}
+
+/// Parse a message into a command.
+/// The value can be given as an argument, e.g. `/dust/trigger 3`,
+/// or at the end of the address, e.g. `/dust/trigger/3`,
+/// which is easier to map to buttons.
+fn parse(msg: &OscMessage) -> Option<Remote> {
+    // Buttons send a 0 when they're released
+    if matches!(msg.args.as_slice(), [arg] if number(arg) == Some(0.)) {
+        return None;
+    }
+
+    let mut parts = msg.addr.trim_start_matches('/').split('/');
+    if parts.next() != Some("dust") {
+        return None;
+    }
+    let command = parts.next()?;
+    let value = parts.next().map(|s| s.to_string())
+        .or_else(|| msg.args.first().and_then(text));
+    match command {
+        "trigger" => value?.parse::<usize>().ok()
+            .and_then(|n| n.checked_sub(1))
+            .map(Remote::Trigger),
+        "key" => value?.parse().ok().map(Remote::Root),
+        "mode" => value?.parse().ok().map(Remote::Mode),
+        "tempo" => value?.parse::<f32>().ok()
+            .map(|bpm| bpm.round() as usize)
+            .filter(|bpm| *bpm > 0)
+            .map(Remote::Tempo),
+        "start" => Some(Remote::Start),
+        "stop" => Some(Remote::Stop),
+        _ => None,
+    }
+}
+
+fn number(arg: &OscType) -> Option<f64> {
+    match arg {
+        OscType::Int(i) => Some(*i as f64),
+        OscType::Long(i) => Some(*i as f64),
+        OscType::Float(f) => Some(*f as f64),
+        OscType::Double(f) => Some(*f),
+        _ => None,
+    }
+}
+
+fn text(arg: &OscType) -> Option<String> {
+    match arg {
+        OscType::String(s) => Some(s.to_string()),
+        _ => number(arg).map(|n| n.to_string()),
+    }
+}
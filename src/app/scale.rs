@@ -0,0 +1,38 @@
+use tui::{
+    style::{Style, Modifier},
+    text::{Span, Spans},
+    widgets::{Block, Paragraph, Borders},
+};
+use super::{theme, symbols};
+use crate::core::{Key, ChordSpec};
+
+// Width of the scale pane
+pub const SCALE_WIDTH: u16 = 28;
+
+/// The key's scale degrees and their notes, with the
+/// triad and seventh chord built on each degree.
+pub fn render<'a>(key: &Key) -> Paragraph<'a> {
+    let mut lines = vec![
+        Spans::from(Span::styled(
+                format!("{:<4}{:<6}{:<7}{}", "deg", "note", "triad", "7th"),
+                theme::muted())),
+    ];
+    for (i, note) in key.scale().iter().enumerate() {
+        let degree = i + 1;
+        let triad = ChordSpec::diatonic(degree, key);
+        let seventh = triad.clone().toggle_extension(7, key);
+        let name = note.spell_pitch(key, symbols::accidentals());
+        lines.push(Spans::from(vec![
+            Span::styled(format!("{:<4}", degree), theme::param()),
+            Span::styled(format!("{:<6}", symbols::note(&name)), Style::default().add_modifier(Modifier::BOLD)),
+            Span::raw(format!("{:<7}", symbols::chord_in_key(&triad, key))),
+            Span::raw(symbols::chord_in_key(&seventh, key)),
+        ]));
+    }
+    Paragraph::new(lines)
+        .block(
+            Block::default()
+                .title(format!("{} {}", symbols::key_root(key), key.mode))
+                .borders(Borders::TOP | Borders::LEFT)
+        )
+}
@@ -1,15 +1,32 @@
 use anyhow::Result;
+use std::cell::Cell;
 use tui::{
     layout::Alignment,
-    style::{Style, Color},
+    style::{Style, Modifier},
     text::{Span, Spans},
     widgets::{Block, Paragraph, Borders},
 };
 use crossterm::event::{KeyEvent, KeyCode};
+use super::theme;
 
 pub struct Select {
     pub idx: usize,
     pub choices: Vec<String>,
+
+    // Character positions to highlight
+    // for each choice, e.g. search matches.
+    pub highlights: Vec<Vec<usize>>,
+
+    // Number of choices pinned to the top,
+    // which are rendered distinctly.
+    pub pinned: usize,
+
+    // Typed prefix to jump to
+    prefix: String,
+
+    // How many choices were visible
+    // when last rendered, for paging.
+    page_size: Cell<usize>,
 }
 
 impl Select {
@@ -17,23 +34,57 @@ impl Select {
         Select {
             idx: 0,
             choices,
+            highlights: vec![],
+            pinned: 0,
+            prefix: String::new(),
+            page_size: Cell::new(1),
         }
     }
 
     pub fn render<'a>(&self, height: usize) -> Paragraph<'a> {
-        let start = self.idx.saturating_sub(height);
-        let end = self.choices.len().min(start+height);
+        let n_choices = self.choices.len();
+        let height = height.max(1);
+        self.page_size.set(height);
+
+        // Scroll so that the selected choice is visible
+        let start = (self.idx + 1).saturating_sub(height);
+        let end = n_choices.min(start+height);
+
+        let thumb = self.thumb(start, height);
 
         let mut rows = vec![];
         for (i, choice) in self.choices[start..end].iter().enumerate() {
-            let choice = choice.to_string();
-            let span = if i + start == self.idx {
-                Span::styled(choice, Style::default().fg(Color::LightBlue))
+            let mut style = if i + start == self.idx {
+                theme::selected()
             } else {
-                Span::raw(choice)
+                Style::default()
             };
-            let row = Spans::from(span);
-            rows.push(row);
+            if i + start < self.pinned {
+                style = style.add_modifier(Modifier::ITALIC);
+            }
+
+            let gutter = match thumb {
+                None => " ",
+                Some((thumb_start, thumb_len)) if i >= thumb_start && i < thumb_start + thumb_len => "┃",
+                Some(_) => "│",
+            };
+            let mut spans = vec![
+                Span::styled(gutter, theme::muted()),
+            ];
+            match self.highlights.get(i + start) {
+                Some(positions) if !positions.is_empty() => {
+                    spans.extend(choice.chars().enumerate().map(|(j, c)| {
+                        if positions.contains(&j) {
+                            Span::styled(c.to_string(), style
+                                .add_modifier(Modifier::BOLD | Modifier::UNDERLINED))
+                        } else {
+                            Span::styled(c.to_string(), style)
+                        }
+                    }));
+                }
+                _ => spans.push(Span::styled(choice.to_string(), style))
+            };
+            rows.push(Spans::from(spans));
         }
         Paragraph::new(rows)
             .style(Style::default())
@@ -43,12 +94,46 @@ impl Select {
             )
     }
 
+    /// The scrollbar thumb's start and length, given
+    /// the first visible choice, if the choices don't fit.
+    fn thumb(&self, start: usize, height: usize) -> Option<(usize, usize)> {
+        let n_choices = self.choices.len();
+        if n_choices <= height {
+            return None;
+        }
+        let len = (height * height / n_choices).max(1);
+        Some(((start * height / n_choices).min(height - len), len))
+    }
+
+    /// Jump to the next choice starting with the typed prefix
+    /// (case-insensitive). If nothing matches, start a new
+    /// prefix from just this character.
+    fn jump_to_prefix(&mut self, c: char) {
+        let find = |prefix: &str| {
+            self.choices.iter()
+                .position(|choice| choice.to_lowercase().starts_with(prefix))
+        };
+        self.prefix.push(c.to_ascii_lowercase());
+        let found = find(&self.prefix).or_else(|| {
+            self.prefix = c.to_ascii_lowercase().to_string();
+            find(&self.prefix)
+        });
+        if let Some(idx) = found {
+            self.idx = idx;
+        }
+    }
+
     /// Process input and returns the selected index
     /// and if the widget should be closed.
     pub fn process_input(&mut self, key: KeyEvent) -> Result<(Option<usize>, bool)> {
         let n_choices = self.choices.len();
+        let page_size = self.page_size.get();
+        if !matches!(key.code, KeyCode::Char(_)) || n_choices == 0 {
+            self.prefix.clear();
+        }
         match key.code {
-            KeyCode::Char('j') => {
+            KeyCode::Char('j') if n_choices > 0 => {
+                self.prefix.clear();
                 if self.idx < self.choices.len() - 1 {
                     self.idx += 1;
                 } else {
@@ -57,7 +142,8 @@ impl Select {
                 }
                 Ok((None, false))
             }
-            KeyCode::Char('k') => {
+            KeyCode::Char('k') if n_choices > 0 => {
+                self.prefix.clear();
                 if self.idx > 0 {
                     self.idx -= 1;
                 } else {
@@ -66,13 +152,89 @@ impl Select {
                 }
                 Ok((None, false))
             }
-            KeyCode::Enter => {
+            KeyCode::PageDown if n_choices > 0 => {
+                self.idx = (self.idx + page_size).min(n_choices - 1);
+                Ok((None, false))
+            }
+            KeyCode::PageUp => {
+                self.idx = self.idx.saturating_sub(page_size);
+                Ok((None, false))
+            }
+            KeyCode::Home => {
+                self.idx = 0;
+                Ok((None, false))
+            }
+            KeyCode::End => {
+                self.idx = n_choices.saturating_sub(1);
+                Ok((None, false))
+            }
+            KeyCode::Char(c) if !c.is_whitespace() => {
+                self.jump_to_prefix(c);
+                Ok((None, false))
+            }
+            KeyCode::Enter if n_choices > 0 => {
                 Ok((Some(self.idx), true))
             }
-            KeyCode::Esc => {
+            KeyCode::Enter | KeyCode::Esc => {
                 Ok((None, true))
             }
             _ => Ok((None, false))
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crossterm::event::KeyModifiers;
+
+    fn press(select: &mut Select, code: KeyCode) -> (Option<usize>, bool) {
+        select.process_input(KeyEvent { code, modifiers: KeyModifiers::NONE }).unwrap()
+    }
+
+    #[test]
+    fn test_scrollbar() {
+        // Fewer choices than rows have no scrollbar
+        let choices: Vec<String> = ["1/4", "1/8", "1/16", "1/32"].iter().map(|c| c.to_string()).collect();
+        let select = Select::new(choices);
+        select.render(10);
+        assert_eq!(select.thumb(0, 10), None);
+
+        // More choices get a thumb sized to the
+        // visible share, kept within the rows
+        let select = Select::new((0..40).map(|i| i.to_string()).collect());
+        select.render(10);
+        assert_eq!(select.thumb(0, 10), Some((0, 2)));
+        assert_eq!(select.thumb(30, 10), Some((7, 2)));
+    }
+
+    #[test]
+    fn test_paging() {
+        let mut select = Select::new((0..40).map(|i| i.to_string()).collect());
+        select.render(10);
+        press(&mut select, KeyCode::PageDown);
+        assert_eq!(select.idx, 10);
+        press(&mut select, KeyCode::End);
+        press(&mut select, KeyCode::PageDown);
+        assert_eq!(select.idx, 39);
+        press(&mut select, KeyCode::PageUp);
+        assert_eq!(select.idx, 29);
+        press(&mut select, KeyCode::Home);
+        assert_eq!(select.idx, 0);
+    }
+
+    #[test]
+    fn test_prefix() {
+        let choices: Vec<String> = ["Dorian", "Lydian", "Locrian", "Minor"].iter().map(|c| c.to_string()).collect();
+        let mut select = Select::new(choices);
+        press(&mut select, KeyCode::Char('l'));
+        assert_eq!(select.idx, 1);
+        press(&mut select, KeyCode::Char('o'));
+        assert_eq!(select.idx, 2);
+
+        // Nothing matching starts over from this character
+        press(&mut select, KeyCode::Char('m'));
+        assert_eq!(select.idx, 3);
+        assert_eq!(press(&mut select, KeyCode::Enter), (Some(3), true));
+    }
+}
@@ -0,0 +1,86 @@
+use tui::{
+    layout::Alignment,
+    style::{Style, Modifier},
+    text::{Span, Spans},
+    widgets::{Block, Paragraph, Borders},
+};
+use super::Sequencer;
+use crate::app::{theme, symbols};
+use crate::core::{Chord, ChordSpec};
+
+// Each chord's column is this wide
+const COLUMN: usize = 12;
+
+/// The marked chord and the chord at the cursor side by side,
+/// with the notes they share highlighted, if both steps have one.
+pub fn render<'a>(seq: &Sequencer) -> Option<(Paragraph<'a>, u16)> {
+    let marked = seq.compare?;
+    let sel_idx = seq.selected_idx();
+    let state = seq.state.lock().unwrap();
+    let sequence = &state.progression().sequence;
+    let a = sequence.get(marked)?.as_ref()?;
+    let b = sequence.get(sel_idx)?.as_ref()?;
+    let chord = |idx: usize, cs: &ChordSpec| -> Chord {
+        cs.chord_for_key(&state.progression().key_at(idx, &state.key))
+    };
+    let name = |idx: usize, cs: &ChordSpec| -> String {
+        symbols::chord_in_key(cs, &state.progression().key_at(idx, &state.key))
+    };
+    let (chord_a, chord_b) = (chord(marked, a), chord(sel_idx, b));
+
+    let pitches = |chord: &Chord| -> Vec<isize> {
+        chord.notes().iter().map(|n| n.semitones.rem_euclid(12)).collect()
+    };
+    let (pitches_a, pitches_b) = (pitches(&chord_a), pitches(&chord_b));
+
+    let mut lines = vec![
+        Spans::from(vec![
+            Span::styled(format!("{:^w$}", name(marked, a), w = COLUMN), Style::default().add_modifier(Modifier::BOLD)),
+            Span::styled(format!("{:^w$}", name(sel_idx, b), w = COLUMN), Style::default().add_modifier(Modifier::BOLD)),
+        ]),
+    ];
+
+    // Notes of both chords, highlighting
+    // the ones the other chord has too
+    let notes_a = chord_a.describe_notes(symbols::accidentals());
+    let notes_b = chord_b.describe_notes(symbols::accidentals());
+    let note_span = |notes: &Vec<String>, pitches: &Vec<isize>, other: &Vec<isize>, i: usize| {
+        match notes.get(i) {
+            Some(note) => {
+                let style = if other.contains(&pitches[i]) {
+                    theme::playing()
+                } else {
+                    Style::default()
+                };
+                Span::styled(format!("{:^w$}", symbols::note(note), w = COLUMN), style)
+            }
+            None => Span::raw(" ".repeat(COLUMN)),
+        }
+    };
+    for i in 0..notes_a.len().max(notes_b.len()) {
+        lines.push(Spans::from(vec![
+            note_span(&notes_a, &pitches_a, &pitches_b, i),
+            note_span(&notes_b, &pitches_b, &pitches_a, i),
+        ]));
+    }
+
+    let mut shared = pitches_a.clone();
+    shared.sort();
+    shared.dedup();
+    shared.retain(|p| pitches_b.contains(p));
+    lines.push(Spans::from(Span::styled(
+        format!("shared:{} distance:{}", shared.len(), a.distance(b)),
+        theme::muted())));
+
+    // Lines plus the block's border
+    let height = lines.len() as u16 + 1;
+    let paragraph = Paragraph::new(lines)
+        .alignment(Alignment::Center)
+        .block(
+            Block::default()
+                .title("Compare")
+                .borders(Borders::TOP)
+                .style(Style::default())
+        );
+    Some((paragraph, height))
+}
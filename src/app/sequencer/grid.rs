@@ -1,18 +1,51 @@
 use anyhow::Result;
 use tui::{
     layout::Alignment,
-    style::{Style, Color},
+    style::{Style, Modifier},
     text::{Span, Spans},
     widgets::{Block, Paragraph, Borders},
 };
 use crossterm::event::{KeyEvent, KeyCode};
-use super::{Sequencer, InputMode, ChordSelect, ChordTarget};
+use super::{Sequencer, InputMode, ChordTarget, TextTarget};
+use crate::app::theme;
+use crate::app::chord_select::ChordSelect;
+use crate::app::text_input::TextInput;
+use crate::core::{Key, ChordSpec};
+
+// Most times a step can trigger its chord
+const MAX_RATCHETS: usize = 4;
+
+// Highest note that can be played,
+// in semitones from A0
+const MAX_NOTE: isize = 127 - 21;
+
+/// The next voicing of a chord up or down, through
+/// its inversions and on into the next octave.
+fn next_voicing(cs: &ChordSpec, key: &Key, up: bool) -> Option<ChordSpec> {
+    let notes = |cs: &ChordSpec| -> Vec<isize> {
+        let mut notes: Vec<isize> = cs.chord_for_key(key).notes().iter()
+            .map(|n| n.semitones).collect();
+        notes.sort();
+        notes
+    };
+    let current = notes(cs);
+    let mut voicings: Vec<(Vec<isize>, ChordSpec)> = (-1..=1)
+        .flat_map(|shift| cs.clone().shift(shift).inversions())
+        .map(|v| (notes(&v), v))
+        .filter(|(notes, _)| notes.iter().all(|n| (0..=MAX_NOTE).contains(n)))
+        .collect();
+    voicings.sort_by(|(a, _), (b, _)| a.cmp(b));
+    if up {
+        voicings.into_iter().find(|(notes, _)| *notes > current)
+    } else {
+        voicings.into_iter().rev().find(|(notes, _)| *notes < current)
+    }.map(|(_, v)| v)
+}
 
 pub fn render<'a>(seq: &Sequencer) -> Paragraph<'a> {
     let state = seq.state.lock().unwrap();
-    let progression = &state.progression.sequence;
     let bars = state.bars;
-    let ticks_per_bar = state.progression.resolution.ticks_per_bar();
+    let ticks_per_bar = state.progression().resolution.ticks_per_bar();
     let cur_idx = state.clip_start() + state.tick;
 
     // The lines that will be rendered.
@@ -27,12 +60,10 @@ pub fn render<'a>(seq: &Sequencer) -> Paragraph<'a> {
             bars.push(Span::raw("|"));
 
             // What character is showing under the cursor
-            let tick_char = if progression[idx].is_some() {
-                let chord_idx = state.progression.seq_idx_to_chord_idx(idx) + 1;
-                chord_idx.to_string()
-            } else if is_selected {
-                "*".to_string()
-            } else if idx == cur_idx {
+            let chord_idx = state.progression().iter_timed().position(|(i, _)| i == idx);
+            let tick_char = if let Some(chord_idx) = chord_idx {
+                (chord_idx + 1).to_string()
+            } else if is_selected || idx == cur_idx {
                 "*".to_string()
             } else {
                 " ".to_string()
@@ -40,10 +71,13 @@ pub fn render<'a>(seq: &Sequencer) -> Paragraph<'a> {
 
             // How the cursor position should be styled
             let mut style = Style::default();
+            if state.progression().steps.get(&idx).is_some_and(|s| s.key.is_some()) {
+                style = style.patch(theme::overridden());
+            }
             if is_selected {
-                style = style.fg(Color::LightBlue);
+                style = style.patch(theme::selected());
             } else if idx == cur_idx {
-                style = style.fg(Color::Yellow);
+                style = style.patch(theme::playing());
             };
 
             // Highlight loop
@@ -51,14 +85,14 @@ pub fn render<'a>(seq: &Sequencer) -> Paragraph<'a> {
             let b = b - 1;
             let is_loop = state.has_loop();
             if is_loop && a == idx {
-                style = style.bg(Color::DarkGray);
+                style = style.patch(theme::looped());
             }
             if is_loop && b == idx {
-                style = style.bg(Color::DarkGray);
+                style = style.patch(theme::looped());
             }
             if is_loop {
                 if a <= idx && idx <= b {
-                    style = style.bg(Color::DarkGray);
+                    style = style.patch(theme::looped());
                 }
             }
 
@@ -69,11 +103,31 @@ pub fn render<'a>(seq: &Sequencer) -> Paragraph<'a> {
         lines.push(Spans::from(bars));
     }
 
+    // The tracks, with the one being edited picked
+    // out and those that can't be heard struck through
+    let mut title = vec![Span::raw("Sequencer:")];
+    for (idx, track) in state.tracks.iter().enumerate() {
+        let audible = state.audible(idx);
+        let mut style = if idx == state.track {
+            theme::selected()
+        } else if !audible {
+            theme::muted()
+        } else {
+            Style::default()
+        };
+        if !audible {
+            style = style.add_modifier(Modifier::CROSSED_OUT);
+        }
+        let solo = if track.solo { "*" } else { "" };
+        title.push(Span::raw(" "));
+        title.push(Span::styled(format!("{}{}", track.name, solo), style));
+    }
+
     Paragraph::new(lines)
         .alignment(Alignment::Center)
         .block(
             Block::default()
-                .title("Sequencer")
+                .title(Spans::from(title))
                 .borders(Borders::TOP)
                 .style(Style::default())
         )
@@ -82,15 +136,16 @@ pub fn render<'a>(seq: &Sequencer) -> Paragraph<'a> {
 pub fn process_input(seq: &mut Sequencer, key: KeyEvent) -> Result<()> {
     let sel_idx = seq.selected_idx();
     let mut state = seq.state.lock().unwrap();
-    let sel_item = &state.progression.sequence[sel_idx];
-    let bars = state.progression.bars();
-    let ticks_per_bar = state.progression.resolution.ticks_per_bar();
+    let sel_item = &state.progression().sequence[sel_idx];
+    let bars = state.progression().bars();
+    let ticks_per_bar = state.progression().resolution.ticks_per_bar();
 
     match key.code {
         // Set the start of the loop
         KeyCode::Char('A') => {
             if state.clip.0 != sel_idx && sel_idx < state.clip.1 {
-                state.clip.0 = sel_idx;
+                let end = state.clip.1;
+                state.set_clip((sel_idx, end));
             }
         }
 
@@ -98,15 +153,79 @@ pub fn process_input(seq: &mut Sequencer, key: KeyEvent) -> Result<()> {
         KeyCode::Char('B') => {
             let idx = sel_idx + 1;
             if state.clip.1 != idx && sel_idx > state.clip.0 {
-                state.clip.1 = idx;
+                let start = state.clip.0;
+                state.set_clip((start, idx));
             }
         }
 
+        // Set how many steps the loop is,
+        // from its start
+        KeyCode::Char('n') => {
+            seq.log.clear();
+            let mut text_input = TextInput::with_history("Loop length (steps): ", |c: char| c.is_ascii_digit(), &seq.history, TextTarget::LoopLength);
+            text_input.set_input(state.clip_len().to_string());
+            seq.input_mode = InputMode::Text(text_input, TextTarget::LoopLength);
+        }
+
         // Clear the loop
         KeyCode::Char('C') => {
             state.reset_clip();
         }
 
+        // Copy the current bar to the next,
+        // adding a bar if it's the last
+        KeyCode::Char('p') => {
+            let looping = state.has_loop();
+            let bar = seq.grid_pos.1;
+            state.progression_mut().duplicate_bar(bar);
+            state.bars = state.progression().bars();
+            if !looping {
+                state.reset_clip();
+            }
+            seq.grid_pos.1 = bar + 1;
+        }
+
+        // Copy the chord at the cursor to the next step,
+        // or the same step of the next bar, following it
+        // so that pressing again carries on the pattern
+        KeyCode::Char('.') | KeyCode::Char(';') => {
+            if sel_item.is_some() {
+                let next_bar = key.code == KeyCode::Char(';');
+                let to = if next_bar {
+                    sel_idx + ticks_per_bar
+                } else {
+                    sel_idx + 1
+                };
+                if to < state.progression().sequence.len() {
+                    state.progression_mut().duplicate_step(sel_idx, to);
+                    seq.grid_pos = (to % ticks_per_bar, to / ticks_per_bar);
+                } else if next_bar {
+                    seq.log.push("No bar after this one");
+                } else {
+                    seq.log.push("No step after this one");
+                }
+            }
+        }
+
+        // Double or halve the length of the progression
+        KeyCode::Char('*') | KeyCode::Char('/') => {
+            state.stretch(key.code == KeyCode::Char('*'));
+        }
+
+        // Insert an empty bar at the cursor
+        KeyCode::Char('+') => {
+            state.insert_bar(seq.grid_pos.1);
+        }
+
+        // Remove the current bar
+        KeyCode::Char('-') => {
+            if bars > 1 {
+                state.delete_bar(seq.grid_pos.1);
+            } else {
+                seq.log.push("Can't remove the only bar");
+            }
+        }
+
         // hjkl navigation
         KeyCode::Char('l') => {
             let (x, _) = seq.grid_pos;
@@ -141,37 +260,118 @@ pub fn process_input(seq: &mut Sequencer, key: KeyEvent) -> Result<()> {
             };
         }
 
+        // Move the chord at the cursor up or
+        // down through its inversions and octaves
+        KeyCode::Char('i') | KeyCode::Char('I') => {
+            if let Some(cs) = sel_item {
+                let up = key.code == KeyCode::Char('i');
+                let key = state.progression().key_at(sel_idx, &state.key);
+                match next_voicing(cs, &key, up) {
+                    Some(voicing) => {
+                        let track = state.current_track();
+                        seq.midi.lock().unwrap().play_chord(&voicing.chord_for_key(&key), 1, track.channel, track.port.as_deref());
+                        state.progression_mut().sequence[sel_idx] = Some(voicing);
+                    }
+                    None => seq.log.push("No more voicings that way"),
+                }
+            }
+        }
+
+        // Toggle a 7th, 9th or sus on the chord at the cursor
+        KeyCode::Char('&') | KeyCode::Char('(') | KeyCode::Char('^') => {
+            if let Some(cs) = sel_item {
+                let code = key.code;
+                let key = state.progression().key_at(sel_idx, &state.key);
+                let cs = match code {
+                    KeyCode::Char('&') => cs.clone().toggle_extension(7, &key),
+                    KeyCode::Char('(') => cs.clone().toggle_extension(9, &key),
+                    _ => cs.clone().toggle_sus(),
+                };
+                let track = state.current_track();
+                seq.midi.lock().unwrap().play_chord(&cs.chord_for_key(&key), 1, track.channel, track.port.as_deref());
+                state.progression_mut().sequence[sel_idx] = Some(cs);
+            }
+        }
+
         // Edit or add chord at cursor
         KeyCode::Char('e') => {
-            let select = if let Some(cs) = sel_item {
-                ChordSelect::with_chord(cs)
-            } else {
-                ChordSelect::default()
-            };
-            seq.message = "";
+            let select = ChordSelect::new(sel_item.as_ref(), seq.template.chord_types.clone(), &seq.recent, seq.audition);
+            seq.log.clear();
             seq.input_mode = InputMode::Chord(
                 select,
                 ChordTarget::Chord);
         }
 
+        // Set the step's own key,
+        // or clear it if it has one
+        KeyCode::Char('K') => {
+            let mut step = state.progression().step(sel_idx);
+            if step.key.is_some() {
+                step.key = None;
+                state.progression_mut().set_step(sel_idx, step);
+            } else if sel_item.is_some() {
+                seq.log.clear();
+                let text_input = TextInput::with_history("Step key: ", |_c: char| true, &seq.history, TextTarget::StepKey);
+                seq.input_mode = InputMode::Text(text_input, TextTarget::StepKey);
+            }
+        }
+
+        // Set how long the step's chord is held for
+        KeyCode::Char('G') => {
+            if sel_item.is_some() {
+                seq.log.clear();
+                let mut text_input = TextInput::with_history("Gate (%, steps or legato): ", |_c: char| true, &seq.history, TextTarget::Gate);
+                text_input.set_input(state.progression().step(sel_idx).gate.to_string());
+                seq.input_mode = InputMode::Text(text_input, TextTarget::Gate);
+            }
+        }
+
+        // Set the chance of the step's chord playing
+        KeyCode::Char('%') => {
+            if sel_item.is_some() {
+                seq.log.clear();
+                let mut text_input = TextInput::with_history("Chance (%): ", |c: char| c.is_ascii_digit() || c == '%', &seq.history, TextTarget::Chance);
+                text_input.set_input(state.progression().step(sel_idx).chance.to_string());
+                seq.input_mode = InputMode::Text(text_input, TextTarget::Chance);
+            }
+        }
+
+        // Retrigger the step's chord within the step,
+        // cycling through up to 4 times
+        KeyCode::Char('t') => {
+            if sel_item.is_some() {
+                let mut step = state.progression().step(sel_idx);
+                step.ratchets = step.ratchets % MAX_RATCHETS + 1;
+                state.progression_mut().set_step(sel_idx, step);
+            }
+        }
+
+        // Make each retrigger quieter than the last
+        KeyCode::Char('y') => {
+            let mut step = state.progression().step(sel_idx);
+            if step.ratchets > 1 {
+                step.decay = !step.decay;
+                state.progression_mut().set_step(sel_idx, step);
+            }
+        }
+
         // Delete chord under cursor
         KeyCode::Char('d') => {
             match sel_item {
                 None => {},
                 Some(_) => {
-                    state.progression.delete_chord_at(sel_idx);
+                    state.progression_mut().delete_chord_at(sel_idx);
                 }
             }
         }
 
         // Select a progression chord by number
         KeyCode::Char(c) => {
-            if c.is_numeric() {
-                let idx = c.to_string().parse::<usize>()? - 1;
-                if let Some(_) = state.progression.chord(idx) {
-                    let seq_idx = state.progression.chord_index[idx];
-
-                    let res = state.progression.resolution.ticks_per_bar();
+            // Chords are numbered from 1
+            let idx = c.to_digit(10).and_then(|d| (d as usize).checked_sub(1));
+            if let Some(idx) = idx {
+                if let Some((seq_idx, _)) = state.progression().iter_timed().nth(idx) {
+                    let res = state.progression().resolution.ticks_per_bar();
                     let i = seq_idx/res;
                     let j = seq_idx.rem_euclid(res);
                     seq.grid_pos = (j, i);
@@ -187,16 +387,35 @@ pub fn process_input(seq: &mut Sequencer, key: KeyEvent) -> Result<()> {
 pub fn controls<'a>(seq: &Sequencer) -> Vec<Span<'a>> {
     let sel_idx = seq.selected_idx();
     let state = seq.state.lock().unwrap();
-    let sel_item = &state.progression.sequence[sel_idx];
+    let sel_item = &state.progression().sequence[sel_idx];
 
     let mut controls = vec![
         Span::raw(" [e]dit"),
     ];
     if sel_item.is_some() {
-        controls.push(Span::raw(" [d]elete"));
+        controls.push(Span::raw(" [d]elete [i]nversion up [I] down toggle:[&]7 [(]9 [^]sus copy to next:[.]step [;]bar"));
+        let step = state.progression().step(sel_idx);
+        if step.key.is_some() {
+            controls.push(Span::raw(" clear [K]ey"));
+        } else {
+            controls.push(Span::raw(" [K]ey"));
+        }
+        controls.push(Span::raw(format!(" [G]ate:{}", step.gate)));
+        controls.push(Span::raw(format!(" [%]chance:{}%", step.chance)));
+        controls.push(Span::raw(format!(" ra[t]chet:{}", step.ratchets)));
+        controls.push(Span::raw(format!(" [Alt-a]rp:{}", step.arp.map_or("off".to_string(), |arp| arp.to_string()))));
+        if step.nudge > 0 || step.velocity < 100 {
+            controls.push(Span::raw(format!(" late:{}% vel:{}%", step.nudge, step.velocity)));
+        }
+        if step.ratchets > 1 {
+            let decay = if step.decay { "on" } else { "off" };
+            controls.push(Span::raw(format!(" deca[y]:{}", decay)));
+        }
     }
 
-    controls.push(Span::raw(" loop:[A]-[B]"));
+    controls.push(Span::raw(" track:[Tab]next [~]add [`]remove [!]mute [?]solo"));
+    controls.push(Span::raw(" bar:du[p]licate [+]insert [-]delete time:[*]2 [/]2"));
+    controls.push(Span::raw(format!(" loop:[A]-[B] le[n]gth:{}", state.clip_len())));
     if state.has_loop() {
         controls.push(Span::raw(" [C]lear"));
     }
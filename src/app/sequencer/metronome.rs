@@ -0,0 +1,82 @@
+use serde::{Serialize, Deserialize};
+use crate::audio::Beat;
+
+// 24 clock ticks per quarter note,
+// and four quarter notes to a bar
+pub const TICKS_PER_BEAT: usize = 24;
+pub const TICKS_PER_BAR: usize = 4 * TICKS_PER_BEAT;
+
+/// How the metronome clicks.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Metronome {
+    // Turned on and off while playing,
+    // so it isn't saved
+    #[serde(skip)]
+    pub on: bool,
+
+    // Accent the first beat of each bar
+    pub accent: bool,
+
+    // Also click on each step between beats
+    pub subdivide: bool,
+
+    // Bars to click before playback starts
+    pub count_off: usize,
+
+    // MIDI channel for clicks on a MIDI output port, 1-16
+    pub channel: u8,
+}
+
+impl Default for Metronome {
+    fn default() -> Self {
+        Metronome {
+            on: false,
+            accent: true,
+            subdivide: false,
+            count_off: 0,
+            channel: 10,
+        }
+    }
+}
+
+impl Metronome {
+    /// The click for a clock tick, if any,
+    /// with steps `step_ticks` long.
+    /// Counting off clicks even if the metronome is off.
+    pub fn beat(&self, tick: usize, step_ticks: usize, counting: bool) -> Option<Beat> {
+        if !self.on && !counting {
+            None
+        } else if tick % TICKS_PER_BAR == 0 && self.accent {
+            Some(Beat::Accent)
+        } else if tick % TICKS_PER_BEAT == 0 {
+            Some(Beat::Beat)
+        } else if self.subdivide && tick % step_ticks.max(1) == 0 {
+            Some(Beat::Subdivision)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_beat() {
+        let mut metronome = Metronome::default();
+        assert_eq!(metronome.beat(0, 12, false), None);
+        assert_eq!(metronome.beat(0, 12, true), Some(Beat::Accent));
+
+        metronome.on = true;
+        assert_eq!(metronome.beat(24, 12, false), Some(Beat::Beat));
+        assert_eq!(metronome.beat(12, 12, false), None);
+
+        metronome.subdivide = true;
+        metronome.accent = false;
+        assert_eq!(metronome.beat(0, 12, false), Some(Beat::Beat));
+        assert_eq!(metronome.beat(12, 12, false), Some(Beat::Subdivision));
+        assert_eq!(metronome.beat(6, 12, false), None);
+    }
+}
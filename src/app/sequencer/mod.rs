@@ -1,30 +1,41 @@
 mod grid;
 mod state;
 mod progression;
+mod metronome;
+mod compare;
+mod transport;
 
 use anyhow::Result;
-use std::sync::{Arc, Mutex};
-use crate::core::{Duration, Mode};
-use crate::file::save_to_midi_file;
-use crate::app::text_input::TextInput;
-use crate::app::chord_select::ChordSelect;
+use std::sync::{Arc, Mutex, atomic::{AtomicUsize, Ordering}};
+use std::collections::HashMap;
+use std::time::Duration as Time;
+use crate::core::{Key, Duration, Chord, ChordSpec};
+use crate::file::{save_tracks_to_midi_file, load_from_midi_file, ExportOptions, TempoChange, EXPORT_TICKS_PER_BEAT};
+use crate::app::text_input::{TextInput, Prompt, Validator, add_to_history, valid_note, valid_key, valid_gate, valid_percent, valid_number, valid_channel, parse_channel, valid_arp, parse_arp};
+use crate::app::chord_select::{ChordSelect, RecentChords};
+use crate::app::log::MessageLog;
 use crate::app::select::Select;
-use crate::progression::ProgressionTemplate;
-use crate::midi::{MIDIOutput, MIDIClock, MIDIError, ClockEvent};
+use crate::app::key_picker::KeyPicker;
+use crate::app::{theme, symbols};
+use crate::progression::{Progression, ProgressionTemplate, Cadence, Accent, Gate, Hit, BassPattern, MAX_SWING};
+use crate::midi::{MIDIOutput, MIDIClock, MIDIError, ClockEvent, ClockSource, Arp};
+use crate::audio::Beat;
 use tui::{
     text::Span,
     widgets::Paragraph,
-    style::{Style, Modifier, Color},
     layout::{Rect, Alignment, Constraint, Direction, Layout},
 };
-use crossterm::event::{KeyEvent, KeyCode};
-use state::PlaybackState;
+use crossterm::event::{KeyEvent, KeyCode, KeyModifiers};
+pub use state::{PlaybackState, Section, Track, DEFAULT_TRACK};
+pub use metronome::Metronome;
+use metronome::TICKS_PER_BAR;
 
 enum InputMode<'a> {
     Normal,
     Select(Select, SelectTarget),
     Text(TextInput<'a>, TextTarget),
     Chord(ChordSelect<'a>, ChordTarget),
+    Key(KeyPicker),
 }
 
 enum ChordTarget {
@@ -32,24 +43,149 @@ enum ChordTarget {
     Chord
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum TextTarget {
     Root,
     Bars,
-    Duration,
     Export,
+    StepKey,
+    Gate,
+    Chance,
+    BakedExport,
+    Euclid,
+    LoopLength,
+    Swing,
+    Accent,
+    Amount,
+    Truncate,
+    Humanize,
+    Channel,
+    NewTrack,
+    RemoveTrack,
+    Arp,
+    Import,
+    ImportKey,
 }
 
+impl Prompt for TextTarget {
+    fn validator(self) -> Option<Validator> {
+        match self {
+            TextTarget::Root => Some(valid_note),
+            TextTarget::StepKey => Some(valid_key),
+            TextTarget::Gate => Some(valid_gate),
+            TextTarget::Chance => Some(valid_percent),
+            TextTarget::Euclid => Some(valid_hits),
+            TextTarget::Bars => Some(valid_bars),
+            TextTarget::LoopLength => Some(valid_number),
+            TextTarget::Swing => Some(valid_swing),
+            TextTarget::Accent => Some(valid_accent),
+            TextTarget::Amount | TextTarget::Humanize => Some(valid_percent),
+            TextTarget::Channel => Some(valid_channel),
+            TextTarget::Arp => Some(valid_arp),
+            TextTarget::ImportKey => Some(valid_import_key),
+            TextTarget::Import | TextTarget::Export | TextTarget::BakedExport | TextTarget::Truncate
+                | TextTarget::NewTrack | TextTarget::RemoveTrack => None,
+        }
+    }
+}
+
+/// Parse how many Euclidean hits to place,
+/// per bar, e.g. "3", or across the loop, e.g. "5/loop".
+fn parse_hits(input: &str) -> Option<(usize, bool)> {
+    match input.strip_suffix("/loop") {
+        Some(hits) => hits.parse().ok().map(|hits| (hits, false)),
+        None => input.parse().ok().map(|hits| (hits, true)),
+    }
+}
+
+/// Parse a number of bars, e.g. "4", and whether
+/// to generate chords for new bars, e.g. "4/fill".
+fn parse_bars(input: &str) -> Option<(usize, bool)> {
+    let (bars, fill) = match input.strip_suffix("/fill") {
+        Some(bars) => (bars, true),
+        None => (input, false),
+    };
+    bars.parse().ok().filter(|bars| *bars > 0).map(|bars| (bars, fill))
+}
+
+fn valid_bars(input: &str) -> Result<(), String> {
+    parse_bars(input).map(|_| ())
+        .ok_or_else(|| "Expected a number of bars, e.g. 4, or 4/fill to fill new bars".to_string())
+}
+
+fn valid_swing(input: &str) -> Result<(), String> {
+    match input.trim_end_matches('%').parse::<usize>() {
+        Ok(swing) if (50..=MAX_SWING).contains(&swing) => Ok(()),
+        _ => Err(format!("Must be 50-{}%", MAX_SWING)),
+    }
+}
+
+fn valid_accent(input: &str) -> Result<(), String> {
+    input.parse::<Accent>().map(|_| ()).map_err(|err| err.to_string())
+}
+
+/// Check that the input is a key, or `auto` to detect it.
+fn valid_import_key(input: &str) -> Result<(), String> {
+    if input == "auto" {
+        Ok(())
+    } else {
+        valid_key(input)
+    }
+}
+
+fn valid_hits(input: &str) -> Result<(), String> {
+    parse_hits(input).map(|_| ())
+        .ok_or_else(|| "Expected a number of hits, e.g. 3 per bar or 5/loop".to_string())
+}
+
+/// Play a chord's hits on a track's channel and port,
+/// arpeggiated if it should be, given how long
+/// the track's steps and a beat last.
+fn play_hits(midi: &mut MIDIOutput, chord: &Chord, hits: Vec<Hit>, arp: Option<Arp>, step: Time, beat: Time, track: &Track) {
+    for hit in hits {
+        let (delay, length) = (step.mul_f64(hit.offset), step.mul_f64(hit.length));
+        match arp {
+            None => midi.play_chord_later(chord, delay, length, hit.level, track.channel, track.port.as_deref()),
+            Some(arp) => {
+                for (note, start, length) in arp.notes(chord, beat, length) {
+                    midi.play_chord_later(&note, delay + start, length, hit.level, track.channel, track.port.as_deref());
+                }
+            }
+        }
+    }
+}
+
+// How many changes can be undone
+const MAX_UNDO: usize = 100;
+
+// Names offered for new tracks, in order
+const TRACK_NAMES: [&str; 4] = ["bass", "pad", "lead", "stabs"];
+
 enum SelectTarget {
     Resolution,
+
+    // The connected ports, after playing on all of them
+    Port(Vec<String>),
+
+    // Chords suggested for the selected step
+    Suggestion(Vec<ChordSpec>),
 }
 
+// Most chords to suggest, so that
+// each has its own number key
+const MAX_SUGGESTIONS: usize = 9;
+
 pub struct Sequencer<'a> {
     midi: Arc<Mutex<MIDIOutput>>,
+    recent: Arc<Mutex<RecentChords>>,
 
     clock: MIDIClock,
+    clock_source: ClockSource,
     state: Arc<Mutex<PlaybackState>>,
 
     save_dir: String,
+    export_options: ExportOptions,
+    tempo: usize,
     input_mode: InputMode<'a>,
 
     template: ProgressionTemplate,
@@ -57,35 +193,217 @@ pub struct Sequencer<'a> {
     grid_pos: (usize, usize),
     ticks_per_bar: usize,
 
-    // Last status message
-    message: &'a str,
+    // Play chords while browsing them
+    audition: bool,
+
+    // Pick the root around the circle
+    // of fifths rather than typing it
+    circle_of_fifths: bool,
+
+    // Previous inputs for each prompt
+    history: HashMap<TextTarget, Vec<String>>,
+
+    // Progressions before and after changes,
+    // most recent last
+    undo: Vec<Progression>,
+    redo: Vec<Progression>,
+
+    // How much the randomize commands
+    // change, as a percentage
+    amount: usize,
+
+    // Bars to cut the progression down to,
+    // once it's confirmed
+    truncate_to: Option<usize>,
+
+    // MIDI file to import,
+    // once its key is given
+    import_from: Option<String>,
+
+    // Step marked to compare
+    // against the selected one
+    compare: Option<usize>,
+
+    // Status messages
+    log: MessageLog,
 }
 
 
 impl<'a> Sequencer<'a> {
-    pub fn new(midi: Arc<Mutex<MIDIOutput>>, template: ProgressionTemplate, save_dir: String) -> Sequencer<'a> {
+    pub fn new(midi: Arc<Mutex<MIDIOutput>>, recent: Arc<Mutex<RecentChords>>, log: MessageLog, template: ProgressionTemplate, save_dir: String) -> Sequencer<'a> {
         let state = PlaybackState::new(&template);
         let ticks_per_bar = state.resolution.ticks_per_bar();
 
         Sequencer {
             midi,
+            recent,
             state: Arc::new(Mutex::new(state)),
             clock: MIDIClock::default(),
+            clock_source: ClockSource::default(),
 
             save_dir,
-            message: "",
+            export_options: ExportOptions::default(),
+            tempo: 120,
+            log,
             input_mode: InputMode::Normal,
 
             template,
             grid_pos: (0, 0),
             ticks_per_bar,
+            audition: true,
+            circle_of_fifths: true,
+            history: HashMap::default(),
+            undo: vec![],
+            redo: vec![],
+            amount: 50,
+            truncate_to: None,
+            import_from: None,
+            compare: None,
         }
     }
 
+    /// Sync playback to a MIDI input port's clock.
     pub fn connect_port(&mut self, idx: usize) -> Result<(), MIDIError> {
+        let handler = self.clock_handler(None, false);
+        self.clock_source = ClockSource::External;
+        self.clock.connect_port(idx, handler)
+    }
+
+    /// Set how much to smooth the
+    /// external clock, as a percentage.
+    pub fn set_clock_smoothing(&mut self, smoothing: usize) {
+        self.clock.set_smoothing(smoothing);
+    }
+
+    /// Sync playback to the internal clock.
+    pub fn use_internal_clock(&mut self) {
+        let handler = self.clock_handler(Some(self.clock.tempo()), true);
+        self.clock_source = ClockSource::Internal;
+        self.clock.start_internal(self.tempo, handler);
+    }
+
+    /// Sync playback to the JACK transport.
+    #[cfg(feature = "jack")]
+    pub fn use_jack_clock(&mut self) -> Result<(), MIDIError> {
+        let handler = self.clock_handler(None, true);
+        self.clock_source = ClockSource::Jack;
+        self.clock.start_jack(handler)
+    }
+
+    pub fn set_tempo(&mut self, bpm: usize) {
+        self.tempo = bpm;
+        self.state.lock().unwrap().tempo = bpm;
+        self.clock.set_tempo(bpm);
+    }
+
+    pub fn set_save_dir(&mut self, save_dir: String) {
+        self.save_dir = save_dir;
+    }
+
+    /// Set how exported files are written,
+    /// e.g. the MIDI channel they're on.
+    pub fn set_export_options(&mut self, options: ExportOptions) {
+        self.export_options = options;
+    }
+
+    pub fn set_bass_channel(&mut self, channel: u8) {
+        self.state.lock().unwrap().bass_channel = channel;
+    }
+
+    pub fn set_circle_of_fifths(&mut self, circle_of_fifths: bool) {
+        self.circle_of_fifths = circle_of_fifths;
+    }
+
+    pub fn set_swing(&mut self, swing: usize) {
+        self.state.lock().unwrap().swing = swing.clamp(50, MAX_SWING);
+    }
+
+    pub fn set_accent(&mut self, accent: Accent) {
+        self.state.lock().unwrap().accent = accent;
+    }
+
+    /// Loop a range of steps, if they're
+    /// within the progression.
+    pub fn set_clip(&mut self, clip: (usize, usize)) {
+        let mut s = self.state.lock().unwrap();
+        if clip.0 < s.progression().sequence.len() {
+            s.set_clip(clip);
+        }
+    }
+
+    /// Replace the tracks, editing the first.
+    pub fn set_tracks(&mut self, tracks: Vec<Track>) {
+        self.state.lock().unwrap().set_tracks(tracks);
+        self.undo.clear();
+        self.redo.clear();
+        self.sync_grid();
+    }
+
+    /// Set the key, bars and resolution,
+    /// generating a new progression for them.
+    pub fn set_params(&mut self, key: Key, bars: usize, resolution: Duration) -> Result<()> {
+        {
+            let mut s = self.state.lock().unwrap();
+            s.key = key;
+            s.bars = bars.max(1);
+            s.resolution = resolution;
+            s.gen_progression(&self.template)?;
+        }
+        self.sync_grid();
+        Ok(())
+    }
+
+    /// Play a progression in a key, e.g.
+    /// to pick up from a previous session.
+    pub fn set_progression(&mut self, key: Key, progression: Progression) {
+        {
+            let mut s = self.state.lock().unwrap();
+            s.key = key;
+            s.bars = progression.bars();
+            s.resolution = progression.resolution;
+            *s.progression_mut() = progression;
+            s.reset_clip();
+        }
+        self.undo.clear();
+        self.redo.clear();
+        self.sync_grid();
+    }
+
+    /// Change the key, generating a new
+    /// progression if the mode changed.
+    #[cfg(feature = "osc")]
+    pub fn set_key(&mut self, key: Key) -> Result<()> {
+        let mut s = self.state.lock().unwrap();
+        let mode_changed = s.key.mode != key.mode;
+        s.key = key;
+        if mode_changed {
+            s.gen_progression(&self.template)?;
+        }
+        Ok(())
+    }
+
+    pub fn key(&self) -> Key {
+        self.state.lock().unwrap().key
+    }
+
+    /// Stop playback until the clock is connected again.
+    #[cfg(feature = "osc")]
+    pub fn stop(&mut self) {
+        self.clock.close();
+        self.state.lock().unwrap().reset_tick();
+    }
+
+    /// Plays the progression as clock events come in,
+    /// following the arrangement's tempo changes if
+    /// given the tempo of the clock to set, and passing
+    /// the clock on to the MIDI ports if it's our own.
+    fn clock_handler(&self, bpm: Option<Arc<AtomicUsize>>, master: bool) -> impl FnMut(ClockEvent) + Send + 'static {
         let state = self.state.clone();
         let midi = self.midi.clone();
-        self.clock.connect_port(idx, move |tick| {
+        move |tick| {
+            if master {
+                midi.lock().unwrap().clock(&tick);
+            }
             let mut s = state.lock().unwrap();
             let emit_ticks = match s.resolution {
                 Duration::Quarter => 24,
@@ -95,21 +413,111 @@ impl<'a> Sequencer<'a> {
             };
             match tick {
                 ClockEvent::Tick(i) => {
+                    // Hold off playing until the count-off is done,
+                    // which ends on the downbeat of a bar
+                    s.count_off = s.count_off.saturating_sub(1);
+                    let counting = s.count_off > 0;
+                    if let Some(beat) = s.metronome.beat(i, emit_ticks, counting) {
+                        let channel = s.metronome.channel.saturating_sub(1);
+                        midi.lock().unwrap().click(beat, channel);
+                    }
+                    if counting {
+                        return;
+                    }
+                    s.step_ticks += 1;
                     if i % emit_ticks == 0 {
+                        s.played = Some(s.tick + s.clip_start());
+                        s.step_ticks = 0;
+
+                        if let Some(bpm) = &bpm {
+                            bpm.store(s.current_tempo(), Ordering::Relaxed);
+                        }
+
                         // Send MIDI data
                         // There might be some timing issues here b/c of the tick rate
-                        if let Some((chord, duration)) = s.current_chord() {
-                            midi.lock().unwrap().play_chord(&chord, duration);
+                        if s.audible(s.track) {
+                            let step = s.step_length();
+                            let track = s.current_track();
+                            let mut midi = midi.lock().unwrap();
+                            if let Some((chord, hits)) = s.current_chord() {
+                                let idx = s.tick + s.clip_start();
+                                if s.progression().plays(idx) {
+                                    let arp = s.progression().step(idx).arp;
+                                    play_hits(&mut midi, &chord, hits, arp, step, s.beat_length(), track);
+                                }
+                            }
+                            if let Some((note, steps)) = s.current_bass() {
+                                midi.play_chord_later(&note, Time::ZERO, step * steps as u32, 1., Some(s.bass_channel), track.port.as_deref());
+                            }
                         }
                         s.tick();
                     }
+
+                    // The other tracks step at their own resolutions
+                    let steps = s.step_tracks(i);
+                    if !steps.is_empty() {
+                        let mut midi = midi.lock().unwrap();
+                        for played in steps {
+                            let track = &s.tracks[played.track];
+                            let step = played.step_length;
+                            if let Some((chord, hits)) = played.chord {
+                                play_hits(&mut midi, &chord, hits, played.arp, step, s.beat_length(), track);
+                            }
+                            if let Some((note, steps)) = played.bass {
+                                midi.play_chord_later(&note, Time::ZERO, step * steps as u32, 1., Some(s.bass_channel), track.port.as_deref());
+                            }
+                        }
+                    }
+                },
+                ClockEvent::Start => {
+                    s.playing = true;
+                    s.count_off = s.metronome.count_off * TICKS_PER_BAR;
+                    if s.count_off > 0 {
+                        let channel = s.metronome.channel.saturating_sub(1);
+                        midi.lock().unwrap().click(Beat::Accent, channel);
+                    }
                 },
                 ClockEvent::Stop => {
+                    s.playing = false;
+                    s.played = None;
+                    s.step_ticks = 0;
+                    s.count_off = 0;
                     s.reset_tick();
                 },
-                _ => {}
             }
-        })
+        }
+    }
+
+    /// Turn the metronome on or off,
+    /// returning whether it's now on.
+    pub fn toggle_metronome(&mut self) -> bool {
+        let mut s = self.state.lock().unwrap();
+        s.metronome.on = !s.metronome.on;
+        s.metronome.on
+    }
+
+    /// Change how the metronome clicks,
+    /// keeping it on or off.
+    pub fn set_metronome(&mut self, metronome: Metronome) {
+        let mut s = self.state.lock().unwrap();
+        s.metronome = Metronome {
+            on: s.metronome.on,
+            ..metronome
+        };
+    }
+
+    /// The shared playback state.
+    pub fn state(&self) -> Arc<Mutex<PlaybackState>> {
+        self.state.clone()
+    }
+
+    /// Keep the grid in sync with the playback state,
+    /// which may be changed elsewhere, e.g. by the arranger.
+    fn sync_grid(&mut self) {
+        let s = self.state.lock().unwrap();
+        self.ticks_per_bar = s.resolution.ticks_per_bar();
+        self.grid_pos.0 = self.grid_pos.0.min(self.ticks_per_bar - 1);
+        self.grid_pos.1 = self.grid_pos.1.min(s.progression().bars().saturating_sub(1));
     }
 
     pub fn selected_idx(&self) -> usize {
@@ -125,6 +533,7 @@ impl<'a> Sequencer<'a> {
     }
 
     pub fn render(&mut self, rect: Rect) -> Vec<(Paragraph, Rect)> {
+        self.sync_grid();
         let mut rects = vec![];
 
         let chunks = Layout::default()
@@ -133,18 +542,23 @@ impl<'a> Sequencer<'a> {
                 // Main
                 Constraint::Min(6),
 
+                // Transport chunk
+                Constraint::Length(1),
+
                 // Messages/input chunk
                 Constraint::Length(1),
             ].as_ref())
             .split(rect);
 
+        rects.push((transport::render(&self), chunks[1]));
+
         let message = match &self.input_mode {
             InputMode::Text(ti, _) => ti.render(),
             InputMode::Chord(select, _) => select.text_input.render(),
-            _ => Paragraph::new(self.message)
+            _ => Paragraph::new(self.log.current())
                 .alignment(Alignment::Right)
         };
-        rects.push((message, chunks[1]));
+        rects.push((message, chunks[2]));
 
         let display_chunks = Layout::default()
             .direction(Direction::Horizontal)
@@ -160,25 +574,213 @@ impl<'a> Sequencer<'a> {
 
         rects.push((grid::render(&self), display_chunks[0]));
 
-        let right_pane = match &self.input_mode {
+        match &self.input_mode {
             InputMode::Chord(select, _) => {
-                let height = display_chunks[1].height as usize;
-                select.render(height)
+                let key = self.state.lock().unwrap().key;
+                rects.extend(select.render(display_chunks[1], &key));
             }
             InputMode::Select(select, _) => {
                 let height = display_chunks[1].height as usize;
-                select.render(height)
+                rects.push((select.render(height), display_chunks[1]));
+            }
+            InputMode::Key(picker) => {
+                rects.push((picker.render(), display_chunks[1]));
+            }
+            _ => {
+                match compare::render(&self) {
+                    Some((compare, height)) => {
+                        let chunks = Layout::default()
+                            .direction(Direction::Vertical)
+                            .constraints([
+                                Constraint::Min(0),
+                                Constraint::Length(height),
+                            ].as_ref())
+                            .split(display_chunks[1]);
+                        rects.push((progression::render(&self), chunks[0]));
+                        rects.push((compare, chunks[1]));
+                    }
+                    None => rects.push((progression::render(&self), display_chunks[1]))
+                }
             }
-            _ => progression::render(&self)
         };
-        rects.push((right_pane, display_chunks[1]));
         rects
     }
 
     pub fn process_input(&mut self, key: KeyEvent) -> Result<()> {
+        if let InputMode::Normal = self.input_mode {
+            match key.code {
+                KeyCode::Char('z') => {
+                    self.undo();
+                    return Ok(());
+                }
+                KeyCode::Char('Z') => {
+                    self.redo();
+                    return Ok(());
+                }
+                _ => {}
+            }
+        }
+
+        // Remember the progression from before any change,
+        // starting over when another track is edited
+        let (tracks, before) = {
+            let s = self.state.lock().unwrap();
+            ((s.track, s.tracks.len()), s.progression().clone())
+        };
+        let result = self.handle_input(key);
+        let (now, changed) = {
+            let s = self.state.lock().unwrap();
+            ((s.track, s.tracks.len()), *s.progression() != before)
+        };
+        if now != tracks {
+            self.undo.clear();
+            self.redo.clear();
+        } else if changed {
+            self.remember(before);
+        }
+        result
+    }
+
+    /// Keep a progression from before a change to undo to.
+    fn remember(&mut self, before: Progression) {
+        self.undo.push(before);
+        if self.undo.len() > MAX_UNDO {
+            self.undo.remove(0);
+        }
+        self.redo.clear();
+    }
+
+    /// Start a new progression in a key from
+    /// these chords, in order, spread evenly
+    /// across the bars. It can be undone.
+    pub fn set_chords(&mut self, key: Key, chords: Vec<ChordSpec>) {
+        let progression = {
+            let mut s = self.state.lock().unwrap();
+            s.key = key;
+            Progression::from_chords(chords, s.bars, s.resolution)
+        };
+        let before = self.restore(progression);
+        self.remember(before);
+    }
+
+    /// Put a chord played on a keyboard on the selected
+    /// step, named in its key. It can be undone.
+    pub fn put_chord(&mut self, chord: &Chord) {
+        self.sync_grid();
+        let idx = self.selected_idx();
+        let before = {
+            let mut s = self.state.lock().unwrap();
+            let key = s.progression().key_at(idx, &s.key);
+            match ChordSpec::from_chord(chord, &key) {
+                Some(cs) => {
+                    self.log.push(format!("Put {} on the step, [z] to undo", symbols::chord_in_key(&cs, &key)));
+                    self.recent.lock().unwrap().push(&cs);
+                    let before = s.progression().clone();
+                    s.progression_mut().insert_chord_at(idx, cs);
+                    before
+                }
+                None => {
+                    self.log.error("Couldn't recognize the chord");
+                    return;
+                }
+            }
+        };
+        self.remember(before);
+    }
+
+    /// Go back to the progression before the last change.
+    fn undo(&mut self) {
+        match self.undo.pop() {
+            Some(progression) => {
+                let current = self.restore(progression);
+                self.redo.push(current);
+                self.log.push("Undid the last change");
+            }
+            None => self.log.push("Nothing to undo"),
+        }
+    }
+
+    /// Reapply the last undone change.
+    fn redo(&mut self) {
+        match self.redo.pop() {
+            Some(progression) => {
+                let current = self.restore(progression);
+                self.undo.push(current);
+                self.log.push("Redid the last change");
+            }
+            None => self.log.push("Nothing to redo"),
+        }
+    }
+
+    /// Swap in a progression,
+    /// returning the current one.
+    fn restore(&mut self, progression: Progression) -> Progression {
+        let current = {
+            let mut s = self.state.lock().unwrap();
+            let current = std::mem::replace(s.progression_mut(), progression);
+            s.bars = s.progression().bars();
+            s.resolution = s.progression().resolution;
+            let clip = s.clip;
+            s.set_clip(clip);
+            current
+        };
+        self.sync_grid();
+        current
+    }
+
+    /// Chords to follow the chord before this step, most
+    /// common first, then substitutes for the step's chord,
+    /// along with how to list them.
+    fn suggestions(&self, seq_idx: usize) -> Option<(Vec<ChordSpec>, Vec<String>)> {
+        let s = self.state.lock().unwrap();
+        let sequence = &s.progression().sequence;
+        let prev = (0..seq_idx).rev()
+            .chain((seq_idx+1..sequence.len()).rev())
+            .find_map(|i| sequence[i].as_ref());
+
+        let mut suggestions: Vec<(ChordSpec, String)> = vec![];
+        if let Some(prev) = prev {
+            for (cs, count) in self.template.ranked_next(prev, &s.key.mode) {
+                let desc = format!("follows {} ×{}", prev, count);
+                suggestions.push((cs, desc));
+            }
+        }
+        if let Some(current) = &sequence[seq_idx] {
+            for cs in self.template.substitutions(current, &s.key.mode) {
+                if !suggestions.iter().any(|(c, _)| *c == cs) {
+                    let desc = format!("substitutes {}", current);
+                    suggestions.push((cs, desc));
+                }
+            }
+        }
+        suggestions.truncate(MAX_SUGGESTIONS);
+        if suggestions.is_empty() {
+            return None;
+        }
+
+        let key = s.progression().key_at(seq_idx, &s.key);
+        let choices = suggestions.iter().enumerate()
+            .map(|(i, (cs, desc))| format!("{} {:<8} {}", i + 1, symbols::chord_in_key(cs, &key), desc))
+            .collect();
+        let chords = suggestions.into_iter().map(|(cs, _)| cs).collect();
+        Some((chords, choices))
+    }
+
+    fn handle_input(&mut self, key: KeyEvent) -> Result<()> {
+        self.sync_grid();
+        let sel_idx = self.selected_idx();
         match &mut self.input_mode {
             InputMode::Select(ref mut select, target) => {
-                let (selection, close) = select.process_input(key)?;
+                let (selection, close) = match (key.code, &target) {
+                    // Number keys pick a suggestion directly
+                    (KeyCode::Char(c), SelectTarget::Suggestion(chords)) if c.is_ascii_digit() => {
+                        let idx = c.to_digit(10)
+                            .and_then(|d| (d as usize).checked_sub(1))
+                            .filter(|idx| *idx < chords.len());
+                        (idx, idx.is_some())
+                    }
+                    _ => select.process_input(key)?,
+                };
                 if close {
                     if let Some(selected) = selection {
                         match target {
@@ -191,11 +793,33 @@ impl<'a> Sequencer<'a> {
                                     _ => Duration::Quarter,
                                 };
                                 {
+                                    // Keep the chords, moving them to the new grid
                                     let mut s = self.state.lock().unwrap();
+                                    let (old, new) = (s.resolution.ticks_per_bar(), res.ticks_per_bar());
                                     s.resolution = res;
-                                    s.gen_progression(&self.template)?;
+                                    *s.progression_mut() = s.progression().with_resolution(res);
+                                    let (a, b) = s.clip;
+                                    s.clip = (a * new / old, b * new / old);
+                                    s.tick = s.tick * new / old;
+                                    let clip = s.clip;
+                                    s.set_clip(clip);
                                 }
+                                let old = self.ticks_per_bar;
                                 self.ticks_per_bar = res.ticks_per_bar();
+
+                                // Keep the cursor on the same
+                                // point in the bar
+                                self.grid_pos.0 = (self.grid_pos.0 * self.ticks_per_bar / old)
+                                    .min(self.ticks_per_bar - 1);
+                            }
+                            SelectTarget::Suggestion(chords) => {
+                                let mut s = self.state.lock().unwrap();
+                                s.progression_mut().insert_chord_at(sel_idx, chords[selected].clone());
+                            }
+                            SelectTarget::Port(ports) => {
+                                let mut s = self.state.lock().unwrap();
+                                s.current_track_mut().port = selected.checked_sub(1)
+                                    .and_then(|idx| ports.get(idx).cloned());
                             }
                         }
                     }
@@ -206,6 +830,7 @@ impl<'a> Sequencer<'a> {
                 let (input, close) = text_input.process_input(key)?;
                 if close {
                     if let Some(input) = input {
+                        add_to_history(self.history.entry(*target).or_default(), &input);
                         let mut s = self.state.lock().unwrap();
                         match target {
                             TextTarget::Root => {
@@ -214,96 +839,363 @@ impl<'a> Sequencer<'a> {
                                         note
                                     }
                                     Err(_) => {
-                                        self.message = "Invalid root note";
+                                        self.log.error("Invalid root note");
                                         s.key.root
                                     }
                                 };
                             }
-                            TextTarget::Duration => {
-                                s.note_duration = input.parse::<u64>()?;
+                            TextTarget::StepKey => {
+                                match input.parse() {
+                                    Ok(key) => {
+                                        let mut step = s.progression().step(sel_idx);
+                                        step.key = Some(key);
+                                        s.progression_mut().set_step(sel_idx, step);
+                                    }
+                                    Err(_) => {
+                                        self.log.error("Invalid key");
+                                    }
+                                }
+                            }
+                            TextTarget::Arp => {
+                                match parse_arp(&input) {
+                                    Ok(arp) => {
+                                        let mut step = s.progression().step(sel_idx);
+                                        step.arp = arp;
+                                        s.progression_mut().set_step(sel_idx, step);
+                                    }
+                                    Err(err) => {
+                                        self.log.error(err);
+                                    }
+                                }
+                            }
+                            TextTarget::Gate => {
+                                match input.parse::<Gate>() {
+                                    Ok(gate) => {
+                                        let mut step = s.progression().step(sel_idx);
+                                        step.gate = gate;
+                                        s.progression_mut().set_step(sel_idx, step);
+                                    }
+                                    Err(err) => {
+                                        self.log.error(err.to_string());
+                                    }
+                                }
                             }
                             TextTarget::Bars => {
-                                s.bars = input.parse::<usize>()?;
-                                s.gen_progression(&self.template)?;
+                                match parse_bars(&input) {
+                                    // Ask before removing any chords
+                                    Some((bars, _)) if s.chords_past(bars) > 0 => {
+                                        self.truncate_to = Some(bars);
+                                    }
+                                    Some((bars, fill)) => {
+                                        s.set_bars(bars, if fill { Some(&self.template) } else { None });
+
+                                        // Keep the cursor within the grid
+                                        self.grid_pos.1 = self.grid_pos.1.min(bars - 1);
+                                    }
+                                    None => {
+                                        self.log.error("Invalid number of bars");
+                                    }
+                                }
+                            }
+                            TextTarget::Humanize => {
+                                if let Ok(amount) = input.trim_end_matches('%').parse::<usize>() {
+                                    s.progression_mut().humanize(amount);
+                                    self.log.push("Humanized, [z] to undo".to_string());
+                                }
+                            }
+                            TextTarget::Import => {
+                                self.import_from = Some(input);
+                            }
+                            TextTarget::ImportKey => {
+                                if let Some(path) = self.import_from.take() {
+                                    // Detecting the key if it's not given
+                                    let key = input.parse().ok();
+                                    match load_from_midi_file(&path, s.resolution, key) {
+                                        Ok((key, progression)) => {
+                                            s.key = key;
+                                            s.bars = progression.bars();
+                                            *s.progression_mut() = progression;
+                                            s.reset_clip();
+                                            self.grid_pos.1 = self.grid_pos.1.min(s.bars - 1);
+                                            self.log.push(format!("Imported in {}, [z] to undo", key));
+                                        }
+                                        Err(err) => {
+                                            self.log.error(format!("Failed to import: {}", err));
+                                        }
+                                    }
+                                }
+                            }
+                            TextTarget::Truncate => {
+                                if let Some(bars) = self.truncate_to.take() {
+                                    if input == "y" {
+                                        s.set_bars(bars, None);
+                                        self.grid_pos.1 = self.grid_pos.1.min(bars - 1);
+                                    }
+                                }
+                            }
+                            TextTarget::Chance => {
+                                match input.trim_end_matches('%').parse::<usize>() {
+                                    Ok(chance) if chance <= 100 => {
+                                        let mut step = s.progression().step(sel_idx);
+                                        step.chance = chance;
+                                        s.progression_mut().set_step(sel_idx, step);
+                                    }
+                                    _ => {
+                                        self.log.error("Invalid chance");
+                                    }
+                                }
+                            }
+                            TextTarget::LoopLength => {
+                                match input.parse::<usize>() {
+                                    Ok(len) if len > 0 => {
+                                        let start = s.clip.0;
+                                        s.set_clip((start, start + len));
+                                    }
+                                    _ => {
+                                        self.log.error("Invalid loop length");
+                                    }
+                                }
+                            }
+                            TextTarget::Swing => {
+                                match input.trim_end_matches('%').parse::<usize>() {
+                                    Ok(swing) => {
+                                        s.swing = swing.clamp(50, MAX_SWING);
+                                    }
+                                    Err(_) => {
+                                        self.log.error("Invalid swing");
+                                    }
+                                }
+                            }
+                            TextTarget::Accent => {
+                                match input.parse() {
+                                    Ok(accent) => {
+                                        s.accent = accent;
+                                    }
+                                    Err(err) => {
+                                        self.log.error(err.to_string());
+                                    }
+                                }
+                            }
+                            TextTarget::NewTrack => {
+                                let name = input.trim();
+                                if name.is_empty() {
+                                    self.log.error("Tracks need a name");
+                                } else {
+                                    s.add_track(name);
+                                    self.log.push(format!("Added the {} track", name));
+                                }
+                            }
+                            TextTarget::RemoveTrack => {
+                                if input == "y" {
+                                    let name = s.current_track().name.clone();
+                                    if s.remove_track() {
+                                        self.log.push(format!("Removed the {} track", name));
+                                    }
+                                }
+                            }
+                            TextTarget::Channel => {
+                                match parse_channel(&input) {
+                                    Ok(channel) => {
+                                        s.current_track_mut().channel = channel;
+                                    }
+                                    Err(err) => {
+                                        self.log.error(err);
+                                    }
+                                }
                             }
-                            TextTarget::Export => {
-                                let result = save_to_midi_file(
-                                    120, // TODO
-                                    s.progression.resolution.ticks_per_beat(),
-                                    &s.progression.in_key(&s.key),
+                            TextTarget::Amount => {
+                                if let Ok(amount) = input.trim_end_matches('%').parse::<usize>() {
+                                    self.amount = amount.min(100);
+                                }
+                            }
+                            TextTarget::Euclid => {
+                                if let Some((hits, per_bar)) = parse_hits(&input) {
+                                    let clip = s.clip;
+                                    s.progression_mut().place_euclidean(hits, clip, per_bar);
+                                }
+                            }
+                            TextTarget::Export | TextTarget::BakedExport => {
+                                // Baking picks which chords
+                                // play, per their chances
+                                let baked = *target == TextTarget::BakedExport;
+                                let tracks = s.export_tracks(self.export_options, baked);
+                                let tempos = [TempoChange { tick: 0, bpm: self.tempo }];
+                                let result = save_tracks_to_midi_file(
+                                    EXPORT_TICKS_PER_BEAT,
+                                    &tracks,
+                                    &tempos,
+                                    Some(&s.key),
+                                    self.export_options.accidentals,
                                     input);
                                 match result {
                                     Ok(_) => {
-                                        self.message = "Saved file";
+                                        self.log.push("Saved file");
                                     },
-                                    Err(_) => {
-                                        self.message = "Failed to save";
+                                    Err(err) => {
+                                        self.log.error(format!("Failed to save: {}", err));
                                     }
                                 }
                             }
                         }
                     }
+                    let confirm = *target == TextTarget::Bars && self.truncate_to.is_some();
+                    let import = *target == TextTarget::Import && self.import_from.is_some();
+                    self.input_mode = InputMode::Normal;
+
+                    // Ask which key to name the imported chords in
+                    if import {
+                        let mut text_input = TextInput::with_history("Key (e.g. C4 minor, or auto to detect): ", |_c: char| true, &self.history, TextTarget::ImportKey);
+                        text_input.set_input("auto".to_string());
+                        self.input_mode = InputMode::Text(text_input, TextTarget::ImportKey);
+                    }
+
+                    // Confirm cutting bars with chords in them
+                    if confirm {
+                        self.input_mode = InputMode::Text(
+                            TextInput::with_history("Remove the chords in the cut bars? (y/n): ", |c: char| c == 'y' || c == 'n', &self.history, TextTarget::Truncate),
+                            TextTarget::Truncate);
+                    } else {
+                        self.truncate_to = None;
+                    }
+                }
+            }
+            InputMode::Key(ref mut picker) => {
+                let (picked, close) = picker.process_input(key);
+                if let Some(key) = picked {
+                    let mut s = self.state.lock().unwrap();
+                    let mode = s.key.mode;
+                    s.key = key;
+
+                    // Changing between major and minor
+                    // modes requires a new progression
+                    if key.mode.quality() != mode.quality() {
+                        s.gen_progression(&self.template)?;
+                    }
+                }
+                if close {
                     self.input_mode = InputMode::Normal;
                 }
             }
             InputMode::Chord(ref mut chord_select, target) => {
                 match chord_select.process_input(key) {
                     Ok((sel, close)) => {
+                        self.audition = chord_select.audition;
                         if close {
                             let mut s = self.state.lock().unwrap();
                             if let Some(cs) = sel {
+                                self.recent.lock().unwrap().push(&cs);
                                 match target {
                                     ChordTarget::Seed => {
                                         s.gen_progression_from_seed(&cs, &self.template)?;
                                     },
                                     ChordTarget::Chord => {
                                         let idx = self.selected_idx();
-                                        s.progression.sequence[idx] = Some(cs);
+                                        s.progression_mut().sequence[idx] = Some(cs);
                                     }
                                 }
                             }
-                            s.progression.update_chords();
+                            s.progression_mut().update_chords();
                             self.input_mode = InputMode::Normal;
-                        } else if let Some(cs) = sel {
+                        } else if let (Some(cs), true) = (sel, self.audition) {
                             let s = self.state.lock().unwrap();
                             let chord = cs.chord_for_key(&s.key);
-                            self.midi.lock().unwrap().play_chord(&chord, 1);
+                            let track = s.current_track();
+                            self.midi.lock().unwrap().play_chord(&chord, 1, track.channel, track.port.as_deref());
                         }
                     }
-                    Err(_) => {
-                        self.message = "Invalid chord";
+                    Err(err) => {
+                        self.log.error(err.to_string());
                         self.input_mode = InputMode::Normal;
                     }
                 }
             }
             InputMode::Normal => {
+                // Cycle the track's bassline, as
+                // [B] already sets the loop's end
+                if key.code == KeyCode::Char('b') && key.modifiers.contains(KeyModifiers::ALT) {
+                    let mut s = self.state.lock().unwrap();
+                    let track = s.current_track_mut();
+                    track.bass = match track.bass {
+                        None => Some(BassPattern::Root),
+                        Some(BassPattern::Walking) => None,
+                        Some(pattern) => Some(pattern.next()),
+                    };
+                    return Ok(());
+                }
+
+                // Import chords from a MIDI file,
+                // as [i] already changes the voicing
+                if key.code == KeyCode::Char('i') && key.modifiers.contains(KeyModifiers::ALT) {
+                    self.log.clear();
+                    let mut text_input = TextInput::with_history("Import from: ", |_c: char| true, &self.history, TextTarget::Import);
+                    text_input.set_input(self.save_dir.to_string());
+                    self.input_mode = InputMode::Text(text_input, TextTarget::Import);
+                    return Ok(());
+                }
+
+                // Arpeggiate the chord at the cursor,
+                // as [a] already sets the amount
+                if key.code == KeyCode::Char('a') && key.modifiers.contains(KeyModifiers::ALT) {
+                    let sel_idx = self.selected_idx();
+                    let s = self.state.lock().unwrap();
+                    if s.progression().sequence[sel_idx].is_some() {
+                        self.log.clear();
+                        let mut text_input = TextInput::with_history("Arpeggio (up, down, updown or random, e.g. up/16, or -): ", |_c: char| true, &self.history, TextTarget::Arp);
+                        text_input.set_input(s.progression().step(sel_idx).arp.map_or("up/16".to_string(), |arp| arp.to_string()));
+                        drop(s);
+                        self.input_mode = InputMode::Text(text_input, TextTarget::Arp);
+                    }
+                    return Ok(());
+                }
                 match key.code {
                     // Change bars
                     KeyCode::Char('b') => {
-                        self.message = "";
+                        self.log.clear();
                         self.input_mode = InputMode::Text(
-                            TextInput::new("Bars: ", |c: char| c.is_numeric()),
+                            TextInput::with_history("Bars: ", |c: char| c.is_alphanumeric() || c == '/', &self.history, TextTarget::Bars),
                             TextTarget::Bars);
                     }
 
                     // Change root
                     KeyCode::Char('r') => {
-                        self.message = "";
-                        self.input_mode = InputMode::Text(
-                            TextInput::new("Root: ", |c: char| c.is_alphanumeric()),
-                            TextTarget::Root);
+                        self.log.clear();
+                        self.input_mode = if self.circle_of_fifths {
+                            let s = self.state.lock().unwrap();
+                            let chords = s.progression().chords().into_iter().cloned().collect();
+                            InputMode::Key(KeyPicker::new(s.key, chords))
+                        } else {
+                            InputMode::Text(
+                                TextInput::with_history("Root: ", |c: char| c.is_alphanumeric(), &self.history, TextTarget::Root),
+                                TextTarget::Root)
+                        };
                     }
 
-                    // Change duration
+                    // Spread the loop's chords evenly
                     KeyCode::Char('u') => {
-                        self.message = "";
+                        self.log.clear();
+                        self.input_mode = InputMode::Text(
+                            TextInput::with_history("Euclidean hits per bar (or e.g. 5/loop): ", |c: char| c.is_alphanumeric() || c == '/', &self.history, TextTarget::Euclid),
+                            TextTarget::Euclid);
+                    }
+
+                    // Change swing
+                    KeyCode::Char('w') => {
+                        self.log.clear();
                         self.input_mode = InputMode::Text(
-                            TextInput::new("Duration: ", |c: char| c.is_numeric()),
-                            TextTarget::Duration);
+                            TextInput::with_history("Swing (50-75%): ", |c: char| c.is_ascii_digit() || c == '%', &self.history, TextTarget::Swing),
+                            TextTarget::Swing);
+                    }
+
+                    // Change the accent pattern
+                    KeyCode::Char('W') => {
+                        self.log.clear();
+                        self.input_mode = InputMode::Text(
+                            TextInput::with_history("Accent (none, 1, 1+3, 4/4 or e.g. 100,60,80,60): ", |c: char| c.is_ascii_alphanumeric() || "+/,%".contains(c), &self.history, TextTarget::Accent),
+                            TextTarget::Accent);
                     }
 
                     KeyCode::Char('s') => {
-                        self.message = "";
+                        self.log.clear();
                         let choices = vec![
                             Duration::Quarter,
                             Duration::Eighth,
@@ -318,18 +1210,44 @@ impl<'a> Sequencer<'a> {
                     // Change mode
                     KeyCode::Char('m') => {
                         let mut s = self.state.lock().unwrap();
-                        s.key.mode = match s.key.mode {
-                            Mode::Major => Mode::Minor,
-                            Mode::Minor => Mode::Major,
-                        };
-                        // Mode changes require a new progression
-                        s.gen_progression(&self.template)?;
+                        let mode = s.key.mode.next();
+                        let quality = s.key.mode.quality();
+                        s.key.mode = mode;
+
+                        // Changing between major and minor
+                        // modes requires a new progression
+                        if mode.quality() != quality {
+                            s.gen_progression(&self.template)?;
+                        }
                     }
 
-                    // Apply voice leading algorithm to progression
+                    // Apply voice leading algorithm to progression,
+                    // leading back into the start of the loop
                     KeyCode::Char('v') => {
                         let mut s = self.state.lock().unwrap();
-                        s.progression = s.progression.voice_lead();
+                        *s.progression_mut() = s.progression().voice_lead_circular();
+                    }
+
+                    // Mark the selected step to compare
+                    // other chords against, or unmark it
+                    KeyCode::Char('=') => {
+                        let idx = self.selected_idx();
+                        self.compare = match self.compare {
+                            Some(marked) if marked == idx => None,
+                            _ => Some(idx),
+                        };
+                    }
+
+                    // Voice lead chords as they're played instead
+                    KeyCode::Char('O') => {
+                        let mut s = self.state.lock().unwrap();
+                        s.voice_lead = !s.voice_lead;
+                    }
+
+                    // Switch between numerals
+                    // and letter names for chords
+                    KeyCode::Char('Y') => {
+                        symbols::toggle_letter_names();
                     }
 
                     // Generate a new random progression
@@ -338,23 +1256,198 @@ impl<'a> Sequencer<'a> {
                         s.gen_progression(&self.template)?;
                     }
 
+                    // Regenerate the loop, or the current
+                    // bar if there isn't one
+                    KeyCode::Char('g') => {
+                        let mut s = self.state.lock().unwrap();
+                        let range = if s.has_loop() {
+                            s.clip
+                        } else {
+                            let start = self.grid_pos.1 * self.ticks_per_bar;
+                            (start, start + self.ticks_per_bar)
+                        };
+                        // Only end with the cadence at the end of the loop
+                        let cadence = s.cadence.filter(|_| range.1 == s.clip.1);
+                        *s.progression_mut() = self.template.regenerate(s.progression(), range, &s.key.mode, cadence);
+                        self.log.push("Regenerated, [z] to undo".to_string());
+                    }
+
+                    // Suggest chords for the selected step
+                    KeyCode::Char('N') => {
+                        self.log.clear();
+                        match self.suggestions(sel_idx) {
+                            Some((chords, choices)) => {
+                                self.input_mode = InputMode::Select(
+                                    Select::new(choices),
+                                    SelectTarget::Suggestion(chords));
+                            }
+                            None => self.log.push("No chords to suggest"),
+                        }
+                    }
+
                     // Generate a new progression with
                     // a seed chord
                     KeyCode::Char('S') => {
-                        self.message = "";
-                        self.input_mode = InputMode::Chord(
-                            ChordSelect::default(), ChordTarget::Seed);
+                        self.log.clear();
+                        let select = ChordSelect::new(None, self.template.chord_types.clone(), &self.recent, self.audition);
+                        self.input_mode = InputMode::Chord(select, ChordTarget::Seed);
                     }
 
                     // Start export to MIDI flow
                     KeyCode::Char('E') => {
-                        self.message = "";
-                        let mut text_input = TextInput::new("Path: ", |_c: char| true);
+                        self.log.clear();
+                        let mut text_input = TextInput::with_history("Path: ", |_c: char| true, &self.history, TextTarget::Export);
                         text_input.set_input(self.save_dir.to_string());
                         self.input_mode = InputMode::Text(
                             text_input, TextTarget::Export);
                     }
 
+                    // Randomize the loop's inversions, octaves or velocities
+                    KeyCode::Char('J') | KeyCode::Char('o') | KeyCode::Char('V') => {
+                        let mut s = self.state.lock().unwrap();
+                        let clip = s.clip;
+                        let what = match key.code {
+                            KeyCode::Char('J') => {
+                                s.progression_mut().randomize_inversions(clip, self.amount);
+                                "inversions"
+                            }
+                            KeyCode::Char('o') => {
+                                s.progression_mut().randomize_octaves(clip, self.amount);
+                                "octaves"
+                            }
+                            _ => {
+                                s.progression_mut().randomize_velocities(clip, self.amount);
+                                "velocities"
+                            }
+                        };
+                        self.log.push(format!("Randomized {}, [z] to undo", what));
+                    }
+
+                    // Cycle the cadence generated progressions end with
+                    KeyCode::Char('c') => {
+                        let mut s = self.state.lock().unwrap();
+                        s.cadence = Cadence::cycle(s.cadence);
+                    }
+
+                    // Reverse the loop's chords, keeping
+                    // the rhythm or reversing it too
+                    KeyCode::Char('f') | KeyCode::Char('F') => {
+                        let mut s = self.state.lock().unwrap();
+                        let clip = s.clip;
+                        let rhythm = key.code == KeyCode::Char('F');
+                        s.progression_mut().reverse(clip, rhythm);
+                        self.log.push("Reversed, [z] to undo".to_string());
+                    }
+
+                    // Edit the next or previous track
+                    KeyCode::Tab | KeyCode::BackTab => {
+                        {
+                            let mut s = self.state.lock().unwrap();
+                            let len = s.tracks.len();
+                            let idx = if key.code == KeyCode::Tab {
+                                (s.track + 1) % len
+                            } else {
+                                (s.track + len - 1) % len
+                            };
+                            s.select_track(idx);
+                        }
+                        self.sync_grid();
+                    }
+
+                    // Add a track
+                    KeyCode::Char('~') => {
+                        self.log.clear();
+                        let name = {
+                            let s = self.state.lock().unwrap();
+                            TRACK_NAMES.iter()
+                                .find(|name| !s.tracks.iter().any(|t| t.name == **name))
+                                .map_or_else(|| format!("track {}", s.tracks.len() + 1), |name| name.to_string())
+                        };
+                        let mut text_input = TextInput::with_history("Track name: ", |_c: char| true, &self.history, TextTarget::NewTrack);
+                        text_input.set_input(name);
+                        self.input_mode = InputMode::Text(text_input, TextTarget::NewTrack);
+                    }
+
+                    // Remove the track, if there are others
+                    KeyCode::Char('`') => {
+                        self.log.clear();
+                        if self.state.lock().unwrap().tracks.len() > 1 {
+                            self.input_mode = InputMode::Text(
+                                TextInput::with_history("Remove this track and its chords? (y/n): ", |c: char| c == 'y' || c == 'n', &self.history, TextTarget::RemoveTrack),
+                                TextTarget::RemoveTrack);
+                        } else {
+                            self.log.push("The only track can't be removed");
+                        }
+                    }
+
+                    // Mute or solo the track
+                    KeyCode::Char('!') | KeyCode::Char('?') => {
+                        let mut s = self.state.lock().unwrap();
+                        let mute = key.code == KeyCode::Char('!');
+                        let track = s.current_track_mut();
+                        if mute {
+                            track.muted = !track.muted;
+                        } else {
+                            track.solo = !track.solo;
+                        }
+                    }
+
+                    // Pick which of the connected ports the track plays on
+                    KeyCode::Char('$') => {
+                        let ports = self.midi.lock().unwrap().port_names();
+                        if ports.len() > 1 {
+                            let mut choices = vec!["all ports".to_string()];
+                            choices.extend(ports.iter().cloned());
+                            self.input_mode = InputMode::Select(
+                                Select::new(choices),
+                                SelectTarget::Port(ports));
+                        } else {
+                            self.log.push("Connect more than one port to play tracks on different ones");
+                        }
+                    }
+
+                    // Change the MIDI channel chords play on
+                    KeyCode::Char('@') => {
+                        self.log.clear();
+                        let channel = self.state.lock().unwrap().current_track().channel;
+                        let mut text_input = TextInput::with_history("Channel (1-16, - for default): ", |c: char| c.is_ascii_digit() || c == '-', &self.history, TextTarget::Channel);
+                        text_input.set_input(channel.map_or("-".to_string(), |c| (c + 1).to_string()));
+                        self.input_mode = InputMode::Text(text_input, TextTarget::Channel);
+                    }
+
+                    // Play chords slightly off, at random
+                    KeyCode::Char('x') => {
+                        self.log.clear();
+                        let mut text_input = TextInput::with_history("Humanize, late only (%): ", |c: char| c.is_ascii_digit() || c == '%', &self.history, TextTarget::Humanize);
+                        text_input.set_input("10".to_string());
+                        self.input_mode = InputMode::Text(text_input, TextTarget::Humanize);
+                    }
+
+                    // Shuffle the loop's chords
+                    KeyCode::Char('H') => {
+                        let mut s = self.state.lock().unwrap();
+                        let clip = s.clip;
+                        s.progression_mut().shuffle(clip);
+                        self.log.push("Shuffled, [z] to undo".to_string());
+                    }
+
+                    // Change how much randomizing changes
+                    KeyCode::Char('a') => {
+                        self.log.clear();
+                        let mut text_input = TextInput::with_history("Randomize amount (%): ", |c: char| c.is_ascii_digit() || c == '%', &self.history, TextTarget::Amount);
+                        text_input.set_input(self.amount.to_string());
+                        self.input_mode = InputMode::Text(text_input, TextTarget::Amount);
+                    }
+
+                    // Export one realization of the steps' chances
+                    KeyCode::Char('X') => {
+                        self.log.clear();
+                        let mut text_input = TextInput::with_history("Path: ", |_c: char| true, &self.history, TextTarget::BakedExport);
+                        text_input.set_input(self.save_dir.to_string());
+                        self.input_mode = InputMode::Text(
+                            text_input, TextTarget::BakedExport);
+                    }
+
                     _ => {}
                 }
 
@@ -366,18 +1459,31 @@ impl<'a> Sequencer<'a> {
     }
 
     pub fn params<'b>(&self) -> Vec<Span<'b>> {
-        let param_style = Style::default().fg(Color::LightBlue)
-            .add_modifier(Modifier::BOLD);
+        let param_style = theme::param();
         let s = self.state.lock().unwrap();
         vec![
             Span::raw("[r]oot:"),
-            Span::styled(s.key.root.to_string(), param_style),
-            Span::raw(" d[u]ration:"),
-            Span::styled(s.note_duration.to_string(), param_style),
+            Span::styled(symbols::key_root(&s.key), param_style),
             Span::raw(" [b]ars:"),
             Span::styled(s.bars.to_string(), param_style),
             Span::raw(" re[s]olution:"),
             Span::styled(s.resolution.to_string(), param_style),
+            Span::raw(" s[w]ing:"),
+            Span::styled(format!("{}%", s.swing), param_style),
+            Span::raw(" accent[W]:"),
+            Span::styled(s.accent.to_string(), param_style),
+            Span::raw(" ch[@]:"),
+            Span::styled(s.current_track().channel.map_or("-".to_string(), |c| (c + 1).to_string()), param_style),
+            Span::raw(" port[$]:"),
+            Span::styled(s.current_track().port.clone().unwrap_or_else(|| "all".to_string()), param_style),
+            Span::raw(" [Alt-b]ass:"),
+            Span::styled(s.current_track().bass.map_or("off".to_string(), |b| b.to_string()), param_style),
+            Span::raw(" [c]adence:"),
+            Span::styled(s.cadence.map_or("none".to_string(), |c| c.to_string()), param_style),
+            Span::raw(" v[O]ice lead:"),
+            Span::styled(if s.voice_lead { "on" } else { "off" }, param_style),
+            Span::raw(" [a]mount:"),
+            Span::styled(format!("{}%", self.amount), param_style),
             Span::raw(" [m]ode:"),
             Span::styled(s.key.mode.to_string(), param_style),
         ]
@@ -388,7 +1494,13 @@ impl<'a> Sequencer<'a> {
         controls.extend(grid::controls(&self));
         controls.extend(progression::controls(&self));
         controls.push(
-            Span::raw(" [R]oll [S]eed [E]xport"));
+            Span::raw(" [R]oll re[g]enerate [S]eed [N]ext chords [=]compare e[u]clid [E]xport [Alt-i]mport to/from performance:[>][<]"));
+        controls.push(
+            Span::raw(" randomize:[J]inversions [o]ctaves [V]elocities reverse:[f]chords [F]rhythm s[H]uffle humanize:[x] [z]undo [Z]redo"));
+        if self.state.lock().unwrap().progression().steps.values().any(|s| s.chance < 100) {
+            controls.push(Span::raw(" e[X]port baked"));
+        }
+        controls.push(Span::raw(if symbols::letter_names() { " [Y]names:letters" } else { " [Y]names:numerals" }));
         controls
     }
 }
@@ -1,24 +1,19 @@
 use anyhow::Result;
 use tui::{
     layout::Alignment,
-    style::{Style, Color, Modifier},
+    style::{Style, Modifier},
     text::{Span, Spans},
     widgets::{Block, Paragraph, Borders},
 };
 use super::Sequencer;
+use crate::app::{theme, symbols};
 use crossterm::event::{KeyEvent, KeyCode};
 
 pub fn render<'a>(seq: &Sequencer) -> Paragraph<'a> {
     let sel_idx = seq.selected_idx();
     let state = seq.state.lock().unwrap();
-    let progression = state.progression.chords();
-    let sel_item = &state.progression.sequence[sel_idx];
-    let selected_chord = if sel_item.is_some() {
-        let chord_idx = state.progression.seq_idx_to_chord_idx(sel_idx);
-        Some(chord_idx)
-    } else {
-        None
-    };
+    let progression = state.progression().chords();
+    let selected_chord = state.progression().iter_timed().position(|(i, _)| i == sel_idx);
     let cur_idx = state.clip_start() + state.tick;
 
     // The lines that will be rendered.
@@ -39,7 +34,7 @@ pub fn render<'a>(seq: &Sequencer) -> Paragraph<'a> {
         let name = format!("{:^5}", (i+1).to_string());
 
         let style = if selected_chord.is_some() && i == selected_chord.unwrap() {
-            Style::default().fg(Color::LightBlue)
+            theme::selected()
         } else {
             Style::default()
         };
@@ -48,20 +43,24 @@ pub fn render<'a>(seq: &Sequencer) -> Paragraph<'a> {
     lines.push(Spans::from(chord_id_spans));
 
     // The spans for the chord
-    let chord_name_spans: Vec<Span> = progression.iter().enumerate().map(|(i, cs)| {
-        // Each chord has 5 spaces to work with
-        let name = format!("{:^5}", cs.to_string());
+    let chord_name_spans: Vec<Span> = state.progression().iter_timed().map(|(seq_idx, cs)| {
+        // For rendering chord notes,
+        // in the step's own key if it has one
+        let key = state.progression().key_at(seq_idx, &state.key);
 
-        // For rendering chord notes
-        let notes = cs.chord_for_key(&state.key).describe_notes();
+        // Each chord has 5 spaces to work with
+        let name = format!("{:^5}", symbols::chord_in_key(cs, &key));
+        let notes: Vec<String> = cs.chord_for_key(&key).describe_notes(symbols::accidentals())
+            .iter().map(|note| symbols::note(note)).collect();
         if notes.len() > required_lines {
             required_lines = notes.len();
         }
         chord_notes.push(notes);
 
-        let chord_idx = state.progression.chord_index[i];
-        let style = if chord_idx == cur_idx {
-            Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD)
+        let style = if seq_idx == cur_idx {
+            theme::playing().add_modifier(Modifier::BOLD)
+        } else if key != state.key {
+            theme::overridden().add_modifier(Modifier::BOLD)
         } else {
             Style::default().add_modifier(Modifier::BOLD)
         };
@@ -74,10 +73,13 @@ pub fn render<'a>(seq: &Sequencer) -> Paragraph<'a> {
         let chord_note_spans: Vec<Span> = chord_notes.iter().enumerate().filter_map(|(j, notes)| {
             if i < notes.len() {
                 let position = j * 5; // Each chord has 5 spaces to work with
-                let padding = position - cur_len;
+
+                // A wider note before this one can
+                // already have taken up its space
+                let padding = position.saturating_sub(cur_len);
                 let padding = std::iter::repeat(' ').take(padding).collect::<String>();
                 let note = format!("{}{:^5}", padding, notes[i]);
-                cur_len += note.len();
+                cur_len += note.chars().count();
                 Some(Span::raw(note))
             } else {
                 None
@@ -99,21 +101,19 @@ pub fn render<'a>(seq: &Sequencer) -> Paragraph<'a> {
 pub fn process_input(seq: &mut Sequencer, key: KeyEvent) -> Result<()> {
     let sel_idx = seq.selected_idx();
     let mut state = seq.state.lock().unwrap();
-    let sel_item = &state.progression.sequence[sel_idx];
-    let selected_chord = if sel_item.is_some() {
-        let chord_idx = state.progression.seq_idx_to_chord_idx(sel_idx);
-        Some(chord_idx)
-    } else {
-        None
-    };
+    let selected_chord = state.progression().iter_timed().position(|(i, _)| i == sel_idx);
 
     match key.code {
         KeyCode::Char('U') => {
             // Cycle up a chord
             if let Some(chord_idx) = selected_chord {
-                let prev_chord = state.progression.prev_chord(chord_idx);
+                let prev_chord = state.progression().prev_chord(chord_idx);
                 let cands = seq.template.next(prev_chord, &state.key.mode);
-                let current = state.progression.chord(chord_idx).unwrap();
+                if cands.is_empty() {
+                    seq.log.push("No chords to cycle through");
+                    return Ok(());
+                }
+                let current = state.progression().chord(chord_idx).unwrap();
                 let idx = if let Some(idx) = cands.iter().position(|cs| cs == current) {
                     if idx == cands.len() - 1 {
                         0
@@ -123,15 +123,19 @@ pub fn process_input(seq: &mut Sequencer, key: KeyEvent) -> Result<()> {
                 } else {
                     0
                 };
-                state.progression.set_chord(chord_idx, cands[idx].clone());
+                state.progression_mut().set_chord(chord_idx, cands[idx].clone());
             }
         }
         KeyCode::Char('D') => {
             // Cycle down a chord
             if let Some(chord_idx) = selected_chord {
-                let prev_chord = state.progression.prev_chord(chord_idx);
+                let prev_chord = state.progression().prev_chord(chord_idx);
                 let cands = seq.template.next(prev_chord, &state.key.mode);
-                let current = state.progression.chord(chord_idx).unwrap();
+                if cands.is_empty() {
+                    seq.log.push("No chords to cycle through");
+                    return Ok(());
+                }
+                let current = state.progression().chord(chord_idx).unwrap();
                 let idx = if let Some(idx) = cands.iter().position(|cs| cs == current) {
                     if idx == 0 {
                         cands.len() - 1
@@ -141,7 +145,7 @@ pub fn process_input(seq: &mut Sequencer, key: KeyEvent) -> Result<()> {
                 } else {
                     0
                 };
-                state.progression.set_chord(chord_idx, cands[idx].clone());
+                state.progression_mut().set_chord(chord_idx, cands[idx].clone());
             }
         }
         _ => {}
@@ -152,7 +156,7 @@ pub fn process_input(seq: &mut Sequencer, key: KeyEvent) -> Result<()> {
 pub fn controls<'a>(seq: &Sequencer) -> Vec<Span<'a>> {
     let sel_idx = seq.selected_idx();
     let state = seq.state.lock().unwrap();
-    let sel_item = &state.progression.sequence[sel_idx];
+    let sel_item = &state.progression().sequence[sel_idx];
     if sel_item.is_some() {
         vec![
             Span::raw(" [U]p [D]own"),
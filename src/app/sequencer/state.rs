@@ -1,6 +1,93 @@
 use anyhow::Result;
+use std::time;
 use crate::core::{Key, Chord, ChordSpec, Duration};
-use crate::progression::{Progression, ProgressionTemplate};
+use crate::progression::{Progression, ProgressionTemplate, Cadence, Accent, Hit, BassPattern, bassline, swing_delay};
+use crate::midi::Arp;
+use crate::file::{TimedChord, VELOCITY, TempoChange, ExportTrack, ExportOptions, EXPORT_TICKS_PER_BEAT, save_timed_to_midi_file};
+use super::metronome::{Metronome, TICKS_PER_BEAT};
+
+/// A section of the arrangement,
+/// playing a scene some number of times.
+#[derive(Debug, Clone, Copy)]
+pub struct Section {
+    pub scene: usize,
+    pub repeats: usize,
+
+    // Tempo to change to, if any, and whether
+    // to ramp up or down to it across the section
+    // instead of changing when it starts
+    pub tempo: Option<usize>,
+    pub ramp: bool,
+}
+
+impl Section {
+    pub fn new(scene: usize) -> Section {
+        Section {
+            scene,
+            repeats: 1,
+            tempo: None,
+            ramp: false,
+        }
+    }
+}
+
+/// Name of the track the sequencer starts with.
+pub const DEFAULT_TRACK: &str = "chords";
+
+/// A track of the sequencer, playing its own
+/// progression on its own MIDI channel and port.
+#[derive(Debug, Clone)]
+pub struct Track {
+    pub name: String,
+    pub progression: Progression,
+
+    // MIDI channel to play on, 0-15, and which of
+    // the connected ports to play on, by name,
+    // if not the output's channel and every port
+    pub channel: Option<u8>,
+    pub port: Option<String>,
+
+    pub muted: bool,
+    pub solo: bool,
+
+    // Bassline played along with the
+    // chords on the bass channel, if any
+    pub bass: Option<BassPattern>,
+
+    // Loop and step to play next, while
+    // another track is being edited
+    pub clip: (usize, usize),
+    pub tick: usize,
+}
+
+impl Track {
+    pub fn new(name: &str, progression: Progression) -> Track {
+        Track {
+            name: name.to_string(),
+            clip: (0, progression.sequence.len()),
+            progression,
+            channel: None,
+            port: None,
+            muted: false,
+            solo: false,
+            bass: None,
+            tick: 0,
+        }
+    }
+}
+
+/// What a track not being edited plays on a step:
+/// its chord and when to trigger it, if it plays,
+/// and whether it's arpeggiated, its bassline's note
+/// and how many steps it's held, if one starts,
+/// with how long its steps last.
+pub struct TrackStep {
+    pub track: usize,
+    pub chord: Option<(Chord, Vec<Hit>)>,
+    pub arp: Option<Arp>,
+    pub bass: Option<(Chord, usize)>,
+    pub step_length: time::Duration,
+}
 
 pub struct PlaybackState {
     pub tick: usize,
@@ -9,10 +96,51 @@ pub struct PlaybackState {
     // Params
     pub bars: usize,
     pub key: Key,
-    pub note_duration: u64,
+    pub tempo: usize,
     pub resolution: Duration,
 
-    pub progression: Progression,
+    // Percentage to swing off-beat steps by,
+    // where 50% is straight
+    pub swing: usize,
+
+    // How loud chords are across each bar
+    pub accent: Accent,
+
+    // Cadence to end generated progressions with
+    pub cadence: Option<Cadence>,
+
+    // Voice lead chords as they're played,
+    // leaving the progression as written
+    pub voice_lead: bool,
+
+    // The tracks and which is being edited
+    pub tracks: Vec<Track>,
+    pub track: usize,
+
+    // MIDI channel basslines play on, 0-15
+    pub bass_channel: u8,
+
+    pub metronome: Metronome,
+
+    // Clock ticks left to count off
+    // before playback starts
+    pub count_off: usize,
+
+    // Whether the clock is running, the step
+    // last played and clock ticks since then
+    pub playing: bool,
+    pub played: Option<usize>,
+    pub step_ticks: usize,
+
+    // Saved progressions and the order
+    // in which they're played
+    pub scenes: Vec<Progression>,
+    pub arrangement: Vec<Section>,
+
+    // The section being played and how many
+    // times it's been repeated so far,
+    // if following the arrangement
+    pub section: Option<(usize, usize)>,
 }
 
 impl PlaybackState {
@@ -20,16 +148,30 @@ impl PlaybackState {
         let bars = 2;
         let key = Key::default();
         let resolution = Duration::Eighth;
-        let progression = template.gen_progression(&key.mode, bars, &resolution);
+        let progression = template.gen_progression(&key.mode, bars, &resolution, None);
 
         PlaybackState {
             tick: 0,
             clip: (0, progression.sequence.len()),
             bars,
             key,
+            tempo: 120,
             resolution,
-            note_duration: 5,
-            progression,
+            swing: 50,
+            accent: Accent::default(),
+            cadence: None,
+            voice_lead: false,
+            tracks: vec![Track::new(DEFAULT_TRACK, progression)],
+            track: 0,
+            bass_channel: 1,
+            metronome: Metronome::default(),
+            count_off: 0,
+            playing: false,
+            played: None,
+            step_ticks: 0,
+            scenes: vec![],
+            arrangement: vec![],
+            section: None,
         }
     }
 
@@ -37,11 +179,237 @@ impl PlaybackState {
         self.tick += 1;
         if self.tick >= self.clip_len() {
             self.tick = 0;
+            self.next_section();
+        }
+    }
+
+    /// The track being edited.
+    pub fn current_track(&self) -> &Track {
+        &self.tracks[self.track]
+    }
+
+    pub fn current_track_mut(&mut self) -> &mut Track {
+        &mut self.tracks[self.track]
+    }
+
+    /// The progression of the track being edited.
+    pub fn progression(&self) -> &Progression {
+        &self.tracks[self.track].progression
+    }
+
+    pub fn progression_mut(&mut self) -> &mut Progression {
+        &mut self.tracks[self.track].progression
+    }
+
+    /// A track's progression, by its index.
+    pub fn track_progression(&self, idx: usize) -> &Progression {
+        &self.tracks[idx].progression
+    }
+
+    /// A track's loop, if it doesn't play its whole
+    /// progression. For the track being edited it's
+    /// the one in play.
+    pub fn track_loop(&self, idx: usize) -> Option<(usize, usize)> {
+        let (a, b) = if idx == self.track { self.clip } else { self.tracks[idx].clip };
+        let len = self.tracks[idx].progression.sequence.len();
+        (a > 0 || b < len).then_some((a, b))
+    }
+
+    /// Whether a track is heard: if any
+    /// are soloed, only those are,
+    /// otherwise all but the muted ones.
+    pub fn audible(&self, idx: usize) -> bool {
+        let track = &self.tracks[idx];
+        if self.tracks.iter().any(|t| t.solo) {
+            track.solo
+        } else {
+            !track.muted
+        }
+    }
+
+    /// Edit another track, which picks up playing from
+    /// where it is in its loop, while the one that was
+    /// being edited carries on from where it was.
+    pub fn select_track(&mut self, idx: usize) {
+        if idx == self.track || idx >= self.tracks.len() {
+            return;
+        }
+        let pos = self.clip_start() + self.tick;
+        let clip = self.clip;
+        let track = &mut self.tracks[self.track];
+        track.clip = clip;
+        track.tick = pos % track.progression.sequence.len().max(1);
+
+        self.track = idx;
+        self.bars = self.progression().bars();
+        self.resolution = self.progression().resolution;
+        let len = self.progression().sequence.len().max(1);
+        let (a, b) = self.tracks[idx].clip;
+        self.clip = if a < b && b <= len { (a, b) } else { (0, len) };
+        let pos = self.tracks[idx].tick % len;
+        self.tick = if pos >= self.clip.0 {
+            (pos - self.clip.0) % self.clip_len()
+        } else {
+            0
+        };
+    }
+
+    /// Replace the tracks, editing the first from its start.
+    pub fn set_tracks(&mut self, tracks: Vec<Track>) {
+        if tracks.is_empty() {
+            return;
+        }
+        self.tracks = tracks;
+        self.track = 0;
+        self.bars = self.progression().bars();
+        self.resolution = self.progression().resolution;
+        self.reset_clip();
+        self.reset_tick();
+    }
+
+    /// Add an empty track, as long as the
+    /// one being edited, and edit it.
+    pub fn add_track(&mut self, name: &str) {
+        let progression = Progression::new(vec![None; self.progression().sequence.len()], self.resolution);
+        let mut track = Track::new(name, progression);
+        track.tick = self.clip_start() + self.tick;
+        self.tracks.push(track);
+        self.select_track(self.tracks.len() - 1);
+    }
+
+    /// Remove the track being edited, editing the
+    /// next one, unless it's the only track.
+    pub fn remove_track(&mut self) -> bool {
+        if self.tracks.len() < 2 {
+            return false;
+        }
+        let idx = self.track;
+        self.select_track(if idx + 1 < self.tracks.len() { idx + 1 } else { idx - 1 });
+        self.tracks.remove(idx);
+        if self.track > idx {
+            self.track -= 1;
+        }
+        true
+    }
+
+    /// Load a scene for playback, from its start.
+    pub fn load_scene(&mut self, idx: usize) {
+        if let Some(progression) = self.scenes.get(idx) {
+            *self.progression_mut() = progression.clone();
+            self.bars = self.progression().bars();
+            self.resolution = self.progression().resolution;
+            self.reset_clip();
+            self.tick = 0;
+        }
+    }
+
+    /// Play the arrangement, starting at the given section.
+    pub fn play_section(&mut self, idx: usize) {
+        if let Some(section) = self.arrangement.get(idx) {
+            let scene = section.scene;
+            self.section = Some((idx, 0));
+            self.load_scene(scene);
+        }
+    }
+
+    /// Move on to the next section of the arrangement
+    /// once the current one has been repeated enough times.
+    fn next_section(&mut self) {
+        if let Some((idx, played)) = self.section {
+            let repeats = match self.arrangement.get(idx) {
+                Some(section) => section.repeats,
+                None => 0,
+            };
+            if played + 1 < repeats {
+                self.section = Some((idx, played + 1));
+            } else if self.arrangement.is_empty() {
+                self.section = None;
+            } else {
+                self.play_section((idx + 1) % self.arrangement.len());
+            }
+        }
+    }
+
+    /// How many steps a section plays for, across its repeats.
+    fn section_len(&self, section: &Section) -> usize {
+        self.scenes.get(section.scene)
+            .map_or(0, |scene| scene.sequence.len() * section.repeats)
+    }
+
+    /// The tempo at a step into a section of the arrangement.
+    /// Sections without a tempo keep the one before them,
+    /// and the arrangement starts at the session tempo.
+    pub fn tempo_at(&self, idx: usize, step: usize) -> usize {
+        let before = self.arrangement[..idx].iter().rev()
+            .find_map(|section| section.tempo)
+            .unwrap_or(self.tempo);
+        match self.arrangement.get(idx) {
+            Some(section) => match section.tempo {
+                Some(tempo) if section.ramp => {
+                    let len = self.section_len(section).max(1);
+                    let progress = step.min(len) as f64 / len as f64;
+                    (before as f64 + (tempo as f64 - before as f64) * progress).round() as usize
+                }
+                Some(tempo) => tempo,
+                None => before,
+            },
+            None => before,
+        }
+    }
+
+    /// The tempo to play the current step at.
+    pub fn current_tempo(&self) -> usize {
+        match self.section {
+            Some((idx, played)) => self.tempo_at(idx, played * self.clip_len() + self.tick),
+            None => self.tempo,
         }
     }
 
+    /// The arrangement's chords and tempo changes,
+    /// timed in ticks of the export resolution.
+    pub fn arrangement_events(&self, options: ExportOptions) -> (Vec<TimedChord>, Vec<TempoChange>) {
+        let mut chords = vec![];
+        let mut tempos = vec![TempoChange { tick: 0, bpm: self.tempo }];
+        let mut tick = 0;
+        for (idx, section) in self.arrangement.iter().enumerate() {
+            let scene = match self.scenes.get(section.scene) {
+                Some(scene) => scene,
+                None => continue,
+            };
+            let step_ticks = EXPORT_TICKS_PER_BEAT / scene.resolution.ticks_per_beat();
+            let pass = scene.timed_chords(&self.key, step_ticks, self.swing, &self.accent, options);
+            for repeat in 0..section.repeats {
+                let offset = tick + repeat * scene.sequence.len() * step_ticks;
+                chords.extend(pass.iter().map(|timed| TimedChord {
+                    start: timed.start + offset,
+                    ..timed.clone()
+                }));
+            }
+            for step in 0..self.section_len(section) {
+                let bpm = self.tempo_at(idx, step);
+                if tempos.last().is_some_and(|change| change.bpm != bpm) {
+                    tempos.push(TempoChange { tick, bpm });
+                }
+                tick += step_ticks;
+            }
+        }
+        (chords, tempos)
+    }
+
+    /// Export the arrangement to a MIDI file, on the
+    /// track's MIDI channel or the export channel.
+    pub fn export_arrangement(&self, options: ExportOptions, path: String) -> Result<()> {
+        let (chords, tempos) = self.arrangement_events(options);
+        let channel = self.current_track().channel.unwrap_or(options.channel);
+        let options = ExportOptions { channel, ..options };
+        save_timed_to_midi_file(EXPORT_TICKS_PER_BEAT, &chords, &tempos, Some(&self.key), options, path)
+    }
+
     pub fn reset_tick(&mut self) {
         self.tick = 0;
+        for track in &mut self.tracks {
+            track.tick = 0;
+        }
     }
 
     pub fn clip_len(&self) -> usize {
@@ -53,19 +421,131 @@ impl PlaybackState {
     }
 
     pub fn reset_clip(&mut self) {
-        self.clip = (0, self.progression.sequence.len());
+        self.set_clip((0, self.progression().sequence.len()));
+    }
+
+    /// Loop a range of steps, which needn't be whole bars.
+    /// Playback stays on the same step if it's in the range,
+    /// otherwise it wraps around into it.
+    pub fn set_clip(&mut self, clip: (usize, usize)) {
+        let (a, b) = clip;
+        let b = b.min(self.progression().sequence.len()).max(a + 1);
+        let pos = self.clip.0 + self.tick;
+        self.clip = (a, b);
+        self.tick = if pos >= a {
+            (pos - a) % (b - a)
+        } else {
+            0
+        };
     }
 
     pub fn has_loop(&self) -> bool {
         let (a, b) = self.clip;
         let a_clip = a > 0;
-        let b_clip = b < self.progression.sequence.len();
+        let b_clip = b < self.progression().sequence.len();
         a_clip || b_clip
     }
 
+    /// Change the number of bars, keeping the chords in
+    /// the bars that are left and, if given a template,
+    /// generating chords for any new bars.
+    pub fn set_bars(&mut self, bars: usize, fill: Option<&ProgressionTemplate>) {
+        let len = self.progression().sequence.len();
+        let looping = self.has_loop();
+        self.bars = bars;
+        *self.progression_mut() = self.progression().with_bars(bars);
+
+        let new_len = self.progression().sequence.len();
+        if let Some(template) = fill {
+            if new_len > len {
+                *self.progression_mut() = template.regenerate(self.progression(), (len, new_len), &self.key.mode, self.cadence);
+            }
+        }
+        if looping {
+            let clip = self.clip;
+            self.set_clip(clip);
+        } else {
+            self.reset_clip();
+        }
+    }
+
+    /// Insert an empty bar before the given bar,
+    /// moving the loop along with the steps after it.
+    pub fn insert_bar(&mut self, bar: usize) {
+        let ticks_per_bar = self.progression().resolution.ticks_per_bar();
+        let at = bar * ticks_per_bar;
+        let looping = self.has_loop();
+        self.progression_mut().insert_bar(bar);
+        self.bars = self.progression().bars();
+        if looping {
+            let (a, b) = self.clip;
+            let a = if a >= at { a + ticks_per_bar } else { a };
+            let b = if b > at { b + ticks_per_bar } else { b };
+            // Keep the playhead where it is in the loop
+            self.clip = (a, b);
+            self.set_clip((a, b));
+        } else {
+            self.reset_clip();
+        }
+    }
+
+    /// Remove a bar, moving the loop
+    /// back with the steps after it.
+    pub fn delete_bar(&mut self, bar: usize) {
+        let ticks_per_bar = self.progression().resolution.ticks_per_bar();
+        let (start, end) = (bar * ticks_per_bar, (bar + 1) * ticks_per_bar);
+        let looping = self.has_loop();
+        self.progression_mut().delete_bar(bar);
+        self.bars = self.progression().bars();
+
+        let shift = |idx: usize| if idx >= end {
+            idx - ticks_per_bar
+        } else {
+            idx.min(start)
+        };
+        let (a, b) = (shift(self.clip.0), shift(self.clip.1));
+        if looping && a < b {
+            // Keep the playhead where it is in the loop
+            self.clip = (a, b);
+            self.set_clip((a, b));
+        } else {
+            self.reset_clip();
+        }
+    }
+
+    /// Stretch the progression to twice its length,
+    /// or compress it to half, along with the loop.
+    pub fn stretch(&mut self, double: bool) {
+        let looping = self.has_loop();
+        let (a, b) = self.clip;
+        let pos = a + self.tick;
+        if double {
+            *self.progression_mut() = self.progression().stretch();
+            self.clip = (a * 2, b * 2);
+            self.tick = pos * 2 - self.clip.0;
+        } else {
+            *self.progression_mut() = self.progression().compress();
+            self.clip = (a / 2, (b + 1) / 2);
+            self.tick = (pos / 2).saturating_sub(self.clip.0);
+        }
+        self.bars = self.progression().bars();
+        if looping {
+            let clip = self.clip;
+            self.set_clip(clip);
+        } else {
+            self.reset_clip();
+        }
+    }
+
+    /// How many chords are past the given number of bars.
+    pub fn chords_past(&self, bars: usize) -> usize {
+        let len = bars * self.progression().resolution.ticks_per_bar();
+        self.progression().iter_timed().filter(|(idx, _)| *idx >= len).count()
+    }
+
     /// Generates and plays a new random progression.
     pub fn gen_progression(&mut self, template: &ProgressionTemplate) -> Result<()> {
-        self.progression = template.gen_progression(&self.key.mode, self.bars, &self.resolution);
+        *self.progression_mut() = template.gen_progression(&self.key.mode, self.bars, &self.resolution, self.cadence);
         self.reset_clip();
         Ok(())
     }
@@ -73,18 +553,340 @@ impl PlaybackState {
     /// Generates and plays a new random progression,
     /// starting with a specific chord.
     pub fn gen_progression_from_seed(&mut self, chord: &ChordSpec, template: &ProgressionTemplate) -> Result<()> {
-        self.progression = template.gen_progression_from_seed(chord, &self.key.mode, self.bars, &self.resolution);
+        *self.progression_mut() = template.gen_progression_from_seed(chord, &self.key.mode, self.bars, &self.resolution, self.cadence);
         self.reset_clip();
         Ok(())
     }
 
-    /// The current chord (if any) for the current tick
-    pub fn current_chord(&self) -> Option<(Chord, u64)> {
-        let i = self.tick + self.clip_start();
-        if let Some(chord_spec) = &self.progression.sequence[i] {
-            Some((chord_spec.chord_for_key(&self.key), self.note_duration))
+    /// The current chord (if any) for the current tick,
+    /// and when to trigger it within the step, with swing
+    /// and the accent for where it is in the bar
+    pub fn current_chord(&self) -> Option<(Chord, Vec<Hit>)> {
+        self.chord_at(self.progression(), self.tick + self.clip_start(), self.clip)
+    }
+
+    /// A progression's chord (if any) at a step, and when
+    /// to trigger it, looping within the given range.
+    fn chord_at(&self, progression: &Progression, i: usize, range: (usize, usize)) -> Option<(Chord, Vec<Hit>)> {
+        if let Some(Some(chord_spec)) = progression.sequence.get(i) {
+            let chord_spec = if self.voice_lead {
+                progression.voice_lead_circular().sequence[i].clone().unwrap()
+            } else {
+                chord_spec.clone()
+            };
+            let key = progression.key_at(i, &self.key);
+            let delay = swing_delay(i, self.swing);
+            let accent = self.accent.level(i, progression.resolution.ticks_per_bar());
+            let hits = progression.hits(i, range).into_iter()
+                .map(|hit| Hit { offset: hit.offset + delay, level: hit.level * accent, ..hit })
+                .collect();
+            Some((chord_spec.chord_for_key(&key), hits))
         } else {
             None
         }
     }
+
+    /// The note the current track's bassline
+    /// starts on the current tick, if any.
+    pub fn current_bass(&self) -> Option<(Chord, usize)> {
+        self.bass_at(self.track, self.tick + self.clip_start())
+    }
+
+    /// The note a track's bassline starts at a step,
+    /// if any, as a chord of one note, and how
+    /// many steps it's held for.
+    pub fn bass_at(&self, idx: usize, i: usize) -> Option<(Chord, usize)> {
+        let pattern = self.tracks[idx].bass?;
+        bassline(self.track_progression(idx), &self.key, pattern).into_iter()
+            .find(|note| note.step == i)
+            .map(|note| (Chord::new(note.note, vec![0]), note.length))
+    }
+
+    /// Step the tracks not being edited along their own
+    /// progressions, at their own resolutions, on a clock
+    /// tick. Returns what the tracks that are heard play.
+    pub fn step_tracks(&mut self, clock_tick: usize) -> Vec<TrackStep> {
+        let mut steps = vec![];
+        for idx in 0..self.tracks.len() {
+            let progression = &self.tracks[idx].progression;
+            let resolution = progression.resolution;
+            if idx == self.track || clock_tick % (TICKS_PER_BEAT / resolution.ticks_per_beat()) != 0 {
+                continue;
+            }
+            let len = progression.sequence.len().max(1);
+            let step = self.tracks[idx].tick % len;
+            if self.audible(idx) {
+                let chord = if progression.plays(step) {
+                    self.chord_at(progression, step, (0, len))
+                } else {
+                    None
+                };
+                let bass = self.bass_at(idx, step);
+                if chord.is_some() || bass.is_some() {
+                    steps.push(TrackStep {
+                        track: idx,
+                        chord,
+                        arp: progression.step(step).arp,
+                        bass,
+                        step_length: self.step_length_at(resolution),
+                    });
+                }
+            }
+            self.tracks[idx].tick = (step + 1) % len;
+        }
+        steps
+    }
+
+    /// Each track as it's exported, its chords timed
+    /// in ticks of the export resolution and written on
+    /// its MIDI channel or the export channel, with
+    /// only the chords that play per their chances if baked.
+    /// Tracks with a bassline are followed by it, on the bass channel.
+    pub fn export_tracks(&self, options: ExportOptions, baked: bool) -> Vec<ExportTrack> {
+        let mut tracks = vec![];
+        for (idx, track) in self.tracks.iter().enumerate() {
+            let progression = self.track_progression(idx);
+            let progression = if baked {
+                progression.bake()
+            } else {
+                progression.clone()
+            };
+            let step_ticks = EXPORT_TICKS_PER_BEAT / progression.resolution.ticks_per_beat();
+            tracks.push(ExportTrack {
+                name: track.name.clone(),
+                channel: track.channel.unwrap_or(options.channel),
+                chords: progression.timed_chords(&self.key, step_ticks, self.swing, &self.accent, options),
+            });
+            if let Some(pattern) = track.bass {
+                let notes = bassline(&progression, &self.key, pattern).into_iter()
+                    .map(|note| TimedChord {
+                        chord: Chord::new(note.note, vec![0]),
+                        start: note.step * step_ticks,
+                        length: note.length * step_ticks,
+                        velocity: VELOCITY,
+                        channel: None,
+                        marker: None,
+                    })
+                    .collect();
+                tracks.push(ExportTrack {
+                    name: format!("{} bass", track.name),
+                    channel: self.bass_channel,
+                    chords: notes,
+                });
+            }
+        }
+        tracks
+    }
+
+    /// Where playback is, as the bar and beat, from 1,
+    /// and the clock tick into the beat, from 0.
+    /// Before anything has played it's the loop's start.
+    pub fn position(&self) -> (usize, usize, usize) {
+        let step = self.played.unwrap_or(self.clip_start() + self.tick);
+        let steps_per_bar = self.resolution.ticks_per_bar();
+        let steps_per_beat = self.resolution.ticks_per_beat();
+        let ticks_per_step = TICKS_PER_BEAT / steps_per_beat;
+        let tick = (step % steps_per_beat) * ticks_per_step + self.step_ticks.min(ticks_per_step - 1);
+        (step / steps_per_bar + 1, (step % steps_per_bar) / steps_per_beat + 1, tick)
+    }
+
+    /// How long a beat lasts at the current tempo.
+    pub fn beat_length(&self) -> time::Duration {
+        time::Duration::from_secs_f64(60. / self.current_tempo().max(1) as f64)
+    }
+
+    /// How long a step lasts at the current tempo.
+    pub fn step_length(&self) -> time::Duration {
+        self.step_length_at(self.resolution)
+    }
+
+    /// How long a step of the given resolution
+    /// lasts at the current tempo.
+    fn step_length_at(&self, resolution: Duration) -> time::Duration {
+        let step_secs = 60. / (self.current_tempo().max(1) * resolution.ticks_per_beat()) as f64;
+        time::Duration::from_secs_f64(step_secs)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::core::ChordSpec;
+
+    #[test]
+    fn test_set_clip() {
+        let template = ProgressionTemplate::default();
+        let mut state = PlaybackState::new(&template);
+        let len = state.progression().sequence.len();
+        state.tick = 5;
+
+        // A 7 step loop keeps playing the same step
+        state.set_clip((2, 9));
+        assert_eq!(state.clip_len(), 7);
+        assert_eq!(state.tick, 3);
+
+        state.set_clip((2, 5));
+        assert_eq!(state.tick, 0);
+        for _ in 0..4 {
+            state.tick();
+        }
+        assert_eq!(state.tick, 1);
+
+        state.reset_clip();
+        assert_eq!(state.clip, (0, len));
+        assert_eq!(state.tick, 3);
+    }
+
+    #[test]
+    fn test_position() {
+        let template = ProgressionTemplate::default();
+        let mut state = PlaybackState::new(&template);
+        assert_eq!(state.position(), (1, 1, 0));
+
+        // Eighths, so the 12th step is the
+        // second half of bar 2's second beat
+        state.played = Some(11);
+        state.step_ticks = 5;
+        assert_eq!(state.position(), (2, 2, 17));
+    }
+
+    #[test]
+    fn test_set_bars() {
+        let template = ProgressionTemplate::default();
+        let mut state = PlaybackState::new(&template);
+        let chords: Vec<ChordSpec> = ["I", "IV", "V", "vi"].iter()
+            .map(|c| c.parse().unwrap()).collect();
+        *state.progression_mut() = Progression::from_chords(chords, 2, Duration::Quarter);
+        state.reset_clip();
+
+        // New bars are empty unless filled
+        state.set_bars(3, None);
+        assert_eq!(state.progression().sequence.len(), 12);
+        assert_eq!(state.progression().iter_timed().map(|(i, _)| i).collect::<Vec<_>>(), vec![0, 2, 4, 6]);
+        assert_eq!(state.clip, (0, 12));
+        state.set_bars(4, Some(&template));
+        assert!(state.progression().sequence[12].is_some());
+
+        assert_eq!(state.chords_past(1), state.progression().chords().len() - 2);
+        state.set_bars(1, None);
+        assert_eq!(state.progression().iter_timed().map(|(i, _)| i).collect::<Vec<_>>(), vec![0, 2]);
+    }
+
+    #[test]
+    fn test_voice_lead() {
+        let template = ProgressionTemplate::default();
+        let mut state = PlaybackState::new(&template);
+        let chords: Vec<ChordSpec> = ["I", "IV", "V"].iter()
+            .map(|c| c.parse().unwrap()).collect();
+        *state.progression_mut() = Progression::from_chords(chords, 1, Duration::Quarter);
+        state.reset_clip();
+        state.tick = 2;
+
+        let voiced = state.progression().voice_lead_circular();
+        let written = state.progression().clone();
+        let as_written = state.current_chord().unwrap().0;
+        state.voice_lead = true;
+        let (chord, _) = state.current_chord().unwrap();
+        assert_eq!(chord, voiced.sequence[2].as_ref().unwrap().chord_for_key(&state.key));
+        assert_ne!(chord, as_written);
+        assert_eq!(*state.progression(), written);
+    }
+
+    #[test]
+    fn test_section_tempo() {
+        let template = ProgressionTemplate::default();
+        let mut state = PlaybackState::new(&template);
+        state.tempo = 100;
+        let chords = vec!["I".parse::<ChordSpec>().unwrap()];
+        state.scenes.push(Progression::from_chords(chords, 1, Duration::Quarter));
+        state.arrangement = vec![
+            Section::new(0),
+            Section { tempo: Some(140), ramp: true, ..Section::new(0) },
+            Section::new(0),
+        ];
+
+        assert_eq!(state.tempo_at(0, 0), 100);
+        assert_eq!(state.tempo_at(1, 0), 100);
+        assert_eq!(state.tempo_at(1, 2), 120);
+        assert_eq!(state.tempo_at(2, 0), 140);
+
+        let (chords, tempos) = state.arrangement_events(ExportOptions::default());
+        assert_eq!(chords.len(), 3);
+        assert_eq!(chords[1].start, 384);
+        assert_eq!(tempos, vec![
+            TempoChange { tick: 0, bpm: 100 },
+            TempoChange { tick: 480, bpm: 110 },
+            TempoChange { tick: 576, bpm: 120 },
+            TempoChange { tick: 672, bpm: 130 },
+            TempoChange { tick: 768, bpm: 140 },
+        ]);
+    }
+
+    #[test]
+    fn test_tracks() {
+        let template = ProgressionTemplate::default();
+        let mut state = PlaybackState::new(&template);
+        let chords: Vec<ChordSpec> = ["I", "IV", "V", "vi"].iter()
+            .map(|c| c.parse().unwrap()).collect();
+        *state.progression_mut() = Progression::from_chords(chords, 1, Duration::Quarter);
+        state.reset_clip();
+        let first = state.progression().clone();
+        state.tick = 1;
+
+        // A new track is empty and picks up where playback is
+        state.add_track("bass");
+        assert_eq!(state.track, 1);
+        assert_eq!(state.progression().sequence, vec![None; 4]);
+        assert_eq!(state.tick, 1);
+        state.progression_mut().sequence[3] = Some("I".parse().unwrap());
+        state.tick();
+
+        // The first track carries on playing from where it was
+        // while the new one is edited, a step per quarter
+        assert!(state.step_tracks(1).is_empty());
+        let played = state.step_tracks(24);
+        assert_eq!(played.len(), 1);
+        let chord = first.sequence[1].as_ref().unwrap().chord_for_key(&state.key);
+        assert_eq!(played[0].chord.as_ref().unwrap().0, chord);
+        assert_eq!(state.tracks[0].tick, 2);
+
+        // Muted or unsoloed tracks aren't heard
+        state.tracks[0].muted = true;
+        assert!(state.step_tracks(48).is_empty());
+        state.tracks[0].muted = false;
+
+        state.current_track_mut().solo = true;
+        assert!(!state.audible(0));
+        assert!(state.audible(1));
+
+        // Switching keeps each track's progression
+        state.select_track(0);
+        assert_eq!(*state.progression(), first);
+        assert_eq!(state.tick, 3);
+        assert!(state.track_progression(1).sequence[3].is_some());
+
+        // And its loop
+        state.set_clip((1, 3));
+        state.select_track(1);
+        assert_eq!(state.clip, (0, 4));
+        state.select_track(0);
+        assert_eq!(state.clip, (1, 3));
+        assert_eq!(state.track_loop(0), Some((1, 3)));
+        assert_eq!(state.track_loop(1), None);
+
+        assert!(state.remove_track());
+        assert_eq!(state.tracks.len(), 1);
+        assert!(state.progression().sequence[3].is_some());
+        assert!(!state.remove_track());
+
+        // A bassline plays along with its track's
+        // chords, and is exported after it
+        state.current_track_mut().bass = Some(BassPattern::Root);
+        state.tick = 3;
+        assert_eq!(state.current_bass().unwrap().1, 1);
+        let exported = state.export_tracks(ExportOptions::default(), false);
+        assert_eq!(exported.len(), 2);
+        assert_eq!(exported[1].name, format!("{} bass", state.current_track().name));
+        assert_eq!(exported[1].channel, state.bass_channel);
+    }
 }
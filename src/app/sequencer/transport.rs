@@ -0,0 +1,40 @@
+use tui::{
+    style::{Style, Modifier},
+    text::{Span, Spans},
+    widgets::Paragraph,
+};
+use super::Sequencer;
+use crate::app::theme;
+use super::metronome::TICKS_PER_BEAT;
+
+/// Where playback is and how: playing or stopped,
+/// the bar:beat:tick of the playhead, the clock
+/// source and how long the loop is.
+pub fn render<'a>(seq: &Sequencer) -> Paragraph<'a> {
+    let state = seq.state.lock().unwrap();
+    let (status, style) = if state.count_off > 0 {
+        // Beats left to count, like a drummer would
+        let beats = (state.count_off + TICKS_PER_BEAT - 1) / TICKS_PER_BEAT;
+        (format!("count-in {}", beats), theme::playing())
+    } else if state.playing {
+        ("playing".to_string(), theme::playing().add_modifier(Modifier::BOLD))
+    } else {
+        ("stopped".to_string(), theme::muted())
+    };
+    let (bar, beat, tick) = state.position();
+
+    let steps = state.clip_len();
+    let steps_per_bar = state.resolution.ticks_per_bar();
+    let loop_len = if steps % steps_per_bar == 0 {
+        let bars = steps / steps_per_bar;
+        format!("{} bar{}", bars, if bars == 1 { "" } else { "s" })
+    } else {
+        format!("{} steps", steps)
+    };
+
+    Paragraph::new(Spans::from(vec![
+        Span::styled(format!(" {:<12}", status), style),
+        Span::styled(format!("{}:{}:{:02}", bar, beat, tick), Style::default().add_modifier(Modifier::BOLD)),
+        Span::styled(format!("  clock:{}  loop:{}", seq.clock_source, loop_len), theme::muted()),
+    ]))
+}
@@ -0,0 +1,688 @@
+use anyhow::{Result, anyhow};
+use super::select::Select;
+use super::text_input::TextInput;
+use super::theme;
+use super::symbols::Symbols;
+use crate::config::Config;
+use crate::midi::{ClockSource, Output, NoteOff, MAX_SMOOTHING, MAX_OVERLAP};
+use crossterm::event::{KeyEvent, KeyCode};
+use tui::{
+    text::{Span, Spans},
+    style::{Style, Modifier},
+    widgets::{Block, Paragraph, Borders},
+    layout::{Rect, Alignment, Constraint, Direction, Layout},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Setting {
+    Output,
+    MidiIn,
+    NoteIn,
+    MidiOut,
+    Broadcast,
+    Channel,
+    BassChannel,
+    ExportChannel,
+    ChordMarkers,
+    NoteOff,
+    ReleaseVelocity,
+    Overlap,
+    Tempo,
+    Clock,
+    ClockOut,
+    Smoothing,
+    SaveDir,
+    Accidentals,
+    Theme,
+    Symbols,
+    KeyPicker,
+    Waveform,
+    Volume,
+    SoundFont,
+    Samples,
+    Metronome,
+    Accent,
+    Subdivide,
+    CountOff,
+}
+
+const SETTINGS: [Setting; 29] = [
+    Setting::Output,
+    Setting::MidiOut,
+    Setting::Broadcast,
+    Setting::Waveform,
+    Setting::Volume,
+    Setting::SoundFont,
+    Setting::Samples,
+    Setting::Metronome,
+    Setting::Channel,
+    Setting::BassChannel,
+    Setting::NoteOff,
+    Setting::ReleaseVelocity,
+    Setting::Overlap,
+    Setting::Clock,
+    Setting::ClockOut,
+    Setting::MidiIn,
+    Setting::Smoothing,
+    Setting::NoteIn,
+    Setting::Tempo,
+    Setting::Accent,
+    Setting::Subdivide,
+    Setting::CountOff,
+    Setting::SaveDir,
+    Setting::ExportChannel,
+    Setting::ChordMarkers,
+    Setting::Accidentals,
+    Setting::Theme,
+    Setting::Symbols,
+    Setting::KeyPicker,
+];
+
+impl Setting {
+    fn label(&self) -> &'static str {
+        match self {
+            Setting::Output => "Output",
+            Setting::MidiIn => "MIDI clock input",
+            Setting::NoteIn => "MIDI note input",
+            Setting::MidiOut => "MIDI output",
+            Setting::Broadcast => "Mirror output to",
+            Setting::Channel => "MIDI channel",
+            Setting::BassChannel => "Bass channel",
+            Setting::ExportChannel => "Export channel",
+            Setting::ChordMarkers => "Export chord markers",
+            Setting::NoteOff => "Note off",
+            Setting::ReleaseVelocity => "Release velocity",
+            Setting::Overlap => "Chord overlap",
+            Setting::Tempo => "Tempo",
+            Setting::Clock => "Clock source",
+            Setting::ClockOut => "Send clock",
+            Setting::Smoothing => "Clock smoothing",
+            Setting::SaveDir => "Save directory",
+            Setting::Accidentals => "Accidentals",
+            Setting::Theme => "Theme",
+            Setting::Symbols => "Symbols",
+            Setting::KeyPicker => "Key picker",
+            Setting::Waveform => "Preview waveform",
+            Setting::Volume => "Preview volume",
+            Setting::SoundFont => "Soundfont",
+            Setting::Samples => "Sample directory",
+            Setting::Metronome => "Metronome level",
+            Setting::Accent => "Accent downbeat",
+            Setting::Subdivide => "Subdivide clicks",
+            Setting::CountOff => "Count-off",
+        }
+    }
+
+    fn value(&self, config: &Config) -> String {
+        let port = |port: &Option<String>| port.clone().unwrap_or_else(|| "(none)".to_string());
+        match self {
+            Setting::Output => config.output.to_string(),
+            Setting::MidiIn => port(&config.midi_in_port),
+            Setting::NoteIn => port(&config.note_in_port),
+            Setting::MidiOut => port(&config.midi_out_port),
+            Setting::Broadcast => if config.broadcast_ports.is_empty() {
+                "(none)".to_string()
+            } else {
+                config.broadcast_ports.join(", ")
+            },
+            Setting::Channel => config.channel.to_string(),
+            Setting::BassChannel => config.bass_channel.to_string(),
+            Setting::ExportChannel => config.export_channel.to_string(),
+            Setting::ChordMarkers => config.chord_markers.to_string(),
+            Setting::NoteOff => config.note_off.to_string(),
+            Setting::ReleaseVelocity => config.release_velocity.to_string(),
+            Setting::Overlap => format!("{} ms", config.overlap),
+            Setting::Tempo => format!("{} bpm", config.tempo),
+            Setting::Clock => config.clock.to_string(),
+            Setting::ClockOut => yes_no(config.clock_out),
+            Setting::Smoothing => format!("{}%", config.clock_smoothing),
+            Setting::SaveDir => config.save_dir.to_string(),
+            Setting::Accidentals => config.accidentals.to_string(),
+            Setting::Theme => config.theme.to_string(),
+            Setting::Symbols => config.symbols.to_string(),
+            Setting::KeyPicker => if config.circle_of_fifths {
+                "circle of fifths".to_string()
+            } else {
+                "text".to_string()
+            },
+            Setting::Waveform => config.preview.waveform.to_string(),
+            Setting::Volume => format!("{}%", percent(config.preview.volume)),
+            Setting::Metronome => format!("{}%", percent(config.mixer.metronome)),
+            Setting::Accent => yes_no(config.metronome.accent),
+            Setting::Subdivide => yes_no(config.metronome.subdivide),
+            Setting::CountOff => match config.metronome.count_off {
+                0 => "off".to_string(),
+                1 => "1 bar".to_string(),
+                bars => format!("{} bars", bars),
+            },
+            Setting::SoundFont => port(&config.soundfont.path),
+            Setting::Samples => port(&config.samples),
+        }
+    }
+
+    /// Copy this setting's value from one config to another.
+    pub fn copy(&self, from: &Config, to: &mut Config) {
+        match self {
+            Setting::Output => to.output = from.output,
+            Setting::MidiIn => to.midi_in_port = from.midi_in_port.clone(),
+            Setting::NoteIn => to.note_in_port = from.note_in_port.clone(),
+            Setting::MidiOut => to.midi_out_port = from.midi_out_port.clone(),
+            Setting::Broadcast => to.broadcast_ports = from.broadcast_ports.clone(),
+            Setting::Channel => to.channel = from.channel,
+            Setting::BassChannel => to.bass_channel = from.bass_channel,
+            Setting::ExportChannel => to.export_channel = from.export_channel,
+            Setting::ChordMarkers => to.chord_markers = from.chord_markers,
+            Setting::NoteOff => to.note_off = from.note_off,
+            Setting::ReleaseVelocity => to.release_velocity = from.release_velocity,
+            Setting::Overlap => to.overlap = from.overlap,
+            Setting::Tempo => to.tempo = from.tempo,
+            Setting::Clock => to.clock = from.clock,
+            Setting::ClockOut => to.clock_out = from.clock_out,
+            Setting::Smoothing => to.clock_smoothing = from.clock_smoothing,
+            Setting::SaveDir => to.save_dir = from.save_dir.clone(),
+            Setting::Accidentals => to.accidentals = from.accidentals,
+            Setting::Theme => to.theme = from.theme,
+            Setting::Symbols => to.symbols = from.symbols,
+            Setting::KeyPicker => to.circle_of_fifths = from.circle_of_fifths,
+            Setting::Waveform => to.preview.waveform = from.preview.waveform,
+            Setting::Volume => to.preview.volume = from.preview.volume,
+            Setting::SoundFont => to.soundfont.path = from.soundfont.path.clone(),
+            Setting::Samples => to.samples = from.samples.clone(),
+            Setting::Metronome => to.mixer.metronome = from.mixer.metronome,
+            Setting::Accent => to.metronome.accent = from.metronome.accent,
+            Setting::Subdivide => to.metronome.subdivide = from.metronome.subdivide,
+            Setting::CountOff => to.metronome.count_off = from.metronome.count_off,
+        }
+    }
+}
+
+fn valid_channel(input: &str) -> Result<(), String> {
+    match input.parse::<u8>() {
+        Ok(1..=16) => Ok(()),
+        _ => Err("Must be 1-16".to_string()),
+    }
+}
+
+fn valid_velocity(input: &str) -> Result<(), String> {
+    match input.parse::<u8>() {
+        Ok(0..=127) => Ok(()),
+        _ => Err("Must be 0-127".to_string()),
+    }
+}
+
+fn valid_overlap(input: &str) -> Result<(), String> {
+    match input.parse::<isize>() {
+        Ok(ms) if ms.abs() <= MAX_OVERLAP => Ok(()),
+        _ => Err(format!("Must be -{} to {} ms", MAX_OVERLAP, MAX_OVERLAP)),
+    }
+}
+
+fn yes_no(value: bool) -> String {
+    if value { "yes" } else { "no" }.to_string()
+}
+
+fn percent(level: f32) -> usize {
+    (level * 100.).round() as usize
+}
+
+fn valid_level(input: &str) -> Result<(), String> {
+    match input.parse::<usize>() {
+        Ok(0..=100) => Ok(()),
+        _ => Err("Must be 0-100".to_string()),
+    }
+}
+
+fn valid_smoothing(input: &str) -> Result<(), String> {
+    match input.parse::<usize>() {
+        Ok(smoothing) if smoothing <= MAX_SMOOTHING => Ok(()),
+        _ => Err(format!("Must be 0-{}", MAX_SMOOTHING)),
+    }
+}
+
+fn valid_count_off(input: &str) -> Result<(), String> {
+    match input.parse::<usize>() {
+        Ok(0..=8) => Ok(()),
+        _ => Err("Must be 0-8".to_string()),
+    }
+}
+
+fn valid_tempo(input: &str) -> Result<(), String> {
+    match input.parse::<usize>() {
+        Ok(bpm) if bpm > 0 => Ok(()),
+        _ => Err("Must be a positive number".to_string()),
+    }
+}
+
+enum InputMode<'a> {
+    Normal,
+    Select(Select),
+    Text(TextInput<'a>),
+}
+
+pub struct Settings<'a> {
+    pub config: Config,
+    idx: usize,
+
+    // Available MIDI ports
+    in_ports: Vec<String>,
+    out_ports: Vec<String>,
+
+    input_mode: InputMode<'a>,
+}
+
+impl<'a> Settings<'a> {
+    pub fn new(config: Config, in_ports: Vec<String>, out_ports: Vec<String>) -> Settings<'a> {
+        Settings {
+            config,
+            idx: 0,
+            in_ports,
+            out_ports,
+            input_mode: InputMode::Normal,
+        }
+    }
+
+    fn selected(&self) -> Setting {
+        SETTINGS[self.idx]
+    }
+
+    pub fn render(&self, rect: Rect) -> Vec<(Paragraph, Rect)> {
+        let mut rects = vec![];
+
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([
+                // Main
+                Constraint::Min(6),
+
+                // Input chunk
+                Constraint::Length(1),
+            ].as_ref())
+            .split(rect);
+
+        let display_chunks = Layout::default()
+            .direction(Direction::Horizontal)
+            .margin(2)
+            .constraints([
+                    // Settings chunk
+                    Constraint::Ratio(1, 2),
+
+                    // Ports chunk
+                    Constraint::Ratio(1, 2),
+                ].as_ref())
+            .split(chunks[0]);
+
+        let label_style = Style::default().add_modifier(Modifier::BOLD);
+        let lines: Vec<Spans> = SETTINGS.iter().enumerate().map(|(i, setting)| {
+            let style = if i == self.idx {
+                theme::selected()
+            } else {
+                Style::default()
+            };
+            Spans::from(vec![
+                Span::styled(format!("{:<18}", setting.label()), label_style.patch(style)),
+                Span::styled(setting.value(&self.config), style),
+            ])
+        }).collect();
+        let settings = Paragraph::new(lines)
+            .block(
+                Block::default()
+                    .title("Settings")
+                    .borders(Borders::TOP)
+                    .style(Style::default())
+            );
+        rects.push((settings, display_chunks[0]));
+
+        match &self.input_mode {
+            InputMode::Select(select) => {
+                let height = display_chunks[1].height as usize;
+                rects.push((select.render(height), display_chunks[1]));
+            }
+            InputMode::Text(text_input) => {
+                rects.push((text_input.render(), chunks[1]));
+            }
+            InputMode::Normal => {
+                let help = Paragraph::new("[Enter] change [Esc] close")
+                    .alignment(Alignment::Right);
+                rects.push((help, chunks[1]));
+            }
+        }
+        rects
+    }
+
+    /// Start changing the selected setting.
+    /// Returns the setting if it was changed right away.
+    fn edit(&mut self) -> Option<Setting> {
+        let setting = self.selected();
+        match setting {
+            Setting::MidiIn | Setting::NoteIn | Setting::MidiOut => {
+                let (ports, current) = match setting {
+                    Setting::MidiIn => (&self.in_ports, &self.config.midi_in_port),
+                    Setting::NoteIn => (&self.in_ports, &self.config.note_in_port),
+                    _ => (&self.out_ports, &self.config.midi_out_port),
+                };
+                let mut select = Select::new(ports.clone());
+                if let Some(idx) = ports.iter().position(|p| Some(p) == current.as_ref()) {
+                    select.idx = idx;
+                }
+                self.input_mode = InputMode::Select(select);
+                None
+            }
+            Setting::Broadcast => {
+                // Choosing a port adds or removes it
+                let choices = self.out_ports.iter().map(|port| {
+                    if self.config.broadcast_ports.contains(port) {
+                        format!("{} (mirrored)", port)
+                    } else {
+                        port.to_string()
+                    }
+                }).collect();
+                self.input_mode = InputMode::Select(Select::new(choices));
+                None
+            }
+            Setting::Channel => {
+                let mut text_input = TextInput::new("Channel: ", |c: char| c.is_numeric())
+                    .validate(valid_channel);
+                text_input.set_input(self.config.channel.to_string());
+                self.input_mode = InputMode::Text(text_input);
+                None
+            }
+            Setting::BassChannel => {
+                let mut text_input = TextInput::new("Bass channel: ", |c: char| c.is_numeric())
+                    .validate(valid_channel);
+                text_input.set_input(self.config.bass_channel.to_string());
+                self.input_mode = InputMode::Text(text_input);
+                None
+            }
+            Setting::ExportChannel => {
+                let mut text_input = TextInput::new("Export channel: ", |c: char| c.is_numeric())
+                    .validate(valid_channel);
+                text_input.set_input(self.config.export_channel.to_string());
+                self.input_mode = InputMode::Text(text_input);
+                None
+            }
+            Setting::ReleaseVelocity => {
+                let mut text_input = TextInput::new("Release velocity: ", |c: char| c.is_numeric())
+                    .validate(valid_velocity);
+                text_input.set_input(self.config.release_velocity.to_string());
+                self.input_mode = InputMode::Text(text_input);
+                None
+            }
+            Setting::Overlap => {
+                // Negative for a gap between chords
+                let mut text_input = TextInput::new("Chord overlap (ms): ", |c: char| c.is_numeric() || c == '-')
+                    .validate(valid_overlap);
+                text_input.set_input(self.config.overlap.to_string());
+                self.input_mode = InputMode::Text(text_input);
+                None
+            }
+            Setting::Tempo => {
+                let mut text_input = TextInput::new("Tempo: ", |c: char| c.is_numeric())
+                    .validate(valid_tempo);
+                text_input.set_input(self.config.tempo.to_string());
+                self.input_mode = InputMode::Text(text_input);
+                None
+            }
+            Setting::Volume => {
+                let mut text_input = TextInput::new("Volume: ", |c: char| c.is_numeric())
+                    .validate(valid_level);
+                text_input.set_input(percent(self.config.preview.volume).to_string());
+                self.input_mode = InputMode::Text(text_input);
+                None
+            }
+            Setting::Metronome => {
+                let mut text_input = TextInput::new("Metronome level: ", |c: char| c.is_numeric())
+                    .validate(valid_level);
+                text_input.set_input(percent(self.config.mixer.metronome).to_string());
+                self.input_mode = InputMode::Text(text_input);
+                None
+            }
+            Setting::SaveDir => {
+                let mut text_input = TextInput::new("Save directory: ", |_c: char| true);
+                text_input.set_input(self.config.save_dir.to_string());
+                self.input_mode = InputMode::Text(text_input);
+                None
+            }
+            Setting::SoundFont => {
+                let mut text_input = TextInput::new("Soundfont (empty for none): ", |_c: char| true)
+                    .allow_empty();
+                text_input.set_input(self.config.soundfont.path.clone().unwrap_or_default());
+                self.input_mode = InputMode::Text(text_input);
+                None
+            }
+            Setting::Samples => {
+                let mut text_input = TextInput::new("Sample directory (empty for none): ", |_c: char| true)
+                    .allow_empty();
+                text_input.set_input(self.config.samples.clone().unwrap_or_default());
+                self.input_mode = InputMode::Text(text_input);
+                None
+            }
+            Setting::Smoothing => {
+                let mut text_input = TextInput::new("Clock smoothing: ", |c: char| c.is_numeric())
+                    .validate(valid_smoothing);
+                text_input.set_input(self.config.clock_smoothing.to_string());
+                self.input_mode = InputMode::Text(text_input);
+                None
+            }
+            Setting::CountOff => {
+                let mut text_input = TextInput::new("Count-off bars: ", |c: char| c.is_numeric())
+                    .validate(valid_count_off);
+                text_input.set_input(self.config.metronome.count_off.to_string());
+                self.input_mode = InputMode::Text(text_input);
+                None
+            }
+            Setting::NoteOff => {
+                self.config.note_off = match self.config.note_off {
+                    NoteOff::Message => NoteOff::ZeroVelocity,
+                    NoteOff::ZeroVelocity => NoteOff::Message,
+                };
+                Some(setting)
+            }
+            Setting::Accent => {
+                self.config.metronome.accent = !self.config.metronome.accent;
+                Some(setting)
+            }
+            Setting::Subdivide => {
+                self.config.metronome.subdivide = !self.config.metronome.subdivide;
+                Some(setting)
+            }
+            Setting::Output => {
+                self.config.output = match self.config.output {
+                    Output::Midi => Output::Audio,
+                    Output::Audio => Output::Midi,
+                };
+                Some(setting)
+            }
+            Setting::Clock => {
+                self.config.clock = match self.config.clock {
+                    ClockSource::External => ClockSource::Internal,
+                    #[cfg(feature = "jack")]
+                    ClockSource::Internal => ClockSource::Jack,
+                    #[cfg(feature = "jack")]
+                    ClockSource::Jack => ClockSource::External,
+                    #[cfg(not(feature = "jack"))]
+                    ClockSource::Internal => ClockSource::External,
+                };
+                Some(setting)
+            }
+            Setting::ClockOut => {
+                self.config.clock_out = !self.config.clock_out;
+                Some(setting)
+            }
+            Setting::Accidentals => {
+                self.config.accidentals = self.config.accidentals.next();
+                Some(setting)
+            }
+            Setting::ChordMarkers => {
+                self.config.chord_markers = self.config.chord_markers.next();
+                Some(setting)
+            }
+            Setting::Theme => {
+                self.config.theme = self.config.theme.next();
+                Some(setting)
+            }
+            Setting::Symbols => {
+                self.config.symbols = match self.config.symbols {
+                    Symbols::Ascii => Symbols::Unicode,
+                    Symbols::Unicode => Symbols::Ascii,
+                };
+                Some(setting)
+            }
+            Setting::KeyPicker => {
+                self.config.circle_of_fifths = !self.config.circle_of_fifths;
+                Some(setting)
+            }
+            Setting::Waveform => {
+                self.config.preview.waveform = self.config.preview.waveform.next();
+                Some(setting)
+            }
+        }
+    }
+
+    /// Process input and returns the setting
+    /// that was changed, if any,
+    /// and if the screen should be closed.
+    pub fn process_input(&mut self, key: KeyEvent) -> Result<(Option<Setting>, bool)> {
+        let setting = self.selected();
+        match &mut self.input_mode {
+            InputMode::Select(select) => {
+                let (selected, close) = select.process_input(key)?;
+                if close {
+                    self.input_mode = InputMode::Normal;
+                }
+                if let Some(idx) = selected {
+                    if setting == Setting::Broadcast {
+                        let port = self.out_ports[idx].to_string();
+                        let ports = &mut self.config.broadcast_ports;
+                        match ports.iter().position(|p| *p == port) {
+                            Some(i) => { ports.remove(i); }
+                            None => ports.push(port),
+                        }
+                        return Ok((Some(setting), false));
+                    }
+                    let ports = match setting {
+                        Setting::MidiIn | Setting::NoteIn => &self.in_ports,
+                        _ => &self.out_ports,
+                    };
+                    let port = Some(ports[idx].to_string());
+                    match setting {
+                        Setting::MidiIn => self.config.midi_in_port = port,
+                        Setting::NoteIn => self.config.note_in_port = port,
+                        _ => self.config.midi_out_port = port,
+                    }
+                    return Ok((Some(setting), false));
+                }
+                Ok((None, false))
+            }
+            InputMode::Text(text_input) => {
+                let (input, close) = text_input.process_input(key)?;
+                if close {
+                    self.input_mode = InputMode::Normal;
+                }
+                match input {
+                    Some(input) => {
+                        match setting {
+                            Setting::Channel => {
+                                valid_channel(&input).map_err(|err| anyhow!("Invalid channel: {}", err))?;
+                                self.config.channel = input.parse()?;
+                            }
+                            Setting::BassChannel => {
+                                valid_channel(&input).map_err(|err| anyhow!("Invalid channel: {}", err))?;
+                                self.config.bass_channel = input.parse()?;
+                            }
+                            Setting::ExportChannel => {
+                                valid_channel(&input).map_err(|err| anyhow!("Invalid channel: {}", err))?;
+                                self.config.export_channel = input.parse()?;
+                            }
+                            Setting::ReleaseVelocity => {
+                                valid_velocity(&input).map_err(|err| anyhow!("Invalid velocity: {}", err))?;
+                                self.config.release_velocity = input.parse()?;
+                            }
+                            Setting::Overlap => {
+                                valid_overlap(&input).map_err(|err| anyhow!("Invalid overlap: {}", err))?;
+                                self.config.overlap = input.parse()?;
+                            }
+                            Setting::Tempo => {
+                                valid_tempo(&input).map_err(|err| anyhow!("Invalid tempo: {}", err))?;
+                                self.config.tempo = input.parse()?;
+                            }
+                            Setting::SaveDir => {
+                                self.config.save_dir = input;
+                            }
+                            Setting::SoundFont => {
+                                self.config.soundfont.path = if input.is_empty() { None } else { Some(input) };
+                            }
+                            Setting::Samples => {
+                                self.config.samples = if input.is_empty() { None } else { Some(input) };
+                            }
+                            Setting::Volume => {
+                                valid_level(&input).map_err(|err| anyhow!("Invalid volume: {}", err))?;
+                                self.config.preview.volume = input.parse::<f32>()? / 100.;
+                            }
+                            Setting::Smoothing => {
+                                valid_smoothing(&input).map_err(|err| anyhow!("Invalid smoothing: {}", err))?;
+                                self.config.clock_smoothing = input.parse()?;
+                            }
+                            Setting::CountOff => {
+                                valid_count_off(&input).map_err(|err| anyhow!("Invalid count-off: {}", err))?;
+                                self.config.metronome.count_off = input.parse()?;
+                            }
+                            Setting::Metronome => {
+                                valid_level(&input).map_err(|err| anyhow!("Invalid level: {}", err))?;
+                                self.config.mixer.metronome = input.parse::<f32>()? / 100.;
+                            }
+                            _ => {}
+                        }
+                        Ok((Some(setting), false))
+                    }
+                    None => Ok((None, false))
+                }
+            }
+            InputMode::Normal => {
+                match key.code {
+                    KeyCode::Char('j') | KeyCode::Down => {
+                        self.idx = (self.idx + 1) % SETTINGS.len();
+                        Ok((None, false))
+                    }
+                    KeyCode::Char('k') | KeyCode::Up => {
+                        self.idx = (self.idx + SETTINGS.len() - 1) % SETTINGS.len();
+                        Ok((None, false))
+                    }
+                    KeyCode::Enter | KeyCode::Char('e') => {
+                        Ok((self.edit(), false))
+                    }
+                    KeyCode::Esc | KeyCode::Char(',') => {
+                        Ok((None, true))
+                    }
+                    _ => Ok((None, false))
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crossterm::event::KeyModifiers;
+
+    #[test]
+    fn test_clear_path() {
+        let mut config = Config::default();
+        config.soundfont.path = Some("piano.sf2".to_string());
+        config.samples = Some("samples".to_string());
+        let mut settings = Settings::new(config, vec![], vec![]);
+
+        let press = |settings: &mut Settings, code: KeyCode, modifiers: KeyModifiers| {
+            settings.process_input(KeyEvent { code, modifiers }).unwrap()
+        };
+        for setting in [Setting::SoundFont, Setting::Samples] {
+            settings.idx = SETTINGS.iter().position(|s| *s == setting).unwrap();
+            press(&mut settings, KeyCode::Enter, KeyModifiers::NONE);
+            press(&mut settings, KeyCode::Char('u'), KeyModifiers::CONTROL);
+            let (changed, _) = press(&mut settings, KeyCode::Enter, KeyModifiers::NONE);
+            assert_eq!(changed, Some(setting));
+        }
+        assert_eq!(settings.config.soundfont.path, None);
+        assert_eq!(settings.config.samples, None);
+    }
+}
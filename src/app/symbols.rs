@@ -0,0 +1,115 @@
+use std::fmt;
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+use serde::{Serialize, Deserialize};
+use crate::core::{Key, ChordSpec, Accidentals};
+
+// Whether to use Unicode symbols
+static UNICODE: AtomicBool = AtomicBool::new(false);
+
+// Whether to name chords by their letters, e.g. "Am7"
+static LETTERS: AtomicBool = AtomicBool::new(false);
+
+// How note names are spelled, as flats,
+// sharps or by the key (the default)
+static ACCIDENTALS: AtomicU8 = AtomicU8::new(2);
+
+/// How chord names and note labels are drawn.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Symbols {
+    // As they're typed, e.g. "bVII" or "vii-"
+    Ascii,
+
+    // With ♭, ♯, °, ø and Δ,
+    // for terminals with a font that has them
+    Unicode,
+}
+
+impl Symbols {
+    pub fn current() -> Symbols {
+        if UNICODE.load(Ordering::Relaxed) {
+            Symbols::Unicode
+        } else {
+            Symbols::Ascii
+        }
+    }
+
+    pub fn set(self) {
+        UNICODE.store(self == Symbols::Unicode, Ordering::Relaxed);
+    }
+}
+
+impl Default for Symbols {
+    fn default() -> Self {
+        Symbols::Ascii
+    }
+}
+
+impl fmt::Display for Symbols {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Symbols::Ascii => write!(f, "ascii"),
+            Symbols::Unicode => write!(f, "unicode"),
+        }
+    }
+}
+
+/// A chord's name for display.
+pub fn chord(cs: &ChordSpec) -> String {
+    match Symbols::current() {
+        Symbols::Ascii => cs.to_string(),
+        Symbols::Unicode => cs.symbol_name(),
+    }
+}
+
+/// Whether chords in a key are named by their letters.
+pub fn letter_names() -> bool {
+    LETTERS.load(Ordering::Relaxed)
+}
+
+/// Switch between numerals and letter names
+/// for chords in a key.
+pub fn toggle_letter_names() {
+    LETTERS.fetch_xor(true, Ordering::Relaxed);
+}
+
+/// How note names are spelled for display.
+pub fn accidentals() -> Accidentals {
+    match ACCIDENTALS.load(Ordering::Relaxed) {
+        0 => Accidentals::Flats,
+        1 => Accidentals::Sharps,
+        _ => Accidentals::Auto,
+    }
+}
+
+pub fn set_accidentals(accidentals: Accidentals) {
+    let idx = match accidentals {
+        Accidentals::Flats => 0,
+        Accidentals::Sharps => 1,
+        Accidentals::Auto => 2,
+    };
+    ACCIDENTALS.store(idx, Ordering::Relaxed);
+}
+
+/// A chord's name for display in a key, as
+/// its letter name if those are toggled on.
+pub fn chord_in_key(cs: &ChordSpec, key: &Key) -> String {
+    if letter_names() {
+        note(&cs.name_in_key(key, accidentals()))
+    } else {
+        chord(cs)
+    }
+}
+
+/// A key's root for display, e.g. "F#".
+pub fn key_root(key: &Key) -> String {
+    note(&key.root_name(accidentals()))
+}
+
+/// A note's name for display, e.g. "Bb4".
+pub fn note(name: &str) -> String {
+    match Symbols::current() {
+        Symbols::Ascii => name.to_string(),
+        Symbols::Unicode => name.replace('b', "♭").replace('#', "♯"),
+    }
+}
@@ -1,16 +1,154 @@
 use anyhow::Result;
+use std::{hash::Hash, collections::HashMap};
 use tui::{
     layout::Alignment,
-    style::{Style, Color},
+    style::{Style, Modifier},
     text::{Span, Spans},
     widgets::{Block, Paragraph},
 };
-use crossterm::event::{KeyEvent, KeyCode};
+use crossterm::event::{KeyEvent, KeyCode, KeyModifiers};
+use crate::core::{Note, Key, ChordSpec};
+use crate::progression::Gate;
+use crate::midi::{Arp, ArpParseError};
+use super::theme;
+
+// How many entries to keep per prompt
+const MAX_HISTORY: usize = 50;
+
+/// Checks an input, giving the error to show.
+pub type Validator = fn(&str) -> Result<(), String>;
+
+/// What a prompt is for, which
+/// decides how its input is checked.
+pub trait Prompt: Copy + Eq + Hash {
+    fn validator(self) -> Option<Validator>;
+}
+
+/// Add a submitted input to a prompt's history,
+/// most recent last.
+pub fn add_to_history(history: &mut Vec<String>, entry: &str) {
+    history.retain(|e| e != entry);
+    history.push(entry.to_string());
+    if history.len() > MAX_HISTORY {
+        history.remove(0);
+    }
+}
+
+/// Check that the input is a note, e.g. "C3".
+pub fn valid_note(input: &str) -> Result<(), String> {
+    input.parse::<Note>().map(|_| ()).map_err(|err| err.to_string())
+}
+
+/// Check that the input is a key, e.g. "D4 minor".
+pub fn valid_key(input: &str) -> Result<(), String> {
+    input.parse::<Key>().map(|_| ()).map_err(|err| err.to_string())
+}
+
+/// Check that the input is a gate, e.g. "50%" or "legato".
+pub fn valid_gate(input: &str) -> Result<(), String> {
+    input.parse::<Gate>().map(|_| ()).map_err(|err| err.to_string())
+}
+
+/// Check that the input is a percentage from 0 to 100,
+/// with or without a `%`.
+pub fn valid_percent(input: &str) -> Result<(), String> {
+    match input.trim_end_matches('%').parse::<usize>() {
+        Ok(percent) if percent <= 100 => Ok(()),
+        Ok(_) => Err("Must be at most 100%".to_string()),
+        Err(err) => Err(err.to_string()),
+    }
+}
+
+/// Check that the input is a chord, e.g. "V:b7".
+pub fn valid_chord(input: &str) -> Result<(), String> {
+    input.parse::<ChordSpec>().map(|_| ()).map_err(|err| err.to_string())
+}
+
+/// Check that the input is a space-delimited list of chords,
+/// as numerals or chord names, e.g. "I vi IV V" or "C Am F G".
+pub fn valid_chords(input: &str) -> Result<(), String> {
+    input.split_whitespace().try_for_each(|cs| {
+        ChordSpec::parse_in_key(cs, &Key::default()).map(|_| ()).map_err(|err| err.to_string())
+    })
+}
+
+/// Check that the input is a whole number.
+pub fn valid_number(input: &str) -> Result<(), String> {
+    input.parse::<usize>().map(|_| ()).map_err(|err| err.to_string())
+}
+
+/// Check that the input is a number above 0.
+pub fn valid_positive(input: &str) -> Result<(), String> {
+    match input.parse::<usize>() {
+        Ok(0) => Err("Must be more than 0".to_string()),
+        Ok(_) => Ok(()),
+        Err(err) => Err(err.to_string()),
+    }
+}
+
+/// Parse a MIDI channel, 1-16, into 0-15,
+/// or `-` for the output's own channel.
+pub fn parse_channel(input: &str) -> Result<Option<u8>, String> {
+    match input.trim() {
+        "-" => Ok(None),
+        channel => match channel.parse::<u8>() {
+            Ok(channel) if (1..=16).contains(&channel) => Ok(Some(channel - 1)),
+            _ => Err("Expected a MIDI channel, 1-16, or - for the default".to_string()),
+        },
+    }
+}
+
+/// Check that the input is a MIDI channel, 1-16, or `-`.
+pub fn valid_channel(input: &str) -> Result<(), String> {
+    parse_channel(input).map(|_| ())
+}
+
+/// Check that the input is a space-delimited
+/// list of MIDI channels, 1-16, or `-`.
+pub fn valid_channels(input: &str) -> Result<(), String> {
+    input.split_whitespace().try_for_each(valid_channel)
+}
+
+/// Parse an arpeggio, e.g. `up/16`,
+/// or `-` or nothing for none.
+pub fn parse_arp(input: &str) -> Result<Option<Arp>, String> {
+    match input.trim() {
+        "" | "-" => Ok(None),
+        arp => arp.parse().map(Some).map_err(|err: ArpParseError| err.to_string()),
+    }
+}
+
+pub fn valid_arp(input: &str) -> Result<(), String> {
+    parse_arp(input).map(|_| ())
+}
+
+/// Check that the input is a space-delimited
+/// list of arpeggios or `-`.
+pub fn valid_arps(input: &str) -> Result<(), String> {
+    input.split_whitespace().try_for_each(valid_arp)
+}
 
 pub struct TextInput<'a> {
     pub input: String,
     label: &'a str,
     valid_chars: fn(char) -> bool,
+
+    // Cursor position, in chars
+    cursor: usize,
+
+    // Previous inputs, most recent last,
+    // and where we are while browsing them.
+    history: Vec<String>,
+    history_idx: Option<usize>,
+
+    // What was typed before browsing history
+    draft: String,
+
+    // Checks the input as it's typed
+    validate: Option<Validator>,
+
+    // Whether submitting nothing is an input
+    allow_empty: bool,
 }
 
 impl<'a> TextInput<'a> {
@@ -19,19 +157,130 @@ impl<'a> TextInput<'a> {
             label,
             valid_chars,
             input: "".to_string(),
+            cursor: 0,
+            history: vec![],
+            history_idx: None,
+            draft: "".to_string(),
+            validate: None,
+            allow_empty: false,
+        }
+    }
+
+    /// A prompt for a target, with the target's
+    /// previous inputs and checked as it's typed.
+    pub fn with_history<T: Prompt>(label: &'a str, valid_chars: fn(char) -> bool, history: &HashMap<T, Vec<String>>, target: T) -> TextInput<'a> {
+        let history = history.get(&target).cloned().unwrap_or_default();
+        let text_input = TextInput::new(label, valid_chars).history(history);
+        match target.validator() {
+            Some(validate) => text_input.validate(validate),
+            None => text_input,
+        }
+    }
+
+    /// Check the input on each keystroke,
+    /// showing any error inline.
+    pub fn validate(mut self, validate: Validator) -> TextInput<'a> {
+        self.validate = Some(validate);
+        self
+    }
+
+    /// Submit an empty input rather than
+    /// treating it as cancelling, e.g. to unset something.
+    pub fn allow_empty(mut self) -> TextInput<'a> {
+        self.allow_empty = true;
+        self
+    }
+
+    /// Previous inputs to recall with Up/Down.
+    pub fn history(mut self, history: Vec<String>) -> TextInput<'a> {
+        self.history = history;
+        self
+    }
+
+    /// Recall an older history entry.
+    fn history_prev(&mut self) {
+        let idx = match self.history_idx {
+            None if self.history.is_empty() => return,
+            None => {
+                self.draft = self.input.clone();
+                self.history.len() - 1
+            }
+            Some(idx) => idx.saturating_sub(1),
+        };
+        self.history_idx = Some(idx);
+        self.set_input(self.history[idx].clone());
+    }
+
+    /// Recall a newer history entry, or
+    /// what was typed before browsing.
+    fn history_next(&mut self) {
+        match self.history_idx {
+            Some(idx) if idx + 1 < self.history.len() => {
+                self.history_idx = Some(idx + 1);
+                self.set_input(self.history[idx + 1].clone());
+            }
+            Some(_) => {
+                self.history_idx = None;
+                self.set_input(self.draft.clone());
+            }
+            None => {}
         }
     }
 
     pub fn set_input(&mut self, input: String) {
+        self.cursor = input.chars().count();
         self.input = input;
     }
 
+    /// Byte offset of the given char position.
+    fn byte_idx(&self, cursor: usize) -> usize {
+        self.input.char_indices()
+            .nth(cursor)
+            .map_or(self.input.len(), |(i, _)| i)
+    }
+
+    /// Delete the word before the cursor,
+    /// along with any whitespace after it.
+    fn delete_word(&mut self) {
+        let chars: Vec<char> = self.input.chars().collect();
+        let mut start = self.cursor;
+        while start > 0 && chars[start - 1].is_whitespace() {
+            start -= 1;
+        }
+        while start > 0 && !chars[start - 1].is_whitespace() {
+            start -= 1;
+        }
+        let (a, b) = (self.byte_idx(start), self.byte_idx(self.cursor));
+        self.input.replace_range(a..b, "");
+        self.cursor = start;
+    }
+
     pub fn render<'b>(&self) -> Paragraph<'b> {
-        let spans = Spans::from(vec![
+        let result = match self.validate {
+            Some(validate) if !self.input.is_empty() => Some(validate(&self.input)),
+            _ => None,
+        };
+        let input_style = match result {
+            None => theme::selected(),
+            Some(Ok(_)) => theme::valid(),
+            Some(Err(_)) => theme::invalid(),
+        };
+        let cursor_style = input_style.add_modifier(Modifier::REVERSED);
+        let (before, after) = self.input.split_at(self.byte_idx(self.cursor));
+        let mut after = after.chars();
+        let under_cursor = after.next().map_or(" ".to_string(), |c| c.to_string());
+
+        let mut spans = vec![
             Span::raw(self.label.to_string()),
-            Span::styled(self.input.clone(),
-                Style::default().fg(Color::LightBlue))
-        ]);
+            Span::styled(before.to_string(), input_style),
+            Span::styled(under_cursor, cursor_style),
+            Span::styled(after.collect::<String>(), input_style),
+        ];
+        if let Some(Err(err)) = result {
+            spans.push(Span::styled(format!(" ({})", err),
+                theme::invalid()));
+        }
+        let spans = Spans::from(spans);
         Paragraph::new(spans)
             .style(Style::default())
             .alignment(Alignment::Right)
@@ -41,11 +290,34 @@ impl<'a> TextInput<'a> {
     }
 
     pub fn process_input(&mut self, key: KeyEvent) -> Result<(Option<String>, bool)> {
+        let n_chars = self.input.chars().count();
+        match key {
+            // Delete the previous word
+            KeyEvent {
+                modifiers: KeyModifiers::CONTROL,
+                code: KeyCode::Char('w'),
+            } => {
+                self.delete_word();
+                return Ok((None, false));
+            }
+
+            // Clear the input
+            KeyEvent {
+                modifiers: KeyModifiers::CONTROL,
+                code: KeyCode::Char('u'),
+            } => {
+                self.set_input("".to_string());
+                return Ok((None, false));
+            }
+            _ => {}
+        }
+
         match key.code {
             KeyCode::Enter => {
+                self.cursor = 0;
                 let input = self.input.drain(..)
                     .collect::<String>();
-                if input.len() > 0 {
+                if !input.is_empty() || self.allow_empty {
                     Ok((Some(input), true))
                 } else {
                     Ok((None, true))
@@ -53,12 +325,49 @@ impl<'a> TextInput<'a> {
             }
             KeyCode::Char(c) => {
                 if (self.valid_chars)(c) {
-                    self.input.push(c);
+                    let idx = self.byte_idx(self.cursor);
+                    self.input.insert(idx, c);
+                    self.cursor += 1;
                 }
                 Ok((None, false))
             }
             KeyCode::Backspace => {
-                self.input.pop();
+                if self.cursor > 0 {
+                    self.cursor -= 1;
+                    let idx = self.byte_idx(self.cursor);
+                    self.input.remove(idx);
+                }
+                Ok((None, false))
+            }
+            KeyCode::Delete => {
+                if self.cursor < n_chars {
+                    let idx = self.byte_idx(self.cursor);
+                    self.input.remove(idx);
+                }
+                Ok((None, false))
+            }
+            KeyCode::Left => {
+                self.cursor = self.cursor.saturating_sub(1);
+                Ok((None, false))
+            }
+            KeyCode::Right => {
+                self.cursor = (self.cursor + 1).min(n_chars);
+                Ok((None, false))
+            }
+            KeyCode::Home => {
+                self.cursor = 0;
+                Ok((None, false))
+            }
+            KeyCode::End => {
+                self.cursor = n_chars;
+                Ok((None, false))
+            }
+            KeyCode::Up => {
+                self.history_prev();
+                Ok((None, false))
+            }
+            KeyCode::Down => {
+                self.history_next();
                 Ok((None, false))
             }
             KeyCode::Esc => Ok((None, true)),
@@ -0,0 +1,121 @@
+use std::fmt;
+use std::sync::atomic::{AtomicU8, Ordering};
+use serde::{Serialize, Deserialize};
+use tui::style::{Style, Color, Modifier};
+
+// The current theme, as its index
+static THEME: AtomicU8 = AtomicU8::new(0);
+
+const THEMES: [Theme; 3] = [Theme::Default, Theme::Light, Theme::Mono];
+
+/// Colors used across the UI.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Theme {
+    Default,
+
+    // For terminals with a light background
+    Light,
+
+    // No colors, only text styles
+    Mono,
+}
+
+impl Theme {
+    pub fn current() -> Theme {
+        THEMES[THEME.load(Ordering::Relaxed) as usize]
+    }
+
+    pub fn set(self) {
+        let idx = THEMES.iter().position(|t| *t == self).unwrap_or(0);
+        THEME.store(idx as u8, Ordering::Relaxed);
+    }
+
+    /// The theme after this one, for cycling through them.
+    pub fn next(self) -> Theme {
+        let idx = THEMES.iter().position(|t| *t == self).unwrap_or(0);
+        THEMES[(idx + 1) % THEMES.len()]
+    }
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme::Default
+    }
+}
+
+impl fmt::Display for Theme {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Theme::Default => write!(f, "default"),
+            Theme::Light => write!(f, "light"),
+            Theme::Mono => write!(f, "mono"),
+        }
+    }
+}
+
+/// The selected item or cursor.
+pub fn selected() -> Style {
+    match Theme::current() {
+        Theme::Default => Style::default().fg(Color::LightBlue),
+        Theme::Light => Style::default().fg(Color::Blue),
+        Theme::Mono => Style::default().add_modifier(Modifier::REVERSED),
+    }
+}
+
+/// Whatever is currently playing.
+pub fn playing() -> Style {
+    match Theme::current() {
+        Theme::Default => Style::default().fg(Color::Yellow),
+        Theme::Light => Style::default().fg(Color::Magenta),
+        Theme::Mono => Style::default().add_modifier(Modifier::UNDERLINED),
+    }
+}
+
+/// Steps with their own settings, e.g. a key.
+pub fn overridden() -> Style {
+    match Theme::current() {
+        Theme::Default => Style::default().fg(Color::Magenta),
+        Theme::Light => Style::default().fg(Color::Red),
+        Theme::Mono => Style::default().add_modifier(Modifier::ITALIC),
+    }
+}
+
+/// Background of a looped region.
+pub fn looped() -> Style {
+    match Theme::current() {
+        Theme::Default => Style::default().bg(Color::DarkGray),
+        Theme::Light => Style::default().bg(Color::Gray),
+        Theme::Mono => Style::default().add_modifier(Modifier::BOLD),
+    }
+}
+
+/// De-emphasized details, e.g. scrollbars.
+pub fn muted() -> Style {
+    match Theme::current() {
+        Theme::Default => Style::default().fg(Color::DarkGray),
+        Theme::Light => Style::default().fg(Color::Gray),
+        Theme::Mono => Style::default().add_modifier(Modifier::DIM),
+    }
+}
+
+/// Parameter values in the help bar.
+pub fn param() -> Style {
+    selected().add_modifier(Modifier::BOLD)
+}
+
+/// Input that checks out.
+pub fn valid() -> Style {
+    match Theme::current() {
+        Theme::Default | Theme::Light => Style::default().fg(Color::Green),
+        Theme::Mono => Style::default(),
+    }
+}
+
+/// Invalid input and errors.
+pub fn invalid() -> Style {
+    match Theme::current() {
+        Theme::Default | Theme::Light => Style::default().fg(Color::Red),
+        Theme::Mono => Style::default().add_modifier(Modifier::BOLD),
+    }
+}
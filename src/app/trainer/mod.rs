@@ -0,0 +1,341 @@
+use anyhow::Result;
+use std::time::Duration as Time;
+use std::sync::{Arc, Mutex};
+use crate::app::log::MessageLog;
+use crate::app::{theme, symbols};
+use crate::app::text_input::TextInput;
+use crate::core::{Key, Duration, ChordSpec, ChordParseError};
+use crate::midi::MIDIOutput;
+use crate::progression::ProgressionTemplate;
+use crossterm::event::{KeyEvent, KeyCode};
+use tui::{
+    text::{Span, Spans},
+    style::{Style, Modifier},
+    widgets::{Block, Paragraph, Borders},
+    layout::{Rect, Alignment, Constraint, Direction, Layout},
+};
+
+// How long each chord of a question is played
+const CHORD_LENGTH: Time = Time::from_millis(900);
+
+// Chords in a progression question
+const PROGRESSION_LEN: usize = 4;
+
+/// What's asked about.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Quiz {
+    Chord,
+    Progression,
+}
+
+/// A question and whether it's been answered.
+struct Question {
+    chords: Vec<ChordSpec>,
+    answered: Option<Answer>,
+}
+
+enum Answer {
+    Right,
+    Wrong(String),
+    GaveUp,
+}
+
+/// Pitch classes of a chord in a key, so chords are
+/// compared by how they sound, regardless of voicing.
+fn pitch_classes(cs: &ChordSpec, key: &Key) -> Vec<isize> {
+    let mut pitches: Vec<isize> = cs.chord_for_key(key).notes().iter()
+        .map(|n| n.semitones.rem_euclid(12)).collect();
+    pitches.sort_unstable();
+    pitches.dedup();
+    pitches
+}
+
+/// Whether the answered chords sound the same as the asked ones.
+fn is_right(asked: &[ChordSpec], answer: &[ChordSpec], key: &Key) -> bool {
+    asked.len() == answer.len() && asked.iter().zip(answer)
+        .all(|(a, b)| pitch_classes(a, key) == pitch_classes(b, key))
+}
+
+pub struct Trainer<'a> {
+    midi: Arc<Mutex<MIDIOutput>>,
+    template: ProgressionTemplate,
+
+    key: Key,
+    quiz: Quiz,
+    question: Option<Question>,
+    text_input: Option<TextInput<'a>>,
+
+    // Questions answered right and asked
+    right: usize,
+    asked: usize,
+
+    // Status messages
+    log: MessageLog,
+}
+
+impl<'a> Trainer<'a> {
+    pub fn new(midi: Arc<Mutex<MIDIOutput>>, log: MessageLog, template: ProgressionTemplate) -> Trainer<'a> {
+        Trainer {
+            midi,
+            template,
+            key: Key::default(),
+            quiz: Quiz::Chord,
+            question: None,
+            text_input: None,
+            right: 0,
+            asked: 0,
+            log,
+        }
+    }
+
+    pub fn key(&self) -> Key {
+        self.key
+    }
+
+    pub fn set_key(&mut self, key: Key) {
+        self.key = key;
+    }
+
+    pub fn capture_input(&self) -> bool {
+        self.text_input.is_some()
+    }
+
+    /// Ask a new question and play it.
+    fn ask(&mut self) {
+        let mode = &self.key.mode;
+        let chords = match self.quiz {
+            Quiz::Chord => vec![self.template.rand_chord_for_mode(mode)],
+            Quiz::Progression => {
+                let progression = self.template.gen_progression(mode, 1, &Duration::Quarter, None);
+                progression.chords().into_iter().take(PROGRESSION_LEN).cloned().collect()
+            }
+        };
+        self.question = Some(Question { chords, answered: None });
+        self.play();
+    }
+
+    /// Play the key's tonic, to hear the key,
+    /// then the question's chords after a rest.
+    fn play(&self) {
+        if let Some(question) = &self.question {
+            let tonic = ChordSpec::diatonic(1, &self.key);
+            let mut midi = self.midi.lock().unwrap();
+            midi.play_chord_later(&tonic.chord_for_key(&self.key), Time::ZERO, CHORD_LENGTH, 1., None, None);
+
+            // Leaving a rest after the tonic
+            for (i, cs) in question.chords.iter().enumerate() {
+                let chord = cs.chord_for_key(&self.key);
+                midi.play_chord_later(&chord, CHORD_LENGTH * (i as u32 + 2), CHORD_LENGTH, 1., None, None);
+            }
+        }
+    }
+
+    /// Check an answer to the current question,
+    /// given as numerals or chord names.
+    fn answer(&mut self, input: &str) -> Result<(), ChordParseError> {
+        let answer: Vec<ChordSpec> = input.split_whitespace()
+            .map(|cs| ChordSpec::parse_in_key(cs, &self.key))
+            .collect::<Result<_, _>>()?;
+        if let Some(question) = &mut self.question {
+            if question.answered.is_none() {
+                self.asked += 1;
+                if is_right(&question.chords, &answer, &self.key) {
+                    self.right += 1;
+                    question.answered = Some(Answer::Right);
+                } else {
+                    question.answered = Some(Answer::Wrong(input.to_string()));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn render(&mut self, rect: Rect) -> Vec<(Paragraph, Rect)> {
+        let mut rects = vec![];
+
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([
+                // Main
+                Constraint::Min(6),
+
+                // Messages/input chunk
+                Constraint::Length(1),
+            ].as_ref())
+            .split(rect);
+
+        let message = match &self.text_input {
+            Some(ti) => ti.render(),
+            None => Paragraph::new(self.log.current())
+                .alignment(Alignment::Right)
+        };
+        rects.push((message, chunks[1]));
+
+        let names = |chords: &[ChordSpec]| -> String {
+            chords.iter().map(symbols::chord).collect::<Vec<_>>().join(" ")
+        };
+        let lines = match &self.question {
+            None => vec![
+                Spans::from("Press [n] for a question."),
+                Spans::from(Span::styled(
+                    "The key's tonic plays first, then name what follows, e.g. \"V:7\".",
+                    theme::muted())),
+            ],
+            Some(question) => {
+                let asking = match self.quiz {
+                    Quiz::Chord => "Which chord was that?".to_string(),
+                    Quiz::Progression => format!("Which {} chords were those?", question.chords.len()),
+                };
+                let mut lines = vec![Spans::from(asking), Spans::from("")];
+                let answer = Span::styled(names(&question.chords), Style::default().add_modifier(Modifier::BOLD));
+                match &question.answered {
+                    None => {}
+                    Some(Answer::Right) => {
+                        lines.push(Spans::from(vec![Span::styled("Right: ", theme::playing()), answer]));
+                    }
+                    Some(Answer::Wrong(input)) => {
+                        lines.push(Spans::from(vec![
+                            Span::styled(format!("Not {}, it was ", input), theme::invalid()),
+                            answer]));
+                    }
+                    Some(Answer::GaveUp) => {
+                        lines.push(Spans::from(vec![Span::raw("It was "), answer]));
+                    }
+                }
+                lines
+            }
+        };
+
+        let display_chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .margin(2)
+            .constraints([Constraint::Min(0)].as_ref())
+            .split(chunks[0]);
+        let question = Paragraph::new(lines)
+            .alignment(Alignment::Center)
+            .block(
+                Block::default()
+                    .title("Trainer")
+                    .borders(Borders::TOP)
+                    .style(Style::default())
+            );
+        rects.push((question, display_chunks[0]));
+
+        rects
+    }
+
+    pub fn process_input(&mut self, key: KeyEvent) -> Result<()> {
+        if let Some(text_input) = &mut self.text_input {
+            let (input, close) = text_input.process_input(key)?;
+            if close {
+                self.text_input = None;
+                if let Some(input) = input {
+                    if let Err(err) = self.answer(&input) {
+                        self.log.error(err.to_string());
+                    }
+                }
+            }
+            return Ok(());
+        }
+
+        match key.code {
+            // Ask a new question
+            KeyCode::Char('n') => {
+                self.ask();
+            }
+
+            // Hear the question again
+            KeyCode::Char('r') => {
+                self.play();
+            }
+
+            // Answer, with a chord or
+            // space-delimited chords
+            KeyCode::Char('a') | KeyCode::Enter => {
+                if matches!(&self.question, Some(q) if q.answered.is_none()) {
+                    self.log.clear();
+                    self.text_input = Some(TextInput::new("Answer: ", |_c: char| true));
+                }
+            }
+
+            // Reveal the answer,
+            // which counts as wrong
+            KeyCode::Char('g') => {
+                if let Some(question) = &mut self.question {
+                    if question.answered.is_none() {
+                        self.asked += 1;
+                        question.answered = Some(Answer::GaveUp);
+                    }
+                }
+            }
+
+            // Switch between single
+            // chords and progressions
+            KeyCode::Char('p') => {
+                self.quiz = match self.quiz {
+                    Quiz::Chord => Quiz::Progression,
+                    Quiz::Progression => Quiz::Chord,
+                };
+                self.question = None;
+            }
+
+            // Change mode
+            KeyCode::Char('m') => {
+                self.key.mode = self.key.mode.next();
+                self.question = None;
+            }
+
+            // Start the score over
+            KeyCode::Char('0') => {
+                self.right = 0;
+                self.asked = 0;
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    pub fn params<'b>(&self) -> Vec<Span<'b>> {
+        let param_style = theme::param();
+        let quiz = match self.quiz {
+            Quiz::Chord => "chords",
+            Quiz::Progression => "progressions",
+        };
+        vec![
+            Span::raw("key:"),
+            Span::styled(symbols::key_root(&self.key), param_style),
+            Span::raw(" [m]ode:"),
+            Span::styled(self.key.mode.to_string(), param_style),
+            Span::raw(" [p]lay:"),
+            Span::styled(quiz, param_style),
+            Span::raw(" score:"),
+            Span::styled(format!("{}/{}", self.right, self.asked), param_style),
+        ]
+    }
+
+    pub fn controls<'b>(&self) -> Vec<Span<'b>> {
+        vec![
+            Span::raw(" [n]ew question [r]eplay [a]nswer [g]ive up [0]reset score"),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_is_right() {
+        let key = Key::default();
+        let chords = |names: &str| -> Vec<ChordSpec> {
+            names.split_whitespace().map(|n| ChordSpec::from_str(n).unwrap()).collect()
+        };
+        assert!(is_right(&chords("V:7"), &chords("V:7"), &key));
+
+        // Voicings sound like the same chord
+        assert!(is_right(&chords("IV"), &chords("IV%1"), &key));
+        assert!(!is_right(&chords("IV"), &chords("ii"), &key));
+        assert!(!is_right(&chords("I IV V"), &chords("I IV"), &key));
+    }
+}
@@ -0,0 +1,101 @@
+use anyhow::Result;
+use super::select::Select;
+use crate::config::Config;
+use crate::midi::{MIDIInput, MIDIOutput, ClockSource, Output};
+use crossterm::event::{self, Event};
+use tui::{
+    Terminal,
+    backend::Backend,
+    widgets::{Block, Paragraph, Borders},
+    layout::{Alignment, Constraint, Direction, Layout},
+};
+
+/// Ask for any MIDI ports that weren't given
+/// on the command line or in the config,
+/// saving the choices for next time.
+pub fn choose_ports<B: Backend>(terminal: &mut Terminal<B>, config: &mut Config) -> Result<()> {
+    let mut chosen = Config::load().unwrap_or_default();
+    let mut changed = false;
+    // The output port isn't needed for the audio preview
+    if config.output == Output::Midi && config.midi_out_port.is_none() {
+        let ports = MIDIOutput::new().available_ports().unwrap_or_default();
+        config.midi_out_port = choose_port(terminal, "MIDI output port", ports)?;
+        chosen.midi_out_port = config.midi_out_port.clone();
+        changed |= config.midi_out_port.is_some();
+    }
+
+    // The input port is only needed for an external clock
+    if config.clock == ClockSource::External && config.midi_in_port.is_none() {
+        let ports = MIDIInput::new().available_ports().unwrap_or_default();
+        config.midi_in_port = choose_port(terminal, "MIDI clock input port", ports)?;
+        chosen.midi_in_port = config.midi_in_port.clone();
+        changed |= config.midi_in_port.is_some();
+    }
+
+    // Only the chosen ports are saved, on top of the
+    // config file, so command line overrides aren't persisted.
+    // Failing to save shouldn't stop dust from starting;
+    // the ports will just be asked for again next time
+    if changed {
+        let _ = chosen.save();
+    }
+    Ok(())
+}
+
+/// Ask whether to pick up from the last session.
+pub fn offer_session<B: Backend>(terminal: &mut Terminal<B>) -> Result<bool> {
+    let choices = vec![
+        "Restore the last session".to_string(),
+        "Start a new session".to_string(),
+    ];
+    Ok(choose(terminal, "Welcome back", choices)? == Some(0))
+}
+
+/// Pick a port from a list, returning its name.
+/// Returns `None` if there are no ports or it was skipped.
+fn choose_port<B: Backend>(terminal: &mut Terminal<B>, title: &str, ports: Vec<String>) -> Result<Option<String>> {
+    if ports.is_empty() {
+        return Ok(None);
+    }
+    let idx = choose(terminal, &format!("Choose the {}", title), ports.clone())?;
+    Ok(idx.and_then(|idx| ports.get(idx).cloned()))
+}
+
+/// Pick from a list, returning the
+/// choice's index, or `None` if skipped.
+fn choose<B: Backend>(terminal: &mut Terminal<B>, title: &str, choices: Vec<String>) -> Result<Option<usize>> {
+    let mut select = Select::new(choices);
+    loop {
+        terminal.draw(|frame| {
+            let rects = Layout::default()
+                .direction(Direction::Vertical)
+                .margin(2)
+                .constraints([
+                    // Ports rect
+                    Constraint::Min(3),
+
+                    // Controls rect
+                    Constraint::Length(1),
+                    ].as_ref())
+                .split(frame.size());
+
+            let height = rects[0].height.saturating_sub(1) as usize;
+            let choices = select.render(height)
+                .block(Block::default()
+                    .title(title.to_string())
+                    .borders(Borders::TOP));
+            frame.render_widget(choices, rects[0]);
+
+            let controls = Paragraph::new(" [j/k] browse [Enter] select [Esc] skip")
+                .alignment(Alignment::Left);
+            frame.render_widget(controls, rects[1]);
+        })?;
+
+        if let Event::Key(key) = event::read()? {
+            let (selected, close) = select.process_input(key)?;
+            if close {
+                return Ok(selected);
+            }
+        }
+    }
+}
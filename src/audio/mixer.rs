@@ -0,0 +1,56 @@
+use std::collections::BTreeMap;
+use serde::{Serialize, Deserialize};
+
+/// Levels of the audio preview's sources,
+/// on top of the preview's volume.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Mixer {
+    // Level of the metronome, 0-1
+    pub metronome: f32,
+
+    // From MIDI channel, 1-16,
+    // to its level, 0-1
+    pub channels: BTreeMap<String, f32>,
+}
+
+impl Default for Mixer {
+    fn default() -> Self {
+        Mixer {
+            metronome: 0.5,
+            channels: BTreeMap::default(),
+        }
+    }
+}
+
+impl Mixer {
+    /// The level of each channel, by index.
+    /// Channels without a level play at full.
+    pub fn gains(&self) -> [f32; 16] {
+        let mut gains = [1.; 16];
+        for (channel, level) in &self.channels {
+            if let Ok(channel @ 1..=16) = channel.parse::<usize>() {
+                gains[channel - 1] = level.clamp(0., 1.);
+            }
+        }
+        gains
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_gains() {
+        let mut mixer = Mixer::default();
+        mixer.channels.insert("2".to_string(), 0.5);
+        mixer.channels.insert("10".to_string(), 2.);
+        mixer.channels.insert("17".to_string(), 0.5);
+
+        let gains = mixer.gains();
+        assert_eq!(gains[0], 1.);
+        assert_eq!(gains[1], 0.5);
+        assert_eq!(gains[9], 1.);
+    }
+}
@@ -0,0 +1,18 @@
+//! Previewing chords without a synth,
+//! by playing them through the default audio device,
+//! either with a simple synth or a soundfont.
+//! The audio device is only available with the `audio` feature.
+
+mod synth;
+mod soundfont;
+mod samples;
+mod mixer;
+#[cfg(feature = "audio")]
+mod output;
+
+pub use synth::{Synth, Patch, Waveform, Click, Beat};
+pub use soundfont::{SoundFont, SoundFontConfig, SoundFontError, Instruments, Sampler};
+pub use samples::{load_samples, SampleError};
+pub use mixer::Mixer;
+#[cfg(feature = "audio")]
+pub use output::{AudioOutput, AudioError};
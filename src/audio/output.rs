@@ -0,0 +1,228 @@
+use super::{Synth, Patch, Sampler, Instruments, Mixer, Click, Beat};
+use thiserror::Error;
+use std::{fmt::Display, thread};
+use std::sync::{Arc, Mutex, mpsc::{self, Sender}};
+use cpal::{Device, Stream, StreamConfig, SampleFormat, SizedSample, FromSample};
+use cpal::traits::{HostTrait, DeviceTrait, StreamTrait};
+
+#[derive(Error, Debug)]
+pub enum AudioError {
+    #[error("No audio output device")]
+    NoDevice,
+
+    #[error("Couldn't open the audio device: {0}")]
+    Stream(String),
+}
+
+fn stream_error<E: Display>(err: E) -> AudioError {
+    AudioError::Stream(err.to_string())
+}
+
+/// The synth and soundfont sampler,
+/// with each MIDI channel played by one of them,
+/// and the metronome.
+struct Engine {
+    // A synth for each channel,
+    // so each can have its own level
+    synths: Vec<Synth>,
+    sampler: Option<Sampler>,
+    clicks: Vec<Click>,
+    mixer: Mixer,
+    gains: [f32; 16],
+    sample_rate: f32,
+}
+
+impl Engine {
+    fn new(patch: Patch, instruments: Option<Instruments>, mixer: Mixer) -> Engine {
+        let mut engine = Engine {
+            synths: (0..16).map(|_| Synth::new(patch)).collect(),
+            sampler: None,
+            clicks: vec![],
+            gains: mixer.gains(),
+            mixer,
+            sample_rate: 44100.,
+        };
+        engine.set_instruments(instruments);
+        engine
+    }
+
+    fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.sample_rate = sample_rate;
+        for synth in &mut self.synths {
+            synth.set_sample_rate(sample_rate);
+        }
+        if let Some(sampler) = &mut self.sampler {
+            sampler.set_sample_rate(sample_rate);
+        }
+    }
+
+    fn set_patch(&mut self, patch: Patch) {
+        for synth in &mut self.synths {
+            synth.patch = patch;
+        }
+    }
+
+    fn set_instruments(&mut self, instruments: Option<Instruments>) {
+        self.sampler = instruments.map(|i| Sampler::new(i, self.sample_rate));
+        self.set_mixer(self.mixer.clone());
+    }
+
+    fn set_mixer(&mut self, mixer: Mixer) {
+        self.gains = mixer.gains();
+        if let Some(sampler) = &mut self.sampler {
+            sampler.gains = self.gains;
+        }
+        self.mixer = mixer;
+    }
+
+    fn note_on(&mut self, channel: u8, note: u8, velocity: u8) {
+        match &mut self.sampler {
+            Some(sampler) if sampler.plays(channel) => sampler.note_on(channel, note, velocity),
+            _ => self.synths[channel as usize].note_on(note, velocity),
+        }
+    }
+
+    fn note_off(&mut self, channel: u8, note: u8) {
+        match &mut self.sampler {
+            Some(sampler) if sampler.plays(channel) => sampler.note_off(channel, note),
+            _ => self.synths[channel as usize].note_off(note),
+        }
+    }
+
+    fn next_sample(&mut self) -> f32 {
+        // The synths apply the volume themselves
+        let volume = self.synths[0].patch.volume.clamp(0., 1.);
+        let mut out = 0.;
+        for (synth, gain) in self.synths.iter_mut().zip(self.gains) {
+            if synth.is_playing() {
+                out += synth.next_sample() * gain;
+            }
+        }
+        if let Some(sampler) = &mut self.sampler {
+            out += sampler.next_sample() * volume;
+        }
+
+        let sample_rate = self.sample_rate;
+        let mut clicks = 0.;
+        self.clicks.retain_mut(|click| match click.next_sample(sample_rate) {
+            Some(value) => {
+                clicks += value;
+                true
+            }
+            None => false,
+        });
+        out + clicks * self.mixer.metronome.clamp(0., 1.) * volume
+    }
+}
+
+/// Plays notes through the default audio device.
+/// It takes MIDI note on and off messages,
+/// so it can stand in for a MIDI output port.
+pub struct AudioOutput {
+    engine: Arc<Mutex<Engine>>,
+
+    // Dropping this closes the stream
+    _stop: Sender<()>,
+}
+
+impl AudioOutput {
+    pub fn new(patch: Patch, instruments: Option<Instruments>, mixer: Mixer) -> Result<AudioOutput, AudioError> {
+        let engine = Arc::new(Mutex::new(Engine::new(patch, instruments, mixer)));
+        let (stop, stopped) = mpsc::channel::<()>();
+        let (opened, ready) = mpsc::channel();
+
+        // Streams can't be sent between threads, so
+        // the stream lives on its own until we're dropped
+        let stream_engine = engine.clone();
+        thread::spawn(move || {
+            match open_stream(stream_engine) {
+                Ok(stream) => {
+                    let _ = opened.send(Ok(()));
+                    let _ = stopped.recv();
+                    drop(stream);
+                }
+                Err(err) => {
+                    let _ = opened.send(Err(err));
+                }
+            }
+        });
+        ready.recv().map_err(stream_error)??;
+        Ok(AudioOutput {
+            engine,
+            _stop: stop,
+        })
+    }
+
+    pub fn set_patch(&self, patch: Patch) {
+        self.engine.lock().unwrap().set_patch(patch);
+    }
+
+    pub fn set_mixer(&self, mixer: Mixer) {
+        self.engine.lock().unwrap().set_mixer(mixer);
+    }
+
+    /// Play a metronome click.
+    pub fn click(&self, beat: Beat) {
+        self.engine.lock().unwrap().clicks.push(Click::new(beat));
+    }
+
+    /// Play channels with a soundfont's presets,
+    /// or only the synth if `None`.
+    pub fn set_instruments(&self, instruments: Option<Instruments>) {
+        self.engine.lock().unwrap().set_instruments(instruments);
+    }
+
+    /// Handle a MIDI message.
+    /// Only note on and note off messages are used.
+    pub fn send(&self, msg: &[u8]) {
+        let mut engine = self.engine.lock().unwrap();
+        match *msg {
+            // Note on, with a velocity of 0 meaning note off
+            [status, note, vel] if status & 0xF0 == 0x90 && vel > 0 => {
+                engine.note_on(status & 0x0F, note, vel);
+            }
+            [status, note, _] if status & 0xF0 == 0x90 || status & 0xF0 == 0x80 => {
+                engine.note_off(status & 0x0F, note);
+            }
+            _ => {}
+        }
+    }
+}
+
+fn open_stream(engine: Arc<Mutex<Engine>>) -> Result<Stream, AudioError> {
+    let device = cpal::default_host().default_output_device()
+        .ok_or(AudioError::NoDevice)?;
+    let supported = device.default_output_config().map_err(stream_error)?;
+    let config = supported.config();
+    engine.lock().unwrap().set_sample_rate(config.sample_rate.0 as f32);
+
+    let stream = match supported.sample_format() {
+        SampleFormat::F32 => build_stream::<f32>(&device, &config, engine),
+        SampleFormat::I16 => build_stream::<i16>(&device, &config, engine),
+        SampleFormat::U16 => build_stream::<u16>(&device, &config, engine),
+        format => Err(AudioError::Stream(format!("Unsupported sample format {:?}", format))),
+    }?;
+    stream.play().map_err(stream_error)?;
+    Ok(stream)
+}
+
+fn build_stream<T>(device: &Device, config: &StreamConfig, engine: Arc<Mutex<Engine>>) -> Result<Stream, AudioError>
+    where T: SizedSample + FromSample<f32> {
+    let channels = config.channels as usize;
+    device.build_output_stream(
+        config,
+        move |data: &mut [T], _: &cpal::OutputCallbackInfo| {
+            let mut engine = engine.lock().unwrap();
+            for frame in data.chunks_mut(channels) {
+                let value = T::from_sample(engine.next_sample());
+                for sample in frame {
+                    *sample = value;
+                }
+            }
+        },
+        // Nowhere to report errors from here;
+        // at worst the preview goes quiet
+        |_err| {},
+        None)
+        .map_err(stream_error)
+}
@@ -0,0 +1,150 @@
+use std::{fs, io, sync::Arc, path::Path, str::FromStr, collections::BTreeMap};
+use thiserror::Error;
+use super::soundfont::{chunks, SoundFont, Instruments};
+use crate::core::Note;
+
+const MANIFEST: &str = "manifest.yaml";
+
+#[derive(Error, Debug)]
+pub enum SampleError {
+    #[error("Couldn't read the sample manifest: {0}")]
+    Io(#[from] io::Error),
+
+    #[error("Invalid sample manifest: {0}")]
+    Manifest(#[from] serde_yaml::Error),
+
+    #[error("Invalid note in the sample manifest: {0}")]
+    Note(String),
+
+    #[error("Missing samples for {0}")]
+    Missing(String),
+
+    #[error("Unsupported sample {0}, only PCM or float WAV files can be used")]
+    Unsupported(String),
+}
+
+/// Samples from a WAV file, mixed down to mono,
+/// and its sample rate.
+fn read_wav(bytes: &[u8]) -> Option<(Vec<i16>, u32)> {
+    if bytes.len() < 12 || &bytes[0..4] != b"RIFF" || &bytes[8..12] != b"WAVE" {
+        return None;
+    }
+    let chunks = chunks(&bytes[12..]).ok()?;
+    let fmt = chunks.iter().find(|(id, _)| id == b"fmt ")?.1;
+    let data = chunks.iter().find(|(id, _)| id == b"data")?.1;
+    if fmt.len() < 16 {
+        return None;
+    }
+
+    let format = u16::from_le_bytes([fmt[0], fmt[1]]);
+    let channels = u16::from_le_bytes([fmt[2], fmt[3]]).max(1) as usize;
+    let sample_rate = u32::from_le_bytes([fmt[4], fmt[5], fmt[6], fmt[7]]);
+    let bits = u16::from_le_bytes([fmt[14], fmt[15]]);
+
+    // Each sample as -1 to 1
+    let width = bits as usize / 8;
+    let value = |b: &[u8]| -> Option<f32> {
+        match (format, bits) {
+            (1, 8) => Some((b[0] as f32 - 128.) / 128.),
+            (1, 16) => Some(i16::from_le_bytes([b[0], b[1]]) as f32 / 32768.),
+            (1, 24) => Some(i32::from_le_bytes([0, b[0], b[1], b[2]]) as f32 / 2147483648.),
+            (3, 32) => Some(f32::from_le_bytes([b[0], b[1], b[2], b[3]])),
+            _ => None,
+        }
+    };
+    let mut samples = vec![];
+    for frame in data.chunks_exact(width * channels) {
+        let mut sum = 0.;
+        for b in frame.chunks_exact(width) {
+            sum += value(b)?;
+        }
+        let mono = (sum / channels as f32).clamp(-1., 1.);
+        samples.push((mono * 32767.) as i16);
+    }
+    Some((samples, sample_rate))
+}
+
+/// Load a directory of samples to play notes with.
+/// Its `manifest.yaml` maps notes to WAV files
+/// in the directory, e.g. `C4: piano-C4.wav`.
+/// Every listed file has to be there.
+pub fn load_samples(dir: &str) -> Result<Instruments, SampleError> {
+    let dir = Path::new(dir);
+    let manifest = fs::read_to_string(dir.join(MANIFEST))?;
+    let manifest: BTreeMap<String, String> = serde_yaml::from_str(&manifest)?;
+
+    let mut samples = vec![];
+    let mut missing = vec![];
+    for (note, file) in manifest {
+        let midi = Note::from_str(&note).ok()
+            .map(|n| n.semitones + 21)
+            .filter(|n| (0..=127).contains(n))
+            .ok_or_else(|| SampleError::Note(note.clone()))?;
+        match fs::read(dir.join(&file)) {
+            Ok(bytes) => {
+                match read_wav(&bytes) {
+                    Some((sample, rate)) if sample.len() > 1 => samples.push((midi as u8, sample, rate)),
+                    _ => return Err(SampleError::Unsupported(file)),
+                }
+            }
+            Err(_) => missing.push(format!("{} ({})", note, file)),
+        }
+    }
+    if !missing.is_empty() {
+        return Err(SampleError::Missing(missing.join(", ")));
+    }
+    if samples.is_empty() {
+        return Err(SampleError::Missing("every note".to_string()));
+    }
+
+    let name = dir.file_name().map_or("samples".into(), |n| n.to_string_lossy());
+    Ok(Instruments {
+        font: Arc::new(SoundFont::from_samples(&name, samples)),
+        presets: [Some(0); 16],
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn wav(samples: &[i16]) -> Vec<u8> {
+        let mut fmt = vec![];
+        for v in [1u16, 1] {
+            fmt.extend(v.to_le_bytes());
+        }
+        fmt.extend(22050u32.to_le_bytes());
+        fmt.extend(44100u32.to_le_bytes());
+        for v in [2u16, 16] {
+            fmt.extend(v.to_le_bytes());
+        }
+        let data: Vec<u8> = samples.iter().flat_map(|s| s.to_le_bytes()).collect();
+
+        let mut bytes = b"RIFF".to_vec();
+        bytes.extend(((4 + 8 + fmt.len() + 8 + data.len()) as u32).to_le_bytes());
+        bytes.extend(b"WAVE");
+        for (id, body) in [(b"fmt ", fmt), (b"data", data)] {
+            bytes.extend(id);
+            bytes.extend((body.len() as u32).to_le_bytes());
+            bytes.extend(body);
+        }
+        bytes
+    }
+
+    #[test]
+    fn test_load_samples() {
+        let dir = std::env::temp_dir().join("dust-test-samples");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("c4.wav"), wav(&[0, 1000, -1000, 0])).unwrap();
+        fs::write(dir.join(MANIFEST), "C4: c4.wav\nE4: e4.wav\n").unwrap();
+
+        // E4 is missing
+        let result = load_samples(dir.to_str().unwrap());
+        assert!(matches!(result, Err(SampleError::Missing(ref notes)) if notes == "E4 (e4.wav)"));
+
+        fs::write(dir.join(MANIFEST), "C4: c4.wav\n").unwrap();
+        let instruments = load_samples(dir.to_str().unwrap()).unwrap();
+        assert_eq!(instruments.font.presets.len(), 1);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,687 @@
+use std::{fs, io, sync::Arc, collections::{BTreeMap, HashMap}};
+use serde::{Serialize, Deserialize};
+use thiserror::Error;
+use super::synth::{Adsr, Envelope};
+
+// Level of each note, leaving
+// headroom for chords
+const GAIN: f32 = 0.3;
+
+// Generators we use, by their number in the SF2 spec.
+// There are 61 generators in all.
+const GENERATORS: usize = 61;
+const START_OFFSET: usize = 0;
+const END_OFFSET: usize = 1;
+const LOOP_START_OFFSET: usize = 2;
+const LOOP_END_OFFSET: usize = 3;
+const START_COARSE_OFFSET: usize = 4;
+const END_COARSE_OFFSET: usize = 12;
+const ATTACK: usize = 34;
+const DECAY: usize = 36;
+const SUSTAIN: usize = 37;
+const RELEASE: usize = 38;
+const INSTRUMENT: usize = 41;
+const KEY_RANGE: usize = 43;
+const VEL_RANGE: usize = 44;
+const LOOP_START_COARSE_OFFSET: usize = 45;
+const ATTENUATION: usize = 48;
+const LOOP_END_COARSE_OFFSET: usize = 50;
+const COARSE_TUNE: usize = 51;
+const FINE_TUNE: usize = 52;
+const SAMPLE_ID: usize = 53;
+const SAMPLE_MODES: usize = 54;
+const ROOT_KEY: usize = 58;
+
+#[derive(Error, Debug)]
+pub enum SoundFontError {
+    #[error("Couldn't read the soundfont: {0}")]
+    Io(#[from] io::Error),
+
+    #[error("Invalid soundfont: {0}")]
+    Invalid(&'static str),
+
+    #[error("Invalid soundfont preset for channel {0}: {1}")]
+    Preset(String, String),
+}
+
+/// Generator values of a zone,
+/// as their raw amounts.
+type Generators = [Option<u16>; GENERATORS];
+
+/// A range of samples for some keys and velocities.
+#[derive(Debug, Clone, Copy)]
+struct Zone {
+    keys: (u8, u8),
+    vels: (u8, u8),
+
+    // Positions in the sample data
+    start: usize,
+    end: usize,
+    loop_start: usize,
+    loop_end: usize,
+    looped: bool,
+
+    // The note the sample was recorded at,
+    // and any tuning on top of that, in cents
+    root: u8,
+    tune: f32,
+
+    sample_rate: f32,
+    gain: f32,
+    adsr: Adsr,
+}
+
+impl Zone {
+    fn plays(&self, note: u8, velocity: u8) -> bool {
+        (self.keys.0..=self.keys.1).contains(&note)
+            && (self.vels.0..=self.vels.1).contains(&velocity)
+    }
+}
+
+pub struct Preset {
+    pub name: String,
+    pub bank: u16,
+    pub program: u16,
+    zones: Vec<Zone>,
+}
+
+/// Sampled instruments loaded from an SF2 file.
+/// Only what's needed to preview notes is supported:
+/// key and velocity ranges, tuning, looping,
+/// attenuation and the volume envelope.
+pub struct SoundFont {
+    pub presets: Vec<Preset>,
+
+    // Samples for every zone, as 16-bit mono
+    data: Vec<i16>,
+}
+
+struct SampleHeader {
+    start: u32,
+    end: u32,
+    loop_start: u32,
+    loop_end: u32,
+    sample_rate: u32,
+    pitch: u8,
+    correction: i8,
+}
+
+fn u16_at(bytes: &[u8], i: usize) -> u16 {
+    u16::from_le_bytes([bytes[i], bytes[i + 1]])
+}
+
+fn u32_at(bytes: &[u8], i: usize) -> u32 {
+    u32::from_le_bytes([bytes[i], bytes[i + 1], bytes[i + 2], bytes[i + 3]])
+}
+
+fn name_at(bytes: &[u8]) -> String {
+    let name = &bytes[..20];
+    let end = name.iter().position(|b| *b == 0).unwrap_or(name.len());
+    String::from_utf8_lossy(&name[..end]).trim().to_string()
+}
+
+/// A RIFF chunk's id and contents.
+type Chunk<'a> = ([u8; 4], &'a [u8]);
+
+/// Split RIFF data into its chunks.
+pub(super) fn chunks(mut bytes: &[u8]) -> Result<Vec<Chunk<'_>>, SoundFontError> {
+    let mut chunks = vec![];
+    while bytes.len() >= 8 {
+        let id = [bytes[0], bytes[1], bytes[2], bytes[3]];
+        let size = u32_at(bytes, 4) as usize;
+        let body = bytes.get(8..8 + size)
+            .ok_or(SoundFontError::Invalid("Truncated chunk"))?;
+        chunks.push((id, body));
+
+        // Chunks are padded to an even size
+        let next = (8 + size + size % 2).min(bytes.len());
+        bytes = &bytes[next..];
+    }
+    Ok(chunks)
+}
+
+/// Time in seconds from timecents,
+/// defaulting to (practically) instant.
+fn seconds(gens: &Generators, gen: usize) -> f32 {
+    let timecents = gens[gen].map_or(-12000, |v| v as i16);
+    2f32.powf(timecents as f32 / 1200.)
+}
+
+/// Level from an attenuation in centibels.
+fn level(centibels: i16) -> f32 {
+    10f32.powf(-(centibels.max(0) as f32) / 200.)
+}
+
+/// Low and high of a key or velocity range.
+fn range(gens: &Generators, gen: usize) -> (u8, u8) {
+    match gens[gen] {
+        Some(amount) => {
+            let [lo, hi] = amount.to_le_bytes();
+            (lo, hi)
+        }
+        None => (0, 127),
+    }
+}
+
+fn intersect(a: (u8, u8), b: (u8, u8)) -> Option<(u8, u8)> {
+    let range = (a.0.max(b.0), a.1.min(b.1));
+    if range.0 <= range.1 { Some(range) } else { None }
+}
+
+/// Generators for each zone in a range of bags.
+fn zones(bags: &[&[u8]], gens: &[&[u8]], from: usize, to: usize) -> Result<Vec<Generators>, SoundFontError> {
+    let invalid = SoundFontError::Invalid("Bad zone index");
+    let mut zones = vec![];
+    for i in from..to {
+        let (bag, next) = match (bags.get(i), bags.get(i + 1)) {
+            (Some(bag), Some(next)) => (u16_at(bag, 0) as usize, u16_at(next, 0) as usize),
+            _ => return Err(invalid),
+        };
+        let mut zone = [None; GENERATORS];
+        for gen in gens.get(bag..next).ok_or(SoundFontError::Invalid("Bad generator index"))? {
+            let oper = u16_at(gen, 0) as usize;
+            if oper < GENERATORS {
+                zone[oper] = Some(u16_at(gen, 2));
+            }
+        }
+        zones.push(zone);
+    }
+    Ok(zones)
+}
+
+/// Split off the global zone, if there is one.
+/// It's the first zone, if it lacks the final generator.
+fn split_global(mut zones: Vec<Generators>, last: usize) -> (Generators, Vec<Generators>) {
+    match zones.first() {
+        Some(zone) if zone[last].is_none() => {
+            let global = zones.remove(0);
+            (global, zones)
+        }
+        _ => ([None; GENERATORS], zones),
+    }
+}
+
+/// Zone generators override the global ones.
+fn merge(global: &Generators, zone: &Generators) -> Generators {
+    let mut gens = *global;
+    for (i, amount) in zone.iter().enumerate() {
+        if amount.is_some() {
+            gens[i] = *amount;
+        }
+    }
+    gens
+}
+
+fn offset(base: u32, gens: &Generators, fine: usize, coarse: usize) -> i64 {
+    let amount = |gen: usize| gens[gen].map_or(0, |v| v as i16 as i64);
+    base as i64 + amount(fine) + amount(coarse) * 32768
+}
+
+/// Resolve an instrument zone into the sample it plays.
+/// Returns `None` if it doesn't point to valid samples.
+fn zone(gens: &Generators, headers: &[SampleHeader], len: usize) -> Option<Zone> {
+    let header = headers.get(gens[SAMPLE_ID]? as usize)?;
+    let clamp = |pos: i64| pos.clamp(0, len as i64) as usize;
+    let start = clamp(offset(header.start, gens, START_OFFSET, START_COARSE_OFFSET));
+    let end = clamp(offset(header.end, gens, END_OFFSET, END_COARSE_OFFSET));
+    let loop_start = clamp(offset(header.loop_start, gens, LOOP_START_OFFSET, LOOP_START_COARSE_OFFSET));
+    let loop_end = clamp(offset(header.loop_end, gens, LOOP_END_OFFSET, LOOP_END_COARSE_OFFSET));
+    if end <= start + 1 {
+        return None;
+    }
+
+    // Modes 1 and 3 loop
+    let looped = gens[SAMPLE_MODES].is_some_and(|v| v & 1 == 1)
+        && start <= loop_start && loop_start < loop_end && loop_end <= end;
+
+    let root = match gens[ROOT_KEY].map(|v| v as i16) {
+        Some(key @ 0..=127) => key as u8,
+        _ if header.pitch <= 127 => header.pitch,
+        _ => 60,
+    };
+    let amount = |gen: usize| gens[gen].map_or(0, |v| v as i16) as f32;
+    let tune = amount(COARSE_TUNE) * 100. + amount(FINE_TUNE) + header.correction as f32;
+
+    Some(Zone {
+        keys: range(gens, KEY_RANGE),
+        vels: range(gens, VEL_RANGE),
+        start,
+        end,
+        loop_start,
+        loop_end,
+        looped,
+        root,
+        tune,
+        sample_rate: header.sample_rate.max(1) as f32,
+        gain: level(gens[ATTENUATION].map_or(0, |v| v as i16)),
+        adsr: Adsr {
+            attack: seconds(gens, ATTACK),
+            decay: seconds(gens, DECAY),
+            sustain: level(gens[SUSTAIN].map_or(0, |v| v as i16)),
+            release: seconds(gens, RELEASE),
+        },
+    })
+}
+
+impl SoundFont {
+    pub fn load(path: &str) -> Result<SoundFont, SoundFontError> {
+        SoundFont::parse(&fs::read(path)?)
+    }
+
+    pub fn parse(bytes: &[u8]) -> Result<SoundFont, SoundFontError> {
+        if bytes.len() < 12 || &bytes[0..4] != b"RIFF" || &bytes[8..12] != b"sfbk" {
+            return Err(SoundFontError::Invalid("Not an SF2 file"));
+        }
+
+        let mut smpl: &[u8] = &[];
+        let mut pdta = HashMap::new();
+        for (id, body) in chunks(&bytes[12..])? {
+            if &id != b"LIST" || body.len() < 4 {
+                continue;
+            }
+            for (sub_id, sub) in chunks(&body[4..])? {
+                match &body[..4] {
+                    b"sdta" if &sub_id == b"smpl" => smpl = sub,
+                    b"pdta" => {
+                        pdta.insert(sub_id, sub);
+                    }
+                    _ => {}
+                }
+            }
+        }
+        let data: Vec<i16> = smpl.chunks_exact(2)
+            .map(|b| i16::from_le_bytes([b[0], b[1]]))
+            .collect();
+
+        // Records of each of the preset data chunks,
+        // each of which ends with a terminal record
+        let records = |id: &[u8; 4], size: usize| -> Result<Vec<&[u8]>, SoundFontError> {
+            let chunk = pdta.get(id).ok_or(SoundFontError::Invalid("Missing preset data"))?;
+            let records: Vec<&[u8]> = chunk.chunks_exact(size).collect();
+            if records.is_empty() {
+                Err(SoundFontError::Invalid("Missing terminal record"))
+            } else {
+                Ok(records)
+            }
+        };
+        let phdr = records(b"phdr", 38)?;
+        let pbag = records(b"pbag", 4)?;
+        let pgen = records(b"pgen", 4)?;
+        let inst = records(b"inst", 22)?;
+        let ibag = records(b"ibag", 4)?;
+        let igen = records(b"igen", 4)?;
+        let shdr = records(b"shdr", 46)?;
+
+        let headers: Vec<SampleHeader> = shdr[..shdr.len() - 1].iter().map(|r| SampleHeader {
+            start: u32_at(r, 20),
+            end: u32_at(r, 24),
+            loop_start: u32_at(r, 28),
+            loop_end: u32_at(r, 32),
+            sample_rate: u32_at(r, 36),
+            pitch: r[40],
+            correction: r[41] as i8,
+        }).collect();
+
+        let mut instruments: Vec<Vec<Zone>> = vec![];
+        for pair in inst.windows(2) {
+            let (from, to) = (u16_at(pair[0], 20) as usize, u16_at(pair[1], 20) as usize);
+            let (global, local) = split_global(zones(&ibag, &igen, from, to)?, SAMPLE_ID);
+            instruments.push(local.iter()
+                .filter_map(|gens| zone(&merge(&global, gens), &headers, data.len()))
+                .collect());
+        }
+
+        let mut presets = vec![];
+        for pair in phdr.windows(2) {
+            let (from, to) = (u16_at(pair[0], 24) as usize, u16_at(pair[1], 24) as usize);
+            let (global, local) = split_global(zones(&pbag, &pgen, from, to)?, INSTRUMENT);
+            let mut zones = vec![];
+            for gens in local {
+                let gens = merge(&global, &gens);
+                let instrument = gens[INSTRUMENT]
+                    .and_then(|idx| instruments.get(idx as usize))
+                    .ok_or(SoundFontError::Invalid("Bad instrument index"))?;
+
+                // Presets narrow down their instruments' ranges
+                for zone in instrument {
+                    let keys = intersect(zone.keys, range(&gens, KEY_RANGE));
+                    let vels = intersect(zone.vels, range(&gens, VEL_RANGE));
+                    if let (Some(keys), Some(vels)) = (keys, vels) {
+                        zones.push(Zone { keys, vels, ..*zone });
+                    }
+                }
+            }
+            presets.push(Preset {
+                name: name_at(pair[0]),
+                program: u16_at(pair[0], 20),
+                bank: u16_at(pair[0], 22),
+                zones,
+            });
+        }
+
+        Ok(SoundFont {
+            presets,
+            data,
+        })
+    }
+
+    /// A soundfont with one preset, playing
+    /// samples recorded at the given notes.
+    /// Notes between them play the nearest sample.
+    pub(super) fn from_samples(name: &str, mut samples: Vec<(u8, Vec<i16>, u32)>) -> SoundFont {
+        samples.sort_by_key(|(note, _, _)| *note);
+        let mut data = vec![];
+        let mut zones = vec![];
+        for (i, (note, sample, sample_rate)) in samples.iter().enumerate() {
+            // Split the keys halfway between sampled notes
+            let lo = if i == 0 { 0 } else { (samples[i - 1].0 + note) / 2 + 1 };
+            let hi = samples.get(i + 1).map_or(127, |(next, _, _)| (note + next) / 2);
+            let start = data.len();
+            data.extend(sample);
+            zones.push(Zone {
+                keys: (lo, hi),
+                vels: (0, 127),
+                start,
+                end: data.len(),
+                loop_start: start,
+                loop_end: data.len(),
+                looped: false,
+                root: *note,
+                tune: 0.,
+                sample_rate: (*sample_rate).max(1) as f32,
+                gain: 1.,
+                adsr: Adsr {
+                    attack: 0.,
+                    decay: 0.,
+                    sustain: 1.,
+                    release: 0.3,
+                },
+            });
+        }
+        SoundFont {
+            presets: vec![Preset {
+                name: name.to_string(),
+                bank: 0,
+                program: 0,
+                zones,
+            }],
+            data,
+        }
+    }
+
+    pub fn preset_index(&self, bank: u16, program: u16) -> Option<usize> {
+        self.presets.iter().position(|p| p.bank == bank && p.program == program)
+    }
+}
+
+/// Which soundfont the audio preview plays,
+/// and the preset each MIDI channel uses.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SoundFontConfig {
+    pub path: Option<String>,
+
+    // From MIDI channel, 1-16,
+    // to preset, as "bank:program"
+    pub presets: BTreeMap<String, String>,
+}
+
+/// A soundfont and the preset for each MIDI channel.
+/// Channels without a preset are played by the synth.
+#[derive(Clone)]
+pub struct Instruments {
+    pub font: Arc<SoundFont>,
+    pub presets: [Option<usize>; 16],
+}
+
+impl SoundFontConfig {
+    /// Load the soundfont, if one is set.
+    /// Channels without a configured preset
+    /// use the first piano (bank 0, program 0).
+    pub fn load(&self) -> Result<Option<Instruments>, SoundFontError> {
+        let path = match &self.path {
+            Some(path) if !path.is_empty() => path,
+            _ => return Ok(None),
+        };
+        let font = SoundFont::load(path)?;
+        self.instruments(font).map(Some)
+    }
+
+    fn instruments(&self, font: SoundFont) -> Result<Instruments, SoundFontError> {
+        let default = font.preset_index(0, 0)
+            .or(if font.presets.is_empty() { None } else { Some(0) });
+        let mut presets = [default; 16];
+        for (channel, preset) in &self.presets {
+            let invalid = |msg: &str| SoundFontError::Preset(channel.to_string(), msg.to_string());
+            let idx = match channel.parse::<usize>() {
+                Ok(idx @ 1..=16) => idx - 1,
+                _ => return Err(invalid("channels must be 1-16")),
+            };
+
+            // The bank can be left out
+            let (bank, program) = preset.split_once(':').unwrap_or(("0", preset));
+            let (bank, program) = match (bank.trim().parse(), program.trim().parse()) {
+                (Ok(bank), Ok(program)) => (bank, program),
+                _ => return Err(invalid("must be \"bank:program\"")),
+            };
+            presets[idx] = Some(font.preset_index(bank, program)
+                .ok_or_else(|| invalid("not in the soundfont"))?);
+        }
+        Ok(Instruments {
+            font: Arc::new(font),
+            presets,
+        })
+    }
+}
+
+struct Voice {
+    channel: u8,
+    note: u8,
+    zone: Zone,
+    velocity: f32,
+
+    // Position in the sample data,
+    // and how far to move it each sample
+    pos: f64,
+    step: f64,
+
+    envelope: Envelope,
+    done: bool,
+}
+
+/// Plays notes with a soundfont's presets.
+pub struct Sampler {
+    pub instruments: Instruments,
+
+    // Level of each channel
+    pub gains: [f32; 16],
+
+    sample_rate: f32,
+    voices: Vec<Voice>,
+}
+
+impl Sampler {
+    pub fn new(instruments: Instruments, sample_rate: f32) -> Sampler {
+        Sampler {
+            instruments,
+            gains: [1.; 16],
+            sample_rate,
+            voices: vec![],
+        }
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.sample_rate = sample_rate;
+    }
+
+    fn preset(&self, channel: u8) -> Option<&Preset> {
+        let idx = self.instruments.presets.get(channel as usize).copied().flatten()?;
+        self.instruments.font.presets.get(idx)
+    }
+
+    /// Whether a channel has a preset to play.
+    pub fn plays(&self, channel: u8) -> bool {
+        self.preset(channel).is_some()
+    }
+
+    pub fn note_on(&mut self, channel: u8, note: u8, velocity: u8) {
+        // Retrigger the note if it's already playing
+        self.voices.retain(|v| v.channel != channel || v.note != note);
+
+        let zones: Vec<Zone> = match self.preset(channel) {
+            Some(preset) => preset.zones.iter()
+                .filter(|z| z.plays(note, velocity))
+                .copied().collect(),
+            None => return,
+        };
+        for zone in zones {
+            let cents = (note as f32 - zone.root as f32) * 100. + zone.tune;
+            let step = 2f64.powf(cents as f64 / 1200.)
+                * zone.sample_rate as f64 / self.sample_rate as f64;
+            self.voices.push(Voice {
+                channel,
+                note,
+                zone,
+                velocity: velocity as f32 / 127.,
+                pos: zone.start as f64,
+                step,
+                envelope: Envelope::new(),
+                done: false,
+            });
+        }
+    }
+
+    pub fn note_off(&mut self, channel: u8, note: u8) {
+        for voice in self.voices.iter_mut().filter(|v| v.channel == channel && v.note == note) {
+            voice.envelope.release();
+        }
+    }
+
+    pub fn next_sample(&mut self) -> f32 {
+        let data = &self.instruments.font.data;
+        let sample = |i: usize| data.get(i).map_or(0., |s| *s as f32 / 32768.);
+        let mut out = 0.;
+        for voice in &mut self.voices {
+            let zone = &voice.zone;
+            voice.envelope.advance(&zone.adsr, self.sample_rate);
+
+            // Interpolate between samples
+            let i = voice.pos as usize;
+            let next = if zone.looped && i + 1 >= zone.loop_end { zone.loop_start } else { i + 1 };
+            let frac = (voice.pos - i as f64) as f32;
+            let value = sample(i) + (sample(next) - sample(i)) * frac;
+            let gain = zone.gain * self.gains[voice.channel as usize & 0x0F];
+            out += value * gain * voice.envelope.level * voice.velocity;
+
+            voice.pos += voice.step;
+            if zone.looped && voice.pos >= zone.loop_end as f64 {
+                voice.pos -= (zone.loop_end - zone.loop_start) as f64;
+            } else if voice.pos >= (zone.end - 1) as f64 {
+                voice.done = true;
+            }
+        }
+        self.voices.retain(|v| !v.done && !v.envelope.is_done());
+        out * GAIN
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn chunk(id: &[u8], body: &[u8]) -> Vec<u8> {
+        let mut bytes = id.to_vec();
+        bytes.extend((body.len() as u32).to_le_bytes());
+        bytes.extend(body);
+        bytes
+    }
+
+    fn list(kind: &[u8], chunks: &[Vec<u8>]) -> Vec<u8> {
+        let mut body = kind.to_vec();
+        for c in chunks {
+            body.extend(c);
+        }
+        chunk(b"LIST", &body)
+    }
+
+    fn header(name: &str, fields: &[u16], size: usize) -> Vec<u8> {
+        let mut bytes = vec![0; 20];
+        bytes[..name.len()].copy_from_slice(name.as_bytes());
+        for f in fields {
+            bytes.extend(f.to_le_bytes());
+        }
+        bytes.resize(size, 0);
+        bytes
+    }
+
+    fn gens(gens: &[(u16, u16)]) -> Vec<u8> {
+        gens.iter().flat_map(|(oper, amount)| {
+            [oper.to_le_bytes(), amount.to_le_bytes()].concat()
+        }).collect()
+    }
+
+    /// A soundfont with a piano preset of one zone,
+    /// covering keys 48-72, and a drum preset.
+    fn soundfont() -> Vec<u8> {
+        let smpl: Vec<u8> = (0..100i16).flat_map(|s| (s * 100).to_le_bytes()).collect();
+        let mut sample = header("sample", &[], 20);
+        for v in [0u32, 100, 10, 90, 22050] {
+            sample.extend(v.to_le_bytes());
+        }
+        sample.extend([60, 0, 0, 0, 1, 0]);
+
+        let phdr = [
+            header("Piano", &[0, 0, 0], 38),
+            header("Drums", &[0, 128, 1], 38),
+            header("EOP", &[0, 0, 2], 38),
+        ].concat();
+        let pbag = [0u16, 0, 1, 0, 2, 0].iter().flat_map(|v| v.to_le_bytes()).collect::<Vec<u8>>();
+        let pgen = gens(&[(INSTRUMENT as u16, 0), (INSTRUMENT as u16, 0)]);
+        let inst = [header("Piano", &[0], 22), header("EOI", &[1], 22)].concat();
+        let ibag = [0u16, 0, 2, 0].iter().flat_map(|v| v.to_le_bytes()).collect::<Vec<u8>>();
+        let igen = gens(&[(KEY_RANGE as u16, u16::from_le_bytes([48, 72])), (SAMPLE_ID as u16, 0)]);
+        let shdr = [sample, header("EOS", &[], 46)].concat();
+
+        let body = [
+            b"sfbk".to_vec(),
+            list(b"sdta", &[chunk(b"smpl", &smpl)]),
+            list(b"pdta", &[
+                 chunk(b"phdr", &phdr), chunk(b"pbag", &pbag), chunk(b"pgen", &pgen),
+                 chunk(b"inst", &inst), chunk(b"ibag", &ibag), chunk(b"igen", &igen),
+                 chunk(b"shdr", &shdr)]),
+        ].concat();
+        chunk(b"RIFF", &body)
+    }
+
+    #[test]
+    fn test_parse() {
+        let font = SoundFont::parse(&soundfont()).unwrap();
+        assert_eq!(font.presets.len(), 2);
+        assert_eq!(font.presets[0].name, "Piano");
+        assert_eq!(font.preset_index(128, 0), Some(1));
+
+        let zone = font.presets[0].zones[0];
+        assert_eq!(zone.keys, (48, 72));
+        assert_eq!((zone.start, zone.end), (0, 100));
+        assert_eq!(zone.root, 60);
+        assert!(zone.plays(60, 100));
+        assert!(!zone.plays(80, 100));
+    }
+
+    #[test]
+    fn test_presets() {
+        let mut config = SoundFontConfig::default();
+        config.presets.insert("10".to_string(), "128:0".to_string());
+        let instruments = config.instruments(SoundFont::parse(&soundfont()).unwrap()).unwrap();
+        assert_eq!(instruments.presets[0], Some(0));
+        assert_eq!(instruments.presets[9], Some(1));
+
+        let mut sampler = Sampler::new(instruments, 22050.);
+        sampler.note_on(0, 60, 127);
+        sampler.note_on(0, 90, 127);
+        assert_eq!(sampler.voices.len(), 1);
+
+        config.presets.insert("2".to_string(), "5".to_string());
+        assert!(config.instruments(SoundFont::parse(&soundfont()).unwrap()).is_err());
+    }
+}
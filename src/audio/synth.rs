@@ -0,0 +1,341 @@
+use std::fmt;
+use std::f32::consts::TAU;
+use serde::{Serialize, Deserialize};
+
+// Shortest fade in or out, in seconds,
+// to avoid clicks
+const MIN_FADE: f32 = 0.005;
+
+// Level of each note, leaving
+// headroom for chords
+const GAIN: f32 = 0.15;
+
+// Length of a metronome click, in seconds
+const CLICK_LENGTH: f32 = 0.03;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Waveform {
+    Sine,
+    Triangle,
+    Saw,
+    Square,
+}
+
+const WAVEFORMS: [Waveform; 4] = [Waveform::Sine, Waveform::Triangle, Waveform::Saw, Waveform::Square];
+
+impl Waveform {
+    /// The waveform after this one, for cycling through them.
+    pub fn next(self) -> Waveform {
+        let idx = WAVEFORMS.iter().position(|w| *w == self).unwrap_or(0);
+        WAVEFORMS[(idx + 1) % WAVEFORMS.len()]
+    }
+
+    /// The waveform's value at a phase, 0-1.
+    fn sample(&self, phase: f32) -> f32 {
+        match self {
+            Waveform::Sine => (phase * TAU).sin(),
+            Waveform::Triangle => 4. * (phase - 0.5).abs() - 1.,
+            Waveform::Saw => 2. * phase - 1.,
+
+            // Quieter, as it's much louder than the others
+            Waveform::Square => if phase < 0.5 { 0.5 } else { -0.5 },
+        }
+    }
+}
+
+impl Default for Waveform {
+    fn default() -> Self {
+        Waveform::Sine
+    }
+}
+
+impl fmt::Display for Waveform {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Waveform::Sine => write!(f, "sine"),
+            Waveform::Triangle => write!(f, "triangle"),
+            Waveform::Saw => write!(f, "saw"),
+            Waveform::Square => write!(f, "square"),
+        }
+    }
+}
+
+/// How the synth sounds.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Patch {
+    pub waveform: Waveform,
+
+    // 0-1
+    pub volume: f32,
+
+    // Envelope times in seconds,
+    // except sustain, which is a level, 0-1
+    pub attack: f32,
+    pub decay: f32,
+    pub sustain: f32,
+    pub release: f32,
+}
+
+impl Default for Patch {
+    fn default() -> Self {
+        Patch {
+            waveform: Waveform::default(),
+            volume: 0.8,
+            attack: 0.01,
+            decay: 0.2,
+            sustain: 0.7,
+            release: 0.3,
+        }
+    }
+}
+
+impl Patch {
+    pub(super) fn adsr(&self) -> Adsr {
+        Adsr {
+            attack: self.attack,
+            decay: self.decay,
+            sustain: self.sustain,
+            release: self.release,
+        }
+    }
+}
+
+/// Envelope times in seconds,
+/// except sustain, which is a level, 0-1.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(super) struct Adsr {
+    pub attack: f32,
+    pub decay: f32,
+    pub sustain: f32,
+    pub release: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Stage {
+    Attack,
+    Decay,
+    Sustain,
+
+    // Fading out from this level
+    Release(f32),
+}
+
+/// The level of a note over time.
+#[derive(Debug, Clone, Copy)]
+pub(super) struct Envelope {
+    stage: Stage,
+    pub level: f32,
+}
+
+impl Envelope {
+    pub fn new() -> Envelope {
+        Envelope {
+            stage: Stage::Attack,
+            level: 0.,
+        }
+    }
+
+    /// Move the envelope along a sample.
+    pub fn advance(&mut self, adsr: &Adsr, sample_rate: f32) {
+        let per_sample = |secs: f32| 1. / (secs.max(MIN_FADE) * sample_rate);
+        let sustain = adsr.sustain.clamp(0., 1.);
+        match self.stage {
+            Stage::Attack => {
+                self.level += per_sample(adsr.attack);
+                if self.level >= 1. {
+                    self.level = 1.;
+                    self.stage = Stage::Decay;
+                }
+            }
+            Stage::Decay => {
+                self.level -= (1. - sustain) * per_sample(adsr.decay);
+                if self.level <= sustain {
+                    self.level = sustain;
+                    self.stage = Stage::Sustain;
+                }
+            }
+            Stage::Sustain => {
+                self.level = sustain;
+            }
+            Stage::Release(from) => {
+                self.level = (self.level - from * per_sample(adsr.release)).max(0.);
+            }
+        }
+    }
+
+    pub fn release(&mut self) {
+        if !self.is_released() {
+            self.stage = Stage::Release(self.level);
+        }
+    }
+
+    pub fn is_released(&self) -> bool {
+        matches!(self.stage, Stage::Release(_))
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.is_released() && self.level <= 0.
+    }
+}
+
+struct Voice {
+    note: u8,
+    freq: f32,
+    velocity: f32,
+
+    // Position in the waveform, 0-1
+    phase: f32,
+
+    envelope: Envelope,
+}
+
+/// A simple polyphonic synth.
+pub struct Synth {
+    pub patch: Patch,
+    sample_rate: f32,
+    voices: Vec<Voice>,
+}
+
+impl Default for Synth {
+    fn default() -> Self {
+        Synth::new(Patch::default())
+    }
+}
+
+/// Frequency of a MIDI note, with A4 (69) at 440Hz.
+pub(super) fn frequency(note: u8) -> f32 {
+    440. * 2f32.powf((note as f32 - 69.) / 12.)
+}
+
+impl Synth {
+    pub fn new(patch: Patch) -> Synth {
+        Synth {
+            patch,
+            sample_rate: 44100.,
+            voices: vec![],
+        }
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.sample_rate = sample_rate;
+    }
+
+    pub fn note_on(&mut self, note: u8, velocity: u8) {
+        // Retrigger the note if it's already playing
+        self.voices.retain(|v| v.note != note);
+        self.voices.push(Voice {
+            note,
+            freq: frequency(note),
+            velocity: velocity as f32 / 127.,
+            phase: 0.,
+            envelope: Envelope::new(),
+        });
+    }
+
+    pub fn note_off(&mut self, note: u8) {
+        for voice in self.voices.iter_mut().filter(|v| v.note == note) {
+            voice.envelope.release();
+        }
+    }
+
+    /// Whether any notes are still sounding.
+    pub fn is_playing(&self) -> bool {
+        !self.voices.is_empty()
+    }
+
+    pub fn next_sample(&mut self) -> f32 {
+        let adsr = self.patch.adsr();
+        let mut out = 0.;
+        for voice in &mut self.voices {
+            voice.envelope.advance(&adsr, self.sample_rate);
+            out += self.patch.waveform.sample(voice.phase) * voice.envelope.level * voice.velocity;
+            voice.phase = (voice.phase + voice.freq / self.sample_rate).fract();
+        }
+        self.voices.retain(|v| !v.envelope.is_done());
+        out * GAIN * self.patch.volume.clamp(0., 1.)
+    }
+}
+
+/// Kinds of metronome clicks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Beat {
+    // The first beat of a bar
+    Accent,
+    Beat,
+
+    // Steps between beats
+    Subdivision,
+}
+
+/// A metronome click, a short blip
+/// that's higher on accented beats
+/// and quieter between beats.
+pub struct Click {
+    freq: f32,
+    level: f32,
+
+    // Seconds since the click started
+    time: f32,
+}
+
+impl Click {
+    pub fn new(beat: Beat) -> Click {
+        let (freq, level) = match beat {
+            Beat::Accent => (1760., 1.),
+            Beat::Beat => (880., 1.),
+            Beat::Subdivision => (880., 0.4),
+        };
+        Click {
+            freq,
+            level,
+            time: 0.,
+        }
+    }
+
+    /// Returns `None` once the click is over.
+    pub fn next_sample(&mut self, sample_rate: f32) -> Option<f32> {
+        if self.time >= CLICK_LENGTH {
+            return None;
+        }
+        let fade = 1. - self.time / CLICK_LENGTH;
+        let value = (self.time * self.freq * TAU).sin() * fade * fade * self.level;
+        self.time += 1. / sample_rate;
+        Some(value)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_frequency() {
+        assert_eq!(frequency(69), 440.);
+        assert_eq!(frequency(81), 880.);
+    }
+
+    #[test]
+    fn test_envelope() {
+        let patch = Patch::default();
+        let mut synth = Synth::new(patch);
+        synth.note_on(60, 127);
+
+        // Through the attack and decay,
+        // with some leeway for rounding
+        let secs = |s: f32| (s * 44100.) as usize + 10;
+        for _ in 0..secs(patch.attack + patch.decay) {
+            synth.next_sample();
+        }
+        assert_eq!(synth.voices[0].envelope.stage, Stage::Sustain);
+        assert_eq!(synth.voices[0].envelope.level, patch.sustain);
+
+        // And released
+        synth.note_off(60);
+        for _ in 0..secs(patch.release) {
+            synth.next_sample();
+        }
+        assert!(!synth.is_playing());
+    }
+}
@@ -0,0 +1,260 @@
+use anyhow::{Result, anyhow};
+use clap::{Subcommand, ValueHint};
+use std::{fs, io::{self, Read, IsTerminal}, path::PathBuf, str::FromStr, thread::sleep};
+use crate::config::Config;
+use crate::file::{save_progression_to_midi_file, ExportOptions};
+use crate::midi::{MIDIInput, MIDIOutput, Output, port_index, port_indices};
+use crate::progression::{Progression, ProgressionTemplate, Cadence, Accent};
+use crate::core::{Key, Mode, Note, Duration, ChordSpec, ChordParseError};
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// List the available MIDI ports and their indices
+    Ports,
+
+    /// Generate a progression without starting the UI
+    Gen(GenArgs),
+
+    /// Play a progression on a loop without starting the UI
+    Play(PlayArgs),
+}
+
+#[derive(clap::Args, Debug)]
+pub struct KeyArgs {
+    /// Root of the key, e.g. "C4"
+    #[clap(short, long, default_value = "C4")]
+    key: Note,
+
+    /// "major", "minor", "dorian", "phrygian", "lydian", "mixolydian" or "locrian"
+    #[clap(short, long, default_value = "major")]
+    mode: Mode,
+}
+
+impl KeyArgs {
+    fn key(&self) -> Key {
+        Key {
+            root: self.key,
+            mode: self.mode,
+        }
+    }
+}
+
+#[derive(clap::Args, Debug)]
+pub struct GenArgs {
+    #[clap(flatten)]
+    key: KeyArgs,
+
+    #[clap(short, long, default_value = "2")]
+    bars: usize,
+
+    /// One of 1/4, 1/8, 1/16 or 1/32
+    #[clap(short, long, default_value = "1/8")]
+    resolution: Duration,
+
+    /// Chord to start the progression with, e.g. "vi"
+    #[clap(long)]
+    seed: Option<ChordSpec>,
+
+    /// Place this many chords in each bar, spread
+    /// evenly, instead of at random
+    #[clap(long)]
+    euclid: Option<usize>,
+
+    /// End the progression with a cadence: "authentic",
+    /// "plagal", "half" or "deceptive"
+    #[clap(long)]
+    cadence: Option<Cadence>,
+
+    /// Write the progression to a MIDI file
+    #[clap(short, long)]
+    export: Option<String>,
+
+    /// Tempo of the exported MIDI file
+    #[clap(short, long, default_value = "120")]
+    tempo: usize,
+
+    /// Swing of off-beat steps in the exported
+    /// MIDI file, from 50% (straight) to 75%
+    #[clap(long, default_value = "50")]
+    swing: usize,
+
+    /// How loud chords are across each bar in the
+    /// exported MIDI file: "none", "1", "1+3", "4/4"
+    /// or levels, e.g. "100,60,80,60"
+    #[clap(long, default_value = "none")]
+    accent: Accent,
+
+    /// MIDI channel of the exported file, 1-16
+    #[clap(long, default_value = "1")]
+    channel: u8,
+
+    /// Don't print the progression
+    #[clap(short, long)]
+    quiet: bool,
+}
+
+/// Print the MIDI ports, for use with
+/// `--midi-in-port` and `--midi-out-port`.
+pub fn list_ports() {
+    let ports = [
+        ("MIDI inputs", MIDIInput::new().available_ports()),
+        ("MIDI outputs", MIDIOutput::new().available_ports()),
+    ];
+    for (title, ports) in ports {
+        println!("{}:", title);
+        match ports {
+            Ok(ports) if ports.is_empty() => println!("  (none)"),
+            Ok(ports) => {
+                for (i, port) in ports.iter().enumerate() {
+                    println!("  {}: {}", i, port);
+                }
+            }
+            Err(err) => println!("  {}", err),
+        }
+    }
+}
+
+/// Read chords piped into stdin, if any.
+fn piped_chords() -> Result<Option<Vec<ChordSpec>>> {
+    let mut stdin = io::stdin();
+    if stdin.is_terminal() {
+        return Ok(None);
+    }
+    let mut input = String::new();
+    stdin.read_to_string(&mut input)?;
+    let chords = parse_chords(&input)?;
+    Ok(if chords.is_empty() { None } else { Some(chords) })
+}
+
+/// Generate a progression, printing its chords
+/// and/or exporting it to a MIDI file.
+/// Chords piped into stdin are used as the
+/// progression instead of generating one.
+pub fn gen(template: &ProgressionTemplate, args: GenArgs) -> Result<()> {
+    let key = args.key.key();
+    let progression = match (piped_chords()?, &args.seed, args.euclid) {
+        (Some(chords), _, hits) => {
+            let mut progression = Progression::from_chords(chords, args.bars, args.resolution);
+            if let Some(hits) = hits {
+                let len = progression.sequence.len();
+                progression.place_euclidean(hits, (0, len), true);
+            }
+            progression
+        }
+        (None, seed, Some(hits)) => {
+            let seed = seed.clone().unwrap_or_else(|| template.rand_chord_for_mode(&key.mode));
+            template.gen_euclidean_progression(&seed, &key.mode, args.bars, &args.resolution, hits, args.cadence)
+        }
+        (None, Some(seed), None) => template.gen_progression_from_seed(seed, &key.mode, args.bars, &args.resolution, args.cadence),
+        (None, None, None) => template.gen_progression(&key.mode, args.bars, &args.resolution, args.cadence),
+    };
+
+
+    if !args.quiet {
+        let chords: Vec<String> = progression.chords().iter()
+            .map(|cs| cs.to_string()).collect();
+        println!("{}", chords.join(" "));
+    }
+
+    if let Some(path) = args.export {
+        if !(1..=16).contains(&args.channel) {
+            return Err(anyhow!("The channel must be 1-16"));
+        }
+        let options = ExportOptions {
+            channel: args.channel - 1,
+            ..ExportOptions::default()
+        };
+        save_progression_to_midi_file(args.tempo, args.swing, &args.accent, &progression, &key, options, path)?;
+    }
+    Ok(())
+}
+
+#[derive(clap::Args, Debug)]
+pub struct PlayArgs {
+    /// Chords to play, e.g. "I vi IV V"
+    chords: Vec<String>,
+
+    /// Read the chords from a file instead
+    #[clap(short, long, value_hint = ValueHint::FilePath)]
+    file: Option<PathBuf>,
+
+    #[clap(flatten)]
+    key: KeyArgs,
+
+    #[clap(short, long, default_value = "120")]
+    tempo: usize,
+
+    /// Beats each chord is played for
+    #[clap(short, long, default_value = "4")]
+    beats: usize,
+
+    /// How many times to play the progression,
+    /// or 0 to loop until interrupted
+    #[clap(short, long, default_value = "0")]
+    loops: usize,
+
+    /// Index of the MIDI output port,
+    /// otherwise the configured port is used
+    #[clap(long)]
+    port: Option<usize>,
+}
+
+/// Parse space-separated chords, e.g. "I vi IV V".
+fn parse_chords(input: &str) -> Result<Vec<ChordSpec>, ChordParseError> {
+    input.split_whitespace().map(ChordSpec::from_str).collect()
+}
+
+/// Play chords on a MIDI output port.
+pub fn play(args: PlayArgs) -> Result<()> {
+    let input = match &args.file {
+        Some(path) => fs::read_to_string(path)?,
+        None => args.chords.join(" "),
+    };
+    let chords = parse_chords(&input)?;
+    if chords.is_empty() {
+        return Err(anyhow!("No chords to play"));
+    }
+
+    let config = Config::load()?;
+    let mut midi = MIDIOutput::new();
+    midi.channel = config.channel.saturating_sub(1);
+    midi.note_off = config.note_off;
+    midi.release_velocity = config.release_velocity;
+    midi.overlap = config.overlap;
+    midi.set_patch(config.preview);
+    match config.instruments() {
+        Ok(instruments) => midi.set_instruments(instruments),
+        Err(err) => eprintln!("{}, using the synth instead", err),
+    }
+    let ports = midi.available_ports().map_err(|err| anyhow!(err.to_string()))?;
+    let idx = match (args.port, config.midi_out_port) {
+        (Some(idx), _) => Some(idx),
+        (None, _) if config.output == Output::Audio => None,
+        (None, Some(name)) => Some(port_index(&ports, &name).map_err(|err| anyhow!(err.to_string()))?),
+        (None, None) => return Err(anyhow!("No MIDI output port, pass one with --port (see `dust ports`)")),
+    };
+    match idx {
+        Some(idx) => {
+            // Mirrored to the broadcast ports too
+            port_indices(&ports, &config.broadcast_ports).and_then(|others| {
+                let mut idxs = vec![idx];
+                idxs.extend(others.into_iter().filter(|other| *other != idx));
+                midi.connect_ports(&idxs)
+            })
+        }
+        None => midi.connect_audio(),
+    }.map_err(|err| anyhow!(err.to_string()))?;
+
+    let key = args.key.key();
+    let length = std::time::Duration::from_millis((args.beats * 60000 / args.tempo.max(1)) as u64);
+    let mut played = 0;
+    while args.loops == 0 || played < args.loops {
+        for cs in &chords {
+            println!("{}", cs);
+            midi.play_chord_for(&cs.chord_for_key(&key), length);
+            sleep(length);
+        }
+        played += 1;
+    }
+    midi.close()
+}
@@ -0,0 +1,278 @@
+use anyhow::Result;
+use std::{fs, env, path::PathBuf, collections::BTreeMap};
+use serde::{Serialize, Deserialize};
+use crate::app::{Theme, Symbols, Metronome};
+use crate::audio::{Patch, Mixer, SoundFontConfig, Instruments, load_samples};
+use crate::core::{Key, Note, Mode, Duration, Accidentals};
+use crate::midi::{ClockSource, Output, NoteOff};
+use crate::progression::Accent;
+use crate::file::{ChordMarkers, ExportOptions};
+
+/// User preferences, persisted
+/// to `~/.config/dust/config.toml`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    // Whether to play notes on the MIDI
+    // output port or the audio preview
+    pub output: Output,
+
+    // MIDI ports, by name
+    pub midi_in_port: Option<String>,
+    pub midi_out_port: Option<String>,
+
+    // MIDI input port chords are played
+    // into, e.g. from a keyboard, by name
+    pub note_in_port: Option<String>,
+
+    // Other MIDI output ports that
+    // get every message too, by name
+    pub broadcast_ports: Vec<String>,
+
+    // MIDI channel, 1-16
+    pub channel: u8,
+
+    // MIDI channel exported files are written on, 1-16
+    pub export_channel: u8,
+
+    // Whether exported files mark each chord with its name
+    pub chord_markers: ChordMarkers,
+
+    // MIDI channel basslines play on, 1-16
+    pub bass_channel: u8,
+
+    // How notes are ended, and
+    // their note off velocity, 0-127
+    pub note_off: NoteOff,
+    pub release_velocity: u8,
+
+    // Milliseconds chords overlap the next,
+    // or if negative, the gap before it
+    pub overlap: isize,
+
+    pub tempo: usize,
+    pub clock: ClockSource,
+
+    // Send MIDI clock to the output ports,
+    // when using the internal or JACK clock
+    pub clock_out: bool,
+
+    // How much to smooth a jittery
+    // external clock, as a percentage
+    pub clock_smoothing: usize,
+
+    pub save_dir: String,
+    pub accidentals: Accidentals,
+    pub theme: Theme,
+    pub symbols: Symbols,
+
+    // Pick keys around the circle of
+    // fifths, rather than typing a root
+    pub circle_of_fifths: bool,
+
+    // What the sequencer and performance
+    // modes start with
+    #[serde(with = "as_str")]
+    pub key: Note,
+    #[serde(with = "as_str")]
+    pub mode: Mode,
+    pub bars: usize,
+    #[serde(with = "as_str")]
+    pub resolution: Duration,
+    pub swing: usize,
+    #[serde(with = "as_str")]
+    pub accent: Accent,
+
+    // Directory of samples for the audio preview,
+    // used when there's no soundfont
+    pub samples: Option<String>,
+
+    // The tables have to be last,
+    // as they come after the other values in TOML
+
+    // How the audio preview sounds
+    pub preview: Patch,
+
+    // Soundfont for the audio preview
+    pub soundfont: SoundFontConfig,
+
+    // Levels of the audio preview's sources
+    pub mixer: Mixer,
+
+    pub metronome: Metronome,
+
+    // Remapped keys, from the key pressed
+    // to the key it stands in for.
+    pub keymap: BTreeMap<String, char>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            output: Output::default(),
+            midi_in_port: None,
+            midi_out_port: None,
+            note_in_port: None,
+            broadcast_ports: vec![],
+            channel: 1,
+            export_channel: 1,
+            chord_markers: ChordMarkers::default(),
+            bass_channel: 2,
+            note_off: NoteOff::default(),
+            release_velocity: 100,
+            overlap: 0,
+            tempo: 120,
+            clock: ClockSource::default(),
+            clock_out: false,
+            clock_smoothing: 0,
+            save_dir: "/tmp/".to_string(),
+            accidentals: Accidentals::default(),
+            theme: Theme::default(),
+            symbols: Symbols::default(),
+            circle_of_fifths: true,
+            key: Key::default().root,
+            mode: Key::default().mode,
+            bars: 2,
+            resolution: Duration::Eighth,
+            swing: 50,
+            accent: Accent::default(),
+            samples: None,
+            preview: Patch::default(),
+            soundfont: SoundFontConfig::default(),
+            mixer: Mixer::default(),
+            metronome: Metronome::default(),
+            keymap: BTreeMap::default(),
+        }
+    }
+}
+
+impl Config {
+    pub fn path() -> PathBuf {
+        let home = env::var("HOME").unwrap_or_default();
+        PathBuf::from(home).join(".config/dust/config.toml")
+    }
+
+    /// Load the config file,
+    /// or the defaults if there isn't one.
+    pub fn load() -> Result<Config> {
+        let path = Config::path();
+        if path.exists() {
+            let contents = fs::read_to_string(path)?;
+            Ok(toml::from_str(&contents)?)
+        } else {
+            Ok(Config::default())
+        }
+    }
+
+    /// The key a pressed key is remapped to, if any.
+    pub fn remap(&self, c: char) -> char {
+        self.keymap.get(&c.to_string()).copied().unwrap_or(c)
+    }
+
+    /// Instruments for the audio preview: the soundfont,
+    /// or the sample directory if there's no soundfont.
+    /// Without either, the preview uses its synth.
+    pub fn instruments(&self) -> Result<Option<Instruments>> {
+        if let Some(instruments) = self.soundfont.load()? {
+            return Ok(Some(instruments));
+        }
+        match &self.samples {
+            Some(dir) if !dir.is_empty() => Ok(Some(load_samples(dir)?)),
+            _ => Ok(None),
+        }
+    }
+
+    /// How exported files are written.
+    pub fn export_options(&self) -> ExportOptions {
+        ExportOptions {
+            channel: self.export_channel.saturating_sub(1),
+            markers: self.chord_markers,
+            accidentals: self.accidentals,
+        }
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let path = Config::path();
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, toml::to_string(self)?)?;
+        Ok(())
+    }
+}
+
+/// For values stored as they're displayed,
+/// e.g. a resolution of "1/8".
+pub mod as_str {
+    use std::{fmt::Display, str::FromStr};
+    use serde::{Deserialize, Deserializer, Serializer, de::Error};
+
+    pub fn serialize<T: Display, S: Serializer>(value: &T, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(value)
+    }
+
+    pub fn deserialize<'de, T, D>(deserializer: D) -> Result<T, D::Error>
+        where T: FromStr, T::Err: Display, D: Deserializer<'de> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(D::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::str::FromStr;
+    use crate::audio::Waveform;
+
+    #[test]
+    fn test_partial_config() {
+        let config: Config = toml::from_str(r#"
+            tempo = 90
+            clock = "internal"
+            accidentals = "sharps"
+        "#).unwrap();
+        assert_eq!(config.tempo, 90);
+        assert_eq!(config.clock, ClockSource::Internal);
+        assert_eq!(config.accidentals, Accidentals::Sharps);
+
+        // Missing fields use the defaults
+        assert_eq!(config.channel, 1);
+        assert_eq!(config.midi_out_port, None);
+    }
+
+    #[test]
+    fn test_musical_defaults() {
+        let config: Config = toml::from_str(r#"
+            key = "Eb3"
+            mode = "minor"
+            resolution = "1/16"
+            theme = "mono"
+            symbols = "unicode"
+            note_off = "zero-velocity"
+
+            [preview]
+            waveform = "saw"
+
+            [keymap]
+            x = "R"
+        "#).unwrap();
+        assert_eq!(config.key, Note::from_str("Eb3").unwrap());
+        assert_eq!(config.mode, Mode::Minor);
+        assert_eq!(config.resolution, Duration::Sixteenth);
+        assert_eq!(config.theme, Theme::Mono);
+        assert_eq!(config.symbols, Symbols::Unicode);
+        assert_eq!(config.note_off, NoteOff::ZeroVelocity);
+        assert_eq!(config.release_velocity, 100);
+        assert_eq!(config.preview.waveform, Waveform::Saw);
+        assert_eq!(config.preview.volume, Patch::default().volume);
+        assert_eq!(config.remap('x'), 'R');
+        assert_eq!(config.remap('y'), 'y');
+
+        // And survives a round trip
+        let saved = toml::to_string(&config).unwrap();
+        let loaded: Config = toml::from_str(&saved).unwrap();
+        assert_eq!(loaded.key, config.key);
+        assert_eq!(loaded.resolution, config.resolution);
+        assert_eq!(loaded.keymap, config.keymap);
+    }
+}
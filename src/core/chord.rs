@@ -1,7 +1,7 @@
 use regex::Regex;
 use thiserror::Error;
 use std::{fmt, str::FromStr};
-use super::note::Note;
+use super::note::{Note, Accidentals};
 use super::key::{Key, Mode};
 use super::interval::Interval;
 use super::degree::{Degree, DegreeParseError};
@@ -9,10 +9,23 @@ use lazy_static::lazy_static;
 
 pub const NUMERALS: [&str; 7] = ["I", "II", "III", "IV", "V", "VI", "VII"];
 
+// Chord qualities notes are recognized
+// as, in the order they're preferred
+const QUALITIES: [&str; 21] = [
+    "", "m", "7", "m7", "maj7", "dim", "m7b5", "dim7", "aug",
+    "sus4", "sus2", "7sus4", "6", "m6", "9", "m9", "maj9",
+    "add9", "madd9", "mMaj7", "5"];
+
 lazy_static! {
     static ref CHORD_RE: Regex = Regex::new(
         r"^([b#])*([IV]+|[iv]+)([+-^_5])?(:([b#]?\d+,?)*)?(/([b#]?\d+)|(%([b#]?\d+)))?(>\d+)?(<\d+)?(~([b#])*([IV]+|[iv]+))?$")
         .unwrap();
+
+    static ref CHORD_NAME_RE: Regex = Regex::new(
+        r"^([A-G][b#]?)(m|min|-|dim|°|o|aug|\+|ø)?(maj|Maj|M|Δ)?(5|6|7|9|11|13)?(sus[24]?)?((?:(?:add|[b#])\d+)*)(/([A-G][b#]?))?$")
+        .unwrap();
+
+    static ref ALTERATION_RE: Regex = Regex::new(r"(add|[b#])(\d+)").unwrap();
 }
 
 fn numeral_to_index(numeral: &str) -> Option<usize> {
@@ -29,6 +42,50 @@ fn numeral_to_mode(numeral: &str) -> Result<Mode, ChordParseError>{
     }
 }
 
+/// The pitch class of a note name without an octave, e.g. "F#".
+fn pitch_class(name: &str) -> Result<isize, ChordParseError> {
+    let note: Note = format!("{}4", name).parse()
+        .map_err(|_| ChordParseError::InvalidChordName(name.to_string()))?;
+    Ok(note.semitones.rem_euclid(12))
+}
+
+/// The degree of a scale for a number of semitones above its root:
+/// the degree itself if it's in the scale, otherwise the
+/// degree above flattened, or failing that the one below sharpened.
+fn degree_in_scale(semitones: isize, scale: &[usize; 7]) -> Degree {
+    let at = |d: usize| scale[d] as isize;
+    if let Some(d) = (0..7).find(|d| at(*d) == semitones) {
+        Degree { degree: d + 1, adj: 0 }
+    } else if let Some(d) = (1..7).find(|d| at(*d) == semitones + 1) {
+        Degree { degree: d + 1, adj: -1 }
+    } else {
+        let d = (0..7).rev().find(|d| at(*d) < semitones).unwrap_or(0);
+        Degree { degree: d + 1, adj: semitones - at(d) }
+    }
+}
+
+/// The pitch classes of some notes, as semitones above A.
+fn pitch_classes(notes: &[Note]) -> Vec<isize> {
+    let mut pitches: Vec<isize> = notes.iter().map(|n| n.semitones.rem_euclid(12)).collect();
+    pitches.sort_unstable();
+    pitches.dedup();
+    pitches
+}
+
+/// Semitones above the root for an
+/// unaltered extension, e.g. 14 for a 9th.
+fn extension_semitones(degree: usize) -> Option<isize> {
+    match degree {
+        2 => Some(2),
+        4 => Some(5),
+        6 => Some(9),
+        9 => Some(14),
+        11 => Some(17),
+        13 => Some(21),
+        _ => None,
+    }
+}
+
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
 pub enum Triad {
     Mode,
@@ -66,6 +123,165 @@ impl ChordSpec {
         }
     }
 
+    /// The triad built from the key's scale on the
+    /// given degree, e.g. "ii" or "vii-" in a major key.
+    pub fn diatonic(degree: usize, key: &Key) -> ChordSpec {
+        let note = |d: usize| key.note(&Degree { degree: d, adj: 0 }).semitones;
+        let third = (note(degree + 2) - note(degree)).rem_euclid(12);
+        let fifth = (note(degree + 4) - note(degree)).rem_euclid(12);
+        let mode = if third == 3 { Mode::Minor } else { Mode::Major };
+        let triad = match fifth {
+            6 => Triad::Diminished,
+            8 => Triad::Augmented,
+            _ => Triad::Mode,
+        };
+        ChordSpec::new(degree, mode).triad(triad)
+    }
+
+    /// Parse a letter-name chord symbol, e.g. "Cmaj7",
+    /// "F#m7b5" or "G7/B", into a chord spec relative to the key.
+    /// Supports the common qualities (m, dim, aug, ø, sus2, sus4, 5),
+    /// 6ths, 7ths, 9ths, 11ths and 13ths, "maj" sevenths,
+    /// alterations like "b9" or "#11", "add" tones and slash basses.
+    pub fn from_name(name: &str, key: &Key) -> Result<ChordSpec, ChordParseError> {
+        let invalid = || ChordParseError::InvalidChordName(name.to_string());
+        let clean = name.replace('♯', "#").replace('♭', "b").replace(['(', ')'], "");
+        let caps = CHORD_NAME_RE.captures(&clean).ok_or_else(invalid)?;
+        let root = pitch_class(&caps[1])?;
+        let quality = caps.get(2).map(|m| m.as_str());
+        let major_seventh = caps.get(3).is_some();
+        let ext = caps.get(4).map(|m| m.as_str());
+        let sus = caps.get(5).map(|m| m.as_str());
+
+        let mode = match quality {
+            Some("m" | "min" | "-" | "dim" | "°" | "o" | "ø") => Mode::Minor,
+            _ => Mode::Major,
+        };
+        let mut triad = match (quality, sus, ext) {
+            (Some("dim" | "°" | "o" | "ø"), _, _) => Triad::Diminished,
+            (Some("aug" | "+"), _, _) => Triad::Augmented,
+            (_, Some("sus2"), _) => Triad::Sus2,
+            (_, Some(_), _) => Triad::Sus4,
+            (_, _, Some("5")) => Triad::Power,
+            _ => Triad::Mode,
+        };
+
+        // Extensions, as their degree and semitones above the root
+        let seventh = if major_seventh {
+            11
+        } else if matches!(quality, Some("dim" | "°" | "o")) {
+            9
+        } else {
+            10
+        };
+        let mut exts: Vec<(usize, isize)> = match ext {
+            Some("6") => vec![(6, 9)],
+            Some("7") => vec![(7, seventh)],
+            Some("9") => vec![(7, seventh), (9, 14)],
+            Some("11") => vec![(7, seventh), (9, 14), (11, 17)],
+            Some("13") => vec![(7, seventh), (9, 14), (13, 21)],
+            _ if quality == Some("ø") || major_seventh => vec![(7, seventh)],
+            _ => vec![],
+        };
+        for alt in ALTERATION_RE.captures_iter(&caps[6]) {
+            let degree: usize = alt[2].parse()?;
+            let natural = if degree == 5 { 7 } else {
+                extension_semitones(degree).ok_or_else(invalid)?
+            };
+            let semitones = match &alt[1] {
+                "b" => natural - 1,
+                "#" => natural + 1,
+                _ => natural,
+            };
+            if degree == 5 {
+                // Altered fifths change the triad
+                triad = match (semitones, mode, triad) {
+                    (6, Mode::Minor, Triad::Mode) => Triad::Diminished,
+                    (8, Mode::Major, Triad::Mode) => Triad::Augmented,
+                    _ => return Err(invalid()),
+                };
+            } else {
+                exts.retain(|(d, _)| *d != degree);
+                exts.push((degree, semitones));
+            }
+        }
+        exts.sort_unstable();
+
+        let root_degree = degree_in_scale((root - key.root.semitones).rem_euclid(12), key.mode.intervals());
+        let mut cs = ChordSpec::new(root_degree.degree, mode).adj(root_degree.adj).triad(triad);
+        for (degree, semitones) in exts {
+            let natural = Degree { degree, adj: 0 }.to_interval(&mode);
+            cs = cs.add(degree, semitones - natural);
+        }
+
+        // A bass note that's not in the chord is added to it
+        if let Some(bass) = caps.get(8) {
+            let semitones = (pitch_class(bass.as_str())? - root).rem_euclid(12);
+            let degree = degree_in_scale(semitones, mode.intervals());
+            if !cs.intervals().iter().any(|intv| intv.rem_euclid(12) == semitones) {
+                cs = cs.add(degree.degree, degree.adj);
+            }
+            cs = cs.bass(degree.degree, degree.adj);
+        }
+        Ok(cs)
+    }
+
+    /// Recognize a chord's notes as a chord spec in a key, going by
+    /// their pitch classes: a triad or seventh chord, with any
+    /// extensions, preferably rooted on the lowest note, otherwise
+    /// inverted so it's in the bass, or failing that with it as a
+    /// slash bass. The chord is voiced in the same octave as the notes.
+    /// If nothing matches exactly, the closest chord is given,
+    /// and if there are no notes, nothing is.
+    pub fn from_chord(chord: &Chord, key: &Key) -> Option<ChordSpec> {
+        let notes = chord.notes();
+        let lowest = notes.first()?.semitones;
+        let pitches = pitch_classes(&notes);
+        let bass = lowest.rem_euclid(12);
+        let mut roots = vec![bass];
+        roots.extend(pitches.iter().filter(|p| **p != bass));
+
+        let name = |pitch: isize| Note { semitones: pitch }.pitch_name(Accidentals::Sharps);
+        let lowest_pitch = |cs: &ChordSpec| cs.chord_for_key(key).notes()[0].semitones;
+        let (_, cs) = roots.iter()
+            .flat_map(|root| QUALITIES.iter().map(move |quality| (root, quality)))
+            .filter_map(|(root, quality)| {
+                let cs = ChordSpec::from_name(&format!("{}{}", name(*root), quality), key).ok()?;
+                if *root == bass {
+                    return Some(cs);
+                }
+
+                // An inversion with the bass note lowest,
+                // or if it's not in the chord, a slash chord
+                let inversions = cs.intervals().len();
+                (1..inversions)
+                    .map(|n| cs.clone().inversion(n))
+                    .find(|cs| lowest_pitch(cs).rem_euclid(12) == bass)
+                    .or_else(|| ChordSpec::from_name(&format!("{}{}/{}", name(*root), quality, name(bass)), key).ok())
+            })
+            .enumerate()
+            .min_by_key(|(i, cs)| {
+                let matched = pitch_classes(&cs.chord_for_key(key).notes());
+                let missing = pitches.iter().filter(|p| !matched.contains(p)).count();
+                let extra = matched.iter().filter(|p| !pitches.contains(p)).count();
+                (missing + extra, *i)
+            })?;
+
+        // In the notes' octave
+        let octaves = ((lowest - lowest_pitch(&cs)) as f64 / 12.).round() as isize;
+        Some(cs.shift(octaves))
+    }
+
+    /// Parse a chord in a key, either as a numeral, e.g. "ii:7",
+    /// or as a letter-name chord symbol, e.g. "Dm7".
+    pub fn parse_in_key(s: &str, key: &Key) -> Result<ChordSpec, ChordParseError> {
+        if s.starts_with(|c: char| ('A'..='G').contains(&c)) {
+            ChordSpec::from_name(s, key)
+        } else {
+            ChordSpec::from_str(s)
+        }
+    }
+
     /// Set the triad type for this chord
     pub fn triad(mut self, triad: Triad) -> ChordSpec {
         self.triad = triad;
@@ -76,7 +292,7 @@ impl ChordSpec {
     ///
     /// Examples:
     ///
-    /// ```
+    /// ```ignore
     /// // Adds a 7
     /// cs.add(7, 0)
     ///
@@ -91,6 +307,43 @@ impl ChordSpec {
         self
     }
 
+    /// Add an extension at this degree, picking the one
+    /// that's in the given key if there is one,
+    /// or remove it if the chord already has one.
+    pub fn toggle_extension(mut self, degree: usize, key: &Key) -> ChordSpec {
+        if self.extensions.iter().any(|ext| ext.degree == degree) {
+            self.extensions.retain(|ext| ext.degree != degree);
+            return self;
+        }
+        let pitches = |cs: &ChordSpec| -> Vec<isize> {
+            cs.chord_for_key(key).notes().iter()
+                .map(|n| n.semitones.rem_euclid(12)).collect()
+        };
+        let scale: Vec<isize> = (1..=7)
+            .map(|d| key.note(&Degree { degree: d, adj: 0 }).semitones.rem_euclid(12))
+            .collect();
+        let current = pitches(&self);
+        [0, -1, 1].iter()
+            .map(|adj| self.clone().add(degree, *adj))
+            .find(|cs| {
+                let added: Vec<isize> = pitches(cs).into_iter()
+                    .filter(|p| !current.contains(p)).collect();
+                !added.is_empty() && added.iter().all(|p| scale.contains(p))
+            })
+            .unwrap_or_else(|| self.add(degree, 0))
+    }
+
+    /// Cycle the chord through sus4, sus2
+    /// and back to its usual triad.
+    pub fn toggle_sus(mut self) -> ChordSpec {
+        self.triad = match self.triad {
+            Triad::Sus4 => Triad::Sus2,
+            Triad::Sus2 => Triad::Mode,
+            _ => Triad::Sus4,
+        };
+        self
+    }
+
     /// Set the bass degree
     pub fn bass(mut self, degree: usize, adj: isize) -> ChordSpec {
         self.bass_degree = Some(Degree { degree, adj });
@@ -136,11 +389,11 @@ impl ChordSpec {
     pub fn intervals(&self) -> Vec<isize> {
         let mut intervals = match self.triad {
             Triad::Mode => {
-                match self.mode {
+                match self.mode.quality() {
                     Mode::Major => {
                         vec![0, 4, 7]
                     }
-                    Mode::Minor => {
+                    _ => {
                         vec![0, 3, 7]
                     }
                 }
@@ -192,20 +445,200 @@ impl ChordSpec {
         self.intervals().iter().map(|intv| intv + offset).collect()
     }
 
+    /// The chord's name with ♭ and ♯ for accidentals,
+    /// ° and ø for diminished and half-diminished chords
+    /// and Δ for major sevenths, e.g. "♭VII" or "I:Δ7".
+    /// Unlike its regular name this can't be parsed.
+    pub fn symbol_name(&self) -> String {
+        self.name(true)
+    }
+
+    /// The chord's letter name in a key, e.g. "Am7" or "G/B",
+    /// going by the notes it has rather than how it's written.
+    pub fn name_in_key(&self, key: &Key, accidentals: Accidentals) -> String {
+        let chord = self.chord_for_key(key);
+        let spelling = chord.key.unwrap_or(*key);
+        let mut pitches: Vec<isize> = self.intervals().iter()
+            .map(|intv| intv.rem_euclid(12)).collect();
+        pitches.sort_unstable();
+        pitches.dedup();
+        let has = |semitones: isize| pitches.contains(&semitones);
+
+        let third = if has(4) { Some(4) } else if has(3) { Some(3) } else { None };
+        let (quality, fifth, seventh) = match (third, self.triad) {
+            (Some(3), Triad::Diminished) if has(10) => ("m", 6, Some(10)),
+            (Some(3), Triad::Diminished) if has(9) => ("dim", 6, Some(9)),
+            (Some(3), Triad::Diminished) => ("dim", 6, None),
+            (Some(4), Triad::Augmented) => ("aug", 8, None),
+            (Some(3), _) => ("m", 7, None),
+            _ => ("", 7, None),
+        };
+        let seventh = seventh.or_else(|| [11, 10].into_iter().find(|s| has(*s)));
+        let sus = match third {
+            Some(_) => "",
+            None if has(5) => "sus4",
+            None if has(2) => "sus2",
+            None => "5",
+        };
+
+        // Notes of the chord that are accounted for so far
+        let mut named = vec![0, fifth];
+        named.extend(third);
+        named.extend(seventh);
+        match sus {
+            "sus4" => named.push(5),
+            "sus2" => named.push(2),
+            _ => {}
+        }
+
+        let mut name = chord.root.spell_pitch(&spelling, accidentals);
+        name.push_str(quality);
+
+        // The highest natural extension stacked on
+        // the 7th names the chord, e.g. "9" or "13"
+        let mut ext = 7;
+        if seventh.is_some() {
+            for (semitones, degree) in [(2, 9), (5, 11), (9, 13)] {
+                if has(semitones) && (degree == 13 || ext == degree - 2) && !named.contains(&semitones) {
+                    ext = degree;
+                    named.push(semitones);
+                }
+            }
+        }
+        match seventh {
+            Some(11) if quality == "m" => name.push_str(&format!("Maj{}", ext)),
+            Some(11) => name.push_str(&format!("maj{}", ext)),
+            Some(10) if quality == "m" && fifth == 6 => name.push_str(&format!("{}b5", ext)),
+            Some(_) => name.push_str(&ext.to_string()),
+            None if has(9) && fifth == 7 && quality != "dim" => {
+                name.push('6');
+                named.push(9);
+            }
+            None => {}
+        }
+        name.push_str(sus);
+
+        // Whatever's left over
+        for (semitones, tone) in [(1, "b9"), (2, "9"), (3, "#9"), (5, "11"), (6, "#11"), (8, "b13"), (9, "13")] {
+            if has(semitones) && !named.contains(&semitones) {
+                if seventh.is_none() || !tone.starts_with(['b', '#']) {
+                    name.push_str("add");
+                }
+                name.push_str(tone);
+            }
+        }
+
+        // The bass, if it isn't the root
+        if let Some(lowest) = chord.notes().first() {
+            if (lowest.semitones - chord.root.semitones).rem_euclid(12) != 0 {
+                name.push('/');
+                name.push_str(&lowest.spell_pitch(&spelling, accidentals));
+            }
+        }
+        name
+    }
+
+    fn name(&self, symbols: bool) -> String {
+        let (flat, sharp) = if symbols { ("♭", "♯") } else { ("b", "#") };
+        let accidentals = |adj: isize| {
+            let sign = if adj < 0 { flat } else { sharp };
+            sign.repeat(adj.unsigned_abs())
+        };
+        let is_seventh = |ext: &Degree, semitones: isize| {
+            ext.degree == 7 && ext.to_interval(&self.mode).rem_euclid(12) == semitones
+        };
+
+        let mut name = "".to_string();
+
+        let count = self.root.adj.unsigned_abs();
+        let octaves = count/12;
+        let rem = count.rem_euclid(12);
+        name.push_str(&accidentals(self.root.adj.signum() * rem as isize));
+
+        // Convert 1-indexed degree to 0-indexed
+        let mut numeral = NUMERALS[(self.root.degree - 1) % 7].to_string();
+        if self.mode == Mode::Minor || self.triad == Triad::Diminished {
+            numeral = numeral.to_lowercase();
+        }
+        name.push_str(&numeral);
+
+        match self.triad {
+            Triad::Diminished if symbols => {
+                if self.extensions.iter().any(|ext| is_seventh(ext, 10)) {
+                    name.push('ø');
+                } else {
+                    name.push('°');
+                }
+            }
+            Triad::Diminished => name.push('-'),
+            Triad::Augmented => name.push('+'),
+            Triad::Sus2 => name.push('_'),
+            Triad::Sus4 => name.push('^'),
+            Triad::Power => name.push('5'),
+            Triad::Mode => {}
+        }
+
+        let exts = self.extensions.iter()
+            .map(|ext| {
+                let ext_name = format!("{}{}", accidentals(ext.adj), ext.degree);
+                if symbols && self.triad != Triad::Diminished && is_seventh(ext, 11) {
+                    format!("Δ{}", ext.degree)
+                } else {
+                    ext_name
+                }
+            })
+            .collect::<Vec<String>>();
+        if !exts.is_empty() {
+            name.push(':');
+            name.push_str(&exts.join(","));
+        }
+
+        if let Some(bass_degree) = &self.bass_degree {
+            name.push('/');
+            name.push_str(&accidentals(bass_degree.adj));
+            name.push_str(&bass_degree.degree.to_string());
+        }
+
+        if self.inversion > 0{
+            name.push('%');
+            name.push_str(&self.inversion.to_string());
+        }
+
+
+        if octaves != 0 {
+            if self.root.adj < 0 {
+                name.push_str(&format!("<{}", octaves));
+            } else if self.root.adj > 0 {
+                name.push_str(&format!(">{}", octaves));
+            }
+        }
+
+        if let Some((degree, mode)) = &self.rel_key {
+            name.push('~');
+            name.push_str(&accidentals(degree.adj));
+            let numeral = NUMERALS[(degree.degree - 1) % 7];
+            if mode == &Mode::Minor {
+                name.push_str(&numeral.to_lowercase());
+            } else {
+                name.push_str(numeral);
+            }
+        }
+        name
+    }
+
     /// Resolve the chord spec into actual semitones
     /// for the given key.
     pub fn chord_for_key(&self, key: &Key) -> Chord {
-        let root = match &self.rel_key {
-            None => key.note(&self.root),
-            Some((degree, mode)) => {
-                let rel_key = Key {
-                    root: key.note(&degree),
-                    mode: *mode,
-                };
-                rel_key.note(&self.root)
-            }
+        let key = match &self.rel_key {
+            None => *key,
+            Some((degree, mode)) => Key {
+                root: key.note(degree),
+                mode: *mode,
+            },
         };
-        Chord::new(root, self.intervals())
+        let mut chord = Chord::new(key.note(&self.root), self.intervals());
+        chord.key = Some(key);
+        chord
     }
 
     /// Calculate the "distance" to another chord,
@@ -249,6 +682,9 @@ pub enum ChordParseError {
     #[error("Invalid triad symbol `{0}`")]
     InvalidTriadSymbol(String),
 
+    #[error("Invalid chord name `{0}`")]
+    InvalidChordName(String),
+
     #[error("Invalid relative key `{0}`")]
     InvalidRelKey(String),
 
@@ -386,76 +822,7 @@ impl TryFrom<String> for ChordSpec {
 
 impl fmt::Display for ChordSpec {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let mut name = "".to_string();
-
-        let count = self.root.adj.abs() as usize;
-        let octaves = count/12;
-        let rem = count.rem_euclid(12);
-        if self.root.adj < 0 {
-            name.push_str(&std::iter::repeat("b").take(rem).collect::<String>());
-        } else if self.root.adj > 0 {
-            name.push_str(&std::iter::repeat("#").take(rem).collect::<String>());
-        }
-
-        // Convert 1-indexed degree to 0-indexed
-        let mut numeral = NUMERALS[(self.root.degree - 1) % 7].to_string();
-        if self.mode == Mode::Minor || self.triad == Triad::Diminished {
-            numeral = numeral.to_lowercase();
-        }
-        name.push_str(&numeral);
-
-        match self.triad {
-            Triad::Diminished => name.push('-'),
-            Triad::Augmented => name.push('+'),
-            Triad::Sus2 => name.push('_'),
-            Triad::Sus4 => name.push('^'),
-            Triad::Power => name.push('5'),
-            Triad::Mode => {}
-        }
-
-        let exts = self.extensions.iter()
-            .map(|ext| ext.to_string())
-            .collect::<Vec<String>>();
-        if exts.len() > 0 {
-            name.push(':');
-            name.push_str(&exts.join(","));
-        }
-
-        if let Some(bass_degree) = &self.bass_degree {
-            name.push('/');
-            name.push_str(&bass_degree.to_string());
-        }
-
-        if self.inversion > 0{
-            name.push('%');
-            name.push_str(&self.inversion.to_string());
-        }
-
-
-        if octaves != 0 {
-            if self.root.adj < 0 {
-                name.push_str(&format!("<{}", octaves));
-            } else if self.root.adj > 0 {
-                name.push_str(&format!(">{}", octaves));
-            }
-        }
-
-        if let Some((degree, mode)) = &self.rel_key {
-            name.push('~');
-            let n = degree.adj.abs() as usize;
-            if degree.adj < 0 {
-                name.push_str(&std::iter::repeat("b").take(n).collect::<String>());
-            } else if degree.adj > 0 {
-                name.push_str(&std::iter::repeat("#").take(n).collect::<String>());
-            }
-            let numeral = NUMERALS[(degree.degree - 1) % 7];
-            if mode == &Mode::Minor {
-                name.push_str(&numeral.to_lowercase());
-            } else {
-                name.push_str(&numeral);
-            }
-        }
-        write!(f, "{}", name)
+        write!(f, "{}", self.name(false))
     }
 }
 
@@ -463,7 +830,11 @@ impl fmt::Display for ChordSpec {
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub struct Chord {
     root: Note,
-    intervals: Vec<Interval>
+    intervals: Vec<Interval>,
+
+    // The key the chord is in, if known,
+    // for spelling its notes
+    key: Option<Key>,
 }
 
 impl Chord {
@@ -471,10 +842,25 @@ impl Chord {
         Chord {
             root,
             intervals: intervals.into_iter()
-                .map(Into::into).collect()
+                .map(Into::into).collect(),
+            key: None,
         }
     }
 
+    /// Parse a letter-name chord symbol, e.g. "Cmaj7" or "F#m7b5",
+    /// with its root in the given octave.
+    pub fn from_name(name: &str, octave: usize) -> Result<Chord, ChordParseError> {
+        // Relative to C, so the root lands in the octave
+        let root: Note = format!("C{}", octave).parse()
+            .map_err(|_| ChordParseError::InvalidChordName(name.to_string()))?;
+        let key = Key { root, mode: Mode::Major };
+        Ok(ChordSpec::from_name(name, &key)?.chord_for_key(&key))
+    }
+
+    pub fn root(&self) -> Note {
+        self.root
+    }
+
     /// Return the notes that make up this chord.
     pub fn notes(&self) -> Vec<Note> {
         let mut notes: Vec<Note> = self.intervals.iter().map(|intv| self.root + *intv).collect();
@@ -482,16 +868,25 @@ impl Chord {
         notes
     }
 
-    pub fn describe_notes(&self) -> Vec<String> {
-        self.notes().iter().map(|n| n.to_string()).collect()
+    /// Names of the notes, spelled for the chord's key,
+    /// or as if its root were a major key's if it has none.
+    pub fn describe_notes(&self, accidentals: Accidentals) -> Vec<String> {
+        let key = self.key.unwrap_or(Key { root: self.root, mode: Mode::Major });
+        self.notes().iter().map(|n| n.spell(&key, accidentals)).collect()
+    }
+
+    /// Names of the intervals from the root,
+    /// in the same (ascending) order as `notes()`.
+    pub fn describe_intervals(&self) -> Vec<String> {
+        let mut intervals = self.intervals.clone();
+        intervals.sort_by_key(|intv| intv.semitones);
+        intervals.iter().map(|intv| intv.to_string()).collect()
     }
 }
 
 impl fmt::Display for Chord {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let notes: Vec<String> = self.notes()
-            .iter().map(|n| n.to_string()).collect();
-        write!(f, "{}", notes.join("-"))
+        write!(f, "{}", self.describe_notes(Accidentals::Auto).join("-"))
     }
 }
 
@@ -518,6 +913,28 @@ pub fn voice_lead(chords: &Vec<ChordSpec>) -> Vec<ChordSpec> {
     }
 }
 
+/// Voice lead chords that loop, so that the last chord
+/// also leads smoothly back into the first.
+/// Tries each voicing of the first chord and keeps the one
+/// with the least movement overall, including the loop back.
+pub fn voice_lead_circular(chords: &Vec<ChordSpec>) -> Vec<ChordSpec> {
+    if chords.len() < 2 {
+        return voice_lead(chords);
+    }
+    let firsts = std::iter::once(chords[0].clone())
+        .chain((-1..1).flat_map(|shift| chords[0].clone().shift(shift).inversions()));
+    firsts.map(|first| {
+        let mut chords = chords.clone();
+        chords[0] = first;
+        let voiced = voice_lead(&chords);
+        let movement: usize = voiced.windows(2)
+            .map(|pair| pair[0].distance(&pair[1]))
+            .sum();
+        let loop_back = voiced[voiced.len() - 1].distance(&voiced[0]);
+        (movement + loop_back, voiced)
+    }).min_by_key(|(movement, _)| *movement).unwrap().1
+}
+
 
 #[cfg(test)]
 mod test {
@@ -542,6 +959,18 @@ mod test {
         }
     }
 
+    #[test]
+    fn test_chord_describe_intervals() {
+        let key = Key {
+            root: "C3".try_into().unwrap(),
+            mode: Mode::Major,
+        };
+        let cs: ChordSpec = "V:b7/3".try_into().unwrap();
+        let chord = cs.chord_for_key(&key);
+        assert_eq!(chord.describe_notes(Accidentals::Auto), vec!["B3", "D4", "F4", "G4"]);
+        assert_eq!(chord.describe_intervals(), vec!["M3", "P5", "m7", "P1"]);
+    }
+
     #[test]
     fn test_chord_spec_names() {
         let spec = ChordSpec::new(1, Mode::Major);
@@ -1041,4 +1470,176 @@ mod test {
         }
     }
 
+    #[test]
+    fn test_toggles() {
+        let key = Key {
+            root: "C4".try_into().unwrap(),
+            mode: Mode::Major,
+        };
+        let toggle = |name: &str, degree: usize| -> String {
+            let cs: ChordSpec = name.try_into().unwrap();
+            cs.toggle_extension(degree, &key).to_string()
+        };
+        assert_eq!(toggle("I", 7), "I:7");
+        assert_eq!(toggle("V", 7), "V:b7");
+        assert_eq!(toggle("ii", 9), "ii:#9");
+        assert_eq!(toggle("V:b7", 7), "V");
+
+        let cs: ChordSpec = "I".try_into().unwrap();
+        let cs = cs.toggle_sus();
+        assert_eq!(cs.to_string(), "I^");
+        let cs = cs.toggle_sus();
+        assert_eq!(cs.to_string(), "I_");
+        assert_eq!(cs.toggle_sus().to_string(), "I");
+    }
+
+    #[test]
+    fn test_diatonic() {
+        let key = Key {
+            root: "C4".try_into().unwrap(),
+            mode: Mode::Major,
+        };
+        let triads: Vec<String> = (1..=7)
+            .map(|d| ChordSpec::diatonic(d, &key).to_string()).collect();
+        assert_eq!(triads, vec!["I", "ii", "iii", "IV", "V", "vi", "vii-"]);
+    }
+
+    #[test]
+    fn test_voice_leading_circular() {
+        let prog: Vec<ChordSpec> = vec![
+            "I".try_into().unwrap(),
+            "IV".try_into().unwrap(),
+            "V".try_into().unwrap(),
+            "vi".try_into().unwrap(),
+        ];
+        let movement = |chords: &Vec<ChordSpec>| -> usize {
+            let n = chords.len();
+            (0..n).map(|i| chords[i].distance(&chords[(i + 1) % n])).sum()
+        };
+        let linear = voice_lead(&prog);
+        let circular = voice_lead_circular(&prog);
+        assert_eq!(circular.len(), prog.len());
+        assert!(movement(&circular) <= movement(&linear));
+    }
+
+    #[test]
+    fn test_symbol_name() {
+        let name = |name: &str| ChordSpec::from_str(name).unwrap().symbol_name();
+        assert_eq!(name("bVII"), "♭VII");
+        assert_eq!(name("#iv-"), "♯iv°");
+        assert_eq!(name("vii-:7"), "viiø:7");
+        assert_eq!(name("vii-:b7"), "vii°:♭7");
+        assert_eq!(name("I:7,9"), "I:Δ7,9");
+        assert_eq!(name("V:b7/3"), "V:♭7/3");
+        assert_eq!(name("IV~bVI"), "IV~♭VI");
+
+        // The regular name is unchanged
+        assert_eq!(ChordSpec::from_str("I:7,9").unwrap().to_string(), "I:7,9");
+    }
+
+    #[test]
+    fn test_name_in_key() {
+        let key: Key = "C4".parse().unwrap();
+        let name = |cs: &str| ChordSpec::from_str(cs).unwrap().name_in_key(&key, Accidentals::Auto);
+        assert_eq!(name("I"), "C");
+        assert_eq!(name("vi"), "Am");
+        assert_eq!(name("vi:7"), "Am7");
+        assert_eq!(name("I:7"), "Cmaj7");
+        assert_eq!(name("V:b7"), "G7");
+        assert_eq!(name("V:b7,9"), "G9");
+        assert_eq!(name("vii-"), "Bdim");
+        assert_eq!(name("vii-:7"), "Bm7b5");
+        assert_eq!(name("vii-:b7"), "Bdim7");
+        assert_eq!(name("I+"), "Caug");
+        assert_eq!(name("V^:b7"), "G7sus4");
+        assert_eq!(name("IV_"), "Fsus2");
+        assert_eq!(name("I5"), "C5");
+        assert_eq!(name("I:6"), "C6");
+        assert_eq!(name("V:b7,b9"), "G7b9");
+        assert_eq!(name("V/3"), "G/B");
+        assert_eq!(name("V~V"), "D");
+
+        // Names parse back into the same chord
+        for cs in ["ii:7", "V:b7,9", "vii-:7", "IV_", "I:7/3", "bVI"] {
+            let cs = ChordSpec::from_str(cs).unwrap();
+            let parsed = ChordSpec::from_name(&cs.name_in_key(&key, Accidentals::Auto), &key).unwrap();
+            assert_eq!(parsed.chord_for_key(&key).notes(), cs.chord_for_key(&key).notes());
+        }
+    }
+
+    #[test]
+    fn test_from_name() {
+        let key: Key = "C4".parse().unwrap();
+        let spec = |name: &str| ChordSpec::from_name(name, &key).unwrap();
+        let numeral = |name: &str| ChordSpec::from_str(name).unwrap();
+        let notes = |cs: &ChordSpec| cs.chord_for_key(&key).notes();
+
+        assert_eq!(spec("C"), numeral("I"));
+        assert_eq!(spec("Dm"), numeral("ii"));
+        assert_eq!(spec("Bb"), numeral("bVII"));
+        assert_eq!(spec("G7"), numeral("V:b7"));
+        assert_eq!(spec("Cmaj7"), numeral("I:7"));
+        assert_eq!(spec("Bm7b5"), numeral("vii-:7"));
+        assert_eq!(spec("Bdim7"), numeral("vii-:b7"));
+        assert_eq!(spec("Fsus2"), numeral("IV_"));
+        assert_eq!(spec("C5"), numeral("I5"));
+        assert_eq!(spec("C/E"), numeral("I/3"));
+
+        // Compare by sound where the spelling differs
+        assert_eq!(notes(&spec("Am9")), notes(&numeral("vi:7,#9")));
+        assert_eq!(notes(&spec("G7b9")), notes(&numeral("V:b7,b9")));
+        assert_eq!(notes(&spec("F#ø")), notes(&numeral("#iv-:7")));
+        assert_eq!(notes(&spec("Cadd9")), notes(&numeral("I:9")));
+
+        // A bass note outside the chord is added under it
+        let chord: Vec<isize> = spec("C/D").intervals();
+        assert_eq!(chord, vec![12, 4, 7, 2]);
+
+        // Relative to the key's root and mode
+        let key: Key = "A3 minor".parse().unwrap();
+        assert_eq!(ChordSpec::from_name("C", &key).unwrap(), numeral("III"));
+        assert_eq!(ChordSpec::from_name("E7", &key).unwrap().chord_for_key(&key).notes(),
+            numeral("V:b7").chord_for_key(&key).notes());
+
+        assert!(ChordSpec::from_name("H7", &key).is_err());
+        assert!(ChordSpec::from_name("C7b5", &key).is_err());
+        assert!(ChordSpec::from_name("Cmaj8", &key).is_err());
+
+        // Absolute chords
+        let chord = Chord::from_name("F#m7", 3).unwrap();
+        let expected: Vec<Note> = ["F#3", "A3", "C#4", "E4"].iter()
+            .map(|n| n.parse().unwrap()).collect();
+        assert_eq!(chord.notes(), expected);
+    }
+
+    #[test]
+    fn test_from_chord() {
+        let key: Key = "C4".parse().unwrap();
+        let chord = |notes: &[&str]| {
+            let notes: Vec<Note> = notes.iter().map(|n| n.parse().unwrap()).collect();
+            let root = notes[0];
+            Chord::new(root, notes.iter().map(|n| n.semitones - root.semitones).collect())
+        };
+        let spec = |notes: &[&str]| ChordSpec::from_chord(&chord(notes), &key).unwrap().to_string();
+
+        assert_eq!(spec(&["C4", "E4", "G4"]), "I");
+        assert_eq!(spec(&["A4", "C5", "E5", "G5"]), "vi:7");
+        assert_eq!(spec(&["G4", "B4", "D5", "F5"]), "V:b7");
+        assert_eq!(spec(&["B4", "D5", "F5"]), "vii-");
+        assert_eq!(spec(&["Bb4", "D5", "F5"]), "bVII");
+
+        // Inversions, or slash chords if the bass isn't a chord tone
+        assert_eq!(spec(&["A4", "C5", "F5"]), "IV%1");
+        assert_eq!(spec(&["D4", "C5", "E5", "G5"]), "I:2/2");
+
+        // In the notes' octave, and voiced the same
+        assert_eq!(spec(&["C3", "E3", "G3"]), "I<1");
+        let notes = chord(&["E4", "G4", "C5"]);
+        let cs = ChordSpec::from_chord(&notes, &key).unwrap();
+        assert_eq!(cs.chord_for_key(&key).notes(), notes.notes());
+
+        // The closest chord if none match, here leaving out the Db
+        assert_eq!(spec(&["C4", "Db4", "D4", "E4"]), "I:9");
+        assert!(ChordSpec::from_chord(&Chord::new(Note { semitones: 0 }, vec![]), &key).is_none());
+    }
 }
@@ -1,7 +1,7 @@
 use regex::Regex;
 use thiserror::Error;
 use std::{fmt, str::FromStr};
-use super::key::{Mode, MAJOR, MINOR};
+use super::key::Mode;
 use lazy_static::lazy_static;
 
 lazy_static! {
@@ -28,10 +28,7 @@ impl Degree {
         // Convert from 1-indexed to 0-indexed degrees
         let degree_0 = self.degree - 1;
         let octaves = (degree_0/8 * 12) as isize;
-        (match mode {
-            Mode::Major => MAJOR[degree_0 % 7],
-            Mode::Minor => MINOR[degree_0 % 7]
-        } as isize) + octaves + self.adj
+        (mode.intervals()[degree_0 % 7] as isize) + octaves + self.adj
     }
 }
 
@@ -42,9 +42,9 @@ pub struct Interval {
 impl Interval {
     pub fn to_degree(&self, mode: &Mode) -> Degree {
         let idx = self.semitones.rem_euclid(12) as usize;
-        match mode {
+        match mode.quality() {
             Mode::Major => MAJ_DEGS[idx].clone(),
-            Mode::Minor => MIN_DEGS[idx].clone()
+            _ => MIN_DEGS[idx].clone()
         }
     }
 }
@@ -1,27 +1,118 @@
-use std::fmt;
-use super::note::Note;
+use thiserror::Error;
+use std::{fmt, str::FromStr};
+use super::note::{Note, NoteParseError, Accidentals};
 use super::degree::Degree;
 use super::interval::Interval;
 
 pub const MAJOR: [usize; 7] = [0, 2, 4, 5, 7, 9, 11];
 pub const MINOR: [usize; 7] = [0, 1, 3, 5, 7, 8, 10];
+pub const DORIAN: [usize; 7] = [0, 2, 3, 5, 7, 9, 10];
+pub const PHRYGIAN: [usize; 7] = [0, 1, 3, 5, 7, 8, 10];
+pub const LYDIAN: [usize; 7] = [0, 2, 4, 6, 7, 9, 11];
+pub const MIXOLYDIAN: [usize; 7] = [0, 2, 4, 5, 7, 9, 10];
+pub const LOCRIAN: [usize; 7] = [0, 1, 3, 5, 6, 8, 10];
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
 pub enum Mode {
     Major,
-    Minor
+    Minor,
+    Dorian,
+    Phrygian,
+    Lydian,
+    Mixolydian,
+    Locrian,
+}
+
+// Modes in the order they're cycled through
+const MODES: [Mode; 7] = [
+    Mode::Major, Mode::Minor, Mode::Dorian, Mode::Phrygian,
+    Mode::Lydian, Mode::Mixolydian, Mode::Locrian];
+
+impl Mode {
+    /// The semitones of each of the mode's scale degrees.
+    pub fn intervals(&self) -> &'static [usize; 7] {
+        match self {
+            Mode::Major => &MAJOR,
+            Mode::Minor => &MINOR,
+            Mode::Dorian => &DORIAN,
+            Mode::Phrygian => &PHRYGIAN,
+            Mode::Lydian => &LYDIAN,
+            Mode::Mixolydian => &MIXOLYDIAN,
+            Mode::Locrian => &LOCRIAN,
+        }
+    }
+
+    /// Whether the mode is major or minor, going by its third,
+    /// which decides the chords and templates it uses.
+    pub fn quality(&self) -> Mode {
+        match self {
+            Mode::Major | Mode::Lydian | Mode::Mixolydian => Mode::Major,
+            _ => Mode::Minor,
+        }
+    }
+
+    /// Which degree of its relative major
+    /// the mode starts on, 0-indexed.
+    fn rotation(&self) -> usize {
+        match self {
+            Mode::Major => 0,
+            Mode::Dorian => 1,
+            Mode::Phrygian => 2,
+            Mode::Lydian => 3,
+            Mode::Mixolydian => 4,
+            Mode::Minor => 5,
+            Mode::Locrian => 6,
+        }
+    }
+
+    /// The mode after this one, wrapping around.
+    pub fn next(&self) -> Mode {
+        let idx = MODES.iter().position(|m| m == self).unwrap();
+        MODES[(idx + 1) % MODES.len()]
+    }
 }
 
 impl fmt::Display for Mode {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let name = match self {
             Mode::Major => "Major",
-            Mode::Minor => "Minor"
+            Mode::Minor => "Minor",
+            Mode::Dorian => "Dorian",
+            Mode::Phrygian => "Phrygian",
+            Mode::Lydian => "Lydian",
+            Mode::Mixolydian => "Mixolydian",
+            Mode::Locrian => "Locrian",
         };
         write!(f, "{}", name)
     }
 }
 
+#[derive(Error, Debug)]
+pub enum ModeParseError {
+    #[error("Invalid mode `{0}`, expected major, minor, dorian, phrygian, lydian, mixolydian or locrian")]
+    InvalidMode(String),
+}
+
+/// Parses a mode name, case-insensitive,
+/// e.g. "major", "Minor", "dorian".
+/// "ionian" is major. Minor keeps its flat second,
+/// so it isn't the aeolian mode.
+impl FromStr for Mode {
+    type Err = ModeParseError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "major" | "ionian" => Ok(Mode::Major),
+            "minor" => Ok(Mode::Minor),
+            "dorian" => Ok(Mode::Dorian),
+            "phrygian" => Ok(Mode::Phrygian),
+            "lydian" => Ok(Mode::Lydian),
+            "mixolydian" => Ok(Mode::Mixolydian),
+            "locrian" => Ok(Mode::Locrian),
+            _ => Err(ModeParseError::InvalidMode(s.to_string()))
+        }
+    }
+}
+
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
 pub struct Key {
     pub root: Note,
@@ -36,10 +127,7 @@ impl Key {
     /// so we have to subtract 1 to make them 0-indexed.
     pub fn interval(&self, degree: &Degree) -> Interval {
         let deg = degree.degree - 1;
-        let semitones = match self.mode {
-            Mode::Major => MAJOR[deg % 7] as isize,
-            Mode::Minor => MINOR[deg % 7] as isize
-        } + degree.adj;
+        let semitones = self.mode.intervals()[deg % 7] as isize + degree.adj;
         Interval {
             semitones
         }
@@ -49,6 +137,67 @@ impl Key {
     pub fn note(&self, degree: &Degree) -> Note {
         self.root + self.interval(degree)
     }
+
+    /// The notes of the key's scale, from its root.
+    pub fn scale(&self) -> Vec<Note> {
+        (1..=7).map(|degree| self.note(&Degree { degree, adj: 0 })).collect()
+    }
+
+    /// The major key with the same notes,
+    /// with its root at or below this key's.
+    pub fn relative_major(&self) -> Key {
+        let offset = MAJOR[self.mode.rotation()] as isize;
+        Key {
+            root: self.root - Interval { semitones: offset },
+            mode: Mode::Major,
+        }
+    }
+
+    /// How many sharps (positive) or flats (negative)
+    /// the key's signature has, from 5 flats to 6 sharps.
+    pub fn signature(&self) -> i8 {
+        // The relative major's root, from C
+        let pitch = (self.relative_major().root.semitones - 3).rem_euclid(12);
+
+        // Each step around the circle of fifths adds a sharp
+        let fifths = (pitch * 7).rem_euclid(12) as i8;
+        if fifths > 6 { fifths - 12 } else { fifths }
+    }
+
+    /// The root's name, spelled for this key.
+    pub fn root_name(&self, accidentals: Accidentals) -> String {
+        self.root.spell(self, accidentals)
+    }
+}
+
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.root_name(Accidentals::Auto), self.mode)
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum KeyParseError {
+    #[error(transparent)]
+    Note(#[from] NoteParseError),
+
+    #[error(transparent)]
+    Mode(#[from] ModeParseError),
+}
+
+/// Parses a root and an optional mode,
+/// which defaults to major, e.g. "D4 minor" or "F3".
+impl FromStr for Key {
+    type Err = KeyParseError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split_whitespace();
+        let root = parts.next().unwrap_or_default().parse()?;
+        let mode = match parts.next() {
+            Some(mode) => mode.parse()?,
+            None => Mode::Major,
+        };
+        Ok(Key { root, mode })
+    }
 }
 
 impl Default for Key {
@@ -105,4 +254,79 @@ mod test {
         let interval = key.interval(&Degree{ degree: 9, adj: 0 });
         assert_eq!(interval, Interval { semitones: 1 });
     }
+
+    #[test]
+    fn test_scale_modes() {
+        let scale = |name: &str| -> Vec<String> {
+            let key: Key = name.parse().unwrap();
+            key.scale().iter().map(|n| n.pitch_name(Accidentals::Sharps).to_string()).collect()
+        };
+        assert_eq!(scale("D4 dorian"), ["D", "E", "F", "G", "A", "B", "C"]);
+        assert_eq!(scale("E4 phrygian"), ["E", "F", "G", "A", "B", "C", "D"]);
+        assert_eq!(scale("F4 lydian"), ["F", "G", "A", "B", "C", "D", "E"]);
+        assert_eq!(scale("G4 mixolydian"), ["G", "A", "B", "C", "D", "E", "F"]);
+        assert_eq!(scale("B3 locrian"), ["B", "C", "D", "E", "F", "G", "A"]);
+
+        assert_eq!(Mode::Major.next(), Mode::Minor);
+        assert_eq!(Mode::Locrian.next(), Mode::Major);
+    }
+
+    #[test]
+    fn test_parse_mode() {
+        let mode: Mode = "major".parse().unwrap();
+        assert_eq!(mode, Mode::Major);
+
+        let mode: Mode = "Minor".parse().unwrap();
+        assert_eq!(mode, Mode::Minor);
+
+        let mode: Mode = "Dorian".parse().unwrap();
+        assert_eq!(mode, Mode::Dorian);
+
+        assert!("aeolian".parse::<Mode>().is_err());
+
+        assert!("hypodorian".parse::<Mode>().is_err());
+    }
+
+    #[test]
+    fn test_parse_key() {
+        let key: Key = "D4 minor".parse().unwrap();
+        assert_eq!(key.root, "D4".try_into().unwrap());
+        assert_eq!(key.mode, Mode::Minor);
+
+        let key: Key = "F3".parse().unwrap();
+        assert_eq!(key.mode, Mode::Major);
+
+        let key: Key = "D4 dorian".parse().unwrap();
+        assert_eq!(key.mode, Mode::Dorian);
+
+        assert!("D4 hypodorian".parse::<Key>().is_err());
+    }
+
+    #[test]
+    fn test_signature() {
+        let signature = |name: &str| name.parse::<Key>().unwrap().signature();
+        assert_eq!(signature("C4"), 0);
+        assert_eq!(signature("A3 minor"), 0);
+        assert_eq!(signature("D4"), 2);
+        assert_eq!(signature("C#4 minor"), 4);
+        assert_eq!(signature("F#4"), 6);
+        assert_eq!(signature("Eb4"), -3);
+        assert_eq!(signature("D4 minor"), -1);
+
+        // Modes share their relative major's signature
+        assert_eq!(signature("D4 dorian"), 0);
+        assert_eq!(signature("E4 phrygian"), 0);
+        assert_eq!(signature("F4 lydian"), 0);
+        assert_eq!(signature("G4 mixolydian"), 0);
+        assert_eq!(signature("B3 locrian"), 0);
+        assert_eq!(signature("A4 dorian"), 1);
+
+        // Auto spells notes the way the key does
+        let key: Key = "E4".parse().unwrap();
+        let note = key.note(&Degree { degree: 3, adj: 0 });
+        assert_eq!(note.name(Accidentals::Auto.for_key(&key)), "G#4");
+        let key: Key = "F4 minor".parse().unwrap();
+        let note = key.note(&Degree { degree: 3, adj: 0 });
+        assert_eq!(note.name(Accidentals::Auto.for_key(&key)), "Ab4");
+    }
 }
@@ -1,3 +1,6 @@
+//! Notes, keys, chords and how they're
+//! spelled, e.g. as roman numerals like "V7".
+
 mod key;
 mod note;
 mod chord;
@@ -5,7 +8,7 @@ mod degree;
 mod timing;
 mod interval;
 
-pub use note::Note;
-pub use key::{Key, Mode};
-pub use chord::{Chord, ChordSpec, ChordParseError, NUMERALS, voice_lead};
-pub use timing::Duration;
+pub use note::{Note, Accidentals, NoteParseError};
+pub use key::{Key, Mode, ModeParseError, KeyParseError};
+pub use chord::{Chord, ChordSpec, ChordParseError, NUMERALS, voice_lead, voice_lead_circular};
+pub use timing::{Duration, DurationParseError};
@@ -1,11 +1,68 @@
 use regex::Regex;
 use thiserror::Error;
+use serde::{Serialize, Deserialize};
 use std::{fmt, str::FromStr};
 use std::ops::{Add, Sub};
 use super::interval::Interval;
+use super::key::{Key, Mode};
 use lazy_static::lazy_static;
 
 const NAMES: [&str; 12] = ["A", "Bb", "B", "C", "Db", "D", "Eb", "E", "F", "Gb", "G", "Ab"];
+const SHARP_NAMES: [&str; 12] = ["A", "A#", "B", "C", "C#", "D", "D#", "E", "F", "F#", "G", "G#"];
+
+// Letters, and their semitones from A
+const LETTERS: [(char, isize); 7] = [('A', 0), ('B', 2), ('C', 3), ('D', 5), ('E', 7), ('F', 8), ('G', 10)];
+
+const SPELLINGS: [Accidentals; 3] = [Accidentals::Flats, Accidentals::Sharps, Accidentals::Auto];
+
+/// How accidentals are spelled in note names.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Accidentals {
+    Flats,
+    Sharps,
+
+    // Whichever the key's signature uses,
+    // with flats for keys without any
+    Auto,
+}
+
+impl Accidentals {
+    /// The spelling after this one, for cycling through them.
+    pub fn next(self) -> Accidentals {
+        let idx = SPELLINGS.iter().position(|a| *a == self).unwrap_or(0);
+        SPELLINGS[(idx + 1) % SPELLINGS.len()]
+    }
+
+    /// The spelling to use for notes in a key,
+    /// which is only different for `Auto`.
+    pub fn for_key(self, key: &Key) -> Accidentals {
+        match self {
+            Accidentals::Auto => if key.signature() > 0 {
+                Accidentals::Sharps
+            } else {
+                Accidentals::Flats
+            },
+            _ => self,
+        }
+    }
+}
+
+impl Default for Accidentals {
+    fn default() -> Self {
+        Accidentals::Auto
+    }
+}
+
+impl fmt::Display for Accidentals {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Accidentals::Flats => write!(f, "flats"),
+            Accidentals::Sharps => write!(f, "sharps"),
+            Accidentals::Auto => write!(f, "auto"),
+        }
+    }
+}
 
 lazy_static! {
     static ref NOTE_RE: Regex = Regex::new(
@@ -43,7 +100,9 @@ impl FromStr for Note {
             .ok_or(NoteParseError::InvalidOctave("(none)".to_string()))?
             .as_str();
 
-        if let Some(offset) = NAMES.iter().position(|&n| n == name) {
+        let offset = NAMES.iter().position(|&n| n == name)
+            .or_else(|| SHARP_NAMES.iter().position(|&n| n == name));
+        if let Some(offset) = offset {
             let offset = offset as isize;
             let mut octave = octave.parse::<isize>()?;
             octave -= (offset+9)/12;
@@ -70,12 +129,67 @@ impl TryFrom<String> for Note {
 }
 
 
+impl Note {
+    /// The note's name, spelling
+    /// accidentals in the given way.
+    pub fn name(&self, accidentals: Accidentals) -> String {
+        let octave = (self.semitones + 9) / 12;
+        format!("{}{}", self.pitch_name(accidentals), octave)
+    }
+
+    /// The note's name spelled for a key, e.g. "F#4" in D major.
+    /// With `Auto` accidentals, notes in the key's scale take
+    /// the letter of their degree, e.g. "E#" in C# major,
+    /// and other notes follow the key's signature.
+    pub fn spell(&self, key: &Key, accidentals: Accidentals) -> String {
+        let (pitch, octave) = self.spelling(key, accidentals);
+        format!("{}{}", pitch, octave)
+    }
+
+    /// The note's name spelled for a key, without its octave.
+    pub fn spell_pitch(&self, key: &Key, accidentals: Accidentals) -> String {
+        self.spelling(key, accidentals).0
+    }
+
+    fn spelling(&self, key: &Key, spelling: Accidentals) -> (String, isize) {
+        let accidentals = spelling.for_key(key);
+        let octave = |semitones: isize| (semitones + 9) / 12;
+        let pitch_class = self.semitones.rem_euclid(12);
+        let degree = key.scale().iter()
+            .position(|n| n.semitones.rem_euclid(12) == pitch_class);
+        let root = key.root.pitch_name(accidentals).chars().next();
+        let root = LETTERS.iter().position(|(l, _)| Some(*l) == root);
+        match (spelling, degree, root) {
+            (Accidentals::Auto, Some(degree), Some(root)) => {
+                let (letter, natural) = LETTERS[(root + degree) % 7];
+                let mut adj = (pitch_class - natural).rem_euclid(12);
+                if adj > 6 {
+                    adj -= 12;
+                }
+                let sign = if adj < 0 { "b" } else { "#" };
+                let pitch = format!("{}{}", letter, sign.repeat(adj.unsigned_abs()));
+                (pitch, octave(self.semitones - adj))
+            }
+            _ => (self.pitch_name(accidentals).to_string(), octave(self.semitones)),
+        }
+    }
+
+    /// The note's name without its octave, e.g. "Bb".
+    pub fn pitch_name(&self, accidentals: Accidentals) -> &'static str {
+        let idx = self.semitones.rem_euclid(12) as usize;
+        match accidentals {
+            Accidentals::Flats | Accidentals::Auto => NAMES[idx],
+            Accidentals::Sharps => SHARP_NAMES[idx],
+        }
+    }
+}
+
+/// On its own a note is spelled as
+/// if it were the root of a major key.
 impl fmt::Display for Note {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let idx = self.semitones.rem_euclid(12) as usize;
-        let name = NAMES[idx];
-        let octave = (self.semitones + 9) / 12;
-        write!(f, "{}{}", name, octave)
+        let key = Key { root: *self, mode: Mode::Major };
+        write!(f, "{}", self.spell(&key, Accidentals::Auto))
     }
 }
 
@@ -151,6 +265,20 @@ mod test {
         let name = "Ab0";
         let note: Note = name.try_into().unwrap();
         assert_eq!(note.semitones, -1);
+
+        let name = "C#3";
+        let note: Note = name.try_into().unwrap();
+        assert_eq!(note.semitones, 28);
+    }
+
+    #[test]
+    fn test_sharp_names() {
+        let note = Note { semitones: 1 };
+        assert_eq!(note.name(Accidentals::Sharps), "A#0".to_string());
+
+        let note = Note { semitones: 28 };
+        assert_eq!(note.name(Accidentals::Sharps), "C#3".to_string());
+        assert_eq!(note.name(Accidentals::Flats), "Db3".to_string());
     }
 
     #[test]
@@ -164,4 +292,19 @@ mod test {
         let new_note = note - intv;
         assert_eq!(new_note.semitones, 8);
     }
+
+    #[test]
+    fn test_spell() {
+        let spell = |note: &str, key: &str| -> String {
+            let note: Note = note.parse().unwrap();
+            note.spell(&key.parse().unwrap(), Accidentals::Auto)
+        };
+        assert_eq!(spell("Gb4", "D4"), "F#4");
+        assert_eq!(spell("A#3", "F4"), "Bb3");
+        assert_eq!(spell("F4", "F#4"), "E#4");
+        assert_eq!(spell("F5", "F#4"), "E#5");
+        assert_eq!(spell("Db4", "Db4"), "Db4");
+        assert_eq!(spell("D#4", "C4"), "Eb4");
+        assert_eq!(spell("G4", "E4 minor"), "G4");
+    }
 }
@@ -1,4 +1,5 @@
-use std::fmt;
+use thiserror::Error;
+use std::{fmt, str::FromStr};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Duration {
@@ -40,3 +41,23 @@ impl fmt::Display for Duration {
     }
 }
 
+#[derive(Error, Debug)]
+pub enum DurationParseError {
+    #[error("Invalid duration `{0}`, expected 1/4, 1/8, 1/16 or 1/32")]
+    InvalidDuration(String),
+}
+
+/// Parses a duration as written
+/// when displayed, e.g. "1/8".
+impl FromStr for Duration {
+    type Err = DurationParseError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "1/4" => Ok(Duration::Quarter),
+            "1/8" => Ok(Duration::Eighth),
+            "1/16" => Ok(Duration::Sixteenth),
+            "1/32" => Ok(Duration::ThirtySecond),
+            _ => Err(DurationParseError::InvalidDuration(s.to_string()))
+        }
+    }
+}
@@ -1,119 +1,612 @@
+//! Exporting progressions to MIDI files,
+//! and importing chords from them.
+
 use midly::{
     Smf, Header, Format, Timing,
     TrackEvent, TrackEventKind,
     MidiMessage, MetaMessage};
 use midly::num::{u4, u7, u15, u24, u28};
-use crate::core::Chord;
-use anyhow::Result;
+use std::fmt;
+use std::collections::{BTreeMap, HashMap};
+use serde::{Serialize, Deserialize};
+use crate::core::{Chord, ChordSpec, Key, Mode, Note, Accidentals, Duration};
+use crate::progression::{Progression, Accent};
+use anyhow::{Result, anyhow};
+
+const MARKINGS: [ChordMarkers; 3] = [ChordMarkers::Off, ChordMarkers::Numerals, ChordMarkers::Names];
+
+// MIDI channel drums are on, 0-15,
+// which are left out when importing
+const DRUM_CHANNEL: u8 = 9;
+
+/// Velocity of exported notes.
+pub const VELOCITY: u8 = 64;
+
+/// Ticks per beat when exporting timed chords,
+/// fine enough for gates shorter than a step.
+pub const EXPORT_TICKS_PER_BEAT: usize = 96;
 
-/// Convert bpm to ms/beat (ms/quarter note)
-/// Reference point: 60bpm is 1000ms/beat
-fn bpm_to_ms_per_beat(bpm: usize) -> u24 {
-    u24::from(60000/bpm as u32)
+/// Convert bpm to μs/beat (μs/quarter note),
+/// which is how MIDI files give tempo.
+/// Reference point: 60bpm is 1,000,000μs/beat
+fn bpm_to_us_per_beat(bpm: usize) -> u24 {
+    u24::from(60_000_000/bpm as u32)
 }
 
-pub fn save_to_midi_file(tempo: usize, ticks_per_beat: usize, progression: &Vec<Option<Chord>>, path: String) -> Result<()> {
-    let channel = u4::new(0);
-    let velocity = u7::from(64);
-    let mut track: Vec<TrackEvent> = vec![];
+/// The key's signature as sharps (positive) or flats,
+/// picking between keys that can be spelled
+/// either way, e.g. F# and Gb, by the spelling used.
+fn key_signature(key: &Key, accidentals: Accidentals) -> i8 {
+    match (key.signature(), accidentals) {
+        (6, Accidentals::Flats) => -6,
+        (-5, Accidentals::Sharps) => 7,
+        (signature, _) => signature,
+    }
+}
 
-    // Delta times are in ticks
-    let start = u28::from(0);
-    let same_time = u28::from(0);
+/// Whether exported chords are marked with their names,
+/// so the file shows its chords when opened in a DAW.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChordMarkers {
+    #[default]
+    Off,
+
+    // As written, e.g. "V:b7"
+    Numerals,
+
+    // By their letters in the key, e.g. "G7"
+    Names,
+}
+
+impl ChordMarkers {
+    /// The marking after this one, for cycling through them.
+    pub fn next(self) -> ChordMarkers {
+        let idx = MARKINGS.iter().position(|m| *m == self).unwrap_or(0);
+        MARKINGS[(idx + 1) % MARKINGS.len()]
+    }
+
+    /// The chord's marker in a key, if chords are marked.
+    pub fn marker(self, cs: &ChordSpec, key: &Key, accidentals: Accidentals) -> Option<String> {
+        match self {
+            ChordMarkers::Off => None,
+            ChordMarkers::Numerals => Some(cs.to_string()),
+            ChordMarkers::Names => Some(cs.name_in_key(key, accidentals)),
+        }
+    }
+}
+
+impl fmt::Display for ChordMarkers {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChordMarkers::Off => write!(f, "off"),
+            ChordMarkers::Numerals => write!(f, "numerals"),
+            ChordMarkers::Names => write!(f, "names"),
+        }
+    }
+}
+
+/// How exported files are written.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ExportOptions {
+    // MIDI channel, 0-15, notes are
+    // written on unless they have their own
+    pub channel: u8,
+
+    pub markers: ChordMarkers,
+
+    // How chord names and the
+    // key signature are spelled
+    pub accidentals: Accidentals,
+}
 
-    // Convert from bpm to ms/beat
-    let tempo = bpm_to_ms_per_beat(tempo);
+/// A chord to export, starting at a tick
+/// and held for some number of ticks.
+#[derive(Debug, Clone)]
+pub struct TimedChord {
+    pub chord: Chord,
+    pub start: usize,
+    pub length: usize,
+    pub velocity: u8,
+
+    // MIDI channel, 0-15, if not the file's
+    pub channel: Option<u8>,
+
+    // Marker written where the chord starts
+    pub marker: Option<String>,
+}
+
+/// A track to export, with its chords written
+/// on its MIDI channel, 0-15, unless they have their own.
+#[derive(Debug, Clone)]
+pub struct ExportTrack {
+    pub name: String,
+    pub channel: u8,
+    pub chords: Vec<TimedChord>,
+}
+
+/// A change of tempo at a tick, in bpm.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TempoChange {
+    pub tick: usize,
+    pub bpm: usize,
+}
+
+/// Save chords in a key a tick apart, each held through
+/// any rests until the next chord, and on its own MIDI
+/// channel if it has one, otherwise the export channel,
+/// with the key's signature.
+pub fn save_to_midi_file(tempo: usize, ticks_per_beat: usize, progression: &[(Option<ChordSpec>, Option<u8>)], key: &Key, options: ExportOptions, path: String) -> Result<()> {
+    let chords: Vec<TimedChord> = progression.iter().enumerate()
+        .filter_map(|(start, (cs, channel))| cs.as_ref().map(|cs| {
+            let length = progression[start + 1..].iter()
+                .position(|(cs, _)| cs.is_some())
+                .map_or(progression.len() - start, |rests| rests + 1);
+            TimedChord {
+                chord: cs.chord_for_key(key),
+                start,
+                length,
+                velocity: VELOCITY,
+                channel: *channel,
+                marker: options.markers.marker(cs, key, options.accidentals),
+            }
+        }))
+        .collect();
+    let tempos = [TempoChange { tick: 0, bpm: tempo }];
+    save_timed_to_midi_file(ticks_per_beat, &chords, &tempos, Some(key), options, path)
+}
+
+/// Save a progression in a key, holding each chord for
+/// as long as its step's gate, with the given swing and accent.
+pub fn save_progression_to_midi_file(tempo: usize, swing: usize, accent: &Accent, progression: &Progression, key: &Key, options: ExportOptions, path: String) -> Result<()> {
+    let step_ticks = EXPORT_TICKS_PER_BEAT / progression.resolution.ticks_per_beat();
+    let chords = progression.timed_chords(key, step_ticks, swing, accent, options);
+    let tempos = [TempoChange { tick: 0, bpm: tempo }];
+    save_timed_to_midi_file(EXPORT_TICKS_PER_BEAT, &chords, &tempos, Some(key), options, path)
+}
+
+/// Save chords at the given times, changing tempo
+/// along the way. The first tempo change should be at
+/// the start, otherwise MIDI's default of 120bpm is used.
+/// Notes are written on the options' MIDI channel, 0-15,
+/// unless a chord has its own,
+/// with the key's signature if there is one.
+pub fn save_timed_to_midi_file(ticks_per_beat: usize, chords: &[TimedChord], tempos: &[TempoChange], key: Option<&Key>, options: ExportOptions, path: String) -> Result<()> {
+    let track = ExportTrack {
+        name: "Dust Chords".to_string(),
+        channel: options.channel,
+        chords: chords.to_vec(),
+    };
+    save_tracks_to_midi_file(ticks_per_beat, &[track], tempos, key, options.accidentals, path)
+}
+
+/// Save tracks of chords, each as its own track
+/// of the file, with the tempo changes, time
+/// signature and key signature on the first,
+/// the key spelled with the given accidentals.
+pub fn save_tracks_to_midi_file(ticks_per_beat: usize, tracks: &[ExportTrack], tempos: &[TempoChange], key: Option<&Key>, accidentals: Accidentals, path: String) -> Result<()> {
+    let smf_tracks = tracks.iter().enumerate()
+        .map(|(i, track)| if i == 0 {
+            track_events(track, true, tempos, key.map(|key| (key, accidentals)))
+        } else {
+            track_events(track, false, &[], None)
+        })
+        .collect();
 
     // A beat is a quarter note
     let ticks_per_beat = u15::from(ticks_per_beat as u16);
+    let format = if tracks.len() > 1 {
+        Format::Parallel
+    } else {
+        Format::SingleTrack
+    };
+    let smf = Smf {
+        header: Header {
+            format,
+            timing: Timing::Metrical(ticks_per_beat)
+        },
+        tracks: smf_tracks,
+    };
+    smf.save(path)?;
+    Ok(())
+}
+
+/// A track's events, along with any tempo changes,
+/// key signature and chord markers, and the time
+/// signature if it's the first track.
+fn track_events<'a>(export: &'a ExportTrack, first: bool, tempos: &[TempoChange], key: Option<(&Key, Accidentals)>) -> Vec<TrackEvent<'a>> {
+    let channel = u4::new(export.channel & 0x0F);
+    let mut track: Vec<TrackEvent> = vec![];
+
+    // Events with the tick they happen on. At the same tick,
+    // tempo changes and markers come first and note offs before
+    // note ons, so chords played back to back don't cut each other off.
+    let mut events: Vec<(usize, u8, TrackEventKind)> = vec![];
+    for change in tempos {
+        // Convert from bpm to μs/beat
+        let tempo = bpm_to_us_per_beat(change.bpm.max(1));
+        events.push((change.tick, 0, TrackEventKind::Meta(MetaMessage::Tempo(tempo))));
+    }
+    for timed in &export.chords {
+        if let Some(marker) = &timed.marker {
+            events.push((timed.start, 0, TrackEventKind::Meta(MetaMessage::Marker(marker.as_bytes()))));
+        }
+        // MIDI note values map A0 to 21.
+        // We set A0 to 0 semitones; this our starting point is 0 semitones = MIDI note 21.
+        let notes: Vec<u8> = timed.chord.notes().iter().map(|note| (note.semitones + 21) as u8).collect();
+        let channel = timed.channel.map_or(channel, |channel| u4::new(channel & 0x0F));
+        for note in notes {
+            let key = u7::from(note);
+            events.push((timed.start, 2, TrackEventKind::Midi {
+                channel,
+                message: MidiMessage::NoteOn { key, vel: u7::from(timed.velocity.min(127)) }
+            }));
+            events.push((timed.start + timed.length.max(1), 1, TrackEventKind::Midi {
+                channel,
+                message: MidiMessage::NoteOff { key, vel: u7::from(VELOCITY) }
+            }));
+        }
+    }
+    events.sort_by_key(|(tick, order, _)| (*tick, *order));
 
     // Prepare meta messages
-    // Default MIDI time is 4/4 so we exclude that MetaMessage
     track.push(TrackEvent {
-        delta: start,
-        kind: TrackEventKind::Meta(MetaMessage::Tempo(tempo))
+        delta: u28::from(0),
+        kind: TrackEventKind::Meta(MetaMessage::TrackName(export.name.as_bytes()))
     });
+    if first {
+        // Bars are four quarter notes. The rest is the
+        // denominator as a power of two, MIDI clocks per
+        // metronome click and 32nd notes per quarter note.
+        let beats = Duration::Quarter.ticks_per_bar() as u8;
+        track.push(TrackEvent {
+            delta: u28::from(0),
+            kind: TrackEventKind::Meta(MetaMessage::TimeSignature(beats, 2, 24, 8))
+        });
+    }
+    if let Some((key, accidentals)) = key {
+        track.push(TrackEvent {
+            delta: u28::from(0),
+            kind: TrackEventKind::Meta(MetaMessage::KeySignature(key_signature(key, accidentals), key.mode == Mode::Minor))
+        });
+    }
+
+    // Delta times are in ticks
+    let mut last = 0;
+    for (tick, _, kind) in events {
+        track.push(TrackEvent {
+            delta: u28::from((tick - last) as u32),
+            kind,
+        });
+        last = tick;
+    }
+
     track.push(TrackEvent {
-        delta: start,
-        kind: TrackEventKind::Meta(MetaMessage::TrackName(b"Dust Chords"))
+        delta: u28::from(0),
+        kind: TrackEventKind::Meta(MetaMessage::EndOfTrack)
     });
+    track
+}
 
-    // Add the chords
-    let mut pause = 0;
-    for tick in progression {
-        if let Some(chord) = tick {
-            // MIDI note values map A0 to 21.
-            // We set A0 to 0 semitones; this our starting point is 0 semitones = MIDI note 21.
-            let notes: Vec<u8> = chord.notes().iter().map(|note| (note.semitones + 21) as u8).collect();
-            for (i, note) in notes.iter().enumerate() {
-                let delta = if i == 0  {
-                    u28::from(pause)
-                } else {
-                    same_time
-                };
-                track.push(TrackEvent {
-                    delta,
-                    kind: TrackEventKind::Midi {
-                        channel,
-                        message: MidiMessage::NoteOn {
-                            key: u7::from(*note),
-                            vel: velocity
+
+/// A note read from a MIDI file, with
+/// the ticks it starts and ends on.
+struct FileNote {
+    note: Note,
+    start: usize,
+    end: usize,
+}
+
+impl FileNote {
+    fn new(key: u8, start: usize, end: usize) -> FileNote {
+        // MIDI note values map A0 to 21
+        let note = Note { semitones: key as isize - 21 };
+        FileNote { note, start, end }
+    }
+}
+
+/// The pitch classes of some notes, as semitones above A.
+fn pitch_classes(notes: impl Iterator<Item=isize>) -> Vec<isize> {
+    let mut pitches: Vec<isize> = notes.map(|n| n.rem_euclid(12)).collect();
+    pitches.sort_unstable();
+    pitches.dedup();
+    pitches
+}
+
+/// A key with its root in the same
+/// octave as the default key's.
+fn key_for_pitch(pitch: isize, mode: Mode) -> Key {
+    let default = Key::default().root.semitones;
+    Key {
+        root: Note { semitones: default + (pitch - default).rem_euclid(12) },
+        mode,
+    }
+}
+
+/// The key of a key signature, given as its
+/// sharps (positive) or flats and whether it's minor.
+fn signature_key(sharps: i8, minor: bool) -> Key {
+    // Each sharp is a fifth up from C
+    let major = 3 + 7 * sharps as isize;
+    if minor {
+        key_for_pitch(major - 3, Mode::Minor)
+    } else {
+        key_for_pitch(major, Mode::Major)
+    }
+}
+
+/// The major or minor key whose scale covers most of
+/// the notes, weighted by how long they're held.
+/// Keys sharing a scale are told apart by whether
+/// their tonic is the given one, e.g. the last chord's bass.
+fn detect_key(notes: &[FileNote], tonic: Option<isize>) -> Key {
+    let mut keys = vec![];
+    for mode in [Mode::Major, Mode::Minor] {
+        for pitch in 0..12 {
+            keys.push(key_for_pitch(pitch, mode));
+        }
+    }
+    keys.into_iter().rev()
+        .max_by_key(|key| {
+            let scale = pitch_classes(key.scale().iter().map(|n| n.semitones));
+            let fit: usize = notes.iter()
+                .filter(|note| scale.contains(&note.note.semitones.rem_euclid(12)))
+                .map(|note| (note.end - note.start).max(1))
+                .sum();
+            (fit, tonic == Some(key.root.semitones.rem_euclid(12)))
+        })
+        .unwrap_or_default()
+}
+
+/// Load the chords of a MIDI file as a progression at a resolution.
+/// Notes starting on the same step, once quantized to it, are
+/// grouped into a chord, and single notes are left out, as are drums.
+/// Chords are named relative to the given key, or if there's none
+/// the file's key signature, or failing that the key that best fits
+/// its notes, which is returned along with the progression.
+pub fn load_from_midi_file(path: &str, resolution: Duration, key: Option<Key>) -> Result<(Key, Progression)> {
+    let bytes = std::fs::read(path)?;
+    let smf = Smf::parse(&bytes)?;
+    let ticks_per_beat = match smf.header.timing {
+        Timing::Metrical(ticks) => ticks.as_int().max(1) as f64,
+        Timing::Timecode(..) => return Err(anyhow!("Files timed in SMPTE frames aren't supported")),
+    };
+
+    let mut notes: Vec<FileNote> = vec![];
+    let mut signature = None;
+    for track in &smf.tracks {
+        // Notes being held, by their channel
+        // and key, with the tick they started on
+        let mut held: HashMap<(u8, u8), usize> = HashMap::default();
+        let mut tick = 0;
+        for event in track {
+            tick += event.delta.as_int() as usize;
+            match event.kind {
+                TrackEventKind::Meta(MetaMessage::KeySignature(sharps, minor)) => {
+                    signature.get_or_insert((sharps, minor));
+                }
+                TrackEventKind::Midi { channel, message } if channel.as_int() != DRUM_CHANNEL => {
+                    match message {
+                        MidiMessage::NoteOn { key, vel } if vel.as_int() > 0 => {
+                            held.insert((channel.as_int(), key.as_int()), tick);
                         }
-                    }
-                });
-            }
-            for (i, note) in notes.iter().enumerate() {
-                let delta = if i == 0  {
-                    u28::from(1)
-                } else {
-                    same_time
-                };
-                track.push(TrackEvent {
-                    delta,
-                    kind: TrackEventKind::Midi {
-                        channel,
-                        message: MidiMessage::NoteOff {
-                            key: u7::from(*note),
-                            vel: velocity
+                        // A note on with no velocity is a note off
+                        MidiMessage::NoteOn { key, .. } | MidiMessage::NoteOff { key, .. } => {
+                            if let Some(start) = held.remove(&(channel.as_int(), key.as_int())) {
+                                notes.push(FileNote::new(key.as_int(), start, tick));
+                            }
                         }
+                        _ => {}
                     }
-                });
+                }
+                _ => {}
             }
-            pause = 0;
-        } else {
-            pause += 1;
         }
+
+        // Notes that never end last until the track does
+        notes.extend(held.into_iter().map(|((_, key), start)| FileNote::new(key, start, tick)));
     }
 
-    track.push(TrackEvent {
-        delta: start,
-        kind: TrackEventKind::Meta(MetaMessage::EndOfTrack)
-    });
-    let smf = Smf {
-        header: Header {
-            format: Format::SingleTrack,
-            timing: Timing::Metrical(ticks_per_beat)
-        },
-        tracks: vec![track],
-    };
-    smf.save(path)?;
-    Ok(())
+    // The notes starting on each step
+    let step_ticks = ticks_per_beat / resolution.ticks_per_beat() as f64;
+    let step = |tick: usize| (tick as f64 / step_ticks).round() as usize;
+    let mut steps: BTreeMap<usize, Vec<Note>> = BTreeMap::default();
+    for note in &notes {
+        steps.entry(step(note.start)).or_default().push(note.note);
+    }
+    steps.retain(|_, chord| pitch_classes(chord.iter().map(|n| n.semitones)).len() > 1);
+    let (last, last_chord) = steps.iter().next_back()
+        .ok_or_else(|| anyhow!("No chords found"))?;
+
+    let key = key
+        .or_else(|| signature.map(|(sharps, minor)| signature_key(sharps, minor)))
+        .unwrap_or_else(|| {
+            let tonic = last_chord.iter().map(|n| n.semitones).min().map(|s| s.rem_euclid(12));
+            detect_key(&notes, tonic)
+        });
+
+    // Whole bars, long enough for the last chord to end
+    let end = notes.iter().map(|note| step(note.end)).max().unwrap_or(0).max(last + 1);
+    let ticks_per_bar = resolution.ticks_per_bar();
+    let mut sequence = vec![None; end.div_ceil(ticks_per_bar) * ticks_per_bar];
+    for (i, notes) in &steps {
+        let root = notes[0];
+        let chord = Chord::new(root, notes.iter().map(|n| n.semitones - root.semitones).collect());
+        sequence[*i] = ChordSpec::from_chord(&chord, &key);
+    }
+    Ok((key, Progression::new(sequence, resolution)))
 }
 
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::core::ChordSpec;
 
     #[test]
-    fn test_bpm_to_ms_per_beat() {
-        let ms_per_beat = bpm_to_ms_per_beat(60);
-        assert_eq!(ms_per_beat, 1000);
+    fn test_bpm_to_us_per_beat() {
+        let us_per_beat = bpm_to_us_per_beat(60);
+        assert_eq!(us_per_beat, 1_000_000);
 
-        let ms_per_beat = bpm_to_ms_per_beat(120);
-        assert_eq!(ms_per_beat, 500);
+        let us_per_beat = bpm_to_us_per_beat(120);
+        assert_eq!(us_per_beat, 500_000);
 
-        let ms_per_beat = bpm_to_ms_per_beat(150);
-        assert_eq!(ms_per_beat, 400);
+        let us_per_beat = bpm_to_us_per_beat(150);
+        assert_eq!(us_per_beat, 400_000);
+
+        // Slow tempos still fit in 24 bits
+        let us_per_beat = bpm_to_us_per_beat(4);
+        assert_eq!(us_per_beat, 15_000_000);
+    }
+
+    #[test]
+    fn test_export_channel() {
+        let chord = ChordSpec::try_from("I").unwrap().chord_for_key(&Key::default());
+        let mut chords = vec![TimedChord { chord, start: 0, length: 2, velocity: VELOCITY, channel: None, marker: None }];
+        let tempos = [TempoChange { tick: 0, bpm: 120 }];
+        let path = std::env::temp_dir().join("dust-test-channel.mid");
+        let channels = |chords: &[TimedChord]| -> Vec<u8> {
+            save_timed_to_midi_file(2, chords, &tempos, None, ExportOptions { channel: 9, ..ExportOptions::default() }, path.to_string_lossy().to_string()).unwrap();
+            let bytes = std::fs::read(&path).unwrap();
+            let smf = Smf::parse(&bytes).unwrap();
+            smf.tracks[0].iter().filter_map(|event| match event.kind {
+                TrackEventKind::Midi { channel, .. } => Some(channel.as_int()),
+                _ => None,
+            }).collect()
+        };
+        let written = channels(&chords);
+        assert!(!written.is_empty());
+        assert!(written.iter().all(|c| *c == 9));
+
+        // A chord's own channel takes precedence
+        chords[0].channel = Some(3);
+        assert!(channels(&chords).iter().all(|c| *c == 3));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_export_sustain() {
+        let chord = |name: &str| Some(ChordSpec::try_from(name).unwrap());
+        let progression = [(chord("I"), None), (None, None), (chord("V"), None)];
+        let path = std::env::temp_dir().join("dust-test-sustain.mid");
+        save_to_midi_file(120, 2, &progression, &Key::default(), ExportOptions::default(), path.to_string_lossy().to_string()).unwrap();
+
+        // I is held through the rest, until V
+        let bytes = std::fs::read(&path).unwrap();
+        let smf = Smf::parse(&bytes).unwrap();
+        let mut tick = 0;
+        let mut offs = vec![];
+        for event in &smf.tracks[0] {
+            tick += event.delta.as_int();
+            if let TrackEventKind::Midi { message: MidiMessage::NoteOff { .. }, .. } = event.kind {
+                offs.push(tick);
+            }
+        }
+        offs.dedup();
+        assert_eq!(offs, vec![2, 3]);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_export_markers() {
+        let key = Key::default();
+        let cs = ChordSpec::try_from("V:b7").unwrap();
+        assert_eq!(ChordMarkers::Off.marker(&cs, &key, Accidentals::Auto), None);
+        assert_eq!(ChordMarkers::Numerals.marker(&cs, &key, Accidentals::Auto), Some("V:b7".to_string()));
+        assert_eq!(ChordMarkers::Names.marker(&cs, &key, Accidentals::Auto), Some("G7".to_string()));
+        assert_eq!(ChordMarkers::Names.next(), ChordMarkers::Off);
+
+        // As chosen for each export
+        let progression = Progression::from_chords(vec![cs.clone()], 1, Duration::Quarter);
+        let marker = |markers| progression.timed_chords(&key, 2, 50, &Accent::default(), ExportOptions { markers, ..ExportOptions::default() })[0].marker.clone();
+        assert_eq!(marker(ChordMarkers::Off), None);
+        assert_eq!(marker(ChordMarkers::Numerals), Some("V:b7".to_string()));
+
+        // Written where the chord starts, before its notes
+        let chord = cs.chord_for_key(&key);
+        let chords = [TimedChord { chord, start: 2, length: 2, velocity: VELOCITY, channel: None, marker: Some("G7".to_string()) }];
+        let path = std::env::temp_dir().join("dust-test-markers.mid");
+        save_timed_to_midi_file(2, &chords, &[], None, ExportOptions::default(), path.to_string_lossy().to_string()).unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+        let smf = Smf::parse(&bytes).unwrap();
+        let idx = smf.tracks[0].iter().position(|event| event.kind == TrackEventKind::Meta(MetaMessage::Marker(b"G7"))).unwrap();
+        assert_eq!(smf.tracks[0][idx].delta.as_int(), 2);
+        assert!(matches!(smf.tracks[0][idx + 1].kind, TrackEventKind::Midi { message: MidiMessage::NoteOn { .. }, .. }));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_import() {
+        let path = std::env::temp_dir().join("dust-test-import.mid");
+        let path = path.to_string_lossy().to_string();
+        let progression = |chords: &[&str]| Progression::from_chords(
+            chords.iter().map(|c| c.parse().unwrap()).collect(), 1, Duration::Quarter);
+        let names = |progression: &Progression, key: &Key| -> Vec<Option<String>> {
+            progression.sequence.iter().map(|cs| cs.as_ref().map(|cs| cs.name_in_key(key, Accidentals::Auto))).collect()
+        };
+
+        // In the key of the file's signature
+        let key: Key = "C4".parse().unwrap();
+        let exported = progression(&["I", "vi:7", "IV/3", "V:b7"]);
+        save_progression_to_midi_file(120, 50, &Accent::default(), &exported, &key, ExportOptions::default(), path.clone()).unwrap();
+        let (imported_key, imported) = load_from_midi_file(&path, Duration::Quarter, None).unwrap();
+        assert_eq!(imported_key, key);
+        assert_eq!(names(&imported, &key), vec![
+            Some("C".to_string()), Some("Am7".to_string()), Some("F/A".to_string()), Some("G7".to_string())]);
+
+        // Quantized onto a finer resolution
+        let (_, imported) = load_from_midi_file(&path, Duration::Eighth, None).unwrap();
+        assert_eq!(imported.sequence.len(), 8);
+        assert!(imported.sequence[2].is_some() && imported.sequence[3].is_none());
+
+        // Without a signature, in the key that fits
+        let key: Key = "A4 minor".parse().unwrap();
+        let chords = progression(&["i", "iv", "VI", "i"]).timed_chords(&key, EXPORT_TICKS_PER_BEAT, 50, &Accent::default(), ExportOptions::default());
+        save_timed_to_midi_file(EXPORT_TICKS_PER_BEAT, &chords, &[], None, ExportOptions::default(), path.clone()).unwrap();
+        let (imported_key, imported) = load_from_midi_file(&path, Duration::Quarter, None).unwrap();
+        assert_eq!(imported_key, key);
+        assert_eq!(imported.sequence[1], Some("iv".parse().unwrap()));
+
+        // Or in the given key
+        let given: Key = "C4".parse().unwrap();
+        let (imported_key, imported) = load_from_midi_file(&path, Duration::Quarter, Some(given)).unwrap();
+        assert_eq!(imported_key, given);
+        assert_eq!(names(&imported, &given)[1], Some("Dm".to_string()));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_export_tracks() {
+        let chord = ChordSpec::try_from("I").unwrap().chord_for_key(&Key::default());
+        let timed = TimedChord { chord, start: 0, length: 2, velocity: VELOCITY, channel: None, marker: None };
+        let tracks: Vec<ExportTrack> = [("chords", 0), ("bass", 1)].iter()
+            .map(|(name, channel)| ExportTrack {
+                name: name.to_string(),
+                channel: *channel,
+                chords: vec![timed.clone()],
+            })
+            .collect();
+        let tempos = [TempoChange { tick: 0, bpm: 120 }];
+        let path = std::env::temp_dir().join("dust-test-tracks.mid");
+        save_tracks_to_midi_file(2, &tracks, &tempos, Some(&Key::default()), Accidentals::Auto, path.to_string_lossy().to_string()).unwrap();
+
+        // One track each, with the tempo and signatures on the first
+        let bytes = std::fs::read(&path).unwrap();
+        let smf = Smf::parse(&bytes).unwrap();
+        assert_eq!(smf.header.format, Format::Parallel);
+        assert_eq!(smf.tracks.len(), 2);
+        for (i, track) in smf.tracks.iter().enumerate() {
+            let tempo = track.iter().any(|event| matches!(event.kind, TrackEventKind::Meta(MetaMessage::Tempo(_))));
+            assert_eq!(tempo, i == 0);
+            let time = track.iter().any(|event| event.kind == TrackEventKind::Meta(MetaMessage::TimeSignature(4, 2, 24, 8)));
+            assert_eq!(time, i == 0);
+            let key = track.iter().any(|event| event.kind == TrackEventKind::Meta(MetaMessage::KeySignature(0, false)));
+            assert_eq!(key, i == 0);
+            assert!(track.iter().all(|event| match event.kind {
+                TrackEventKind::Midi { channel, .. } => channel.as_int() == i as u8,
+                _ => true,
+            }));
+        }
+        std::fs::remove_file(&path).unwrap();
     }
 }
@@ -0,0 +1,24 @@
+//! Chord parsing, progression generation,
+//! voice leading and MIDI output, as used by
+//! the `dust` chord sequencer.
+//!
+//! The TUI and command line are only built
+//! with the `tui` feature (on by default);
+//! disable default features to just use the library.
+//!
+//! ```
+//! use dust::core::{ChordSpec, Key, Accidentals, voice_lead};
+//!
+//! let chords: Vec<ChordSpec> = ["I", "vi", "IV", "V"].iter()
+//!     .map(|c| c.parse().unwrap()).collect();
+//! let key = Key::default(); // C Major
+//! for cs in voice_lead(&chords) {
+//!     println!("{}: {:?}", cs, cs.chord_for_key(&key).describe_notes(Accidentals::Auto));
+//! }
+//! ```
+
+pub mod core;
+pub mod progression;
+pub mod file;
+pub mod midi;
+pub mod audio;
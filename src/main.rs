@@ -1,16 +1,16 @@
 mod app;
-mod core;
-mod file;
-mod midi;
-mod progression;
+mod config;
+mod cli;
+mod session;
 
 use clap::{Parser, ValueHint};
 use std::{fs::File, path::{Path, PathBuf}, env};
-use std::{io, io::BufReader};
-use app::{App, run_app};
-use anyhow::Result;
+use std::{io, io::BufReader, panic};
+use app::{App, run_app, choose_ports, offer_session};
+use anyhow::{Result, Context};
 use crossterm::{
     execute,
+    cursor::Show,
     event::{DisableMouseCapture, EnableMouseCapture},
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
@@ -18,55 +18,241 @@ use tui::{
     Terminal,
     backend::CrosstermBackend,
 };
-use progression::ProgressionTemplate;
+use dust::{core, file, midi, progression, audio};
+use progression::{ProgressionTemplate, Accent};
+use config::Config;
+use session::{Session, Pads};
+use cli::Command;
+use midi::{MIDIInput, MIDIOutput};
+use crate::core::{Note, Mode, Key, Duration};
 
 #[derive(Parser, Debug)]
 #[clap(author, version, about, long_about = None)]
 struct Args {
+    #[clap(subcommand)]
+    command: Option<Command>,
+
     #[clap(short, long, value_hint = ValueHint::FilePath)]
     patterns: Option<PathBuf>,
 
-    #[clap(short, long, default_value = "/tmp/", value_hint = ValueHint::DirPath)]
-    save_dir: String,
+    #[clap(short, long, value_hint = ValueHint::DirPath)]
+    save_dir: Option<String>,
+
+    #[clap(long)]
+    midi_in_port: Option<usize>,
+
+    #[clap(long)]
+    midi_out_port: Option<usize>,
+
+    /// Root of the key to start in, e.g. "C4"
+    #[clap(long)]
+    key: Option<Note>,
+
+    /// "major", "minor", "dorian", "phrygian", "lydian", "mixolydian" or "locrian"
+    #[clap(long)]
+    mode: Option<Mode>,
+
+    /// Bars in the sequencer's progression
+    #[clap(long)]
+    bars: Option<usize>,
+
+    /// Sequencer resolution, one of 1/4, 1/8, 1/16 or 1/32
+    #[clap(long)]
+    resolution: Option<Duration>,
+
+    #[clap(long)]
+    tempo: Option<usize>,
+
+    /// Swing of off-beat steps, from 50% (straight) to 75%
+    #[clap(long)]
+    swing: Option<usize>,
+
+    /// How loud chords are across each bar: "none", "1",
+    /// "1+3", "4/4" or levels, e.g. "100,60,80,60"
+    #[clap(long)]
+    accent: Option<Accent>,
+
+    /// Session file to pick up from, e.g. one saved
+    /// with Ctrl-s, rather than being offered the last one
+    #[clap(long, value_hint = ValueHint::FilePath)]
+    session: Option<PathBuf>,
+
+    /// Performance mode chords to start with,
+    /// e.g. ones saved with W in performance mode
+    #[clap(long, value_hint = ValueHint::FilePath)]
+    pads: Option<PathBuf>,
+
+    /// MIDI file whose chords to open in the sequencer,
+    /// named in --key if it's given
+    #[clap(long, value_hint = ValueHint::FilePath)]
+    open: Option<PathBuf>,
 
-    #[clap(long, default_value = "1")]
-    midi_in_port: usize,
+    /// UDP port to listen for OSC messages on
+    #[cfg(feature = "osc")]
+    #[clap(long)]
+    osc_port: Option<u16>,
+}
 
-    #[clap(long, default_value = "1")]
-    midi_out_port: usize,
+/// Resolve the port to use to its name.
+/// A port index given on the command line takes
+/// precedence over the configured port.
+/// If neither is given, the port is asked for on startup.
+fn port_name(ports: Vec<String>, idx: Option<usize>, configured: Option<String>) -> Option<String> {
+    match idx {
+        Some(idx) => ports.get(idx).cloned(),
+        None => configured,
+    }
+}
+
+fn load_template(path: &Path) -> Result<ProgressionTemplate> {
+    let file = File::open(path)
+        .with_context(|| format!("could not open {}", path.display()))?;
+    let reader = BufReader::new(file);
+    let mut template: ProgressionTemplate = serde_yaml::from_reader(reader)
+        .with_context(|| format!("error while reading {}", path.display()))?;
+    template.update_transitions();
+    Ok(template)
+}
+
+/// Put the terminal back the way it was,
+/// out of raw mode and the alternate screen.
+fn restore_terminal() -> Result<()> {
+    disable_raw_mode()?;
+    execute!(io::stdout(), LeaveAlternateScreen, DisableMouseCapture, Show)?;
+    Ok(())
 }
 
 fn main() -> Result<()> {
     let args = Args::parse();
-    let path = match args.patterns {
-        Some(p) => p,
+    match args.command {
+        Some(Command::Ports) => {
+            cli::list_ports();
+            return Ok(());
+        }
+        Some(Command::Play(play_args)) => {
+            return cli::play(play_args);
+        }
+        _ => {}
+    }
+
+    let template = match args.patterns {
+        Some(path) => load_template(&path)?,
         None => {
-            let home = env::var("HOME").unwrap();
-            Path::new(&home).join(".config/dust/patterns.yaml")
+            // Fall back to the bundled patterns
+            // if the user hasn't defined their own
+            let home = env::var("HOME").unwrap_or_default();
+            let path = Path::new(&home).join(".config/dust/patterns.yaml");
+            if path.exists() {
+                load_template(&path)?
+            } else {
+                ProgressionTemplate::default()
+            }
         }
     };
-    let file = File::open(path).expect("could not open file");
-    let reader = BufReader::new(file);
-    let mut template: ProgressionTemplate = serde_yaml::from_reader(reader).expect("error while reading yaml");
-    template.update_transitions();
 
-    enable_raw_mode()?;
+    if let Some(Command::Gen(gen_args)) = args.command {
+        return cli::gen(&template, gen_args);
+    }
 
-    let mut stdout = io::stdout();
-    execute!(stdout, EnterAlternateScreen, EnableMouseCapture)?;
-    let backend = CrosstermBackend::new(stdout);
+    // Command line arguments override the config file
+    let mut config = Config::load()?;
+    if let Some(save_dir) = args.save_dir {
+        config.save_dir = save_dir;
+    }
+    if let Some(key) = args.key {
+        config.key = key;
+    }
+    if let Some(mode) = args.mode {
+        config.mode = mode;
+    }
+    if let Some(bars) = args.bars {
+        config.bars = bars;
+    }
+    if let Some(resolution) = args.resolution {
+        config.resolution = resolution;
+    }
+    if let Some(tempo) = args.tempo {
+        config.tempo = tempo;
+    }
+    if let Some(swing) = args.swing {
+        config.swing = swing;
+    }
+    if let Some(accent) = args.accent {
+        config.accent = accent;
+    }
+    config.midi_in_port = port_name(
+        MIDIInput::new().available_ports().unwrap_or_default(),
+        args.midi_in_port, config.midi_in_port);
+    config.midi_out_port = port_name(
+        MIDIOutput::new().available_ports().unwrap_or_default(),
+        args.midi_out_port, config.midi_out_port);
+
+    // A session or pads given on the command line have to be readable
+    let given = args.session.as_deref().map(Session::load_from).transpose()?;
+    let pads = args.pads.as_deref().map(Pads::load_from).transpose()?;
+
+    // A file given to open has to be readable
+    let opened = args.open.as_deref().map(|path| {
+        let key = args.key.map(|root| Key { root, mode: config.mode });
+        file::load_from_midi_file(&path.to_string_lossy(), config.resolution, key)
+            .with_context(|| format!("could not import {}", path.display()))
+    }).transpose()?;
+
+    // Restore the terminal before a panic's message
+    // is printed, or it's left unusable
+    let hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        let _ = restore_terminal();
+        hook(info);
+    }));
+
+    enable_raw_mode()?;
+    if let Err(err) = execute!(io::stdout(), EnterAlternateScreen, EnableMouseCapture) {
+        let _ = restore_terminal();
+        return Err(err.into());
+    }
+    let backend = CrosstermBackend::new(io::stdout());
     let mut terminal = Terminal::new(backend)?;
 
-    let app = App::new(template, args.midi_in_port, args.midi_out_port, args.save_dir);
-    let res = run_app(&mut terminal, app);
+    // A session that can't be read is left for a fresh start
+    let res = match given {
+        Some(session) => Ok(Some(session)),
+        None => Session::load().unwrap_or_default().map_or(Ok(None), |session| {
+            offer_session(&mut terminal).map(|restore| restore.then(|| session))
+        }),
+    }.and_then(|session| {
+        // Ports given on the command line
+        // take precedence over the session's
+        if let Some(session) = &session {
+            if args.midi_in_port.is_none() && session.midi_in_port.is_some() {
+                config.midi_in_port = session.midi_in_port.clone();
+            }
+            if args.midi_out_port.is_none() && session.midi_out_port.is_some() {
+                config.midi_out_port = session.midi_out_port.clone();
+            }
+        }
+        choose_ports(&mut terminal, &mut config)?;
 
-    disable_raw_mode()?;
-    execute!(
-        terminal.backend_mut(),
-        LeaveAlternateScreen,
-        DisableMouseCapture
-    )?;
-    terminal.show_cursor()?;
+        let mut app = App::new(template, config);
+        app.silence_on_panic();
+        if let Some(session) = session {
+            app.restore(&session);
+        }
+        if let Some(pads) = &pads {
+            app.set_pads(pads);
+        }
+        if let Some((key, progression)) = opened {
+            app.open(key, progression);
+        }
+        #[cfg(feature = "osc")]
+        let app = match args.osc_port {
+            Some(port) => app.listen_osc(port)?,
+            None => app,
+        };
+        run_app(&mut terminal, app)
+    });
+
+    restore_terminal()?;
 
     if let Err(err) = res {
         println!("{:?}", err)
@@ -0,0 +1,144 @@
+use std::{fmt, str::FromStr, time::Duration as Time};
+use rand::Rng;
+use thiserror::Error;
+use crate::core::{Chord, Duration};
+
+/// The order an arpeggio plays a chord's notes in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArpPattern {
+    Up,
+    Down,
+
+    // Up then back down, without
+    // repeating the top or bottom note
+    UpDown,
+
+    Random,
+}
+
+impl fmt::Display for ArpPattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ArpPattern::Up => write!(f, "up"),
+            ArpPattern::Down => write!(f, "down"),
+            ArpPattern::UpDown => write!(f, "updown"),
+            ArpPattern::Random => write!(f, "random"),
+        }
+    }
+}
+
+/// Plays a chord's notes one after another in a
+/// pattern, a note per division of a beat,
+/// instead of all at once.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Arp {
+    pub pattern: ArpPattern,
+    pub division: Duration,
+}
+
+impl Arp {
+    /// Which of a chord's notes, counting from the
+    /// lowest, are played on each of so many divisions.
+    pub fn order(&self, notes: usize, divisions: usize) -> Vec<usize> {
+        if notes == 0 {
+            return vec![];
+        }
+        let mut rng = rand::thread_rng();
+        let turn = (notes * 2).saturating_sub(2).max(1);
+        (0..divisions).map(|i| match self.pattern {
+            ArpPattern::Up => i % notes,
+            ArpPattern::Down => notes - 1 - i % notes,
+            ArpPattern::UpDown => {
+                let j = i % turn;
+                if j < notes { j } else { turn - j }
+            }
+            ArpPattern::Random => rng.gen_range(0..notes),
+        }).collect()
+    }
+
+    /// How many divisions there are per beat.
+    pub fn per_beat(&self) -> usize {
+        self.division.ticks_per_beat()
+    }
+
+    /// The notes played in place of a chord held for some time,
+    /// given how long a beat lasts, each as a chord of one note
+    /// with when it starts and how long it lasts. The last
+    /// note is cut short if the chord ends partway through it.
+    pub fn notes(&self, chord: &Chord, beat: Time, length: Time) -> Vec<(Chord, Time, Time)> {
+        let notes = chord.notes();
+        let division = (beat / self.per_beat() as u32).max(Time::from_millis(1));
+        let divisions = (length.as_secs_f64() / division.as_secs_f64()).ceil().max(1.) as usize;
+        self.order(notes.len(), divisions).into_iter().enumerate().map(|(i, idx)| {
+            let start = division * i as u32;
+            let length = length.saturating_sub(start).min(division).max(Time::from_millis(1));
+            (Chord::new(notes[idx], vec![0]), start, length)
+        }).collect()
+    }
+}
+
+/// Shown as the pattern and the division's
+/// denominator, e.g. "updown/16".
+impl fmt::Display for Arp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.pattern, self.per_beat() * 4)
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum ArpParseError {
+    #[error("Invalid arpeggio `{0}`, expected `up`, `down`, `updown` or `random`, optionally with a division of 4, 8, 16 or 32, e.g. `up/16`")]
+    InvalidArp(String),
+}
+
+/// Parses a pattern, e.g. "up", played in 16ths,
+/// or with a division, e.g. "updown/8".
+impl FromStr for Arp {
+    type Err = ArpParseError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || ArpParseError::InvalidArp(s.to_string());
+        let (pattern, division) = s.split_once('/').unwrap_or((s, "16"));
+        let pattern = match pattern.trim() {
+            "up" => ArpPattern::Up,
+            "down" => ArpPattern::Down,
+            "updown" => ArpPattern::UpDown,
+            "random" => ArpPattern::Random,
+            _ => return Err(invalid()),
+        };
+        let division = format!("1/{}", division.trim()).parse().map_err(|_| invalid())?;
+        Ok(Arp { pattern, division })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::core::Note;
+
+    #[test]
+    fn test_arp() {
+        let arp: Arp = "updown/8".parse().unwrap();
+        assert_eq!(arp.division, Duration::Eighth);
+        assert_eq!(arp.to_string(), "updown/8");
+        assert_eq!(arp.order(3, 6), vec![0, 1, 2, 1, 0, 1]);
+        assert_eq!(arp.order(1, 3), vec![0, 0, 0]);
+
+        let arp: Arp = "down".parse().unwrap();
+        assert_eq!(arp.division, Duration::Sixteenth);
+        assert_eq!(arp.order(3, 4), vec![2, 1, 0, 2]);
+
+        // C major in 16ths at 150 bpm, held for three of them
+        let chord = Chord::new(Note { semitones: 3 }, vec![0, 4, 7]);
+        let notes = Arp { pattern: ArpPattern::Up, division: Duration::Sixteenth }
+            .notes(&chord, Time::from_millis(400), Time::from_millis(300));
+        let notes: Vec<_> = notes.iter()
+            .map(|(note, start, length)| (note.notes()[0].semitones, start.as_millis(), length.as_millis()))
+            .collect();
+        assert_eq!(notes, vec![(3, 0, 100), (7, 100, 100), (10, 200, 100)]);
+
+        let arp: Arp = "random/32".parse().unwrap();
+        assert!(arp.order(4, 8).iter().all(|i| *i < 4));
+        assert!("sideways".parse::<Arp>().is_err());
+        assert!("up/12".parse::<Arp>().is_err());
+    }
+}
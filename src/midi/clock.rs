@@ -1,4 +1,9 @@
 use super::{MIDIInput, MIDIError};
+use std::fmt;
+use std::thread::{self, sleep};
+use std::time::{Duration, Instant};
+use std::sync::{Arc, mpsc::{channel, Sender}, atomic::{AtomicBool, AtomicUsize, Ordering}};
+use serde::{Serialize, Deserialize};
 
 // 4/4 time
 const QUARTERS_PER_BAR: usize = 4;
@@ -7,6 +12,10 @@ const QUARTERS_PER_BAR: usize = 4;
 // https://en.wikipedia.org/wiki/MIDI_beat_clock
 const TICKS_PER_QUARTER: usize = 24;
 
+/// Most smoothing of an external clock, as a
+/// percentage; any more and it'd barely follow it.
+pub const MAX_SMOOTHING: usize = 90;
+
 #[derive(Debug, PartialEq, Eq)]
 pub enum ClockEvent {
     Tick(usize),
@@ -14,52 +23,296 @@ pub enum ClockEvent {
     Stop,
 }
 
+/// Where clock ticks come from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ClockSource {
+    // A MIDI input port, e.g. from a DAW
+    External,
+
+    // Our own clock, at a set tempo
+    Internal,
+
+    // The JACK transport
+    #[cfg(feature = "jack")]
+    Jack,
+}
+
+impl Default for ClockSource {
+    fn default() -> Self {
+        ClockSource::External
+    }
+}
+
+impl fmt::Display for ClockSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClockSource::External => write!(f, "external"),
+            ClockSource::Internal => write!(f, "internal"),
+            #[cfg(feature = "jack")]
+            ClockSource::Jack => write!(f, "jack"),
+        }
+    }
+}
+
+/// Evens out jittery clock ticks, e.g. over USB,
+/// by tracking the average time between ticks and
+/// only partly following each tick's actual timing.
+#[derive(Debug, Default)]
+struct Smoother {
+    // When the last tick was let through,
+    // and the average time between ticks
+    last: Option<Instant>,
+    interval: Option<Duration>,
+}
+
+impl Smoother {
+    /// When to let through a tick that arrived at `now`,
+    /// given a smoothing amount from 0 to `MAX_SMOOTHING`%.
+    /// Every tick is held back by up to half a tick,
+    /// more with more smoothing, so that late ticks
+    /// can be evened out as well as early ones.
+    fn next(&mut self, now: Instant, smoothing: usize) -> Instant {
+        let follow = 1. - smoothing.min(MAX_SMOOTHING) as f64 / 100.;
+        let at = match (self.last, self.interval) {
+            (Some(last), Some(interval)) => {
+                let expected = last + interval;
+                let actual = now.saturating_duration_since(last).as_secs_f64();
+                let error = actual - interval.as_secs_f64();
+
+                // Too far off to be jitter, e.g. the tempo
+                // changed, so start over from this tick
+                if error.abs() > interval.as_secs_f64() {
+                    self.interval = Some(now.saturating_duration_since(last));
+                    now
+                } else {
+                    self.interval = Some(Duration::from_secs_f64(interval.as_secs_f64() + follow * error));
+                    if error < 0. {
+                        expected - Duration::from_secs_f64(-error * follow)
+                    } else {
+                        expected + Duration::from_secs_f64(error * follow)
+                    }
+                }
+            }
+            (Some(last), None) => {
+                self.interval = Some(now.saturating_duration_since(last));
+                now
+            }
+            _ => now,
+        };
+        self.last = Some(at);
+        let hold = self.interval.unwrap_or_default().mul_f64((1. - follow) / 2.);
+        at + hold
+    }
+}
+
+/// Follows an external clock's messages,
+/// working out when to let each event through.
+#[derive(Debug, Default)]
+struct Follower {
+    tick: usize,
+    playing: bool,
+    smoother: Smoother,
+}
+
+impl Follower {
+    /// The event for a message that arrived at `now`, if any,
+    /// and when to let it through. Ticks may be held back
+    /// to smooth them out, the rest go right away.
+    fn message(&mut self, msg: &[u8], now: Instant, smoothing: usize) -> Option<(Instant, ClockEvent)> {
+        match msg {
+            [248] => {
+                if self.playing {
+                    let at = self.smoother.next(now, smoothing);
+                    self.tick += 1;
+                    if self.tick >= QUARTERS_PER_BAR * TICKS_PER_QUARTER {
+                        self.tick = 0;
+                    }
+                    Some((at, ClockEvent::Tick(self.tick)))
+                } else {
+                    None
+                }
+            },
+            [250] => {
+                self.playing = true;
+                self.smoother = Smoother::default();
+                Some((now, ClockEvent::Start))
+            },
+            [252] => {
+                self.playing = false;
+                Some((now, ClockEvent::Stop))
+            },
+            _ => None,
+        }
+    }
+}
+
+/// Pass events on to `tick_fn` at their times, in order.
+/// Waiting happens on its own thread so the MIDI
+/// input's callback isn't blocked by held back ticks.
+/// The thread ends once the sender is dropped.
+fn spawn_timer<F>(mut tick_fn: F) -> Sender<(Instant, ClockEvent)>
+    where F: FnMut(ClockEvent) + Send + 'static {
+    let (tx, rx) = channel::<(Instant, ClockEvent)>();
+    thread::spawn(move || {
+        for (at, ev) in rx {
+            sleep(at.saturating_duration_since(Instant::now()));
+            tick_fn(ev);
+        }
+    });
+    tx
+}
+
 pub struct MIDIClock {
     midi_in: MIDIInput,
+
+    // How much to smooth an external
+    // clock's ticks, as a percentage
+    smoothing: Arc<AtomicUsize>,
+
+    // Set to false to stop the internal
+    // clock or following JACK
+    internal: Option<Arc<AtomicBool>>,
+
+    // Tempo of the internal clock
+    bpm: Arc<AtomicUsize>,
 }
 
 impl MIDIClock {
     pub fn new() -> MIDIClock {
         MIDIClock {
             midi_in: MIDIInput::new(),
+            smoothing: Arc::new(AtomicUsize::new(0)),
+            internal: None,
+            bpm: Arc::new(AtomicUsize::new(120)),
         }
     }
 
-    pub fn connect_port<F>(&mut self, idx: usize, mut tick_fn: F) -> Result<(), MIDIError>
+    pub fn connect_port<F>(&mut self, idx: usize, tick_fn: F) -> Result<(), MIDIError>
         where F: FnMut(ClockEvent) + Send + 'static {
-        let mut tick = 0;
-        let mut playing = false;
+        self.close();
+        let mut follower = Follower::default();
+        let smoothing = self.smoothing.clone();
+        let timer = spawn_timer(tick_fn);
         self.midi_in.connect_port(idx, move |_, msg, _| {
-            let ev = match msg {
-                [248] => {
-                    if playing {
-                        tick += 1;
-                        if tick >= QUARTERS_PER_BAR * TICKS_PER_QUARTER {
-                            tick = 0;
-                        }
-                        Some(ClockEvent::Tick(tick))
-                    } else {
-                        None
-                    }
-                },
-                [250] => {
-                    playing = true;
-                    Some(ClockEvent::Start)
-                },
-                [252] => {
-                    playing = false;
-                    Some(ClockEvent::Stop)
-                },
-                _ => None,
-            };
-            if let Some(ev) = ev {
-                tick_fn(ev);
+            let smoothing = smoothing.load(Ordering::Relaxed);
+            if let Some(ev) = follower.message(msg, Instant::now(), smoothing) {
+                let _ = timer.send(ev);
             }
         })
     }
 
+    /// Generate clock ticks ourselves at the given tempo,
+    /// instead of listening to a MIDI input port.
+    pub fn start_internal<F>(&mut self, bpm: usize, mut tick_fn: F)
+        where F: FnMut(ClockEvent) + Send + 'static {
+        self.close();
+        self.set_tempo(bpm);
+
+        let running = Arc::new(AtomicBool::new(true));
+        self.internal = Some(running.clone());
+        let bpm = self.bpm.clone();
+        thread::spawn(move || {
+            let mut tick = 0;
+            let mut next = Instant::now();
+            tick_fn(ClockEvent::Start);
+            while running.load(Ordering::Relaxed) {
+                // Schedule from the previous tick
+                // rather than from now, to avoid drift
+                let bpm = bpm.load(Ordering::Relaxed).max(1);
+                next += Duration::from_secs_f64(60. / (bpm * TICKS_PER_QUARTER) as f64);
+                sleep(next.saturating_duration_since(Instant::now()));
+
+                tick += 1;
+                if tick >= QUARTERS_PER_BAR * TICKS_PER_QUARTER {
+                    tick = 0;
+                }
+                tick_fn(ClockEvent::Tick(tick));
+            }
+            tick_fn(ClockEvent::Stop);
+        });
+    }
+
+    /// Follow the JACK transport, ticking along
+    /// with its position while it's rolling.
+    #[cfg(feature = "jack")]
+    pub fn start_jack<F>(&mut self, mut tick_fn: F) -> Result<(), MIDIError>
+        where F: FnMut(ClockEvent) + Send + 'static {
+        self.close();
+        let (client, _) = jack::Client::new("dust", jack::ClientOptions::NO_START_SERVER)?;
+
+        let running = Arc::new(AtomicBool::new(true));
+        self.internal = Some(running.clone());
+        let bpm = self.bpm.clone();
+        thread::spawn(move || {
+            let transport = client.transport();
+            let mut rolling = false;
+            let mut last = 0;
+            while running.load(Ordering::Relaxed) {
+                sleep(Duration::from_millis(1));
+                let status = match transport.query() {
+                    Ok(status) => status,
+                    Err(_) => continue,
+                };
+
+                // Use the transport's tempo if it has one
+                if let Some(bbt) = status.pos.bbt() {
+                    bpm.store(bbt.bpm.round() as usize, Ordering::Relaxed);
+                }
+                let rate = status.pos.frame_rate().unwrap_or(48000).max(1) as f64;
+                let secs = status.pos.frame() as f64 / rate;
+                let quarters = secs * bpm.load(Ordering::Relaxed) as f64 / 60.;
+                let now = (quarters * TICKS_PER_QUARTER as f64) as usize;
+
+                let is_rolling = status.state == jack::TransportState::Rolling;
+                if is_rolling && (!rolling || now < last) {
+                    // Started, or moved back; play from the top
+                    if rolling {
+                        tick_fn(ClockEvent::Stop);
+                    }
+                    tick_fn(ClockEvent::Start);
+                    last = now;
+                } else if !is_rolling && rolling {
+                    tick_fn(ClockEvent::Stop);
+                }
+                rolling = is_rolling;
+
+                if rolling {
+                    for tick in last+1..=now {
+                        tick_fn(ClockEvent::Tick(tick % (QUARTERS_PER_BAR * TICKS_PER_QUARTER)));
+                    }
+                    last = now;
+                }
+            }
+            if rolling {
+                tick_fn(ClockEvent::Stop);
+            }
+        });
+        Ok(())
+    }
+
+    /// The tempo of the internal clock,
+    /// to change it while playing.
+    pub fn tempo(&self) -> Arc<AtomicUsize> {
+        self.bpm.clone()
+    }
+
+    /// Set how much to smooth an external clock,
+    /// from 0 to `MAX_SMOOTHING`%.
+    pub fn set_smoothing(&self, smoothing: usize) {
+        self.smoothing.store(smoothing.min(MAX_SMOOTHING), Ordering::Relaxed);
+    }
+
+    /// Set the tempo of the internal clock.
+    pub fn set_tempo(&self, bpm: usize) {
+        self.bpm.store(bpm, Ordering::Relaxed);
+    }
+
     pub fn close(&mut self) {
         self.midi_in.close();
+        if let Some(running) = self.internal.take() {
+            running.store(false, Ordering::Relaxed);
+        }
     }
 }
 
@@ -68,3 +321,71 @@ impl Default for MIDIClock {
         MIDIClock::new()
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_smoothing() {
+        let start = Instant::now();
+        let interval = Duration::from_millis(20);
+
+        // Ticks every 20ms, alternately 4ms early and late
+        let arrivals: Vec<Instant> = (0..50).map(|i| {
+            let jitter = Duration::from_millis(4);
+            let on_time = start + interval * i;
+            if i % 2 == 0 { on_time + jitter } else { on_time - jitter }
+        }).collect();
+        let spread = |times: &[Instant]| {
+            let gaps: Vec<f64> = times.windows(2)
+                .map(|w| w[1].saturating_duration_since(w[0]).as_secs_f64())
+                .collect();
+            let max = gaps.iter().cloned().fold(f64::MIN, f64::max);
+            let min = gaps.iter().cloned().fold(f64::MAX, f64::min);
+            max - min
+        };
+
+        // Without smoothing ticks go right away
+        let mut smoother = Smoother::default();
+        let unsmoothed: Vec<Instant> = arrivals.iter().map(|t| smoother.next(*t, 0)).collect();
+        assert_eq!(unsmoothed, arrivals);
+
+        // With it, once settled the time
+        // between ticks varies less
+        let mut smoother = Smoother::default();
+        let smoothed: Vec<Instant> = arrivals.iter().map(|t| smoother.next(*t, 80).max(*t)).collect();
+        assert!(spread(&smoothed[10..]) < spread(&arrivals[10..]) / 2.);
+    }
+
+    #[test]
+    fn test_held_ticks() {
+        let start = Instant::now();
+        let interval = Duration::from_millis(20);
+        let mut follower = Follower::default();
+        assert_eq!(follower.message(&[248], start, 80), None);
+        assert_eq!(follower.message(&[250], start, 80), Some((start, ClockEvent::Start)));
+
+        // Once the tempo's known, ticks are held back
+        let held: Vec<(Instant, ClockEvent)> = (1..4)
+            .filter_map(|i| follower.message(&[248], start + interval * i, 80))
+            .collect();
+        assert_eq!(held[0].1, ClockEvent::Tick(1));
+        assert!(held[2].0 > start + interval * 3);
+
+        // The timer lets them through in order, at their
+        // times, without holding up whoever sends them
+        let (tx, rx) = channel();
+        let timer = spawn_timer(move |ev| tx.send((Instant::now(), ev)).unwrap());
+        let now = Instant::now();
+        let later = now + Duration::from_millis(30);
+        timer.send((later, ClockEvent::Tick(1))).unwrap();
+        timer.send((now, ClockEvent::Stop)).unwrap();
+        assert!(now.elapsed() < Duration::from_millis(30));
+
+        let (at, ev) = rx.recv().unwrap();
+        assert_eq!(ev, ClockEvent::Tick(1));
+        assert!(at >= later);
+        assert_eq!(rx.recv().unwrap().1, ClockEvent::Stop);
+    }
+}
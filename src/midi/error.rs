@@ -1,5 +1,7 @@
 use thiserror::Error;
 use midir::{MidiOutput, MidiInput, InitError, ConnectError};
+#[cfg(feature = "audio")]
+use crate::audio::AudioError;
 
 #[derive(Error, Debug)]
 pub enum MIDIError {
@@ -9,6 +11,9 @@ pub enum MIDIError {
     #[error("Invalid port index: {0}")]
     InvalidPort(usize),
 
+    #[error("No port named `{0}`")]
+    UnknownPort(String),
+
     #[error("Couldn't initialize")]
     InitError(#[from] InitError),
 
@@ -17,4 +22,15 @@ pub enum MIDIError {
 
     #[error("Couldn't connect to input port")]
     InputConnect(#[from] ConnectError<MidiInput>),
+
+    #[error("dust was built without the audio preview")]
+    NoAudio,
+
+    #[cfg(feature = "audio")]
+    #[error(transparent)]
+    Audio(#[from] AudioError),
+
+    #[cfg(feature = "jack")]
+    #[error("Couldn't connect to JACK: {0}")]
+    Jack(#[from] jack::Error),
 }
@@ -0,0 +1,185 @@
+use super::{MIDIInput, MIDIError};
+use std::sync::{Arc, Mutex, mpsc::{self, Receiver}};
+use crate::core::{Chord, Note};
+
+// MIDI note values map A0 to 21
+const A0: u8 = 21;
+
+/// A note starting or ending,
+/// from a MIDI message.
+#[derive(Debug, PartialEq, Eq)]
+enum NoteEvent {
+    On(u8),
+    Off(u8),
+}
+
+impl NoteEvent {
+    /// The note event of a MIDI message, if it is one,
+    /// on any channel. A note on with no velocity
+    /// is a note off.
+    fn parse(msg: &[u8]) -> Option<NoteEvent> {
+        match msg {
+            [status, note, velocity] if status & 0xF0 == 0x90 && *velocity > 0 => Some(NoteEvent::On(*note)),
+            [status, note, _] if status & 0xF0 == 0x90 || status & 0xF0 == 0x80 => Some(NoteEvent::Off(*note)),
+            _ => None,
+        }
+    }
+}
+
+/// The notes held down, and the chord they make.
+/// Notes played together are grouped into a chord,
+/// which stays once they're let go, so it can be
+/// read after playing it; the next note played after
+/// letting any go starts a new chord from the notes
+/// still held.
+#[derive(Debug, Default)]
+struct Held {
+    notes: Vec<u8>,
+    chord: Vec<u8>,
+    released: bool,
+}
+
+impl Held {
+    fn update(&mut self, event: NoteEvent) {
+        match event {
+            NoteEvent::On(note) => {
+                if self.released {
+                    self.chord = self.notes.clone();
+                    self.released = false;
+                }
+                if !self.notes.contains(&note) {
+                    self.notes.push(note);
+                }
+                if !self.chord.contains(&note) {
+                    self.chord.push(note);
+                }
+            }
+            NoteEvent::Off(note) => {
+                self.notes.retain(|n| *n != note);
+                self.released = true;
+            }
+        }
+    }
+
+    /// The chord last played, if it
+    /// has at least two notes.
+    fn chord(&self) -> Option<Chord> {
+        let mut notes: Vec<Note> = self.chord.iter()
+            .map(|n| Note { semitones: *n as isize - A0 as isize })
+            .collect();
+        notes.sort_by_key(|n| n.semitones);
+        if notes.len() < 2 {
+            return None;
+        }
+        let root = notes[0];
+        let intervals = notes.iter().map(|n| n.semitones - root.semitones).collect();
+        Some(Chord::new(root, intervals))
+    }
+}
+
+/// Listens to notes played on a MIDI input
+/// port, e.g. from a keyboard or a controller's pads.
+pub struct MIDIKeyboard {
+    midi_in: MIDIInput,
+    held: Arc<Mutex<Held>>,
+
+    // Notes started since they were last taken
+    played: Option<Receiver<u8>>,
+}
+
+impl MIDIKeyboard {
+    pub fn new() -> MIDIKeyboard {
+        MIDIKeyboard {
+            midi_in: MIDIInput::new(),
+            held: Arc::new(Mutex::new(Held::default())),
+            played: None,
+        }
+    }
+
+    /// The connected port's name, if any.
+    pub fn name(&self) -> Option<&str> {
+        self.midi_in.name.as_deref()
+    }
+
+    pub fn connect_port(&mut self, idx: usize) -> Result<(), MIDIError> {
+        self.close();
+        let held = self.held.clone();
+        let (tx, rx) = mpsc::channel();
+        self.played = Some(rx);
+        self.midi_in.connect_port(idx, move |_, msg, _| {
+            if let Some(event) = NoteEvent::parse(msg) {
+                if let NoteEvent::On(note) = event {
+                    let _ = tx.send(note);
+                }
+                held.lock().unwrap().update(event);
+            }
+        })
+    }
+
+    /// The MIDI notes started since this was
+    /// last called, in the order they were played.
+    pub fn notes_played(&self) -> Vec<u8> {
+        match &self.played {
+            Some(rx) => rx.try_iter().collect(),
+            None => vec![],
+        }
+    }
+
+    /// The chord last played, if any.
+    pub fn chord(&self) -> Option<Chord> {
+        self.held.lock().unwrap().chord()
+    }
+
+    pub fn close(&mut self) {
+        self.midi_in.close();
+        self.midi_in.name = None;
+        self.played = None;
+        *self.held.lock().unwrap() = Held::default();
+    }
+}
+
+impl Default for MIDIKeyboard {
+    fn default() -> Self {
+        MIDIKeyboard::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_held_chord() {
+        assert_eq!(NoteEvent::parse(&[0x91, 60, 100]), Some(NoteEvent::On(60)));
+        assert_eq!(NoteEvent::parse(&[0x90, 60, 0]), Some(NoteEvent::Off(60)));
+        assert_eq!(NoteEvent::parse(&[0x80, 60, 64]), Some(NoteEvent::Off(60)));
+        assert_eq!(NoteEvent::parse(&[248]), None);
+
+        let semitones = |held: &Held| -> Option<Vec<isize>> {
+            held.chord().map(|c| c.notes().iter().map(|n| n.semitones).collect())
+        };
+
+        // C4 E4 G4, played from the top down
+        let mut held = Held::default();
+        for note in [67, 64, 60] {
+            held.update(NoteEvent::On(note));
+        }
+        assert_eq!(semitones(&held), Some(vec![39, 43, 46]));
+
+        // Stays once let go
+        for note in [60, 64, 67] {
+            held.update(NoteEvent::Off(note));
+        }
+        assert_eq!(semitones(&held), Some(vec![39, 43, 46]));
+
+        // Until the next one is played,
+        // here holding onto the G
+        held.update(NoteEvent::On(67));
+        assert_eq!(semitones(&held), None);
+        held.update(NoteEvent::On(71));
+        held.update(NoteEvent::On(74));
+        held.update(NoteEvent::Off(71));
+        held.update(NoteEvent::On(62));
+        assert_eq!(semitones(&held), Some(vec![41, 46, 53]));
+    }
+}
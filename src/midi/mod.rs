@@ -1,9 +1,37 @@
+//! Sending chords to MIDI output ports
+//! (or the audio preview), arpeggiated or not,
+//! syncing to a MIDI clock, and listening
+//! to notes played on a MIDI keyboard.
+
 mod error;
 mod clock;
 mod input;
 mod output;
+mod keyboard;
+mod arp;
 
 pub use error::MIDIError;
 pub use input::MIDIInput;
-pub use output::MIDIOutput;
-pub use clock::{MIDIClock, ClockEvent};
+pub use output::{MIDIOutput, Output, NoteOff, HeldChord, MAX_OVERLAP};
+pub use clock::{MIDIClock, ClockEvent, ClockSource, MAX_SMOOTHING};
+pub use keyboard::MIDIKeyboard;
+pub use arp::{Arp, ArpPattern, ArpParseError};
+
+/// Find a port's index by its name.
+pub fn port_index(ports: &[String], name: &str) -> Result<usize, MIDIError> {
+    ports.iter().position(|p| p == name)
+        .ok_or_else(|| MIDIError::UnknownPort(name.to_string()))
+}
+
+/// Find several ports' indices by their names,
+/// leaving out any repeats.
+pub fn port_indices(ports: &[String], names: &[String]) -> Result<Vec<usize>, MIDIError> {
+    let mut idxs = vec![];
+    for name in names {
+        let idx = port_index(ports, name)?;
+        if !idxs.contains(&idx) {
+            idxs.push(idx);
+        }
+    }
+    Ok(idxs)
+}
@@ -1,22 +1,244 @@
 use anyhow::Result;
-use super::MIDIError;
+use super::{MIDIError, ClockEvent};
 use crate::core::Chord;
 use midir::{MidiOutput, MidiOutputConnection};
-use std::{thread, sync::{Arc, Mutex}};
+use serde::{Serialize, Deserialize};
+use std::{fmt, thread, sync::{Arc, Mutex, atomic::{AtomicUsize, Ordering}}};
 use std::{thread::sleep, time::Duration};
 use std::collections::HashMap;
+use crate::audio::{Patch, Instruments, Mixer, Beat};
+#[cfg(feature = "audio")]
+use crate::audio::AudioOutput;
 
 const VELOCITY: u8 = 0x64;
+
+// Metronome clicks on a MIDI port are
+// General MIDI high and low wood blocks
+const CLICK_NOTES: (u8, u8) = (76, 77);
+const CLICK_LENGTH: u64 = 50;
 const NOTE_ON_MSG: u8 = 0x90;
 const NOTE_OFF_MSG: u8 = 0x80;
+const CONTROL_MSG: u8 = 0xB0;
+
+// System real-time messages
+const CLOCK_MSG: u8 = 0xF8;
+const START_MSG: u8 = 0xFA;
+const STOP_MSG: u8 = 0xFC;
+
+// All Sound Off and Reset All Controllers
+const ALL_SOUND_OFF: u8 = 120;
+const RESET_CONTROLLERS: u8 = 121;
+
+/// Most that chords can overlap,
+/// or be cut short by, in ms.
+pub const MAX_OVERLAP: isize = 1000;
+
+/// Where notes are played.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Output {
+    // A MIDI output port
+    Midi,
+
+    // The built-in audio preview
+    Audio,
+}
+
+impl Default for Output {
+    fn default() -> Self {
+        Output::Midi
+    }
+}
+
+impl fmt::Display for Output {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Output::Midi => write!(f, "midi"),
+            Output::Audio => write!(f, "audio"),
+        }
+    }
+}
+
+/// How notes are ended. Some synths only
+/// handle one or the other properly.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum NoteOff {
+    // A note off message
+    Message,
+
+    // A note on message with zero velocity
+    ZeroVelocity,
+}
+
+impl Default for NoteOff {
+    fn default() -> Self {
+        NoteOff::Message
+    }
+}
+
+impl fmt::Display for NoteOff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NoteOff::Message => write!(f, "note off"),
+            NoteOff::ZeroVelocity => write!(f, "zero velocity"),
+        }
+    }
+}
+
+/// Where MIDI messages are sent.
+enum Connection {
+    // Every message goes to each port,
+    // unless it's meant for one of them
+    Ports(Vec<(String, MidiOutputConnection)>),
+
+    #[cfg(feature = "audio")]
+    Audio(AudioOutput),
+}
+
+impl Connection {
+    fn send(&mut self, msg: &[u8]) {
+        self.send_to(msg, None);
+    }
+
+    /// Send a message to a port, by name, or every port
+    /// if none is given or that port isn't connected.
+    fn send_to(&mut self, msg: &[u8], port: Option<&str>) {
+        match self {
+            // Dropped messages aren't worth interrupting playback for
+            Connection::Ports(conns) => {
+                let port = port.filter(|port| conns.iter().any(|(name, _)| name == port));
+                for (name, conn) in conns {
+                    if port.map_or(true, |port| port == name) {
+                        let _ = conn.send(msg);
+                    }
+                }
+            }
+
+            #[cfg(feature = "audio")]
+            Connection::Audio(audio) => audio.send(msg),
+        }
+    }
+}
+
+// A note playing on a port, if not every
+// port, by its channel and note value
+type NoteKey = (Option<String>, u8, u8);
+
+/// A chord held until it's released: the
+/// notes it started, and the number they're
+/// owned by, on the channel and port they're on.
+pub struct HeldChord {
+    notes: Vec<u8>,
+    channel: u8,
+    port: Option<String>,
+    owner: usize,
+}
+
+/// Starts and ends notes, keeping track of which
+/// notes are owned by whom. Cloned into the
+/// threads that play notes for a length of time.
+#[derive(Clone)]
+struct Voices {
+    conn: Arc<Mutex<Option<Connection>>>,
+    note_owners: Arc<Mutex<HashMap<NoteKey, usize>>>,
+    next_owner: Arc<AtomicUsize>,
+}
+
+impl Voices {
+    /// Start notes, taking ownership of them.
+    ///
+    /// When we play a set of notes, we need to track
+    /// which thread has the right to stop those notes
+    /// (i.e. send the notes off message).
+    /// This is to avoid the following scenario:
+    /// - t=0.0: Thread A plays CEG for 1 second
+    /// - t=0.5: Thread B plays CEG again
+    /// - t=1.0: Thread A stops CEG, prematurely ending thread B's CEG by 0.5 seconds
+    ///
+    /// Here we assign a number to each thread that plays a given note.
+    /// Then before that thread stops its notes, it checks to see if it
+    /// is the owner (has the highest number) of those notes.
+    /// For simplicity just saying one note (C) but this applies for multiple notes too.
+    /// - t=0.0: Thread A plays C for 1 second and is assigned #1.
+    /// - t=0.5: Thread B plays C again and is assigned #2
+    /// - t=1.0: Thread A wants to stop C, so it compares its number (#1)
+    ///   against C's current number (#2). Because #1 < #2, thread A doesn't stop C.
+    /// - t=1.5: Thread B wants to stop C, so it compares its number (#2)
+    ///   against C's current number (#2). Because #2 = #2, thread A can stop C.
+    ///
+    /// Numbers are assigned when the notes start rather than when
+    /// they're scheduled, so delayed notes don't hold on earlier ones.
+    /// They keep counting up even when the owners are cleared,
+    /// so threads from before then never stop newer notes.
+    fn start(&self, notes: Vec<u8>, note_on: u8, velocity: u8, port: Option<String>) -> HeldChord {
+        let channel = note_on & 0x0F;
+        let owner = self.next_owner.fetch_add(1, Ordering::Relaxed) + 1;
+        {
+            let mut note_owners = self.note_owners.lock().unwrap();
+            for note in &notes {
+                note_owners.insert((port.clone(), channel, *note), owner);
+            }
+        }
+        let mut conn = self.conn.lock().unwrap();
+        if let Some(ref mut conn) = *conn {
+            for note in &notes {
+                conn.send_to(&[note_on, *note, velocity], port.as_deref());
+            }
+        }
+        HeldChord { notes, channel, port, owner }
+    }
+
+    /// End notes that were started, except any
+    /// that were played again since, giving up
+    /// ownership of them.
+    fn end(&self, held: &HeldChord, note_off: u8, release: u8) {
+        let mut conn = self.conn.lock().unwrap();
+        let mut owners = self.note_owners.lock().unwrap();
+        for note in &held.notes {
+            // No owner means the notes were already silenced
+            let key = (held.port.clone(), held.channel, *note);
+            if owners.get(&key) == Some(&held.owner) {
+                owners.remove(&key);
+                if let Some(ref mut conn) = *conn {
+                    conn.send_to(&[note_off, *note, release], held.port.as_deref());
+                }
+            }
+        }
+    }
+}
 
 pub struct MIDIOutput {
     pub name: Option<String>,
-    conn: Arc<Mutex<Option<MidiOutputConnection>>>,
+    conn: Arc<Mutex<Option<Connection>>>,
+
+    // MIDI channel to send notes on, 0-15
+    pub channel: u8,
+
+    // How notes are ended, and their
+    // note off velocity, 0-127, which
+    // some synths map to release
+    pub note_off: NoteOff,
+    pub release_velocity: u8,
+
+    // Milliseconds to hold chords past their end,
+    // so they blend into the next, or if negative,
+    // to cut them short by, for tighter stabs
+    pub overlap: isize,
+
+    // Whether to send our clock to the ports,
+    // so other gear can follow it
+    pub send_clock: bool,
+
+    // How the audio preview sounds
+    patch: Patch,
+    instruments: Option<Instruments>,
+    mixer: Mixer,
 
     // We use this to determine when a note off
     // signal should be sent, to avoid conflicts
-    note_owners: Arc<Mutex<HashMap<u8, usize>>>,
+    note_owners: Arc<Mutex<HashMap<NoteKey, usize>>>,
+    next_owner: Arc<AtomicUsize>,
 }
 
 
@@ -25,7 +247,16 @@ impl MIDIOutput {
         MIDIOutput {
             name: None,
             conn: Arc::new(Mutex::new(None)),
+            channel: 0,
+            note_off: NoteOff::default(),
+            release_velocity: VELOCITY,
+            overlap: 0,
+            send_clock: false,
+            patch: Patch::default(),
+            instruments: None,
+            mixer: Mixer::default(),
             note_owners: Arc::new(Mutex::new(HashMap::default())),
+            next_owner: Arc::new(AtomicUsize::new(0)),
         }
     }
 
@@ -46,109 +277,292 @@ impl MIDIOutput {
     }
 
     pub fn connect_port(&mut self, idx: usize) -> Result<(), MIDIError> {
-        let out = self.output()?;
-        let out_ports = out.ports();
-        if idx >= out_ports.len() {
-            Err(MIDIError::InvalidPort(idx))
-        } else {
-            let port_names = self.available_ports()?;
-            let conn_out = out.connect(&out_ports[idx], "dust")?;
-            let _ = self.conn.clone().lock().unwrap().insert(conn_out);
-            self.name = Some(port_names[idx].to_string());
+        self.connect_ports(&[idx])
+    }
+
+    /// Broadcast to several ports at once,
+    /// sending each of them every message,
+    /// e.g. to layer synths.
+    pub fn connect_ports(&mut self, idxs: &[usize]) -> Result<(), MIDIError> {
+        let port_names = self.available_ports()?;
+        let mut conns = vec![];
+        for idx in idxs {
+            // Connecting uses up the output
+            let out = self.output()?;
+            let out_ports = out.ports();
+            if *idx >= out_ports.len() {
+                return Err(MIDIError::InvalidPort(*idx));
+            }
+            conns.push((port_names[*idx].clone(), out.connect(&out_ports[*idx], "dust")?));
+        }
+        let names: Vec<&str> = idxs.iter().map(|idx| port_names[*idx].as_str()).collect();
+        self.silence();
+        let _ = self.conn.clone().lock().unwrap().insert(Connection::Ports(conns));
+        self.name = Some(names.join(" + "));
+        Ok(())
+    }
+
+    /// Names of the connected ports,
+    /// which chords can be sent to alone.
+    pub fn port_names(&self) -> Vec<String> {
+        match &*self.conn.lock().unwrap() {
+            Some(Connection::Ports(conns)) => conns.iter().map(|(name, _)| name.clone()).collect(),
+            _ => vec![],
+        }
+    }
+
+    /// Play notes through the built-in
+    /// audio preview instead of a MIDI port.
+    pub fn connect_audio(&mut self) -> Result<(), MIDIError> {
+        #[cfg(feature = "audio")]
+        {
+            let audio = AudioOutput::new(self.patch, self.instruments.clone(), self.mixer.clone())?;
+            self.silence();
+            let _ = self.conn.lock().unwrap().insert(Connection::Audio(audio));
+            self.name = Some("Audio preview".to_string());
             Ok(())
         }
+
+        #[cfg(not(feature = "audio"))]
+        Err(MIDIError::NoAudio)
+    }
+
+    /// Change how the audio preview sounds.
+    pub fn set_patch(&mut self, patch: Patch) {
+        self.patch = patch;
+
+        #[cfg(feature = "audio")]
+        if let Some(Connection::Audio(audio)) = &*self.conn.lock().unwrap() {
+            audio.set_patch(patch);
+        }
     }
 
-    pub fn play_chord(&mut self, chord: &Chord, duration: u64) {
+    /// Play the audio preview with a soundfont,
+    /// or only the synth if `None`.
+    pub fn set_instruments(&mut self, instruments: Option<Instruments>) {
+        #[cfg(feature = "audio")]
+        if let Some(Connection::Audio(audio)) = &*self.conn.lock().unwrap() {
+            audio.set_instruments(instruments.clone());
+        }
+
+        self.instruments = instruments;
+    }
+
+    /// Change the levels of the audio preview's sources.
+    pub fn set_mixer(&mut self, mixer: Mixer) {
+        #[cfg(feature = "audio")]
+        if let Some(Connection::Audio(audio)) = &*self.conn.lock().unwrap() {
+            audio.set_mixer(mixer.clone());
+        }
+
+        self.mixer = mixer;
+    }
+
+    /// Play a metronome click. The audio preview
+    /// has its own click; on a MIDI port a note
+    /// is played on the given channel, 0-15.
+    pub fn click(&self, beat: Beat, channel: u8) {
+        let (note, velocity) = match beat {
+            Beat::Accent => (CLICK_NOTES.0, 127),
+            Beat::Beat => (CLICK_NOTES.1, VELOCITY),
+            Beat::Subdivision => (CLICK_NOTES.1, VELOCITY / 2),
+        };
+        let channel = channel & 0x0F;
+        let (note_off, release) = self.note_off_bytes(channel);
+        let conn = self.conn.clone();
+        match &mut *conn.lock().unwrap() {
+            #[cfg(feature = "audio")]
+            Some(Connection::Audio(audio)) => {
+                audio.click(beat);
+                return;
+            }
+            Some(conn) => conn.send(&[NOTE_ON_MSG | channel, note, velocity]),
+            None => return,
+        }
+        thread::spawn(move || {
+            sleep(Duration::from_millis(CLICK_LENGTH));
+            if let Some(ref mut conn) = *conn.lock().unwrap() {
+                conn.send(&[note_off, note, release]);
+            }
+        });
+    }
+
+    /// Send a clock event on to the MIDI ports,
+    /// if sending the clock is on.
+    pub fn clock(&self, event: &ClockEvent) {
+        if !self.send_clock {
+            return;
+        }
+        let msg = match event {
+            ClockEvent::Tick(_) => CLOCK_MSG,
+            ClockEvent::Start => START_MSG,
+            ClockEvent::Stop => STOP_MSG,
+        };
+        if let Some(conn @ Connection::Ports(_)) = &mut *self.conn.lock().unwrap() {
+            conn.send(&[msg]);
+        }
+    }
+
+    /// Note on and note off status bytes for a channel,
+    /// or the output's channel if none is given,
+    /// and the note off velocity.
+    fn status_bytes(&self, channel: Option<u8>) -> (u8, u8, u8) {
+        let channel = channel.unwrap_or(self.channel) & 0x0F;
+        let (note_off, release) = self.note_off_bytes(channel);
+        (NOTE_ON_MSG | channel, note_off, release)
+    }
+
+    /// The status byte and velocity
+    /// to end notes on a channel with.
+    fn note_off_bytes(&self, channel: u8) -> (u8, u8) {
+        match self.note_off {
+            NoteOff::Message => (NOTE_OFF_MSG | channel, self.release_velocity & 0x7F),
+            NoteOff::ZeroVelocity => (NOTE_ON_MSG | channel, 0),
+        }
+    }
+
+    /// Play a chord on a channel, 0-15, or the output's
+    /// channel if none is given, and on one of the
+    /// connected ports, by name, or all of them.
+    pub fn play_chord(&mut self, chord: &Chord, duration: u64, channel: Option<u8>, port: Option<&str>) {
+        self.play_chord_later(chord, Duration::ZERO, Duration::from_millis(duration * 150), 1., channel, port);
+    }
+
+    /// Play a chord for an exact length of time.
+    pub fn play_chord_for(&mut self, chord: &Chord, length: Duration) {
+        self.play_chord_later(chord, Duration::ZERO, length, 1., None, None);
+    }
+
+    /// Play a chord after a delay, for an exact length of time,
+    /// at a level from 0 to 1 of the usual velocity,
+    /// on a channel and port or the output's.
+    pub fn play_chord_later(&mut self, chord: &Chord, delay: Duration, length: Duration, level: f64, channel: Option<u8>, port: Option<&str>) {
         // MIDI note values map A0 to 21.
         // We set A0 to 0 semitones; this our starting point is 0 semitones = MIDI note 21.
         let notes: Vec<u8> = chord.notes().iter().map(|note| (note.semitones + 21) as u8).collect();
-        self.play_notes(notes, duration);
+        let velocity = (VELOCITY as f64 * level.clamp(0., 1.)).round().max(1.) as u8;
+        self.play_notes_for(notes, delay, self.with_overlap(length), velocity, channel, port);
     }
 
-    pub fn play_notes(&mut self, notes: Vec<u8>, duration: u64) {
-        let conn = self.conn.clone();
-
-        // When we play a set of notes, we need to track
-        // which thread has the right to stop those notes
-        // (i.e. send the notes off message).
-        // This is to avoid the following scenario:
-        // - t=0.0: Thread A plays CEG for 1 second
-        // - t=0.5: Thread B plays CEG again
-        // - t=1.0: Thread A stops CEG, prematurely ending thread B's CEG by 0.5 seconds
-        // Here we assign a number to each thread that plays a given note.
-        // Then before that thread stops its notes, it checks to see if it
-        // is the owner (has the highest number) of those notes.
-        // For simplicity just saying one note (C) but this applies for multiple notes too.
-        // - t=0.0: Thread A plays C for 1 second and is assigned #1.
-        // - t=0.5: Thread B plays C again and is assigned #2
-        // - t=1.0: Thread A wants to stop C, so it compares its number (#1)
-        //  against C's current number (#2). Because #1 < #2, thread A doesn't stop C.
-        // - t=1.5: Thread B wants to stop C, so it compares its number (#2)
-        //  against C's current number (#2). Because #2 = #2, thread A can stop C.
-        let mut my_notes: HashMap<u8, usize> = HashMap::default();
-        for note in &notes {
-            let mut note_owners = self.note_owners.lock().unwrap();
-            let n = note_owners.entry(*note).or_insert(0);
-            *n += 1;
-            my_notes.insert(*note, *n);
+    /// A chord's length with the overlap added,
+    /// always leaving at least a millisecond.
+    fn with_overlap(&self, length: Duration) -> Duration {
+        let overlap = Duration::from_millis(self.overlap.unsigned_abs() as u64);
+        if self.overlap < 0 {
+            length.saturating_sub(overlap).max(Duration::from_millis(1))
+        } else {
+            length + overlap
         }
-        let note_owners = self.note_owners.clone();
+    }
+
+    pub fn play_notes(&mut self, notes: Vec<u8>, duration: u64, channel: Option<u8>) {
+        self.play_notes_for(notes, Duration::ZERO, Duration::from_millis(duration * 150), VELOCITY, channel, None);
+    }
 
+    fn play_notes_for(&mut self, notes: Vec<u8>, delay: Duration, length: Duration, velocity: u8, channel: Option<u8>, port: Option<&str>) {
+        let voices = self.voices();
+        let (note_on, note_off, release) = self.status_bytes(channel);
+        let port = port.map(|port| port.to_string());
         let _handler = thread::spawn(move || {
-            {
-                let mut conn = conn.lock().unwrap();
-                if let Some(ref mut conn) = *conn {
-                    for note in &notes {
-                        let _ = conn.send(&[NOTE_ON_MSG, *note, VELOCITY]);
-                    }
-                }
-            }
-            sleep(Duration::from_millis(duration * 150));
-            {
-                let mut conn = conn.lock().unwrap();
-                if let Some(ref mut conn) = *conn {
-                    let owners = note_owners.lock().unwrap();
-                    for note in &notes {
-                        let my_number = my_notes.get(note).unwrap();
-                        if my_number >= owners.get(note).unwrap() {
-                            let _ = conn.send(&[NOTE_OFF_MSG, *note, VELOCITY]);
-                        }
-                    }
-                }
-            }
+            sleep(delay);
+            let held = voices.start(notes, note_on, velocity, port);
+            sleep(length);
+            voices.end(&held, note_off, release);
         });
     }
 
+    fn voices(&self) -> Voices {
+        Voices {
+            conn: self.conn.clone(),
+            note_owners: self.note_owners.clone(),
+            next_owner: self.next_owner.clone(),
+        }
+    }
+
+    /// Start a chord on a channel, 0-15, or the output's
+    /// channel, and on one of the connected ports, by name,
+    /// or all of them, held until it's released rather
+    /// than for a length of time.
+    pub fn hold_chord(&mut self, chord: &Chord, channel: Option<u8>, port: Option<&str>) -> HeldChord {
+        let notes: Vec<u8> = chord.notes().iter().map(|note| (note.semitones + 21) as u8).collect();
+        let (note_on, _, _) = self.status_bytes(channel);
+        self.voices().start(notes, note_on, VELOCITY, port.map(|port| port.to_string()))
+    }
+
+    /// End a held chord, except any of its
+    /// notes that were played again since.
+    pub fn release(&mut self, held: HeldChord) {
+        let (note_off, release) = self.note_off_bytes(held.channel);
+        self.voices().end(&held, note_off, release);
+    }
+
     pub fn play_note(&mut self, note: u8, duration: u64) {
         let conn = self.conn.clone();
+        let (note_on, note_off, release) = self.status_bytes(None);
         let _handler = thread::spawn(move || {
             {
                 let mut conn = conn.lock().unwrap();
                 if let Some(ref mut conn) = *conn {
-                    let _ = conn.send(&[NOTE_ON_MSG, note, VELOCITY]);
+                    conn.send(&[note_on, note, VELOCITY]);
                 }
             }
             sleep(Duration::from_millis(duration * 150));
             {
                 let mut conn = conn.lock().unwrap();
                 if let Some(ref mut conn) = *conn {
-                    let _ = conn.send(&[NOTE_OFF_MSG, note, VELOCITY]);
+                    conn.send(&[note_off, note, release]);
                 }
             }
         });
     }
 
-    pub fn close(&mut self) -> Result<()> {
+    /// Stop everything playing on the current connection,
+    /// so nothing's left hanging when it's switched or closed.
+    /// Besides ending our notes, every channel is sent
+    /// All Sound Off and Reset All Controllers.
+    fn silence(&self) {
         let conn = self.conn.clone();
         let mut conn = conn.lock().unwrap();
 
+        let mut note_owners = self.note_owners.lock().unwrap();
         if let Some(ref mut conn) = *conn {
-            let note_owners = self.note_owners.lock().unwrap();
-            for note in note_owners.keys() {
-                let _ = conn.send(&[NOTE_OFF_MSG, *note, VELOCITY]);
+            for (port, channel, note) in note_owners.keys() {
+                let (note_off, release) = self.note_off_bytes(*channel);
+                conn.send_to(&[note_off, *note, release], port.as_deref());
+            }
+            for channel in 0..16 {
+                conn.send(&[CONTROL_MSG | channel, ALL_SOUND_OFF, 0]);
+                conn.send(&[CONTROL_MSG | channel, RESET_CONTROLLERS, 0]);
             }
         }
+        note_owners.clear();
+    }
+
+    pub fn close(&mut self) -> Result<()> {
+        self.silence();
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_release_owners() {
+        let voices = Voices {
+            conn: Arc::new(Mutex::new(None)),
+            note_owners: Arc::new(Mutex::new(HashMap::default())),
+            next_owner: Arc::new(AtomicUsize::new(0)),
+        };
+        let held = voices.start(vec![60, 64, 67], NOTE_ON_MSG, VELOCITY, None);
+
+        // Notes played again since stay owned by those
+        let replayed = voices.start(vec![67], NOTE_ON_MSG, VELOCITY, None);
+        voices.end(&held, NOTE_OFF_MSG, 0);
+        assert_eq!(voices.note_owners.lock().unwrap().len(), 1);
+
+        voices.end(&replayed, NOTE_OFF_MSG, 0);
+        assert!(voices.note_owners.lock().unwrap().is_empty());
+    }
+}
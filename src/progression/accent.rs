@@ -0,0 +1,74 @@
+use std::{fmt, str::FromStr};
+use thiserror::Error;
+
+// Named patterns, as levels across a bar of 4/4
+const PATTERNS: [(&str, &[usize]); 4] = [
+    ("none", &[]),
+
+    // The downbeat
+    ("1", &[100, 75, 75, 75]),
+
+    // Beats 1 and 3
+    ("1+3", &[100, 75, 100, 75]),
+
+    // Strong, weak, medium, weak
+    ("4/4", &[100, 70, 85, 70]),
+];
+
+/// How loud chords are played across a bar, as a curve of
+/// percentages of their velocity spread evenly over it,
+/// e.g. `100,75,100,75` accents beats 1 and 3.
+/// With no levels every step is played as it is.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Accent {
+    levels: Vec<usize>,
+}
+
+impl Accent {
+    /// The level, from 0 to 1, at a step
+    /// of a bar with so many steps.
+    pub fn level(&self, step: usize, steps_per_bar: usize) -> f64 {
+        if self.levels.is_empty() {
+            return 1.;
+        }
+        let idx = (step % steps_per_bar) * self.levels.len() / steps_per_bar;
+        self.levels[idx] as f64 / 100.
+    }
+}
+
+impl fmt::Display for Accent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match PATTERNS.iter().find(|(_, levels)| *levels == self.levels) {
+            Some((name, _)) => write!(f, "{}", name),
+            None => {
+                let levels: Vec<String> = self.levels.iter().map(|l| l.to_string()).collect();
+                write!(f, "{}", levels.join(","))
+            }
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum AccentParseError {
+    #[error("Invalid accent `{0}`, expected `none`, `1`, `1+3`, `4/4` or levels from 1-100% across the bar, e.g. `100,60,80,60`")]
+    InvalidAccent(String),
+}
+
+/// Parses a named pattern, e.g. "1+3", or
+/// comma-separated levels, e.g. "100,60,80,60".
+impl FromStr for Accent {
+    type Err = AccentParseError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some((_, levels)) = PATTERNS.iter().find(|(name, _)| *name == s) {
+            return Ok(Accent { levels: levels.to_vec() });
+        }
+        let invalid = || AccentParseError::InvalidAccent(s.to_string());
+        let levels = s.split(',')
+            .map(|level| match level.trim().trim_end_matches('%').parse::<usize>() {
+                Ok(level) if (1..=100).contains(&level) => Ok(level),
+                _ => Err(invalid()),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Accent { levels })
+    }
+}
@@ -0,0 +1,146 @@
+use std::fmt;
+use serde::{Serialize, Deserialize};
+use crate::core::{Key, Chord, Note};
+use super::Progression;
+
+// Lowest note basslines play, E1,
+// as notes count up from A0
+const LOWEST: isize = 7;
+
+/// How a bassline follows the chords.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum BassPattern {
+    // Each chord's root, held until the next chord
+    Root,
+
+    // The root and fifth, alternating every two beats
+    RootFifth,
+
+    // A note a beat, up through the chord's tones
+    // and into the next chord's root by a half step
+    Walking,
+}
+
+impl BassPattern {
+    pub fn next(&self) -> BassPattern {
+        match self {
+            BassPattern::Root => BassPattern::RootFifth,
+            BassPattern::RootFifth => BassPattern::Walking,
+            BassPattern::Walking => BassPattern::Root,
+        }
+    }
+}
+
+impl fmt::Display for BassPattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BassPattern::Root => write!(f, "root"),
+            BassPattern::RootFifth => write!(f, "root-fifth"),
+            BassPattern::Walking => write!(f, "walking"),
+        }
+    }
+}
+
+/// A note of a bassline, starting on a step
+/// of the progression and held for some steps.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BassNote {
+    pub step: usize,
+    pub note: Note,
+    pub length: usize,
+}
+
+/// The note's pitch in the bass register, from E1 up to D#2.
+fn in_bass(note: Note) -> Note {
+    Note { semitones: LOWEST + (note.semitones - LOWEST).rem_euclid(12) }
+}
+
+/// The chord's tones as semitones above its root,
+/// within an octave and from the root up.
+fn tones(chord: &Chord) -> Vec<isize> {
+    let root = chord.root().semitones;
+    let mut tones: Vec<isize> = chord.notes().iter()
+        .map(|n| (n.semitones - root).rem_euclid(12)).collect();
+    tones.sort_unstable();
+    tones.dedup();
+    tones
+}
+
+/// A bassline for the progression's chords in a key,
+/// each chord's part lasting until the next chord,
+/// with the last leading back into the first.
+/// Steps before the first chord are rests.
+pub fn bassline(progression: &Progression, key: &Key, pattern: BassPattern) -> Vec<BassNote> {
+    let len = progression.sequence.len();
+    let steps_per_beat = progression.resolution.ticks_per_beat();
+    let chords: Vec<(usize, Chord)> = progression.iter_timed()
+        .map(|(i, cs)| (i, cs.chord_for_key(&progression.key_at(i, key))))
+        .collect();
+
+    let mut notes: Vec<(usize, Note)> = vec![];
+    for (n, (start, chord)) in chords.iter().enumerate() {
+        let end = chords.get(n + 1).map_or(len, |(i, _)| *i);
+        let root = in_bass(chord.root());
+        let above = |semitones: isize| Note { semitones: root.semitones + semitones };
+        match pattern {
+            BassPattern::Root => notes.push((*start, root)),
+            BassPattern::RootFifth => {
+                for (k, step) in (*start..end).step_by(steps_per_beat * 2).enumerate() {
+                    notes.push((step, if k % 2 == 0 { root } else { above(7) }));
+                }
+            }
+            BassPattern::Walking => {
+                let tones = tones(chord);
+                let next = in_bass(chords[(n + 1) % chords.len()].1.root());
+                let beats: Vec<usize> = (*start..end).step_by(steps_per_beat).collect();
+                for (k, step) in beats.iter().enumerate() {
+                    let note = if k > 0 && k == beats.len() - 1 {
+                        // From below, unless that's out of range
+                        let approach = if next.semitones > LOWEST { -1 } else { 1 };
+                        Note { semitones: next.semitones + approach }
+                    } else {
+                        above(tones[k % tones.len()])
+                    };
+                    notes.push((*step, note));
+                }
+            }
+        }
+    }
+
+    // Each note is held until the next
+    notes.iter().enumerate().map(|(n, (step, note))| BassNote {
+        step: *step,
+        note: *note,
+        length: notes.get(n + 1).map_or(len, |(next, _)| *next) - step,
+    }).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::core::{ChordSpec, Duration};
+
+    #[test]
+    fn test_bassline() {
+        let key: Key = "C4".parse().unwrap();
+        let chords: Vec<ChordSpec> = ["I", "V:7"].iter()
+            .map(|c| c.parse().unwrap()).collect();
+        let progression = Progression::from_chords(chords, 2, Duration::Quarter);
+        let notes = |pattern: BassPattern| -> Vec<(usize, isize, usize)> {
+            bassline(&progression, &key, pattern).iter()
+                .map(|n| (n.step, n.note.semitones, n.length))
+                .collect()
+        };
+
+        // C2 for a bar, then G1
+        assert_eq!(notes(BassPattern::Root), vec![(0, 15, 4), (4, 10, 4)]);
+        assert_eq!(notes(BassPattern::RootFifth), vec![
+            (0, 15, 2), (2, 22, 2), (4, 10, 2), (6, 17, 2)]);
+
+        // Up C-E-G then F# into G, up G-B-D then B back into C
+        assert_eq!(notes(BassPattern::Walking), vec![
+            (0, 15, 1), (1, 19, 1), (2, 22, 1), (3, 9, 1),
+            (4, 10, 1), (5, 14, 1), (6, 17, 1), (7, 14, 1)]);
+    }
+}
@@ -0,0 +1,82 @@
+use std::{fmt, str::FromStr};
+use thiserror::Error;
+use crate::core::{Mode, ChordSpec};
+
+/// A pair of chords to end generated progressions with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Cadence {
+    // V to I
+    Authentic,
+
+    // IV to I
+    Plagal,
+
+    // IV to V
+    Half,
+
+    // V to vi
+    Deceptive,
+}
+
+impl Cadence {
+    /// The cadence after this one, cycling
+    /// back around to no cadence.
+    pub fn cycle(cadence: Option<Cadence>) -> Option<Cadence> {
+        match cadence {
+            None => Some(Cadence::Authentic),
+            Some(Cadence::Authentic) => Some(Cadence::Plagal),
+            Some(Cadence::Plagal) => Some(Cadence::Half),
+            Some(Cadence::Half) => Some(Cadence::Deceptive),
+            Some(Cadence::Deceptive) => None,
+        }
+    }
+
+    /// The cadence's chords in the given mode,
+    /// going by whether it's major or minor.
+    pub fn chords(&self, mode: &Mode) -> Vec<ChordSpec> {
+        let chords = match (self, mode.quality()) {
+            (Cadence::Authentic, Mode::Major) => ["V", "I"],
+            (Cadence::Authentic, _) => ["V", "i"],
+            (Cadence::Plagal, Mode::Major) => ["IV", "I"],
+            (Cadence::Plagal, _) => ["iv", "i"],
+            (Cadence::Half, Mode::Major) => ["IV", "V"],
+            (Cadence::Half, _) => ["iv", "V"],
+            (Cadence::Deceptive, Mode::Major) => ["V", "vi"],
+            (Cadence::Deceptive, _) => ["V", "VI"],
+        };
+        chords.iter()
+            .map(|c| (*c).try_into().expect("cadence chords are valid"))
+            .collect()
+    }
+}
+
+impl fmt::Display for Cadence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Cadence::Authentic => "authentic",
+            Cadence::Plagal => "plagal",
+            Cadence::Half => "half",
+            Cadence::Deceptive => "deceptive",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum CadenceParseError {
+    #[error("Invalid cadence `{0}`, expected `authentic`, `plagal`, `half` or `deceptive`")]
+    InvalidCadence(String),
+}
+
+impl FromStr for Cadence {
+    type Err = CadenceParseError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "authentic" => Ok(Cadence::Authentic),
+            "plagal" => Ok(Cadence::Plagal),
+            "half" => Ok(Cadence::Half),
+            "deceptive" => Ok(Cadence::Deceptive),
+            _ => Err(CadenceParseError::InvalidCadence(s.to_string()))
+        }
+    }
+}
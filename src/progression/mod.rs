@@ -1,9 +1,26 @@
+//! Chord progressions and generating
+//! them from pattern templates.
+
 mod template;
+mod step;
+mod cadence;
+mod accent;
+mod bass;
+
+use std::collections::BTreeMap;
+use rand::{Rng, seq::SliceRandom};
+pub use template::{ProgressionTemplate, ModeTemplate, ChordTypes};
+pub use step::{Step, Gate, GateParseError, Hit, MAX_NUDGE};
+pub use cadence::{Cadence, CadenceParseError};
+pub use accent::{Accent, AccentParseError};
+pub use bass::{BassPattern, BassNote, bassline};
+use crate::core::{Key, ChordSpec, Chord, Duration, voice_lead, voice_lead_circular};
+use crate::file::{TimedChord, ExportOptions, VELOCITY};
 
-pub use template::{ProgressionTemplate, ModeTemplate};
-use crate::core::{Key, ChordSpec, Chord, Duration, voice_lead};
+/// Most swing there can be, as a percentage.
+pub const MAX_SWING: usize = 75;
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Progression {
     pub resolution: Duration,
 
@@ -18,6 +35,10 @@ pub struct Progression {
     // so self.sequence[self.chord_index][0].unwrap()
     // will return the chord itself.
     pub chord_index: Vec<usize>,
+
+    // Settings of steps that have any,
+    // by their position in the sequence
+    pub steps: BTreeMap<usize, Step>,
 }
 
 impl Progression {
@@ -26,7 +47,170 @@ impl Progression {
             resolution,
             chord_index: index_chords(&sequence),
             sequence,
+            steps: BTreeMap::default(),
+        }
+    }
+
+    /// Spread chords evenly across the given bars,
+    /// adding bars if there are too many chords to fit.
+    pub fn from_chords(chords: Vec<ChordSpec>, bars: usize, resolution: Duration) -> Progression {
+        let ticks_per_bar = resolution.ticks_per_bar();
+        let bars = bars.max((chords.len() + ticks_per_bar - 1) / ticks_per_bar).max(1);
+        let total = bars * ticks_per_bar;
+        let n_chords = chords.len();
+        let mut sequence = vec![None; total];
+        for (i, chord) in chords.into_iter().enumerate() {
+            sequence[i * total / n_chords] = Some(chord);
+        }
+        Progression::new(sequence, resolution)
+    }
+
+    /// The chords of the progression,
+    /// with the steps they're on.
+    pub fn iter_timed(&self) -> TimedChords<'_> {
+        TimedChords {
+            steps: self.sequence.iter().enumerate(),
+        }
+    }
+
+    /// The chords of the progression,
+    /// with the beats they're on.
+    pub fn iter_beats(&self) -> impl Iterator<Item=(f64, &ChordSpec)> {
+        let ticks_per_beat = self.resolution.ticks_per_beat() as f64;
+        self.iter_timed().map(move |(i, cs)| (i as f64 / ticks_per_beat, cs))
+    }
+
+    /// The progression at another resolution, with each chord
+    /// (and its step settings) moved to the nearest step.
+    /// If chords land on the same step, the one closest to it
+    /// is kept, or the earlier one if they're as close.
+    pub fn with_resolution(&self, resolution: Duration) -> Progression {
+        let (old, new) = (self.resolution.ticks_per_bar(), resolution.ticks_per_bar());
+        let mut sequence = vec![None; self.bars() * new];
+        let mut steps = BTreeMap::default();
+
+        // How far each placed chord is from
+        // its exact position, in 1/2*old steps
+        let mut offsets: BTreeMap<usize, usize> = BTreeMap::default();
+        for (i, cs) in self.iter_timed() {
+            // Round to the nearest step, halves down
+            let exact = i * new * 2;
+            let idx = ((exact + old - 1) / (old * 2)).min(sequence.len() - 1);
+            let offset = exact.abs_diff(idx * old * 2);
+            if offsets.get(&idx).map_or(true, |o| offset < *o) {
+                offsets.insert(idx, offset);
+                sequence[idx] = Some(cs.clone());
+                match self.steps.get(&i) {
+                    Some(step) => steps.insert(idx, step.clone()),
+                    None => steps.remove(&idx),
+                };
+            }
+        }
+        let mut prog = Progression::new(sequence, resolution);
+        prog.steps = steps;
+        prog
+    }
+
+    /// The progression with bars added or removed
+    /// at the end, keeping the chords that are left.
+    pub fn with_bars(&self, bars: usize) -> Progression {
+        let len = bars * self.resolution.ticks_per_bar();
+        let mut prog = self.clone();
+        prog.sequence.resize(len, None);
+        prog.steps.retain(|idx, _| *idx < len);
+        prog.update_chords();
+        prog
+    }
+
+    /// Copy a bar's chords and step settings over
+    /// the next bar, adding a bar if it's the last.
+    pub fn duplicate_bar(&mut self, bar: usize) {
+        if bar >= self.bars() {
+            return;
         }
+        if bar + 1 == self.bars() {
+            *self = self.with_bars(bar + 2);
+        }
+        let ticks_per_bar = self.resolution.ticks_per_bar();
+        for i in bar * ticks_per_bar..(bar + 1) * ticks_per_bar {
+            let j = i + ticks_per_bar;
+            self.sequence[j] = self.sequence[i].clone();
+            self.set_step(j, self.step(i));
+        }
+        self.update_chords();
+    }
+
+    /// Copy a step's chord and settings over another step.
+    pub fn duplicate_step(&mut self, from: usize, to: usize) {
+        if from >= self.sequence.len() || to >= self.sequence.len() {
+            return;
+        }
+        self.sequence[to] = self.sequence[from].clone();
+        self.set_step(to, self.step(from));
+        self.update_chords();
+    }
+
+    /// Insert an empty bar before the given bar,
+    /// moving the steps after it along.
+    pub fn insert_bar(&mut self, bar: usize) {
+        let ticks_per_bar = self.resolution.ticks_per_bar();
+        let at = (bar * ticks_per_bar).min(self.sequence.len());
+        self.sequence.splice(at..at, vec![None; ticks_per_bar]);
+        self.steps = std::mem::take(&mut self.steps).into_iter()
+            .map(|(idx, step)| if idx >= at { (idx + ticks_per_bar, step) } else { (idx, step) })
+            .collect();
+        self.update_chords();
+    }
+
+    /// Remove a bar, moving the steps after it back.
+    /// The last bar can't be removed.
+    pub fn delete_bar(&mut self, bar: usize) {
+        if bar >= self.bars() || self.bars() == 1 {
+            return;
+        }
+        let ticks_per_bar = self.resolution.ticks_per_bar();
+        let (start, end) = (bar * ticks_per_bar, (bar + 1) * ticks_per_bar);
+        self.sequence.drain(start..end);
+        self.steps = std::mem::take(&mut self.steps).into_iter()
+            .filter(|(idx, _)| !(start..end).contains(idx))
+            .map(|(idx, step)| if idx >= end { (idx - ticks_per_bar, step) } else { (idx, step) })
+            .collect();
+        self.update_chords();
+    }
+
+    /// The progression at twice the length,
+    /// with each chord held twice as long.
+    pub fn stretch(&self) -> Progression {
+        let mut sequence = vec![None; self.sequence.len() * 2];
+        for (i, cs) in self.iter_timed() {
+            sequence[i * 2] = Some(cs.clone());
+        }
+        let mut prog = Progression::new(sequence, self.resolution);
+        prog.steps = self.steps.iter()
+            .map(|(idx, step)| (idx * 2, step.clone()))
+            .collect();
+        prog
+    }
+
+    /// The progression at half the length, with each
+    /// chord held half as long. If two chords land
+    /// on the same step, the earlier one is kept.
+    pub fn compress(&self) -> Progression {
+        let bars = ((self.bars() + 1) / 2).max(1);
+        let mut sequence = vec![None; bars * self.resolution.ticks_per_bar()];
+        let mut steps = BTreeMap::default();
+        for (i, cs) in self.iter_timed() {
+            let idx = i / 2;
+            if sequence[idx].is_none() {
+                sequence[idx] = Some(cs.clone());
+                if let Some(step) = self.steps.get(&i) {
+                    steps.insert(idx, step.clone());
+                }
+            }
+        }
+        let mut prog = Progression::new(sequence, self.resolution);
+        prog.steps = steps;
+        prog
     }
 
     pub fn bars(&self) -> usize {
@@ -34,8 +218,264 @@ impl Progression {
     }
 
     pub fn in_key(&self, key: &Key) -> Vec<Option<Chord>> {
-        self.sequence.iter()
-            .map(|cs| cs.as_ref().and_then(|c| Some(c.chord_for_key(key))))
+        self.sequence.iter().enumerate()
+            .map(|(i, cs)| cs.as_ref().map(|c| c.chord_for_key(&self.key_at(i, key))))
+            .collect()
+    }
+
+    /// The settings of a step.
+    pub fn step(&self, seq_idx: usize) -> Step {
+        self.steps.get(&seq_idx).cloned().unwrap_or_default()
+    }
+
+    /// Change the settings of a step.
+    pub fn set_step(&mut self, seq_idx: usize, step: Step) {
+        if step.is_default() {
+            self.steps.remove(&seq_idx);
+        } else {
+            self.steps.insert(seq_idx, step);
+        }
+    }
+
+    /// The key a step is played in: its own,
+    /// if it has one, or the given key.
+    pub fn key_at(&self, seq_idx: usize, key: &Key) -> Key {
+        self.steps.get(&seq_idx).and_then(|s| s.key).unwrap_or(*key)
+    }
+
+    /// Roll whether a step's chord plays
+    /// this time around, per its chance.
+    pub fn plays(&self, seq_idx: usize) -> bool {
+        let chance = self.step(seq_idx).chance;
+        chance >= 100 || rand::thread_rng().gen_range(0..100) < chance
+    }
+
+    /// One realization of the steps' chances,
+    /// without the chords that didn't play.
+    pub fn bake(&self) -> Progression {
+        let mut prog = self.clone();
+        for i in 0..prog.sequence.len() {
+            if prog.sequence[i].is_some() && !self.plays(i) {
+                prog.sequence[i] = None;
+                prog.steps.remove(&i);
+            } else {
+                let mut step = prog.step(i);
+                step.chance = 100;
+                prog.set_step(i, step);
+            }
+        }
+        prog.update_chords();
+        prog
+    }
+
+    /// How many steps the chord at a step is held for,
+    /// looping within the given range of the sequence.
+    pub fn gate_steps(&self, seq_idx: usize, range: (usize, usize)) -> f64 {
+        match self.step(seq_idx).gate {
+            Gate::Percent(percent) => percent as f64 / 100.,
+            Gate::Steps(steps) => steps as f64,
+            Gate::Legato => {
+                let len = range.1.saturating_sub(range.0).max(1);
+                let offset = seq_idx.saturating_sub(range.0);
+                (1..len).find(|d| {
+                    let idx = range.0 + (offset + d) % len;
+                    matches!(self.sequence.get(idx), Some(Some(_)))
+                }).unwrap_or(len) as f64
+            }
+        }
+    }
+
+    /// When a step's chord is triggered, per its
+    /// ratchets, looping within the given range of the sequence.
+    pub fn hits(&self, seq_idx: usize, range: (usize, usize)) -> Vec<Hit> {
+        self.step(seq_idx).hits(self.gate_steps(seq_idx, range))
+    }
+
+    /// The chords of one pass through the progression,
+    /// timed in ticks of the given length per step,
+    /// with off-beat steps swung by a percentage
+    /// and accented by where they are in the bar.
+    /// Arpeggiated steps have a chord for each note.
+    pub fn timed_chords(&self, key: &Key, step_ticks: usize, swing: usize, accent: &Accent, options: ExportOptions) -> Vec<TimedChord> {
+        let range = (0, self.sequence.len());
+        let ticks = |steps: f64| (steps * step_ticks as f64).round() as usize;
+        let steps_per_bar = self.resolution.ticks_per_bar();
+        let beat_ticks = step_ticks * self.resolution.ticks_per_beat();
+        self.iter_timed()
+            .flat_map(|(i, cs)| {
+                let key = self.key_at(i, key);
+                let chord = cs.chord_for_key(&key);
+                let marker = options.markers.marker(cs, &key, options.accidentals);
+                let accent = accent.level(i, steps_per_bar);
+                let arp = self.step(i).arp;
+
+                // Only the first hit and note are marked
+                self.hits(i, range).into_iter().enumerate().flat_map(move |(h, hit)| {
+                    let timed = TimedChord {
+                        chord: chord.clone(),
+                        start: i * step_ticks + ticks(hit.offset + swing_delay(i, swing)),
+                        length: ticks(hit.length).max(1),
+                        velocity: (VELOCITY as f64 * hit.level * accent).round().max(1.) as u8,
+                        channel: None,
+                        marker: if h == 0 { marker.clone() } else { None },
+                    };
+                    match arp {
+                        None => vec![timed],
+                        Some(arp) => {
+                            let notes = timed.chord.notes();
+                            let division = (beat_ticks / arp.per_beat()).max(1);
+                            let divisions = timed.length.div_ceil(division);
+                            arp.order(notes.len(), divisions).into_iter().enumerate()
+                                .map(|(k, idx)| TimedChord {
+                                    chord: Chord::new(notes[idx], vec![0]),
+                                    start: timed.start + k * division,
+                                    length: division.min(timed.length - k * division),
+                                    marker: if k == 0 { timed.marker.clone() } else { None },
+                                    ..timed.clone()
+                                })
+                                .collect()
+                        }
+                    }
+                })
+            })
+            .collect()
+    }
+
+    /// Rearrange the chords in a range of the sequence into
+    /// evenly spread hits, per the Euclidean algorithm, either
+    /// so many per bar or across the whole range. The chords
+    /// keep their order, repeating if there are more hits
+    /// than chords; their step settings are cleared.
+    pub fn place_euclidean(&mut self, hits: usize, range: (usize, usize), per_bar: bool) {
+        let (start, end) = (range.0, range.1.min(self.sequence.len()));
+        let chords: Vec<ChordSpec> = self.sequence[start..end].iter().flatten().cloned().collect();
+        if chords.is_empty() || start >= end {
+            return;
+        }
+        let ticks_per_bar = self.resolution.ticks_per_bar();
+        let pattern = if per_bar {
+            euclidean(hits, ticks_per_bar)
+        } else {
+            euclidean(hits, end - start)
+        };
+
+        let mut chords = chords.into_iter().cycle();
+        for idx in start..end {
+            let hit = if per_bar {
+                pattern[idx % ticks_per_bar]
+            } else {
+                pattern[idx - start]
+            };
+            self.sequence[idx] = if hit { chords.next() } else { None };
+            self.steps.remove(&idx);
+        }
+        self.update_chords();
+    }
+
+    /// Give chords in a range of the sequence a random
+    /// inversion, each with a percent chance.
+    pub fn randomize_inversions(&mut self, range: (usize, usize), amount: usize) {
+        let mut rng = rand::thread_rng();
+        for idx in self.chords_in(range) {
+            if rng.gen_range(0..100) < amount {
+                let cs = self.sequence[idx].as_ref().unwrap();
+                if let Some(inversion) = cs.inversions().choose(&mut rng) {
+                    self.sequence[idx] = Some(inversion.clone());
+                }
+            }
+        }
+    }
+
+    /// Shift chords in a range of the sequence up or
+    /// down an octave, each with a percent chance.
+    pub fn randomize_octaves(&mut self, range: (usize, usize), amount: usize) {
+        let mut rng = rand::thread_rng();
+        for idx in self.chords_in(range) {
+            if rng.gen_range(0..100) < amount {
+                let octaves = if rng.gen_bool(0.5) { 1 } else { -1 };
+                self.sequence[idx] = self.sequence[idx].take().map(|cs| cs.shift(octaves));
+            }
+        }
+    }
+
+    /// Set the velocity of chords in a range of the sequence
+    /// to a random percentage, down to 100 less the amount.
+    pub fn randomize_velocities(&mut self, range: (usize, usize), amount: usize) {
+        let mut rng = rand::thread_rng();
+        for idx in self.chords_in(range) {
+            let mut step = self.step(idx);
+            step.velocity = 100 - rng.gen_range(0..=amount.min(99));
+            self.set_step(idx, step);
+        }
+    }
+
+    /// Reverse the order of the chords in a range of the
+    /// sequence, with their step settings. Either the rhythm
+    /// stays as is, or it's reversed too, so that each chord
+    /// ends where it started, holding until the next chord.
+    pub fn reverse(&mut self, range: (usize, usize), rhythm: bool) {
+        let (start, end) = (range.0, range.1.min(self.sequence.len()));
+        let positions = self.chords_in((start, end));
+        let chords: Vec<(ChordSpec, Option<Step>)> = positions.iter()
+            .map(|idx| (self.sequence[*idx].take().unwrap(), self.steps.remove(idx)))
+            .collect();
+
+        let reversed: Vec<usize> = if rhythm {
+            // Each chord is held until the next one
+            positions.iter().skip(1).chain(std::iter::once(&end))
+                .map(|next| start + end - next)
+                .rev()
+                .collect()
+        } else {
+            positions
+        };
+        for (idx, (cs, step)) in reversed.into_iter().zip(chords.into_iter().rev()) {
+            self.sequence[idx] = Some(cs);
+            if let Some(step) = step {
+                self.steps.insert(idx, step);
+            }
+        }
+        self.update_chords();
+    }
+
+    /// Play each chord in the progression a little late and a
+    /// little louder or quieter than it was, at random, by up to
+    /// a percentage of a step and of the usual velocity.
+    /// Chords are only ever nudged late, as a step can't
+    /// start before the sequencer reaches it.
+    pub fn humanize(&mut self, amount: usize) {
+        let mut rng = rand::thread_rng();
+        let amount = amount.min(100) as isize;
+        for idx in self.chords_in((0, self.sequence.len())) {
+            let mut step = self.step(idx);
+            step.nudge = rng.gen_range(0..=amount as usize).min(MAX_NUDGE);
+            let velocity = step.velocity as isize + rng.gen_range(-amount..=amount);
+            step.velocity = velocity.clamp(1, 100) as usize;
+            self.set_step(idx, step);
+        }
+    }
+
+    /// Randomly reorder the chords in a range of the
+    /// sequence, with their step settings, keeping the rhythm.
+    pub fn shuffle(&mut self, range: (usize, usize)) {
+        let positions = self.chords_in(range);
+        let mut chords: Vec<(ChordSpec, Option<Step>)> = positions.iter()
+            .map(|idx| (self.sequence[*idx].take().unwrap(), self.steps.remove(idx)))
+            .collect();
+        chords.shuffle(&mut rand::thread_rng());
+        for (idx, (cs, step)) in positions.into_iter().zip(chords) {
+            self.sequence[idx] = Some(cs);
+            if let Some(step) = step {
+                self.steps.insert(idx, step);
+            }
+        }
+    }
+
+    /// Positions of the chords in a range of the sequence.
+    fn chords_in(&self, range: (usize, usize)) -> Vec<usize> {
+        self.iter_timed()
+            .map(|(idx, _)| idx)
+            .filter(|idx| (range.0..range.1).contains(idx))
             .collect()
     }
 
@@ -49,9 +489,7 @@ impl Progression {
     }
 
     pub fn chords(&self) -> Vec<&ChordSpec> {
-        self.chord_index.iter()
-            .filter_map(|c_idx| self.sequence[*c_idx].as_ref())
-            .collect()
+        self.iter_timed().map(|(_, cs)| cs).collect()
     }
 
     pub fn set_chord(&mut self, chord_idx: usize, chord: ChordSpec) {
@@ -68,6 +506,7 @@ impl Progression {
 
     pub fn delete_chord_at(&mut self, seq_idx: usize) {
         self.sequence[seq_idx] = None;
+        self.steps.remove(&seq_idx);
         self.update_chords();
     }
 
@@ -80,25 +519,27 @@ impl Progression {
         self.chord_index = index_chords(&self.sequence);
     }
 
+    /// How many chords come before a step.
     pub fn seq_idx_to_chord_idx(&self, seq_idx: usize) -> usize {
-        // Bleh kind of hacky
-        let mut chord_idx = 0;
-        for (i, tick) in self.sequence.iter().enumerate() {
-            if i == seq_idx {
-                break;
-            }
-            if tick.is_some() {
-                chord_idx += 1;
-            }
-        }
-        chord_idx
+        self.chord_index.partition_point(|idx| *idx < seq_idx)
     }
 
     pub fn voice_lead(&self) -> Progression {
+        self.voice_lead_with(voice_lead)
+    }
+
+    /// Voice lead the progression as a loop, so that
+    /// the last chord also leads back into the first.
+    pub fn voice_lead_circular(&self) -> Progression {
+        self.voice_lead_with(voice_lead_circular)
+    }
+
+    fn voice_lead_with(&self, voice_lead: fn(&Vec<ChordSpec>) -> Vec<ChordSpec>) -> Progression {
         let mut prog = Progression {
             resolution: self.resolution.clone(),
             chord_index: self.chord_index.clone(),
-            sequence: self.sequence.clone()
+            sequence: self.sequence.clone(),
+            steps: self.steps.clone(),
         };
         if self.chord_index.is_empty() {
             prog
@@ -113,6 +554,47 @@ impl Progression {
     }
 }
 
+impl<'a> IntoIterator for &'a Progression {
+    type Item = (usize, &'a ChordSpec);
+    type IntoIter = TimedChords<'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter_timed()
+    }
+}
+
+/// Iterates over the chords of a progression,
+/// with the steps they're on.
+pub struct TimedChords<'a> {
+    steps: std::iter::Enumerate<std::slice::Iter<'a, Option<ChordSpec>>>,
+}
+
+impl<'a> Iterator for TimedChords<'a> {
+    type Item = (usize, &'a ChordSpec);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.steps.find_map(|(i, cs)| cs.as_ref().map(|cs| (i, cs)))
+    }
+}
+
+/// Spread hits as evenly as possible over
+/// some steps, starting with a hit on the first,
+/// e.g. 3 over 8 gives `x..x..x.`.
+pub fn euclidean(hits: usize, steps: usize) -> Vec<bool> {
+    (0..steps).map(|i| hits > 0 && (i * hits) % steps < hits).collect()
+}
+
+/// How many steps late a step is played with swing,
+/// where 50% is straight and off-beat steps are pushed
+/// later as it goes up, e.g. 66% for a triplet feel.
+pub fn swing_delay(seq_idx: usize, swing: usize) -> f64 {
+    if seq_idx % 2 == 1 {
+        swing.clamp(50, MAX_SWING) as f64 / 50. - 1.
+    } else {
+        0.
+    }
+}
+
 fn index_chords(seq: &Vec<Option<ChordSpec>>) -> Vec<usize> {
     seq.iter().enumerate()
         .filter_map(|(i, cs)| cs.as_ref().and(Some(i)))
@@ -124,6 +606,396 @@ mod test {
     use super::*;
     use crate::core::Mode;
 
+    #[test]
+    fn test_from_chords() {
+        let chords: Vec<ChordSpec> = ["I", "vi", "IV", "V"].iter()
+            .map(|c| (*c).try_into().unwrap()).collect();
+        let prog = Progression::from_chords(chords.clone(), 2, Duration::Quarter);
+        assert_eq!(prog.bars(), 2);
+        assert_eq!(prog.chord_index, vec![0, 2, 4, 6]);
+
+        // Chords that just fit get a step each
+        let prog = Progression::from_chords(chords, 1, Duration::Quarter);
+        assert_eq!(prog.bars(), 1);
+        assert_eq!(prog.chord_index, vec![0, 1, 2, 3]);
+
+        // Too many chords to fit adds bars
+        let prog = Progression::from_chords(vec!["I".try_into().unwrap(); 5], 1, Duration::Quarter);
+        assert_eq!(prog.bars(), 2);
+    }
+
+    #[test]
+    fn test_iter_timed() {
+        let chords: Vec<ChordSpec> = ["I", "V"].iter()
+            .map(|c| (*c).try_into().unwrap()).collect();
+        let prog = Progression::from_chords(chords, 1, Duration::Eighth);
+        let steps: Vec<usize> = prog.iter_timed().map(|(i, _)| i).collect();
+        assert_eq!(steps, vec![0, 4]);
+        let beats: Vec<f64> = prog.iter_beats().map(|(b, _)| b).collect();
+        assert_eq!(beats, vec![0., 2.]);
+        assert_eq!((&prog).into_iter().count(), 2);
+
+        assert_eq!(prog.seq_idx_to_chord_idx(0), 0);
+        assert_eq!(prog.seq_idx_to_chord_idx(3), 1);
+        assert_eq!(prog.seq_idx_to_chord_idx(5), 2);
+    }
+
+    #[test]
+    fn test_with_resolution() {
+        let chords: Vec<ChordSpec> = ["I", "V", "IV", "vi"].iter()
+            .map(|c| (*c).try_into().unwrap()).collect();
+        let mut prog = Progression::new(vec![None; 16], Duration::Sixteenth);
+        for (i, cs) in [0, 4, 5, 14].iter().zip(chords) {
+            prog.sequence[*i] = Some(cs);
+        }
+        prog.update_chords();
+        prog.set_step(5, Step { chance: 50, ..Step::default() });
+
+        // IV rounds to V's step but is further from it,
+        // so it's dropped along with its settings
+        let eighths = prog.with_resolution(Duration::Eighth);
+        assert_eq!(eighths.sequence.len(), 8);
+        assert_eq!(eighths.chord_index, vec![0, 2, 7]);
+        let names: Vec<String> = eighths.chords().iter().map(|c| c.to_string()).collect();
+        assert_eq!(names, vec!["I", "V", "vi"]);
+        assert!(eighths.steps.is_empty());
+
+        // And back, without losing anything else
+        let sixteenths = eighths.with_resolution(Duration::Sixteenth);
+        assert_eq!(sixteenths.chord_index, vec![0, 4, 14]);
+    }
+
+    #[test]
+    fn test_duplicate_bar() {
+        let chords: Vec<ChordSpec> = ["I", "V"].iter()
+            .map(|c| (*c).try_into().unwrap()).collect();
+        let mut prog = Progression::from_chords(chords, 1, Duration::Quarter);
+        prog.set_step(2, Step { chance: 50, ..Step::default() });
+
+        // The last bar is copied to a new bar
+        prog.duplicate_bar(0);
+        assert_eq!(prog.bars(), 2);
+        assert_eq!(prog.chord_index, vec![0, 2, 4, 6]);
+        assert_eq!(prog.step(6).chance, 50);
+
+        // Otherwise it replaces the next bar
+        prog.sequence[0] = None;
+        prog.update_chords();
+        prog.duplicate_bar(0);
+        assert_eq!(prog.bars(), 2);
+        assert_eq!(prog.chord_index, vec![2, 6]);
+    }
+
+    #[test]
+    fn test_duplicate_step() {
+        let chords: Vec<ChordSpec> = ["I", "V"].iter()
+            .map(|c| (*c).try_into().unwrap()).collect();
+        let mut prog = Progression::from_chords(chords, 1, Duration::Quarter);
+        prog.set_step(0, Step { ratchets: 2, ..Step::default() });
+
+        prog.duplicate_step(0, 1);
+        assert_eq!(prog.sequence[1], prog.sequence[0]);
+        assert_eq!(prog.step(1).ratchets, 2);
+        assert_eq!(prog.chord_index, vec![0, 1, 2]);
+
+        // Past the end does nothing
+        prog.duplicate_step(0, 4);
+        assert_eq!(prog.sequence.len(), 4);
+    }
+
+    #[test]
+    fn test_insert_delete_bar() {
+        let chords: Vec<ChordSpec> = ["I", "V"].iter()
+            .map(|c| (*c).try_into().unwrap()).collect();
+        let mut prog = Progression::from_chords(chords, 2, Duration::Quarter);
+        prog.set_step(4, Step { chance: 50, ..Step::default() });
+
+        prog.insert_bar(1);
+        assert_eq!(prog.bars(), 3);
+        assert_eq!(prog.chord_index, vec![0, 8]);
+        assert_eq!(prog.step(8).chance, 50);
+
+        prog.delete_bar(0);
+        assert_eq!(prog.chord_index, vec![4]);
+        assert_eq!(prog.step(4).chance, 50);
+        prog.delete_bar(1);
+        prog.delete_bar(0);
+        assert_eq!(prog.bars(), 1);
+        assert!(prog.chord_index.is_empty());
+    }
+
+    #[test]
+    fn test_reverse() {
+        let chords: Vec<ChordSpec> = ["I", "IV", "V"].iter()
+            .map(|c| (*c).try_into().unwrap()).collect();
+        let mut prog = Progression::new(vec![None; 8], Duration::Eighth);
+        for (i, cs) in [0, 1, 4].iter().zip(chords) {
+            prog.sequence[*i] = Some(cs);
+        }
+        prog.update_chords();
+        prog.set_step(0, Step { chance: 50, ..Step::default() });
+        let names = |prog: &Progression| -> Vec<String> {
+            prog.chords().iter().map(|c| c.to_string()).collect()
+        };
+
+        // Keeping the rhythm
+        let mut reversed = prog.clone();
+        reversed.reverse((0, 8), false);
+        assert_eq!(names(&reversed), vec!["V", "IV", "I"]);
+        assert_eq!(reversed.chord_index, vec![0, 1, 4]);
+        assert_eq!(reversed.step(4).chance, 50);
+
+        // Reversing it too
+        prog.reverse((0, 8), true);
+        assert_eq!(names(&prog), vec!["V", "IV", "I"]);
+        assert_eq!(prog.chord_index, vec![0, 4, 7]);
+        assert_eq!(prog.step(7).chance, 50);
+
+        let before = prog.clone();
+        prog.shuffle((0, 8));
+        assert_eq!(prog.chord_index, before.chord_index);
+        let mut shuffled = names(&prog);
+        shuffled.sort();
+        assert_eq!(shuffled, vec!["I", "IV", "V"]);
+    }
+
+    #[test]
+    fn test_stretch() {
+        let chords: Vec<ChordSpec> = ["I", "IV", "V"].iter()
+            .map(|c| (*c).try_into().unwrap()).collect();
+        let mut prog = Progression::new(vec![None; 8], Duration::Eighth);
+        for (i, cs) in [0, 4, 5].iter().zip(chords) {
+            prog.sequence[*i] = Some(cs);
+        }
+        prog.update_chords();
+        prog.set_step(5, Step { chance: 50, ..Step::default() });
+
+        let stretched = prog.stretch();
+        assert_eq!(stretched.bars(), 2);
+        assert_eq!(stretched.chord_index, vec![0, 8, 10]);
+        assert_eq!(stretched.step(10).chance, 50);
+        assert_eq!(stretched.compress(), prog);
+
+        // IV and V land on the same step
+        let compressed = prog.compress();
+        assert_eq!(compressed.bars(), 1);
+        assert_eq!(compressed.chord_index, vec![0, 2]);
+        assert!(compressed.steps.is_empty());
+    }
+
+    #[test]
+    fn test_step_key() {
+        let chords: Vec<ChordSpec> = ["I", "V"].iter()
+            .map(|c| (*c).try_into().unwrap()).collect();
+        let mut prog = Progression::from_chords(chords, 1, Duration::Quarter);
+        let key = Key::default();
+        prog.set_step(2, Step {
+            key: Some(Key { root: "F4".try_into().unwrap(), mode: Mode::Major }),
+            ..Step::default()
+        });
+        let chords: Vec<String> = prog.in_key(&key).into_iter().flatten()
+            .map(|c| c.to_string()).collect();
+        assert_eq!(chords, vec!["C4-E4-G4", "C5-E5-G5"]);
+
+        // Deleting the chord clears its settings
+        prog.delete_chord_at(2);
+        assert!(prog.steps.is_empty());
+    }
+
+    #[test]
+    fn test_gate() {
+        let chords: Vec<ChordSpec> = ["I", "V"].iter()
+            .map(|c| (*c).try_into().unwrap()).collect();
+        let mut prog = Progression::from_chords(chords, 1, Duration::Quarter);
+        prog.set_step(2, Step {
+            gate: Gate::Percent(25),
+            ..Step::default()
+        });
+
+        // Legato chords are held until the next one,
+        // looping around to the start
+        assert_eq!(prog.gate_steps(0, (0, 4)), 2.);
+        assert_eq!(prog.gate_steps(2, (0, 4)), 0.25);
+        prog.set_step(2, Step::default());
+        assert_eq!(prog.gate_steps(2, (0, 4)), 2.);
+        assert_eq!(prog.gate_steps(2, (1, 4)), 3.);
+
+        let lengths: Vec<usize> = prog.timed_chords(&Key::default(), 4, 50, &Accent::default(), ExportOptions::default()).iter()
+            .map(|c| c.length).collect();
+        assert_eq!(lengths, vec![8, 8]);
+        assert_eq!("50%".parse::<Gate>().unwrap(), Gate::Percent(50));
+        assert!("0".parse::<Gate>().is_err());
+
+        // Held for a number of steps, past the next chord
+        prog.set_step(0, Step { gate: Gate::Steps(3), ..Step::default() });
+        assert_eq!(prog.timed_chords(&Key::default(), 4, 50, &Accent::default(), ExportOptions::default())[0].length, 12);
+        assert_eq!("3 steps".parse::<Gate>().unwrap(), Gate::Steps(3));
+        assert_eq!(Gate::Steps(1).to_string(), "1 step");
+        assert_eq!("1step".parse::<Gate>().unwrap(), Gate::Steps(1));
+        assert!("0 steps".parse::<Gate>().is_err());
+    }
+
+    #[test]
+    fn test_bake() {
+        let chords: Vec<ChordSpec> = ["I", "IV", "V"].iter()
+            .map(|c| (*c).try_into().unwrap()).collect();
+        let mut prog = Progression::from_chords(chords, 1, Duration::Quarter);
+        prog.set_step(1, Step { chance: 0, ..Step::default() });
+        prog.set_step(2, Step { chance: 100, gate: Gate::Percent(50), ..Step::default() });
+        assert!(!prog.plays(1));
+        assert!(prog.plays(2));
+
+        let baked = prog.bake();
+        assert_eq!(baked.chord_index, vec![0, 2]);
+        assert_eq!(baked.step(2).gate, Gate::Percent(50));
+        assert!(!baked.steps.contains_key(&1));
+    }
+
+    #[test]
+    fn test_ratchets() {
+        let chords: Vec<ChordSpec> = ["I", "V"].iter()
+            .map(|c| (*c).try_into().unwrap()).collect();
+        let mut prog = Progression::from_chords(chords, 1, Duration::Quarter);
+        prog.set_step(0, Step {
+            ratchets: 4,
+            decay: true,
+            gate: Gate::Percent(50),
+            ..Step::default()
+        });
+        prog.set_step(2, Step { ratchets: 2, ..Step::default() });
+
+        let timed = prog.timed_chords(&Key::default(), 8, 50, &Accent::default(), ExportOptions::default());
+        let starts: Vec<usize> = timed.iter().map(|c| c.start).collect();
+        let lengths: Vec<usize> = timed.iter().map(|c| c.length).collect();
+        let velocities: Vec<u8> = timed.iter().map(|c| c.velocity).collect();
+        assert_eq!(starts, vec![0, 2, 4, 6, 16, 20]);
+        assert_eq!(lengths, vec![1, 1, 1, 1, 4, 12]);
+        assert_eq!(velocities, vec![64, 45, 31, 22, 64, 64]);
+    }
+
+    #[test]
+    fn test_arp() {
+        let chords: Vec<ChordSpec> = ["I", "V"].iter()
+            .map(|c| (*c).try_into().unwrap()).collect();
+        let mut prog = Progression::from_chords(chords, 1, Duration::Quarter);
+        prog.set_step(0, Step { arp: Some("up/8".parse().unwrap()), ..Step::default() });
+
+        // Two beats of I in eighths, up through its notes and around
+        let timed = prog.timed_chords(&Key::default(), 8, 50, &Accent::default(), ExportOptions::default());
+        let starts: Vec<usize> = timed.iter().map(|c| c.start).collect();
+        let lengths: Vec<usize> = timed.iter().map(|c| c.length).collect();
+        assert_eq!(starts, vec![0, 4, 8, 12, 16]);
+        assert_eq!(lengths, vec![4, 4, 4, 4, 16]);
+        let notes = prog.chord(0).unwrap().chord_for_key(&Key::default()).notes();
+        assert_eq!(timed[1].chord.notes(), vec![notes[1]]);
+        assert_eq!(timed[3].chord.notes(), vec![notes[0]]);
+        assert_eq!(timed[4].chord.notes().len(), 3);
+    }
+
+    #[test]
+    fn test_humanize() {
+        let chords: Vec<ChordSpec> = ["I", "V"].iter()
+            .map(|c| (*c).try_into().unwrap()).collect();
+        let mut prog = Progression::from_chords(chords, 1, Duration::Quarter);
+        prog.set_step(2, Step { nudge: 25, ..Step::default() });
+        let timed = prog.timed_chords(&Key::default(), 8, 50, &Accent::default(), ExportOptions::default());
+        assert_eq!(timed[1].start, 18);
+        assert_eq!(timed[1].length, 14);
+
+        // Velocities are offset from what they were
+        prog.set_step(0, Step { velocity: 50, ..Step::default() });
+        prog.humanize(10);
+        let step = prog.step(0);
+        assert!(step.nudge <= 10);
+        assert!((40..=60).contains(&step.velocity));
+        let step = prog.step(2);
+        assert!(step.nudge <= 10);
+        assert!(step.velocity >= 90);
+        assert!(prog.step(1).is_default());
+    }
+
+    #[test]
+    fn test_euclidean() {
+        let rhythm: String = euclidean(3, 8).iter()
+            .map(|hit| if *hit { 'x' } else { '.' }).collect();
+        assert_eq!(rhythm, "x..x..x.");
+        assert_eq!(euclidean(0, 4), vec![false; 4]);
+        assert_eq!(euclidean(5, 4), vec![true; 4]);
+
+        let chords: Vec<ChordSpec> = ["I", "V"].iter()
+            .map(|c| (*c).try_into().unwrap()).collect();
+        let mut prog = Progression::from_chords(chords, 2, Duration::Eighth);
+        prog.place_euclidean(3, (0, 16), true);
+        assert_eq!(prog.chord_index, vec![0, 3, 6, 8, 11, 14]);
+        let names: Vec<String> = prog.chords().iter().map(|c| c.to_string()).collect();
+        assert_eq!(names, vec!["I", "V", "I", "V", "I", "V"]);
+
+        // Across the loop, leaving the rest as is
+        prog.place_euclidean(2, (8, 16), false);
+        assert_eq!(prog.chord_index, vec![0, 3, 6, 8, 12]);
+    }
+
+    #[test]
+    fn test_swing() {
+        assert_eq!(swing_delay(0, 75), 0.);
+        assert_eq!(swing_delay(1, 50), 0.);
+        assert_eq!(swing_delay(1, 75), 0.5);
+        assert_eq!(swing_delay(3, 90), 0.5);
+
+        let chords: Vec<ChordSpec> = ["I", "V"].iter()
+            .map(|c| (*c).try_into().unwrap()).collect();
+        let mut prog = Progression::from_chords(chords, 1, Duration::Eighth);
+        prog.place_euclidean(3, (0, 8), true);
+        let starts: Vec<usize> = prog.timed_chords(&Key::default(), 12, 75, &Accent::default(), ExportOptions::default()).iter()
+            .map(|c| c.start).collect();
+        assert_eq!(starts, vec![0, 42, 72]);
+    }
+
+    #[test]
+    fn test_accent() {
+        let accent: Accent = "1+3".parse().unwrap();
+        assert_eq!(accent.to_string(), "1+3");
+        assert_eq!("100,60".parse::<Accent>().unwrap().to_string(), "100,60");
+        assert!("100,0".parse::<Accent>().is_err());
+
+        // Levels are spread across the bar
+        assert_eq!(accent.level(0, 8), 1.);
+        assert_eq!(accent.level(3, 8), 0.75);
+        assert_eq!(accent.level(12, 8), 1.);
+        assert_eq!(Accent::default().level(3, 8), 1.);
+
+        let chords: Vec<ChordSpec> = ["I", "IV", "V", "I"].iter()
+            .map(|c| (*c).try_into().unwrap()).collect();
+        let prog = Progression::from_chords(chords, 1, Duration::Quarter);
+        let velocities: Vec<u8> = prog.timed_chords(&Key::default(), 8, 50, &accent, ExportOptions::default()).iter()
+            .map(|c| c.velocity).collect();
+        assert_eq!(velocities, vec![64, 48, 64, 48]);
+    }
+
+    #[test]
+    fn test_randomize() {
+        let chords: Vec<ChordSpec> = ["I", "IV", "V"].iter()
+            .map(|c| (*c).try_into().unwrap()).collect();
+        let prog = Progression::from_chords(chords, 1, Duration::Quarter);
+
+        // Only chords in the range change
+        let mut randomized = prog.clone();
+        randomized.randomize_octaves((1, 4), 100);
+        assert_eq!(randomized.chord(0), prog.chord(0));
+        assert_ne!(randomized.chord(1), prog.chord(1));
+        assert_ne!(randomized.chord(2), prog.chord(2));
+
+        let mut randomized = prog.clone();
+        randomized.randomize_inversions((0, 4), 0);
+        assert_eq!(randomized, prog);
+
+        randomized.randomize_velocities((0, 2), 30);
+        for idx in [0, 1] {
+            assert!((70..=100).contains(&randomized.step(idx).velocity));
+        }
+        assert_eq!(randomized.step(2).velocity, 100);
+    }
+
     #[test]
     fn test_voice_leading() {
         let prog = Progression::new(
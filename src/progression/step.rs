@@ -0,0 +1,163 @@
+use std::{fmt, str::FromStr};
+use thiserror::Error;
+use crate::core::Key;
+use crate::midi::Arp;
+
+// How loud each retrigger of a decaying
+// ratchet is relative to the one before
+const RATCHET_DECAY: f64 = 0.7;
+
+/// Most a step can be played late by,
+/// as a percentage of the step.
+pub const MAX_NUDGE: usize = 50;
+
+/// Most steps a chord can be held for.
+pub const MAX_GATE_STEPS: usize = 64;
+
+/// How long a step's chord is held for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Gate {
+    // A percentage of the step
+    Percent(usize),
+
+    // A number of steps, whether or
+    // not another chord comes first
+    Steps(usize),
+
+    // Until the next chord
+    Legato,
+}
+
+impl Default for Gate {
+    fn default() -> Self {
+        Gate::Legato
+    }
+}
+
+impl fmt::Display for Gate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Gate::Percent(percent) => write!(f, "{}%", percent),
+            Gate::Steps(1) => write!(f, "1 step"),
+            Gate::Steps(steps) => write!(f, "{} steps", steps),
+            Gate::Legato => write!(f, "legato"),
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum GateParseError {
+    #[error("Invalid gate `{0}`, expected a percentage from 1 to 100, 1 to 64 steps, e.g. `2 steps`, or `legato`")]
+    InvalidGate(String),
+}
+
+/// Parses a gate as a percentage, e.g. "50" or "50%",
+/// a number of steps, e.g. "2 steps", or "legato".
+impl FromStr for Gate {
+    type Err = GateParseError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "legato" {
+            return Ok(Gate::Legato);
+        }
+        if let Some(steps) = s.strip_suffix("steps").or_else(|| s.strip_suffix("step")) {
+            return match steps.trim().parse::<usize>() {
+                Ok(steps) if (1..=MAX_GATE_STEPS).contains(&steps) => Ok(Gate::Steps(steps)),
+                _ => Err(GateParseError::InvalidGate(s.to_string()))
+            };
+        }
+        match s.trim_end_matches('%').parse::<usize>() {
+            Ok(percent) if (1..=100).contains(&percent) => Ok(Gate::Percent(percent)),
+            _ => Err(GateParseError::InvalidGate(s.to_string()))
+        }
+    }
+}
+
+/// Settings for a single step of a progression,
+/// on top of its chord.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Step {
+    // Play the step's chord in this key
+    // instead of the progression's
+    pub key: Option<Key>,
+
+    // How long the step's chord is held for
+    pub gate: Gate,
+
+    // Percent chance of the step's
+    // chord playing each time around
+    pub chance: usize,
+
+    // How many times the step's chord is
+    // triggered within the step, and whether
+    // each retrigger is quieter than the last
+    pub ratchets: usize,
+    pub decay: bool,
+
+    // Percentage of the usual velocity
+    pub velocity: usize,
+
+    // Percentage of the step to play
+    // the step's chord late by
+    pub nudge: usize,
+
+    // Play the step's chord's notes one
+    // at a time rather than all at once
+    pub arp: Option<Arp>,
+}
+
+/// A trigger of a step's chord, timed in steps
+/// from the start of the step, at a level from
+/// 0 to 1 of the usual velocity.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Hit {
+    pub offset: f64,
+    pub length: f64,
+    pub level: f64,
+}
+
+impl Default for Step {
+    fn default() -> Self {
+        Step {
+            key: None,
+            gate: Gate::default(),
+            chance: 100,
+            ratchets: 1,
+            decay: false,
+            velocity: 100,
+            nudge: 0,
+            arp: None,
+        }
+    }
+}
+
+impl Step {
+    /// Whether the step has any settings
+    /// besides the defaults.
+    pub fn is_default(&self) -> bool {
+        *self == Step::default()
+    }
+
+    /// The triggers of the step's chord, given
+    /// how many steps its gate holds it for.
+    pub fn hits(&self, gate_steps: f64) -> Vec<Hit> {
+        let n = self.ratchets.max(1);
+        let spacing = 1. / n as f64;
+        let nudge = self.nudge.min(MAX_NUDGE) as f64 / 100.;
+        (0..n).map(|i| {
+            let offset = nudge + i as f64 * spacing;
+            let length = match self.gate {
+                Gate::Percent(_) | Gate::Steps(_) => gate_steps * spacing,
+
+                // Legato ratchets run into each other,
+                // and the last into the next chord
+                Gate::Legato if i + 1 < n => spacing,
+                Gate::Legato => gate_steps - offset,
+            };
+            let mut level = self.velocity.min(100) as f64 / 100.;
+            if self.decay {
+                level *= RATCHET_DECAY.powi(i as i32);
+            }
+            Hit { offset, length, level }
+        }).collect()
+    }
+}
@@ -1,8 +1,12 @@
 use rand::{Rng, seq::SliceRandom};
 use std::collections::HashMap;
-use serde::{Deserialize, Deserializer};
-use crate::core::{Mode, ChordSpec, Duration};
-use super::Progression;
+use serde::{Deserialize, Deserializer, de::Error};
+use crate::core::{Key, Mode, ChordSpec, Duration};
+use super::{Progression, Cadence, euclidean};
+
+// How many times to try generating chords
+// that lead into the chord after them
+const REGEN_ATTEMPTS: usize = 100;
 
 #[derive(Deserialize, PartialEq, Clone, Debug)]
 pub struct ModeTemplate {
@@ -46,6 +50,47 @@ impl ModeTemplate {
         let chord_name = chord.to_string();
         self.transitions.get(&chord_name).unwrap_or(&default).clone()
     }
+
+    /// Get the distinct chords that can follow the given chord,
+    /// with how often they do, most common first
+    pub fn ranked_next(&self, chord: &ChordSpec) -> Vec<(ChordSpec, usize)> {
+        let mut ranked: Vec<(ChordSpec, usize)> = vec![];
+        for cand in self.next(chord) {
+            match ranked.iter_mut().find(|(cs, _)| *cs == cand) {
+                Some((_, count)) => *count += 1,
+                None => ranked.push((cand, 1)),
+            }
+        }
+        ranked.sort_by(|(_, a), (_, b)| b.cmp(a));
+        ranked
+    }
+
+    /// Get chords from the patterns that share at least
+    /// two notes with the given chord, most shared first
+    pub fn substitutions(&self, chord: &ChordSpec, mode: &Mode) -> Vec<ChordSpec> {
+        let key = Key { mode: *mode, ..Key::default() };
+        let pitches = |cs: &ChordSpec| -> Vec<isize> {
+            let mut pitches: Vec<isize> = cs.chord_for_key(&key).notes().iter()
+                .map(|n| n.semitones.rem_euclid(12)).collect();
+            pitches.sort();
+            pitches.dedup();
+            pitches
+        };
+        let notes = pitches(chord);
+        let mut subs: Vec<(ChordSpec, usize)> = vec![];
+        for cand in self.patterns.iter().flatten() {
+            if cand == chord || subs.iter().any(|(cs, _)| cs == cand) {
+                continue;
+            }
+            let cand_notes = pitches(cand);
+            let shared = cand_notes.iter().filter(|n| notes.contains(n)).count();
+            if shared >= 2 {
+                subs.push((cand.clone(), shared));
+            }
+        }
+        subs.sort_by(|(_, a), (_, b)| b.cmp(a));
+        subs.into_iter().map(|(cs, _)| cs).collect()
+    }
 }
 
 /// Lets us write progressions as space-separated strings in yaml,
@@ -60,10 +105,69 @@ where
     }).collect())
 }
 
+/// Additional chord types to offer when selecting chords,
+/// e.g. ":4,b7" for a quartal voicing.
+/// Major types are appended to uppercase numerals
+/// and minor types to lowercase numerals.
+#[derive(Deserialize, PartialEq, Clone, Debug, Default)]
+pub struct ChordTypes {
+    #[serde(default, deserialize_with = "major_chord_types")]
+    pub major: Vec<String>,
+
+    #[serde(default, deserialize_with = "minor_chord_types")]
+    pub minor: Vec<String>,
+}
+
+/// Check that each chord type makes a valid chord
+/// when appended to the given numeral.
+fn validate_chord_types<'de, D>(deserializer: D, numeral: &str) -> Result<Vec<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let types: Vec<String> = Deserialize::deserialize(deserializer)?;
+    for t in &types {
+        let name = format!("{}{}", numeral, t);
+        if let Err(err) = ChordSpec::try_from(name.as_str()) {
+            return Err(D::Error::custom(format!("chord type `{}`: {}", t, err)));
+        }
+    }
+    Ok(types)
+}
+
+fn major_chord_types<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    validate_chord_types(deserializer, "I")
+}
+
+fn minor_chord_types<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    validate_chord_types(deserializer, "i")
+}
+
+// Patterns bundled with dust,
+// used if there's no patterns file
+const DEFAULT_PATTERNS: &str = include_str!("../../patterns.yaml");
+
 #[derive(Deserialize, PartialEq, Clone, Debug)]
 pub struct ProgressionTemplate {
     major: ModeTemplate,
     minor: ModeTemplate,
+
+    #[serde(default)]
+    pub chord_types: ChordTypes,
+}
+
+impl Default for ProgressionTemplate {
+    fn default() -> Self {
+        let mut template: ProgressionTemplate = serde_yaml::from_str(DEFAULT_PATTERNS)
+            .expect("bundled patterns are valid");
+        template.update_transitions();
+        template
+    }
 }
 
 impl ProgressionTemplate {
@@ -77,49 +181,153 @@ impl ProgressionTemplate {
     /// Return a list of candidate chord specs
     /// to follow this one.
     pub fn next(&self, chord: &ChordSpec, mode: &Mode) -> Vec<ChordSpec> {
-        match mode {
+        match mode.quality() {
             Mode::Major => self.major.next(chord),
-            Mode::Minor => self.minor.next(chord)
+            _ => self.minor.next(chord)
+        }
+    }
+
+    /// Return the distinct candidate chord specs to follow
+    /// this one, with how often they do, most common first.
+    pub fn ranked_next(&self, chord: &ChordSpec, mode: &Mode) -> Vec<(ChordSpec, usize)> {
+        match mode.quality() {
+            Mode::Major => self.major.ranked_next(chord),
+            _ => self.minor.ranked_next(chord)
+        }
+    }
+
+    /// Return chord specs that could substitute for this one.
+    pub fn substitutions(&self, chord: &ChordSpec, mode: &Mode) -> Vec<ChordSpec> {
+        match mode.quality() {
+            Mode::Major => self.major.substitutions(chord, mode),
+            _ => self.minor.substitutions(chord, mode)
         }
     }
 
     /// Generate a progression of chord specs starting with this chord spec.
-    pub fn gen_progression_from_seed(&self, seed: &ChordSpec, mode: &Mode, bars: usize, resolution: &Duration) -> Progression  {
-        let mut rng = rand::thread_rng();
+    pub fn gen_progression_from_seed(&self, seed: &ChordSpec, mode: &Mode, bars: usize, resolution: &Duration, cadence: Option<Cadence>) -> Progression  {
         let timings = self.gen_timing(bars, resolution);
-        let mut last = seed.clone();
-        let template = match mode {
+        self.gen_progression_with_timing(seed, mode, timings, resolution, cadence)
+    }
+
+    /// Generate a progression with chords spread evenly
+    /// across each bar, per the Euclidean algorithm.
+    pub fn gen_euclidean_progression(&self, seed: &ChordSpec, mode: &Mode, bars: usize, resolution: &Duration, hits: usize, cadence: Option<Cadence>) -> Progression {
+        let bar = euclidean(hits.max(1), resolution.ticks_per_bar());
+        let timings = bar.repeat(bars);
+        self.gen_progression_with_timing(seed, mode, timings, resolution, cadence)
+    }
+
+    /// Generate a progression starting with this chord spec,
+    /// with chords wherever the timings have one,
+    /// ending with the cadence (if any).
+    fn gen_progression_with_timing(&self, seed: &ChordSpec, mode: &Mode, mut timings: Vec<bool>, resolution: &Duration, cadence: Option<Cadence>) -> Progression {
+        let ending = cadence.map_or(vec![], |c| c.chords(mode));
+
+        // Make room for the seed and the cadence,
+        // adding chords to the end if needed
+        let mut missing = (ending.len() + 1).saturating_sub(timings.iter().filter(|t| **t).count());
+        for has_chord in timings.iter_mut().rev() {
+            if missing == 0 {
+                break;
+            }
+            if !*has_chord {
+                *has_chord = true;
+                missing -= 1;
+            }
+        }
+
+        let mut prog: Vec<Option<ChordSpec>> = vec![];
+        if let Some(first) = timings.iter().position(|t| *t) {
+            prog.resize(first, None);
+            prog.push(Some(seed.clone()));
+            prog.extend(self.walk(mode, Some(seed), None, &ending, &timings[first+1..]));
+        } else {
+            prog.resize(timings.len(), None);
+        }
+        Progression::new(prog, *resolution)
+    }
+
+    /// Fill in chords wherever the timings have one, following
+    /// on from the chord before (if any), and ending with the
+    /// given chords. Tries to lead into the ending or,
+    /// if there isn't one, into the chord after (if any).
+    fn walk(&self, mode: &Mode, before: Option<&ChordSpec>, after: Option<&ChordSpec>, ending: &[ChordSpec], timings: &[bool]) -> Vec<Option<ChordSpec>> {
+        let mut rng = rand::thread_rng();
+        let template = match mode.quality() {
             Mode::Major => &self.major,
-            Mode::Minor => &self.minor,
+            _ => &self.minor,
         };
-        let mut prog: Vec<Option<ChordSpec>> = vec![];
-        for has_chord in timings {
-            if has_chord {
-                let next = if prog.len() == 0 {
-                    seed.clone()
-                } else {
-                    let cands = template.next(&last);
-                    let next = cands.choose(&mut rng);
-                    if next.is_none() {
-                        self.rand_chord_for_mode(mode)
-                    } else {
-                        next.unwrap().clone()
-                    }
-                };
-
-                last = next.clone();
-                prog.push(Some(next));
-            } else {
-                prog.push(None);
+        let slots = timings.iter().filter(|t| **t).count();
+        let ending = &ending[ending.len().saturating_sub(slots)..];
+        let target = ending.first().or(after);
+
+        let mut chords = vec![];
+        for _ in 0..REGEN_ATTEMPTS {
+            chords.clear();
+            let mut last = before.cloned();
+            for _ in 0..slots - ending.len() {
+                let next = last.as_ref()
+                    .and_then(|last| template.next(last).choose(&mut rng).cloned())
+                    .unwrap_or_else(|| self.rand_chord_for_mode(mode));
+                last = Some(next.clone());
+                chords.push(next);
+            }
+            let connects = match (&last, target) {
+                (Some(last), Some(target)) => template.next(last).contains(target),
+                _ => true,
+            };
+            if connects {
+                break;
             }
         }
-        Progression::new(prog, *resolution)
+        chords.extend(ending.iter().cloned());
+
+        let mut chords = chords.into_iter();
+        timings.iter()
+            .map(|has_chord| if *has_chord { chords.next() } else { None })
+            .collect()
+    }
+
+    /// Regenerate a range of a progression, following on
+    /// from the chord before it and, if possible, leading
+    /// into the chord after it, looping around the progression.
+    /// The range ends with the cadence, if there is one.
+    pub fn regenerate(&self, progression: &Progression, range: (usize, usize), mode: &Mode, cadence: Option<Cadence>) -> Progression {
+        let len = progression.sequence.len();
+        let (start, end) = (range.0.min(len), range.1.min(len));
+        if start >= end {
+            return progression.clone();
+        }
+
+        // The nearest chords outside the range
+        let outside: Vec<usize> = (end..len).chain(0..start).collect();
+        let after = outside.iter()
+            .find_map(|i| progression.sequence[*i].as_ref());
+        let before = outside.iter().rev()
+            .find_map(|i| progression.sequence[*i].as_ref());
+
+        let ticks_per_bar = progression.resolution.ticks_per_bar();
+        let bars = (end - start + ticks_per_bar - 1) / ticks_per_bar;
+        let mut timings = self.gen_timing(bars, &progression.resolution);
+        timings.truncate(end - start);
+
+        let ending = cadence.map_or(vec![], |c| c.chords(mode));
+        let region = self.walk(mode, before, after, &ending, &timings);
+
+        let mut prog = progression.clone();
+        for (i, chord) in region.into_iter().enumerate() {
+            prog.sequence[start + i] = chord;
+            prog.steps.remove(&(start + i));
+        }
+        prog.update_chords();
+        prog
     }
 
     /// Generate a progression of chord specs for a given mode.
-    pub fn gen_progression(&self, mode: &Mode, bars: usize, resolution: &Duration) -> Progression {
+    pub fn gen_progression(&self, mode: &Mode, bars: usize, resolution: &Duration, cadence: Option<Cadence>) -> Progression {
         let seed = self.rand_chord_for_mode(mode);
-        self.gen_progression_from_seed(&seed, mode, bars, resolution)
+        self.gen_progression_from_seed(&seed, mode, bars, resolution, cadence)
     }
 
     /// Generates random timings for chords in the progression.
@@ -144,9 +352,9 @@ impl ProgressionTemplate {
     /// Randomly chooses a pattern given a mode.
     pub fn rand_pattern(&self, mode: &Mode) -> Vec<ChordSpec> {
         let mut rng = rand::thread_rng();
-        let cands = match mode {
+        let cands = match mode.quality() {
             Mode::Major => &self.major.patterns,
-            Mode::Minor => &self.minor.patterns
+            _ => &self.minor.patterns
         };
         cands.choose(&mut rng).unwrap().clone()
     }
@@ -180,9 +388,87 @@ mod test {
             minor: ModeTemplate {
                 patterns: vec![],
                 transitions: HashMap::default()
-            }
+            },
+            chord_types: ChordTypes::default(),
         };
-        let progression = template.gen_progression(&mode, bars, &Duration::Eighth);
+        let progression = template.gen_progression(&mode, bars, &Duration::Eighth, None);
         assert_eq!(progression.sequence.len(), bars * Duration::Eighth.ticks_per_bar());
+
+        let progression = template.gen_progression(&mode, 1, &Duration::Quarter, Some(Cadence::Deceptive));
+        let chords: Vec<ChordSpec> = progression.chords().into_iter().cloned().collect();
+        assert_eq!(chords[chords.len()-2..], Cadence::Deceptive.chords(&mode));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_chord_types() {
+        let yaml = "
+major:
+  patterns:
+    - I IV V
+minor:
+  patterns:
+    - i iv v
+chord_types:
+  major:
+    - :4,b7
+";
+        let template: ProgressionTemplate = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!(template.chord_types.major, vec![":4,b7"]);
+        assert!(template.chord_types.minor.is_empty());
+
+        let yaml = yaml.replace(":4,b7", ":x");
+        let template: Result<ProgressionTemplate, _> = serde_yaml::from_str(&yaml);
+        assert!(template.is_err());
+    }
+
+    #[test]
+    fn test_regenerate() {
+        let template = ProgressionTemplate::default();
+        let chords: Vec<ChordSpec> = ["I", "IV", "V", "I"].iter()
+            .map(|c| (*c).try_into().unwrap()).collect();
+        let prog = Progression::from_chords(chords, 4, Duration::Quarter);
+        let regen = template.regenerate(&prog, (4, 12), &Mode::Major, None);
+        assert_eq!(regen.sequence.len(), 16);
+        assert_eq!(regen.sequence[0..4], prog.sequence[0..4]);
+        assert_eq!(regen.sequence[12..], prog.sequence[12..]);
+
+        // Regenerated chords start on the first step
+        // and lead into the chord after them
+        assert!(regen.sequence[4].is_some());
+        let last = regen.sequence[4..12].iter().flatten().last().unwrap();
+        assert!(template.major.next(last).contains(&"I".try_into().unwrap()));
+    }
+
+    #[test]
+    fn test_suggestions() {
+        let yaml = "
+major:
+  patterns:
+    - I V vi IV
+    - I V I ii
+minor:
+  patterns:
+    - i iv v
+";
+        let mut template: ProgressionTemplate = serde_yaml::from_str(yaml).unwrap();
+        template.update_transitions();
+        let chord = |name: &str| -> ChordSpec { name.try_into().unwrap() };
+
+        // I follows V three times, more than anything else
+        let ranked = template.ranked_next(&chord("V"), &Mode::Major);
+        assert_eq!(ranked[0], (chord("I"), 3));
+        assert_eq!(ranked.len(), 3);
+
+        // vi shares two notes with I, V shares one
+        let subs = template.substitutions(&chord("I"), &Mode::Major);
+        assert_eq!(subs, vec![chord("vi")]);
+    }
+
+    #[test]
+    fn test_default_template() {
+        let template = ProgressionTemplate::default();
+        assert!(!template.major.patterns.is_empty());
+        assert!(!template.minor.patterns.is_empty());
+        assert!(!template.major.transitions.is_empty());
+    }
+}
@@ -0,0 +1,370 @@
+use anyhow::Result;
+use std::{fs, env, str::FromStr, path::{Path, PathBuf}, collections::BTreeMap};
+use serde::{Serialize, Deserialize, de::DeserializeOwned};
+use crate::config::as_str;
+use crate::core::{Key, ChordSpec, Duration};
+use crate::progression::{Progression, Step, Gate, Accent, BassPattern};
+use crate::midi::Arp;
+
+/// What was being worked on, saved to
+/// `~/.config/dust/session.yaml` when `dust`
+/// quits, or to another file to load later.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Session {
+    // The sequencer's key and timing
+    #[serde(with = "as_str")]
+    pub key: Key,
+    pub tempo: usize,
+    pub swing: usize,
+    #[serde(default, with = "as_str")]
+    pub accent: Accent,
+
+    // The sequencer's first track: its name, and the
+    // MIDI channel, 0-15, and port it plays on,
+    // if not the output's channel and every port
+    #[serde(default)]
+    pub track_name: Option<String>,
+    #[serde(default)]
+    pub channel: Option<u8>,
+    #[serde(default)]
+    pub port: Option<String>,
+
+    // The bassline it plays, if any
+    #[serde(default)]
+    pub bass: Option<BassPattern>,
+
+    // The first track's progression, one chord
+    // per step or nothing for rests, and
+    // the settings of steps that have any
+    #[serde(with = "as_str")]
+    pub resolution: Duration,
+    pub sequence: Vec<Option<String>>,
+    pub steps: BTreeMap<usize, SavedStep>,
+
+    // The steps looped, if not all of them
+    #[serde(default)]
+    pub clip: Option<(usize, usize)>,
+
+    // The sequencer's other tracks
+    #[serde(default)]
+    pub tracks: Vec<SavedTrack>,
+
+    // The performance mode's key
+    // and chords bound to keys
+    #[serde(with = "as_str")]
+    pub performance_key: Key,
+    pub mappings: Vec<Option<String>>,
+
+    // MIDI channel each slot plays on, if not the output's
+    #[serde(default)]
+    pub channels: Vec<Option<u8>>,
+
+    // Arpeggio each slot plays as, if any
+    #[serde(default)]
+    pub arps: Vec<Option<String>>,
+
+    // MIDI note that plays each slot, if any
+    #[serde(default)]
+    pub notes: Vec<Option<u8>>,
+
+    // MIDI ports, by name
+    pub midi_in_port: Option<String>,
+    pub midi_out_port: Option<String>,
+}
+
+/// One of the sequencer's tracks after the first,
+/// saved like the first track is.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SavedTrack {
+    pub name: String,
+    pub channel: Option<u8>,
+    pub port: Option<String>,
+    #[serde(default)]
+    pub bass: Option<BassPattern>,
+    #[serde(default)]
+    pub clip: Option<(usize, usize)>,
+    #[serde(with = "as_str")]
+    pub resolution: Duration,
+    pub sequence: Vec<Option<String>>,
+    pub steps: BTreeMap<usize, SavedStep>,
+}
+
+impl SavedTrack {
+    pub fn new(name: &str, progression: &Progression) -> SavedTrack {
+        SavedTrack {
+            name: name.to_string(),
+            channel: None,
+            port: None,
+            bass: None,
+            clip: None,
+            resolution: progression.resolution,
+            sequence: chord_names(&progression.sequence),
+            steps: saved_steps(progression),
+        }
+    }
+
+    pub fn progression(&self) -> Result<Progression> {
+        saved_progression(self.resolution, &self.sequence, &self.steps)
+    }
+}
+
+/// A step's settings, with its
+/// key and gate as they're displayed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SavedStep {
+    pub key: Option<String>,
+    pub gate: String,
+    pub chance: usize,
+    pub ratchets: usize,
+    pub decay: bool,
+    pub velocity: usize,
+    pub nudge: usize,
+    pub arp: Option<String>,
+}
+
+impl From<&Step> for SavedStep {
+    fn from(step: &Step) -> Self {
+        SavedStep {
+            key: step.key.map(|key| key.to_string()),
+            gate: step.gate.to_string(),
+            chance: step.chance,
+            ratchets: step.ratchets,
+            decay: step.decay,
+            velocity: step.velocity,
+            nudge: step.nudge,
+            arp: step.arp.map(|arp| arp.to_string()),
+        }
+    }
+}
+
+impl Default for SavedStep {
+    fn default() -> Self {
+        SavedStep::from(&Step::default())
+    }
+}
+
+impl SavedStep {
+    fn step(&self) -> Result<Step> {
+        Ok(Step {
+            key: self.key.as_deref().map(Key::from_str).transpose()?,
+            gate: Gate::from_str(&self.gate)?,
+            chance: self.chance,
+            ratchets: self.ratchets,
+            decay: self.decay,
+            velocity: self.velocity,
+            nudge: self.nudge,
+            arp: self.arp.as_deref().map(Arp::from_str).transpose()?,
+        })
+    }
+}
+
+/// The performance mode's chords in every bank, with
+/// its key and how long chords are held, saved to a
+/// file of their own to load into any session.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Pads {
+    #[serde(with = "as_str")]
+    pub key: Key,
+    pub duration: u64,
+    pub mappings: Vec<Option<String>>,
+}
+
+impl Pads {
+    /// Leaves out the empty slots after the last chord.
+    pub fn new(key: Key, duration: u64, mappings: &[Option<ChordSpec>]) -> Pads {
+        let len = mappings.iter().rposition(|cs| cs.is_some()).map_or(0, |idx| idx + 1);
+        Pads {
+            key,
+            duration,
+            mappings: chord_names(&mappings[..len]),
+        }
+    }
+
+    pub fn load_from(path: &Path) -> Result<Pads> {
+        read_yaml(path)
+    }
+
+    /// Save the chords to a file,
+    /// creating its directory if need be.
+    pub fn save_to(&self, path: &Path) -> Result<()> {
+        write_yaml(path, self)
+    }
+
+    pub fn mappings(&self) -> Result<Vec<Option<ChordSpec>>> {
+        parse_chords(&self.mappings)
+    }
+}
+
+fn read_yaml<T: DeserializeOwned>(path: &Path) -> Result<T> {
+    let contents = fs::read_to_string(path)?;
+    Ok(serde_yaml::from_str(&contents)?)
+}
+
+fn write_yaml<T: Serialize>(path: &Path, value: &T) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, serde_yaml::to_string(value)?)?;
+    Ok(())
+}
+
+/// Chords as they're displayed, `None` for rests.
+fn chord_names(chords: &[Option<ChordSpec>]) -> Vec<Option<String>> {
+    chords.iter().map(|cs| cs.as_ref().map(|cs| cs.to_string())).collect()
+}
+
+fn parse_chords(names: &[Option<String>]) -> Result<Vec<Option<ChordSpec>>> {
+    names.iter()
+        .map(|name| Ok(name.as_deref().map(ChordSpec::from_str).transpose()?))
+        .collect()
+}
+
+/// The settings of steps that have any.
+fn saved_steps(progression: &Progression) -> BTreeMap<usize, SavedStep> {
+    progression.steps.iter()
+        .map(|(idx, step)| (*idx, SavedStep::from(step)))
+        .collect()
+}
+
+fn saved_progression(resolution: Duration, sequence: &[Option<String>], steps: &BTreeMap<usize, SavedStep>) -> Result<Progression> {
+    let mut progression = Progression::new(parse_chords(sequence)?, resolution);
+    for (idx, step) in steps {
+        if *idx < progression.sequence.len() {
+            progression.steps.insert(*idx, step.step()?);
+        }
+    }
+    Ok(progression)
+}
+
+impl Session {
+    pub fn new(key: Key, tempo: usize, swing: usize, progression: &Progression, performance_key: Key, mappings: &[Option<ChordSpec>]) -> Session {
+        Session {
+            key,
+            tempo,
+            swing,
+            accent: Accent::default(),
+            track_name: None,
+            channel: None,
+            port: None,
+            bass: None,
+            resolution: progression.resolution,
+            sequence: chord_names(&progression.sequence),
+            steps: saved_steps(progression),
+            clip: None,
+            tracks: vec![],
+            performance_key,
+            mappings: chord_names(mappings),
+            channels: vec![],
+            arps: vec![],
+            notes: vec![],
+            midi_in_port: None,
+            midi_out_port: None,
+        }
+    }
+
+    pub fn path() -> PathBuf {
+        let home = env::var("HOME").unwrap_or_default();
+        PathBuf::from(home).join(".config/dust/session.yaml")
+    }
+
+    /// Load the last session, if there is one.
+    pub fn load() -> Result<Option<Session>> {
+        let path = Session::path();
+        if path.exists() {
+            Ok(Some(Session::load_from(&path)?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Save the session to pick up from next time.
+    pub fn save(&self) -> Result<()> {
+        self.save_to(&Session::path())
+    }
+
+    /// Load a session saved to a file.
+    pub fn load_from(path: &Path) -> Result<Session> {
+        read_yaml(path)
+    }
+
+    /// Save the session to a file,
+    /// creating its directory if need be.
+    pub fn save_to(&self, path: &Path) -> Result<()> {
+        write_yaml(path, self)
+    }
+
+    /// The sequencer's first track's progression.
+    pub fn progression(&self) -> Result<Progression> {
+        saved_progression(self.resolution, &self.sequence, &self.steps)
+    }
+
+    /// The performance mode's chords.
+    pub fn mappings(&self) -> Result<Vec<Option<ChordSpec>>> {
+        parse_chords(&self.mappings)
+    }
+
+    pub fn arps(&self) -> Result<Vec<Option<Arp>>> {
+        Ok(self.arps.iter()
+            .map(|arp| arp.as_deref().map(Arp::from_str).transpose())
+            .collect::<Result<_, _>>()?)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let chords = vec!["I", "vi:7", "IV/3", "V%1"].into_iter()
+            .map(|name| ChordSpec::from_str(name).unwrap())
+            .collect();
+        let mut progression = Progression::from_chords(chords, 2, Duration::Eighth);
+        progression.steps.insert(2, Step {
+            key: Some(Key::from_str("D4 minor").unwrap()),
+            gate: Gate::Percent(50),
+            ratchets: 3,
+            arp: Some(Arp::from_str("updown/8").unwrap()),
+            ..Step::default()
+        });
+        let mappings = vec![Some(ChordSpec::from_str("ii").unwrap()), None];
+        let mut session = Session::new(Key::default(), 90, 60, &progression, Key::default(), &mappings);
+        session.clip = Some((2, 6));
+        session.notes = vec![Some(36), None, Some(38)];
+
+        let yaml = serde_yaml::to_string(&session).unwrap();
+        let loaded: Session = serde_yaml::from_str(&yaml).unwrap();
+        assert_eq!(loaded.key, Key::default());
+        assert_eq!(loaded.tempo, 90);
+        assert_eq!(loaded.progression().unwrap(), progression);
+        assert_eq!(loaded.mappings().unwrap(), mappings);
+        assert_eq!(loaded.clip, Some((2, 6)));
+        assert_eq!(loaded.notes, session.notes);
+
+        // And through a file
+        let path = env::temp_dir().join("dust-test-session/session.yaml");
+        session.save_to(&path).unwrap();
+        let loaded = Session::load_from(&path).unwrap();
+        assert_eq!(loaded.progression().unwrap(), progression);
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_pads() {
+        let mappings = vec![
+            Some(ChordSpec::from_str("ii:7").unwrap()), None,
+            Some(ChordSpec::from_str("V%1").unwrap()), None, None];
+        let key = Key::from_str("D4 minor").unwrap();
+        let pads = Pads::new(key, 8, &mappings);
+        assert_eq!(pads.mappings.len(), 3);
+
+        let path = env::temp_dir().join("dust-test-pads/pads.yaml");
+        pads.save_to(&path).unwrap();
+        let loaded = Pads::load_from(&path).unwrap();
+        assert_eq!(loaded.key, key);
+        assert_eq!(loaded.duration, 8);
+        assert_eq!(loaded.mappings().unwrap(), mappings[..3]);
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}